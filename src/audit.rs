@@ -0,0 +1,209 @@
+use crate::cache;
+use crate::summary::{NewDirectory, NewFile, OnText, Reply, Summarizer, SummaryRequest};
+use crate::tokens::Estimator;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+/// One prompt sent to a provider and what came back.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub provider: String,
+    pub prompt: String,
+    pub response: Option<String>,
+    pub error: Option<String>,
+    /// Estimated locally, with the provider's `token-factor`
+    pub prompt_tokens: u32,
+    pub response_tokens: u32,
+    pub duration_ms: u64,
+}
+
+/// The audit log lives next to the summary cache.
+pub fn path() -> Option<PathBuf> {
+    cache::dir().map(|dir| dir.join("audit.jsonl"))
+}
+
+/// Reads every record in the audit log, oldest first.
+pub fn read() -> Result<Vec<AuditRecord>> {
+    match path() {
+        Some(path) => cache::read_jsonl(&path),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Deletes the audit log. Returns whether there was one to delete.
+pub fn purge() -> Result<bool> {
+    let Some(path) = path().filter(|path| path.exists()) else {
+        return Ok(false);
+    };
+    std::fs::remove_file(&path)
+        .with_context(|| format!("Failed to delete audit log {}", path.display()))?;
+    Ok(true)
+}
+
+/// Records every prompt sent to the wrapped provider, and its response, in the audit log.
+/// It wraps the provider directly so the log shows exactly what left the machine.
+pub struct AuditedSummarizer {
+    inner: Box<dyn Summarizer>,
+    path: PathBuf,
+    tokens: Estimator,
+    // Keeps concurrent requests from interleaving their lines
+    lock: Mutex<()>,
+}
+
+impl AuditedSummarizer {
+    pub fn new(inner: Box<dyn Summarizer>, tokens: Estimator) -> Result<Self> {
+        let path = path().ok_or_else(|| {
+            anyhow::anyhow!("Could not locate the cache dir for the audit log; set HOME")
+        })?;
+        Ok(Self {
+            inner,
+            path,
+            tokens,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Logs a request that started at `start`, passing its result through.
+    async fn record(
+        &self,
+        prompt: String,
+        timestamp: DateTime<Utc>,
+        start: Instant,
+        result: Result<String>,
+    ) -> Result<String> {
+        let (response, error) = match &result {
+            Ok(response) => (Some(response.clone()), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        self.append(&AuditRecord {
+            timestamp,
+            provider: self.inner.provider().to_string(),
+            prompt_tokens: self.tokens.estimate(&prompt),
+            response_tokens: response
+                .as_deref()
+                .map_or(0, |response| self.tokens.estimate(response)),
+            prompt,
+            response,
+            error,
+            duration_ms: start.elapsed().as_millis() as u64,
+        })
+        .await?;
+        result
+    }
+
+    async fn append(&self, record: &AuditRecord) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        cache::append_jsonl(&self.path, record)
+    }
+}
+
+#[async_trait]
+impl Summarizer for AuditedSummarizer {
+    async fn complete(&self, prompt: String, reply: Reply) -> Result<String> {
+        let timestamp = Utc::now();
+        let start = Instant::now();
+        let result = self.inner.complete(prompt.clone(), reply).await;
+        self.record(prompt, timestamp, start, result).await
+    }
+
+    async fn complete_streaming(
+        &self,
+        prompt: String,
+        reply: Reply,
+        on_text: &mut OnText<'_>,
+    ) -> Result<String> {
+        let timestamp = Utc::now();
+        let start = Instant::now();
+        let result = self
+            .inner
+            .complete_streaming(prompt.clone(), reply, on_text)
+            .await;
+        self.record(prompt, timestamp, start, result).await
+    }
+
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn estimator(&self) -> Estimator {
+        self.tokens
+    }
+
+    fn summarize_prompt(&self, request: &SummaryRequest) -> String {
+        self.inner.summarize_prompt(request)
+    }
+
+    fn update_summary_prompt(&self, previous: &str, delta: &str) -> String {
+        self.inner.update_summary_prompt(previous, delta)
+    }
+
+    fn summarize_sides_prompt(
+        &self,
+        staged: &SummaryRequest,
+        unstaged: &SummaryRequest,
+    ) -> Option<String> {
+        self.inner.summarize_sides_prompt(staged, unstaged)
+    }
+
+    fn summarize_new_file_prompt(&self, file: &NewFile) -> String {
+        self.inner.summarize_new_file_prompt(file)
+    }
+
+    fn summarize_new_directory_prompt(&self, directory: &NewDirectory) -> String {
+        self.inner.summarize_new_directory_prompt(directory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::CommandSummarizer;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_audited_summarizer() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let summarizer = AuditedSummarizer {
+            inner: Box::new(CommandSummarizer::new("tr a-z A-Z")),
+            path: temp_dir.path().join("audit.jsonl"),
+            tokens: Estimator::default(),
+            lock: Mutex::new(()),
+        };
+
+        assert_eq!(
+            summarizer
+                .summarize(&SummaryRequest::new("+added line"))
+                .await?
+                .text,
+            "+ADDED LINE"
+        );
+        let failing = AuditedSummarizer {
+            inner: Box::new(CommandSummarizer::new("exit 1")),
+            ..summarizer
+        };
+        assert!(failing
+            .complete("prompt".to_string(), Reply::Text(10))
+            .await
+            .is_err());
+
+        let records: Vec<AuditRecord> = cache::read_jsonl(&failing.path)?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].provider, "command");
+        assert_eq!(records[0].prompt, "+added line");
+        assert_eq!(records[0].response.as_deref(), Some("+ADDED LINE"));
+        assert!(records[1].response.is_none());
+        assert!(records[1].error.is_some());
+
+        Ok(())
+    }
+}
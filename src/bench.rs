@@ -0,0 +1,175 @@
+//! Timings for the status pipeline on a synthetic repo with many changed files, to
+//! check performance work like batched diffs or parallel binary detection against.
+//! They're ignored by default; run them with
+//! `cargo test --release bench_ -- --ignored --nocapture --test-threads=1`, and set
+//! `GIT_HUD_BENCH_FILES` to change how many files the repo has (1000 by default).
+
+use crate::cache::{self, CacheBackend, CachedSummary, FileBackend};
+use crate::git::tests::git;
+use crate::git::{Repository, StatusBackend};
+use crate::plan::SummaryPlan;
+use crate::summary::{CommandSummarizer, Summarizer};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::time::Instant;
+use tempfile::TempDir;
+
+const DEFAULT_FILES: usize = 1000;
+/// How many times each step is timed; the fastest and the median are reported.
+const ITERATIONS: usize = 5;
+
+fn file_count() -> usize {
+    std::env::var("GIT_HUD_BENCH_FILES")
+        .ok()
+        .and_then(|files| files.parse().ok())
+        .unwrap_or(DEFAULT_FILES)
+}
+
+/// A repo of `files` committed files spread over directories, with half of them
+/// modified, a quarter of those staged, and a tenth as many untracked files.
+fn synthetic_repo(files: usize) -> Result<(TempDir, Repository)> {
+    let temp_dir = TempDir::new()?;
+    let root = temp_dir.path();
+    git(root, &["init", "-q"])?;
+
+    let path = |i: usize| root.join(format!("src/module_{}/file_{}.rs", i % 50, i));
+    let content = |i: usize, version: usize| -> String {
+        (0..40)
+            .map(|line| format!("fn f_{}_{}() -> usize {{ {} }}\n", i, line, line * version))
+            .collect()
+    };
+    for i in 0..files {
+        let path = path(i);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, content(i, 1))?;
+    }
+    git(root, &["add", "."])?;
+    git(root, &["commit", "-q", "-m", "base"])?;
+
+    for i in (0..files).step_by(2) {
+        fs::write(path(i), content(i, 2))?;
+    }
+    git(
+        root,
+        &["add", "src/module_0", "src/module_2", "src/module_4"],
+    )?;
+    for i in 0..files / 10 {
+        fs::write(root.join(format!("src/untracked_{}.rs", i)), content(i, 3))?;
+    }
+
+    let repo = Repository::open_current_directory(root.to_str())?;
+    Ok((temp_dir, repo))
+}
+
+/// Runs `step` `ITERATIONS` times and prints how long it took.
+fn measure<T>(label: &str, mut step: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut times = Vec::with_capacity(ITERATIONS);
+    let mut result = None;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        result = Some(step()?);
+        times.push(start.elapsed());
+    }
+    times.sort();
+    println!(
+        "{:<40} fastest {:>10.2?}  median {:>10.2?}",
+        label,
+        times[0],
+        times[times.len() / 2]
+    );
+    Ok(result.expect("ITERATIONS is more than zero"))
+}
+
+#[test]
+#[ignore]
+fn bench_status() -> Result<()> {
+    let files = file_count();
+    let (_temp_dir, mut repo) = synthetic_repo(files)?;
+    for backend in [StatusBackend::Git, StatusBackend::Libgit2] {
+        repo.set_status_backend(backend);
+        let status = measure(&format!("status, {:?}, {} files", backend, files), || {
+            repo.get_status()
+        })?;
+        assert!(status.entries.len() >= files / 2);
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn bench_diffs() -> Result<()> {
+    let files = file_count();
+    let (_temp_dir, repo) = synthetic_repo(files)?;
+    let status = repo.get_status()?;
+    measure(
+        &format!("diffs, {} entries", status.entries.len()),
+        || -> Result<usize> {
+            let mut total = 0;
+            for entry in &status.entries {
+                for (side, _) in entry.sides() {
+                    total += repo
+                        .get_side_diff(entry, side)?
+                        .map_or(0, |diff| diff.len());
+                }
+            }
+            Ok(total)
+        },
+    )?;
+    Ok(())
+}
+
+/// The path a run takes when nothing changed since the last one: load the cache, find
+/// every summary still applies, and save it again.
+#[test]
+#[ignore]
+fn bench_cache_hits() -> Result<()> {
+    let files = file_count();
+    let (temp_dir, repo) = synthetic_repo(files)?;
+    let status = repo.get_status()?;
+    let summarizer = CommandSummarizer::new("cat");
+    let mut diffs = Vec::new();
+    for entry in &status.entries {
+        for (side, _) in entry.sides() {
+            if let Some(diff) = repo.get_side_diff(entry, side)? {
+                diffs.push((entry, side, diff));
+            }
+        }
+    }
+    let entries: HashMap<String, CachedSummary> = diffs
+        .iter()
+        .map(|(entry, side, diff)| {
+            let summary = CachedSummary {
+                diff_hash: cache::hash(diff),
+                blob: None,
+                summary: format!("Change {}", entry.display_path),
+                provider: summarizer.provider().to_string(),
+                model: summarizer.model().to_string(),
+                tokens: 100,
+            };
+            (format!("{}:{}", side.label(), entry.display_path), summary)
+        })
+        .collect();
+
+    let backend = FileBackend::new(temp_dir.path().join("cache"));
+    backend.save("bench", &entries)?;
+    let label = format!("cache load and save, {} entries", entries.len());
+    measure(&label, || {
+        let loaded = backend.load("bench")?;
+        backend.save("bench", &loaded)
+    })?;
+    measure(&format!("plans, {} cache hits", diffs.len()), || {
+        let mut hits = 0;
+        for (entry, side, diff) in &diffs {
+            let previous = entries.get(&format!("{}:{}", side.label(), entry.display_path));
+            if let SummaryPlan::Cached(_) =
+                SummaryPlan::new(&repo, entry, *side, diff, previous, &summarizer)?
+            {
+                hits += 1;
+            }
+        }
+        assert_eq!(hits, diffs.len());
+        Ok(())
+    })?;
+    Ok(())
+}
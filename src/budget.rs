@@ -0,0 +1,187 @@
+//! What one run may spend on summaries: how many requests it sends, how much they may
+//! cost before it asks whether to go ahead, and when to stop waiting on them.
+
+use crate::config::ProviderConfig;
+use crate::prompt;
+use crate::stats::Pricing;
+use crate::tokens::Estimator;
+use anyhow::Result;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::sync::Once;
+use tokio_util::sync::CancellationToken;
+
+/// About how many tokens a summary's reply takes, to price requests before they're sent.
+const REPLY_TOKENS: u64 = 40;
+
+/// The limits one run's requests are held to. The default holds them to none.
+#[derive(Default)]
+pub struct Budget<'a> {
+    /// The most sides sent for a summary; the rest are marked as not summarized
+    pub max_requests: Option<usize>,
+    /// Asks before sending requests that cost more than `status.confirm-cost`
+    pub gate: Option<&'a Gate<'a>>,
+    /// Stops the requests still out when cancelled; their sides are marked as not
+    /// summarized, and what already came back is kept
+    pub cancel: CancellationToken,
+    /// Whether Ctrl-C cancels, once requests are being sent
+    pub cancel_on_ctrl_c: bool,
+}
+
+/// What a run's requests are expected to take.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub tokens: u64,
+    /// In US dollars
+    pub cost: f64,
+}
+
+/// `status.confirm-cost`: a run whose requests are estimated to cost more asks first on
+/// the terminal. With no terminal to ask on, like in a script, it doesn't send them.
+pub struct Gate<'a> {
+    threshold: f64,
+    providers: &'a HashMap<String, ProviderConfig>,
+    held_back: Cell<Option<Estimate>>,
+}
+
+impl<'a> Gate<'a> {
+    /// Asks before spending more than `threshold` US dollars, with prices from
+    /// `providers`' settings.
+    pub fn new(threshold: f64, providers: &'a HashMap<String, ProviderConfig>) -> Self {
+        Self {
+            threshold,
+            providers,
+            held_back: Cell::new(None),
+        }
+    }
+
+    /// The tokens and cost of `requests`, each given as its provider and its prompt.
+    pub fn estimate(&self, requests: &[(&str, String)]) -> Estimate {
+        let mut estimate = Estimate {
+            tokens: 0,
+            cost: 0.0,
+        };
+        for (provider, prompt) in requests {
+            let config = self.providers.get(*provider);
+            let prompt_tokens = Estimator::new(config).estimate(prompt);
+            let pricing = Pricing::for_provider(provider, config);
+            estimate.tokens += u64::from(prompt_tokens) + REPLY_TOKENS;
+            estimate.cost += pricing.cost(u64::from(prompt_tokens), REPLY_TOKENS);
+        }
+        estimate
+    }
+
+    /// Whether to send `requests`: yes when they're within the threshold or the user says
+    /// to go ahead.
+    pub fn allows(&self, requests: &[(&str, String)]) -> Result<bool> {
+        let estimate = self.estimate(requests);
+        if estimate.cost <= self.threshold {
+            return Ok(true);
+        }
+        // The question goes to stdout, so it needs the terminal on both ends
+        let interactive = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+        let allowed = interactive
+            && prompt::confirm(&format!(
+                "This will send ~{} tokens (~${:.2}). Continue?",
+                format_tokens(estimate.tokens),
+                estimate.cost
+            ))?;
+        if !allowed {
+            self.held_back.set(Some(estimate));
+        }
+        Ok(allowed)
+    }
+
+    /// Like `allows`, but fails if `requests` aren't to be sent, for commands that have
+    /// nothing to show without their summaries.
+    pub fn require(&self, requests: &[(&str, String)]) -> Result<()> {
+        match self.allows(requests)? {
+            true => Ok(()),
+            false => Err(over_threshold(
+                self.held_back().unwrap_or_else(|| self.estimate(requests)),
+            )),
+        }
+    }
+
+    /// What wasn't sent because it cost too much, if anything was held back.
+    pub fn held_back(&self) -> Option<Estimate> {
+        self.held_back.get()
+    }
+}
+
+/// The error for a command whose summaries weren't sent because they cost too much.
+pub fn over_threshold(estimate: Estimate) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Sending ~{} tokens (~${:.2}) is over `status.confirm-cost`; run with --yes to send them",
+        format_tokens(estimate.tokens),
+        estimate.cost
+    )
+}
+
+/// Cancels `cancel` on the first Ctrl-C, so a run that's waiting on slow summaries can
+/// show the rest instead of throwing it all away. A second Ctrl-C exits as usual. Until
+/// this is called, Ctrl-C always exits, so it's only listened for once requests are sent,
+/// and only once per process.
+pub fn cancel_on_ctrl_c(cancel: &CancellationToken) {
+    static LISTENING: Once = Once::new();
+    LISTENING.call_once(|| {
+        let cancel = cancel.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
+            }
+            // Handling Ctrl-C here keeps it from ending the process on its own
+            let _ = tokio::signal::ctrl_c().await;
+            std::process::exit(130);
+        });
+    });
+}
+
+/// A token count rounded to thousands once it's in them, like `90k`.
+pub fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..1000 => tokens.to_string(),
+        _ => format!("{}k", (tokens + 500) / 1000),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate() {
+        let providers = HashMap::from([(
+            "command".to_string(),
+            ProviderConfig {
+                input_price: Some(1.0),
+                output_price: Some(10.0),
+                ..ProviderConfig::default()
+            },
+        )]);
+        let gate = Gate::new(0.1, &providers);
+        // Six-letter words are a token each
+        let prompt = |tokens: usize| "abcdef ".repeat(tokens);
+        let estimate = gate.estimate(&[
+            ("anthropic", prompt(960)),
+            ("command", prompt(4960)),
+            ("plugin", prompt(60)),
+        ]);
+        assert_eq!(estimate.tokens, 6100);
+        // Haiku's list price, then the configured price, and plugins are free
+        let cost = (960.0 * 0.25 + 40.0 * 1.25) / 1e6 + (4960.0 + 400.0) / 1e6;
+        assert!((estimate.cost - cost).abs() < 1e-12, "{}", estimate.cost);
+        // Within the threshold nothing is asked
+        assert!(gate.allows(&[("anthropic", prompt(960))]).unwrap());
+        assert_eq!(gate.held_back(), None);
+    }
+
+    #[test]
+    fn test_format_tokens() {
+        assert_eq!(format_tokens(0), "0");
+        assert_eq!(format_tokens(999), "999");
+        assert_eq!(format_tokens(1499), "1k");
+        assert_eq!(format_tokens(89_600), "90k");
+    }
+}
@@ -0,0 +1,615 @@
+use crate::config::{Backend, CacheConfig};
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::redis;
+use crate::shared_cache::SharedCache;
+use crate::strings;
+use crate::summary::Summarizer;
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How long a working tree file's content is kept after it was summarized. Past that,
+/// its next summary is made from the whole diff again.
+const SNAPSHOT_MAX_AGE: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// The summary last shown for a file, so an unchanged diff isn't summarized twice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedSummary {
+    pub diff_hash: String,
+    /// The blob id of the file's content when it was summarized, so later runs can diff
+    /// against it: the index's for staged changes, or for unstaged ones, a copy kept in
+    /// the cache dir
+    pub blob: Option<String>,
+    pub summary: String,
+    /// Who wrote the summary, as `Summarizer::provider` and `model`, since a file a
+    /// `[paths]` rule moves to another provider has to be summarized by that one instead
+    pub provider: String,
+    pub model: String,
+    /// What writing it took, estimated; none for a summary found elsewhere, like in the
+    /// shared cache, or made without the summarizer
+    pub tokens: u32,
+}
+
+impl CachedSummary {
+    /// Whether `summarizer` wrote this summary, so it can be shown for it.
+    pub fn written_by(&self, summarizer: &dyn Summarizer) -> bool {
+        self.provider == summarizer.provider() && self.model == summarizer.model()
+    }
+}
+
+/// Where summary caches are kept. Each cache is a set of entries stored under a name,
+/// read whole at the start of a run and written at the end: replaced, for a cache of
+/// one's own, or for a shared one, merged into what others wrote since.
+pub trait CacheBackend {
+    fn load(&self, name: &str) -> Result<HashMap<String, CachedSummary>>;
+    fn save(&self, name: &str, entries: &HashMap<String, CachedSummary>) -> Result<()>;
+}
+
+/// Per-repo summaries, keyed by display path, in the backend `cache.backend` picks.
+pub struct SummaryCache {
+    backend: Option<Box<dyn CacheBackend>>,
+    name: String,
+    entries: HashMap<String, CachedSummary>,
+    shared: Option<SharedCache>,
+}
+
+impl SummaryCache {
+    /// Reads the repo's cache. One that can't be read, like on a Redis server that's down,
+    /// starts out empty.
+    pub fn load(repo: &Repository, config: &CacheConfig) -> Result<Self> {
+        let backend = backend(config)?;
+        let name = name(repo, config);
+        let entries = match &backend {
+            Some(backend) => backend.load(&name).unwrap_or_else(|e| {
+                StatusFormatter::new()
+                    .display_warning(&format!("{:#}; summarizing without the cache", e));
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+        Ok(Self {
+            backend,
+            name,
+            entries,
+            shared: SharedCache::from_config(config),
+        })
+    }
+
+    pub fn get(&self, path: &str) -> Option<&CachedSummary> {
+        self.entries.get(path)
+    }
+
+    /// The team cache to read through before making a request, if one is configured.
+    pub fn shared(&self) -> Option<&SharedCache> {
+        self.shared.as_ref()
+    }
+
+    /// Saves `entries` as the cache, dropping files that no longer have changes from a
+    /// cache of one's own. A cache that can't be written is left as it was.
+    pub fn save(&mut self, entries: HashMap<String, CachedSummary>) {
+        self.entries = entries;
+        if let Some(dir) = snapshots_dir() {
+            prune_snapshots(&dir);
+        }
+        if let Some(Err(e)) = self
+            .backend
+            .as_ref()
+            .map(|backend| backend.save(&self.name, &self.entries))
+        {
+            StatusFormatter::new()
+                .display_warning(&format!("{:#}; this run's summaries weren't cached", e));
+        }
+    }
+}
+
+/// What a repo's cache is stored under. A Redis cache is shared, so it goes by the repo's
+/// first commit, which every clone has; file caches go by where the repo is, so two
+/// clones on one machine don't trade summaries of different changes.
+fn name(repo: &Repository, config: &CacheConfig) -> String {
+    let root_commit = match config.backend {
+        Backend::Redis => repo.root_commit(),
+        Backend::File | Backend::Sqlite => None,
+    };
+    let id = root_commit.unwrap_or_else(|| repo.root().to_string_lossy().into_owned());
+    hash(&id)[..16].to_string()
+}
+
+/// The configured backend, or none when there's nowhere to keep a file cache.
+pub fn backend(config: &CacheConfig) -> Result<Option<Box<dyn CacheBackend>>> {
+    Ok(match config.backend {
+        Backend::File => dir()
+            .map(|dir| Box::new(FileBackend::new(dir.join("summaries"))) as Box<dyn CacheBackend>),
+        Backend::Redis => {
+            let url = config.redis_url.as_deref().ok_or_else(|| {
+                anyhow::anyhow!("`cache.backend` is `redis`, but `cache.redis-url` isn't set")
+            })?;
+            Some(Box::new(RedisBackend {
+                url: redis::Url::parse(url).context("Invalid `cache.redis-url`")?,
+            }))
+        }
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => dir().map(|dir| {
+            Box::new(SqliteBackend::new(dir.join("summaries.db"))) as Box<dyn CacheBackend>
+        }),
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => {
+            return Err(anyhow::anyhow!(
+                "`cache.backend` is `sqlite`, but git-hud was built without the `sqlite` feature"
+            ))
+        }
+    })
+}
+
+/// One JSON file per cache in the cache dir.
+pub struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl CacheBackend for FileBackend {
+    fn load(&self, name: &str) -> Result<HashMap<String, CachedSummary>> {
+        read_entries(&self.dir.join(format!("{}.json", name)))
+    }
+
+    fn save(&self, name: &str, entries: &HashMap<String, CachedSummary>) -> Result<()> {
+        write_atomic(&self.dir.join(format!("{}.json", name)), &encode(entries)?)
+    }
+}
+
+/// One hash per cache on a Redis server, shared by everyone pointed at it, with a field
+/// per entry so a save only overwrites the entries it has. Nothing is dropped from it, so
+/// it goes once no one has saved to it for `REDIS_MAX_AGE`.
+pub struct RedisBackend {
+    url: redis::Url,
+}
+
+const REDIS_MAX_AGE: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+impl RedisBackend {
+    fn key(name: &str) -> String {
+        format!("git-hud:summaries:{}", name)
+    }
+}
+
+impl CacheBackend for RedisBackend {
+    fn load(&self, name: &str) -> Result<HashMap<String, CachedSummary>> {
+        let fields = redis::Connection::open(&self.url)?.hgetall(&Self::key(name))?;
+        Ok(fields
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, intact(serde_json::from_slice(&value).ok()?)?)))
+            .collect())
+    }
+
+    fn save(&self, name: &str, entries: &HashMap<String, CachedSummary>) -> Result<()> {
+        let records = entries
+            .iter()
+            .map(|(key, entry)| Ok((key.as_str(), serde_json::to_vec(&Record::new(entry))?)))
+            .collect::<Result<Vec<_>>>()?;
+        let fields: Vec<(&str, &[u8])> = records
+            .iter()
+            .map(|(key, record)| (*key, record.as_slice()))
+            .collect();
+        let mut connection = redis::Connection::open(&self.url)?;
+        connection.hset(&Self::key(name), &fields)?;
+        connection.expire(&Self::key(name), REDIS_MAX_AGE.as_secs())
+    }
+}
+
+/// One SQLite database in the cache dir, with a row per entry, so a run's save rewrites
+/// only its own cache's rows.
+#[cfg(feature = "sqlite")]
+pub struct SqliteBackend {
+    path: PathBuf,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteBackend {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    fn open(&self) -> Result<rusqlite::Connection> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let connection = rusqlite::Connection::open(&self.path)
+            .with_context(|| format!("Failed to open cache database {}", self.path.display()))?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS summaries (
+                cache TEXT NOT NULL,
+                key TEXT NOT NULL,
+                record TEXT NOT NULL,
+                PRIMARY KEY (cache, key)
+            )",
+        )?;
+        Ok(connection)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl CacheBackend for SqliteBackend {
+    fn load(&self, name: &str) -> Result<HashMap<String, CachedSummary>> {
+        let connection = self.open()?;
+        let mut select =
+            connection.prepare("SELECT key, record FROM summaries WHERE cache = ?1")?;
+        let rows = select.query_map([name], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut entries = HashMap::new();
+        for row in rows {
+            let (key, record) = row?;
+            if let Some(entry) = serde_json::from_str(&record).ok().and_then(intact) {
+                entries.insert(key, entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    fn save(&self, name: &str, entries: &HashMap<String, CachedSummary>) -> Result<()> {
+        let mut connection = self.open()?;
+        // Other runs see the old cache or the new one, never part of each
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM summaries WHERE cache = ?1", [name])?;
+        {
+            let mut insert = transaction
+                .prepare("INSERT INTO summaries (cache, key, record) VALUES (?1, ?2, ?3)")?;
+            for (key, entry) in entries {
+                insert.execute((name, key, serde_json::to_string(&Record::new(entry))?))?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// A cached summary as stored, with a checksum of its fields so an entry that was
+/// damaged on disk is noticed rather than shown.
+#[derive(Serialize, Deserialize)]
+struct Record<'a> {
+    #[serde(flatten)]
+    entry: std::borrow::Cow<'a, CachedSummary>,
+    checksum: String,
+}
+
+impl<'a> Record<'a> {
+    fn new(entry: &'a CachedSummary) -> Self {
+        Self {
+            entry: std::borrow::Cow::Borrowed(entry),
+            checksum: checksum(entry),
+        }
+    }
+}
+
+fn checksum(entry: &CachedSummary) -> String {
+    hash(&format!(
+        "{}\0{}\0{}\0{}\0{}\0{}",
+        entry.diff_hash,
+        entry.blob.as_deref().unwrap_or(""),
+        entry.summary,
+        entry.provider,
+        entry.model,
+        entry.tokens
+    ))
+}
+
+fn encode(entries: &HashMap<String, CachedSummary>) -> Result<String> {
+    let records: HashMap<&String, Record> = entries
+        .iter()
+        .map(|(key, entry)| (key, Record::new(entry)))
+        .collect();
+    Ok(serde_json::to_string(&records)?)
+}
+
+/// Every intact entry of a stored cache. Entries that don't parse or whose checksum
+/// doesn't match are dropped, and so re-summarized; `None` if it isn't a cache at all.
+fn decode(contents: &[u8]) -> Option<HashMap<String, CachedSummary>> {
+    let records: HashMap<String, serde_json::Value> = serde_json::from_slice(contents).ok()?;
+    Some(
+        records
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, intact(value)?)))
+            .collect(),
+    )
+}
+
+/// The entry a stored record holds, if it parses and its checksum matches.
+fn intact(value: serde_json::Value) -> Option<CachedSummary> {
+    let record: Record = serde_json::from_value(value).ok()?;
+    let entry = record.entry.into_owned();
+    (checksum(&entry) == record.checksum).then_some(entry)
+}
+
+/// Reads a summary cache file, keeping every entry that's intact. A file that isn't a
+/// cache at all is moved aside to `<name>.corrupt` so the next save starts fresh.
+fn read_entries(path: &Path) -> Result<HashMap<String, CachedSummary>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let contents = std::fs::read(path)
+        .with_context(|| format!("Failed to read cache file {}", path.display()))?;
+    Ok(decode(&contents).unwrap_or_else(|| {
+        let _ = std::fs::rename(path, path.with_extension("corrupt"));
+        HashMap::new()
+    }))
+}
+
+/// Keeps a working tree file's content under its blob id, for diffing its next version
+/// against.
+pub fn store_snapshot(id: &str, contents: &[u8]) -> Result<()> {
+    let dir = snapshots_dir().ok_or_else(|| anyhow::anyhow!("No cache dir to keep it in"))?;
+    store_snapshot_in(&dir, id, contents)
+}
+
+fn store_snapshot_in(dir: &Path, id: &str, contents: &[u8]) -> Result<()> {
+    let path = dir.join(id);
+    match path.exists() {
+        true => Ok(()),
+        false => write_atomic(&path, contents),
+    }
+}
+
+/// The content kept under a blob id, if it's still there.
+pub fn snapshot(id: &str) -> Option<Vec<u8>> {
+    std::fs::read(snapshots_dir()?.join(id)).ok()
+}
+
+fn snapshots_dir() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("snapshots"))
+}
+
+/// Removes the snapshots in `dir` older than `SNAPSHOT_MAX_AGE`.
+fn prune_snapshots(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let modified = entry.metadata().and_then(|metadata| metadata.modified());
+        let age = modified.map(|modified| SystemTime::now().duration_since(modified));
+        if let Ok(Ok(age)) = age {
+            if age > SNAPSHOT_MAX_AGE {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+/// Replaces `path` with `contents` all at once: they're written and flushed to a
+/// temporary file beside it, which is then renamed over it, so a process killed midway
+/// leaves either the old file or the new one, never half of one.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent)
+        .with_context(|| format!("Failed to create {}", parent.display()))?;
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    let written = std::fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(contents.as_ref())?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| std::fs::rename(&temp, path)) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("Failed to write {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Appends `record` as one line of a JSON Lines file, creating the file if needed.
+pub fn append_jsonl<T: Serialize>(path: &Path, record: &T) -> Result<()> {
+    let line = serde_json::to_string(record)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create cache dir {}", parent.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", line).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Reads every record of a JSON Lines file, or nothing if it doesn't exist. Lines that
+/// don't parse, like one cut short by a crash, are skipped.
+pub fn read_jsonl<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Where git-hud keeps local state; `GIT_HUD_CACHE_DIR` overrides the XDG location.
+pub fn dir() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(strings::GIT_HUD_CACHE_DIR) {
+        return Some(PathBuf::from(path));
+    }
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_dir.join("git-hud"))
+}
+
+/// Hex SHA-256 of `text`.
+pub fn hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(
+            hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_write_atomic() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("nested").join("a.json");
+        write_atomic(&path, "one")?;
+        write_atomic(&path, "two")?;
+        assert_eq!(std::fs::read_to_string(&path)?, "two");
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(path.parent().unwrap())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_names() -> Result<()> {
+        let (dir, repo) = crate::git::tests::setup_test_repo()?;
+        crate::git::tests::git(dir.path(), &["commit", "--allow-empty", "-m", "first"])?;
+        let clone = tempfile::tempdir()?;
+        crate::git::tests::git(
+            clone.path(),
+            &["clone", "--quiet", &dir.path().to_string_lossy(), "."],
+        )?;
+        let clone = Repository::open_current_directory(clone.path().to_str())?;
+        let redis = CacheConfig {
+            backend: Backend::Redis,
+            ..CacheConfig::default()
+        };
+
+        // Clones share a Redis cache, but not a local one
+        assert_eq!(name(&repo, &redis), name(&clone, &redis));
+        let local = CacheConfig::default();
+        assert_ne!(name(&repo, &local), name(&clone, &local));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unreachable_redis() -> Result<()> {
+        let (_dir, repo) = crate::git::tests::setup_test_repo()?;
+        // Nothing listens on a port that was just given back
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?
+            .local_addr()?
+            .port();
+        let config = CacheConfig {
+            backend: Backend::Redis,
+            redis_url: Some(format!("redis://127.0.0.1:{}", port)),
+            ..CacheConfig::default()
+        };
+        let mut cache = SummaryCache::load(&repo, &config)?;
+        assert!(cache.entries.is_empty());
+        cache.save(HashMap::new());
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_backend() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let backend = SqliteBackend::new(dir.path().join("nested").join("summaries.db"));
+        let entry = |summary: &str| CachedSummary {
+            diff_hash: "hash".to_string(),
+            blob: None,
+            summary: summary.to_string(),
+            provider: "command".to_string(),
+            model: "cat".to_string(),
+            tokens: 10,
+        };
+        assert!(backend.load("repo")?.is_empty());
+
+        backend.save(
+            "repo",
+            &HashMap::from([
+                ("staged:a.rs".to_string(), entry("one")),
+                ("staged:b.rs".to_string(), entry("two")),
+            ]),
+        )?;
+        backend.save(
+            "other",
+            &HashMap::from([("staged:a.rs".to_string(), entry("other"))]),
+        )?;
+        // A save replaces that cache's entries, and only that cache's
+        backend.save(
+            "repo",
+            &HashMap::from([("staged:a.rs".to_string(), entry("three"))]),
+        )?;
+        let entries = backend.load("repo")?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries["staged:a.rs"].summary, "three");
+        assert_eq!(backend.load("other")?["staged:a.rs"].summary, "other");
+
+        // A damaged entry is dropped
+        backend.open()?.execute(
+            "UPDATE summaries SET record = replace(record, 'three', 'tree')",
+            [],
+        )?;
+        assert!(backend.load("repo")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshots() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        store_snapshot_in(dir.path(), "new", b"one\n")?;
+        store_snapshot_in(dir.path(), "old", b"two\n")?;
+        // Content under an id never changes, so it's only written once
+        store_snapshot_in(dir.path(), "new", b"other\n")?;
+        assert_eq!(std::fs::read(dir.path().join("new"))?, b"one\n");
+
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("old"))?
+            .set_modified(SystemTime::now() - SNAPSHOT_MAX_AGE * 2)?;
+        prune_snapshots(dir.path());
+        assert!(dir.path().join("new").exists());
+        assert!(!dir.path().join("old").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_entries_recovers() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("cache.json");
+        let entry = |summary: &str| CachedSummary {
+            diff_hash: "hash".to_string(),
+            blob: None,
+            summary: summary.to_string(),
+            provider: "command".to_string(),
+            model: "cat".to_string(),
+            tokens: 10,
+        };
+        let (good, bad) = (entry("good"), entry("bad"));
+        let mut records = HashMap::new();
+        records.insert("good.rs", serde_json::to_value(Record::new(&good))?);
+        let mut tampered = serde_json::to_value(Record::new(&bad))?;
+        tampered["summary"] = "changed on disk".into();
+        records.insert("tampered.rs", tampered);
+        records.insert("garbage.rs", serde_json::json!(["not", "a", "record"]));
+        write_atomic(&path, &serde_json::to_string(&records)?)?;
+
+        let entries = read_entries(&path)?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries["good.rs"].summary, "good");
+
+        // A file cut short is moved aside instead of failing every later run
+        std::fs::write(&path, "{\"good.rs\": {\"diff_")?;
+        assert!(read_entries(&path)?.is_empty());
+        assert!(!path.exists());
+        assert!(path.with_extension("corrupt").exists());
+        Ok(())
+    }
+}
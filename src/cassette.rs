@@ -0,0 +1,369 @@
+//! Recorded HTTP exchanges for testing the summarizers that talk to an API, without
+//! making live calls. A cassette is a JSON file under `src/cassettes/` listing requests
+//! and the responses they got, in order. Playing one starts a local server that checks
+//! each request against the recording and answers with the recorded response.
+//!
+//! With `RECORD_CASSETTES=1` and `ANTHROPIC_API_KEY` set, the server forwards requests
+//! to the real API instead and saves what happened. Re-recording is how a change to the
+//! provider's request or response format shows up. API keys are never saved.
+//!
+//! Scripted cassettes under `src/cassettes/scripted/` have the same format but were
+//! written by hand, to drive error handling and retries that are hard to provoke. They
+//! only say what this crate does with a response, not how the API behaves.
+
+use crate::strings;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// Where recordings are made from.
+const UPSTREAM: &str = "https://api.anthropic.com";
+/// The key requests carry when played back.
+const REPLAY_API_KEY: &str = "test-key";
+/// Request headers that are saved and checked; anything else, like the API key, isn't.
+const RECORDED_HEADERS: &[&str] = &["content-type", "anthropic-version"];
+/// Response headers worth saving.
+const RESPONSE_HEADERS: &[&str] = &["content-type", "retry-after"];
+
+#[derive(Serialize, Deserialize, Default)]
+struct Recording {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct RecordedRequest {
+    method: String,
+    path: String,
+    headers: BTreeMap<String, String>,
+    body: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct RecordedResponse {
+    status: u16,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+#[derive(Default)]
+struct State {
+    interactions: Vec<Interaction>,
+    played: usize,
+    mismatches: Vec<String>,
+}
+
+pub struct Cassette {
+    path: PathBuf,
+    recording: bool,
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    server: JoinHandle<()>,
+}
+
+impl Cassette {
+    /// Starts serving `src/cassettes/<name>.json`, or recording it.
+    pub fn play(name: &str) -> Result<Self> {
+        let recording = std::env::var_os("RECORD_CASSETTES").is_some();
+        Self::start(cassette_path("", name), recording)
+    }
+
+    /// Starts serving the hand-written `src/cassettes/scripted/<name>.json`, which is never
+    /// re-recorded.
+    pub fn script(name: &str) -> Result<Self> {
+        Self::start(cassette_path("scripted", name), false)
+    }
+
+    fn start(path: PathBuf, recording: bool) -> Result<Self> {
+        let interactions = match recording {
+            true => Vec::new(),
+            false => {
+                let contents = std::fs::read_to_string(&path)
+                    .with_context(|| format!("No cassette at {}", path.display()))?;
+                serde_json::from_str::<Recording>(&contents)?.interactions
+            }
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State {
+            interactions,
+            ..State::default()
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let server = {
+            let state = state.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || serve(listener, recording, &state, &stop))
+        };
+        Ok(Self {
+            path,
+            recording,
+            address,
+            state,
+            stop,
+            server,
+        })
+    }
+
+    /// What to use as the API's base URL.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// The API key to send: the real one when recording.
+    pub fn api_key(&self) -> String {
+        match self.recording {
+            true => std::env::var(strings::ANTHROPIC_API_KEY).unwrap_or_default(),
+            false => REPLAY_API_KEY.to_string(),
+        }
+    }
+
+    /// Stops the server, then saves the recording, or checks every recorded request
+    /// was made as recorded.
+    pub fn finish(self) -> Result<()> {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the server from waiting for a connection so it sees it should stop
+        let _ = TcpStream::connect(self.address);
+        self.server
+            .join()
+            .map_err(|_| anyhow::anyhow!("The cassette server panicked"))?;
+
+        let state = self.state.lock().unwrap();
+        if self.recording {
+            let recording = Recording {
+                interactions: state.interactions.clone(),
+            };
+            std::fs::create_dir_all(self.path.parent().unwrap())?;
+            std::fs::write(&self.path, serde_json::to_string_pretty(&recording)? + "\n")?;
+            return Ok(());
+        }
+        if !state.mismatches.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Requests didn't match {}:\n{}",
+                self.path.display(),
+                state.mismatches.join("\n")
+            ));
+        }
+        if state.played < state.interactions.len() {
+            return Err(anyhow::anyhow!(
+                "Only {} of the {} requests in {} were made",
+                state.played,
+                state.interactions.len(),
+                self.path.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn cassette_path(dir: &str, name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/cassettes")
+        .join(dir)
+        .join(format!("{}.json", name))
+}
+
+fn serve(listener: TcpListener, recording: bool, state: &Mutex<State>, stop: &AtomicBool) {
+    // Recording forwards requests from this thread, outside the test's runtime
+    let runtime = recording.then(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to start a runtime for recording")
+    });
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if let Err(e) = exchange(stream, runtime.as_ref(), state) {
+            state.lock().unwrap().mismatches.push(e.to_string());
+        }
+    }
+}
+
+/// Reads one request and answers it. Responses close the connection, so each request
+/// comes on its own.
+fn exchange(
+    stream: TcpStream,
+    runtime: Option<&tokio::runtime::Runtime>,
+    state: &Mutex<State>,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (request, api_key) = read_request(&mut reader)?;
+    let response = match runtime {
+        Some(runtime) => {
+            let response = runtime.block_on(forward(&request, api_key.as_deref()))?;
+            state.lock().unwrap().interactions.push(Interaction {
+                request,
+                response: response.clone(),
+            });
+            response
+        }
+        None => replay(request, api_key.as_deref(), &mut state.lock().unwrap()),
+    };
+    write_response(stream, &response)
+}
+
+/// The next recorded response, or a 418 explaining why the request doesn't match. 418
+/// isn't retried, so a mismatch fails at once.
+fn replay(request: RecordedRequest, api_key: Option<&str>, state: &mut State) -> RecordedResponse {
+    let mismatch = match state.interactions.get(state.played) {
+        None => Some(format!(
+            "Unexpected request {} {}: the cassette has {} requests",
+            request.method,
+            request.path,
+            state.interactions.len()
+        )),
+        Some(_) if api_key != Some(REPLAY_API_KEY) => Some(format!(
+            "Request {} didn't send the API key",
+            state.played + 1
+        )),
+        Some(interaction) if interaction.request != request => Some(format!(
+            "Request {} was\n{:#?}\nbut the cassette has\n{:#?}",
+            state.played + 1,
+            request,
+            interaction.request
+        )),
+        Some(_) => None,
+    };
+    if let Some(mismatch) = mismatch {
+        state.mismatches.push(mismatch.clone());
+        return RecordedResponse {
+            status: 418,
+            headers: BTreeMap::new(),
+            body: mismatch,
+        };
+    }
+    state.played += 1;
+    state.interactions[state.played - 1].response.clone()
+}
+
+async fn forward(request: &RecordedRequest, api_key: Option<&str>) -> Result<RecordedResponse> {
+    let mut upstream = reqwest::Client::new()
+        .request(
+            request.method.parse()?,
+            format!("{}{}", UPSTREAM, request.path),
+        )
+        .json(&request.body);
+    for (name, value) in &request.headers {
+        upstream = upstream.header(name, value);
+    }
+    if let Some(api_key) = api_key {
+        upstream = upstream.header("x-api-key", api_key);
+    }
+    let response = upstream.send().await?;
+    let headers = RESPONSE_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let value = response.headers().get(*name)?.to_str().ok()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+    Ok(RecordedResponse {
+        status: response.status().as_u16(),
+        headers,
+        body: response.text().await?,
+    })
+}
+
+/// The request, with the API key it carried kept apart so it's never recorded.
+fn read_request(reader: &mut impl BufRead) -> Result<(RecordedRequest, Option<String>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(anyhow::anyhow!(
+            "Malformed request line `{}`",
+            line.trim_end()
+        ));
+    };
+
+    let mut headers = BTreeMap::new();
+    let mut api_key = None;
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let Some((name, value)) = line.trim_end().split_once(':') else {
+            break;
+        };
+        let (name, value) = (name.to_ascii_lowercase(), value.trim().to_string());
+        match name.as_str() {
+            "content-length" => length = value.parse()?,
+            "x-api-key" => api_key = Some(value),
+            name if RECORDED_HEADERS.contains(&name) => {
+                headers.insert(name.to_string(), value);
+            }
+            _ => {}
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = match body.is_empty() {
+        true => serde_json::Value::Null,
+        false => serde_json::from_slice(&body)?,
+    };
+    Ok((
+        RecordedRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers,
+            body,
+        },
+        api_key,
+    ))
+}
+
+fn write_response(mut stream: TcpStream, response: &RecordedResponse) -> Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} Recorded\r\ncontent-length: {}\r\nconnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::{ClaudeSummarizer, Reply, Summarizer};
+
+    #[tokio::test]
+    async fn test_mismatched_request() -> Result<()> {
+        let cassette = Cassette::script("claude_complete")?;
+        let summarizer =
+            ClaudeSummarizer::new(None).with_base_url(&cassette.url(), &cassette.api_key());
+        let error = summarizer
+            .complete("Something else".to_string(), Reply::Line(50))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("418"));
+        let error = cassette.finish().unwrap_err().to_string();
+        assert!(error.contains("Something else"));
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+use crate::display::ColorChoice;
+use crate::git::UntrackedFiles;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(name = "git-hud", version, about = "git status++")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Show what would be sent for each file, and whether it's cached, without sending it
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Override a config setting for this run, like `-c status.show-hidden=true`
+    #[arg(short = 'c', long = "config", value_name = "KEY=VALUE", global = true)]
+    pub config_overrides: Vec<String>,
+
+    /// Print how long each step took, per pipeline phase and per file
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Print each file's diff under its summary, like `git status -v`
+    #[arg(short, long)]
+    pub patch: bool,
+
+    /// Print a `path<TAB>summary` line per changed file instead of the status view, for a
+    /// fuzzy finder like fzf. `git hud show` and `git hud open` take a picked line
+    #[arg(long, conflicts_with = "patch")]
+    pub pick: bool,
+
+    /// Also show and summarize the changes inside each changed submodule, under its entry
+    #[arg(long, conflicts_with = "pick")]
+    pub recurse_submodules: bool,
+
+    /// Summarize even when more files changed than `status.max-files`
+    #[arg(long)]
+    pub ai: bool,
+
+    /// Send summaries without asking, however much they're estimated to cost
+    #[arg(long)]
+    pub yes: bool,
+
+    /// Only summarize files matching this gitignore-style pattern, like `src/api/**`, and
+    /// list the rest without a summary. Can be given more than once
+    #[arg(long, value_name = "GLOB")]
+    pub focus: Vec<String>,
+
+    /// Summarize every changed file, not just the `status.max-summaries` most recently
+    /// modified, even in the middle of a rebase or merge
+    #[arg(long)]
+    pub all: bool,
+
+    /// Fetch from the branch's remote first, so how it stands against its upstream is up
+    /// to date. Gives up after 10 seconds
+    #[arg(long)]
+    pub fetch: bool,
+
+    /// Exit non-zero if any change looks suspicious: a secret, a conflict marker, a debug
+    /// statement, or an oversized file. For pre-commit hooks and CI
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Show untracked files like `git status -u`: `no`, `normal`, or `all` (the default
+    /// when no mode is given)
+    #[arg(
+        short = 'u',
+        long,
+        value_name = "MODE",
+        num_args = 0..=1,
+        default_missing_value = "all"
+    )]
+    pub untracked_files: Option<UntrackedFiles>,
+
+    /// When to color output: `always`, `auto` (when writing to a terminal), or `never`
+    #[arg(long, value_name = "WHEN", default_value = "auto", global = true)]
+    pub color: ColorChoice,
+
+    /// Lay output out for screen readers: one line per record, with words like `STAGED:`
+    /// and `CONFLICT:` instead of color, headings, or symbols, and no color at all
+    #[arg(long, global = true)]
+    pub accessible: bool,
+
+    /// Print the help for every command, config key, and environment variable
+    #[arg(long)]
+    pub help_long: bool,
+
+    /// Print a git-hud(1) man page, which `git hud --help` opens once it's installed
+    #[arg(long)]
+    pub man: bool,
+
+    /// Only show and summarize changes under these paths, like `git status -- <pathspec>`
+    #[arg(last = true, value_name = "PATHSPEC")]
+    pub pathspecs: Vec<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Explain one file's pending changes in depth
+    Explain {
+        /// The changed file to explain
+        path: PathBuf,
+
+        /// Also describe how behavior differs before and after the change
+        #[arg(long)]
+        compare: bool,
+    },
+
+    /// Show one file's summary above its full diff
+    Show {
+        /// The changed file to show: its path or part of it, its number in the status
+        /// view's list, or a line from `--pick`
+        path: PathBuf,
+
+        /// Mark the words that changed within lines instead of showing whole lines
+        #[arg(long)]
+        word_diff: bool,
+    },
+
+    /// Open a changed file in your editor, at its first change
+    Open {
+        /// The file's number in the status view's list, counting from 1, or its path or
+        /// part of it
+        #[arg(default_value = "1")]
+        target: String,
+    },
+
+    /// Throw away unstaged changes to files, keeping a copy that `git hud undo` restores
+    Restore {
+        /// Each file's number in the status view's list, its path, or part of it
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+
+    /// Bring back the changes the last `git hud restore` threw away
+    Undo {
+        /// Undo even over files changed since the discard, losing those changes
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Explain how a file's pending changes relate to its recent commits
+    Why {
+        /// The changed file to look into
+        path: PathBuf,
+
+        /// How many recent commits to consider
+        #[arg(short = 'n', long, default_value_t = 3)]
+        commits: usize,
+    },
+
+    /// Check whether the staged changes belong in HEAD or in a new commit
+    AmendCheck,
+
+    /// Summarize the commits that `git push` would send
+    Prepush {
+        /// Compare against this ref instead of the branch's upstream
+        #[arg(long)]
+        upstream: Option<String>,
+
+        /// Also send the summary to the `notify.webhook` channel
+        #[arg(long)]
+        post: bool,
+    },
+
+    /// Post a summary of each file the branch changes to its GitLab merge request or
+    /// Gerrit change
+    Review {
+        /// Compare against this ref instead of the branch's upstream
+        #[arg(long)]
+        base: Option<String>,
+
+        /// The merge request's number or the change's number or ID, when it can't be told
+        /// from the CI job or HEAD's Change-Id
+        #[arg(long)]
+        change: Option<String>,
+    },
+
+    /// Summarize how two branches or commits differ
+    Compare {
+        /// The ref to compare from
+        from: String,
+
+        /// The ref to compare to
+        to: String,
+    },
+
+    /// Summarize a commit and find which local branches already have it
+    Where {
+        /// The commit to look for
+        commit: String,
+    },
+
+    /// List recent reflog entries and summarize commits no branch points to anymore
+    Rescue {
+        /// How many reflog entries to look through
+        #[arg(short = 'n', long, default_value_t = 20)]
+        count: usize,
+    },
+
+    /// During a bisect, summarize what changed since the last good commit
+    Bisect {
+        /// Describe the bug so the changes can be judged against it
+        #[arg(long)]
+        symptom: Option<String>,
+    },
+
+    /// Write release notes covering everything since the previous tag
+    TagNotes {
+        /// The tag to write notes for; it doesn't need to exist yet
+        tag: String,
+
+        /// Where a new tag would point
+        #[arg(long, default_value = "HEAD")]
+        target: String,
+
+        /// Create the annotated tag with the generated notes after confirming
+        #[arg(long)]
+        create: bool,
+    },
+    /// Write a standup update from your recent commits and work in progress
+    Standup {
+        /// How far back to look for commits, as git understands dates, like `monday` or
+        /// `2 days ago`
+        #[arg(long, default_value = "yesterday")]
+        since: String,
+
+        /// Also send the update to the `notify.webhook` channel
+        #[arg(long)]
+        post: bool,
+    },
+
+    /// Save the annotated status under a name, or see what changed since one was saved
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+
+    /// Check or inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Check git, the repo, the config, the cache, and the summarizer, and say how to fix
+    /// what doesn't work
+    Doctor {
+        /// Skip the checks that need the network
+        #[arg(long)]
+        offline: bool,
+    },
+
+    /// Print the diagnostics saved when git hud last crashed, formatted for an issue
+    BugReport {
+        /// A saved report to print instead of the latest
+        path: Option<PathBuf>,
+    },
+
+    /// Inspect the log of prompts sent to the summarizer, if auditing is enabled
+    Audit {
+        #[command(subcommand)]
+        action: AuditAction,
+    },
+
+    /// Report runs, cache hit rate, and token usage from local data only
+    Stats {
+        /// How many days back to report
+        #[arg(long, default_value_t = 14)]
+        days: u32,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotAction {
+    /// Record the changed files and their summaries, replacing any snapshot by that name
+    Save {
+        /// What to call the snapshot, like `morning`
+        name: String,
+    },
+
+    /// Show which files were changed, changed further, or stopped changing since a
+    /// snapshot
+    Diff {
+        /// The snapshot to compare against
+        name: String,
+    },
+
+    /// List the snapshots saved for this repo
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Validate the configuration, print each setting and where it came from, and check
+    /// that the summarizer answers
+    Check {
+        /// Skip the request that checks the summarizer's credentials
+        #[arg(long)]
+        offline: bool,
+    },
+
+    /// Print a setting's effective value, like `config get status.show-hidden`
+    Get {
+        /// A dotted key
+        key: String,
+    },
+
+    /// Set a value in the user config file, keeping its comments and layout
+    Set {
+        /// A dotted key
+        key: String,
+
+        /// Read as TOML if it parses, so `true` and `3` aren't strings, and as a string
+        /// otherwise
+        value: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuditAction {
+    /// Print logged prompts and responses, oldest first
+    Show {
+        /// How many of the most recent records to print
+        #[arg(short = 'n', long, default_value_t = 10)]
+        last: usize,
+    },
+
+    /// Delete the audit log
+    Purge {
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
@@ -0,0 +1,64 @@
+use crate::config::TemplatesConfig;
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::scopes;
+use crate::summary::{self, Summarizer};
+use crate::template::{self, Vars};
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// How many recent commit messages a suggested message is styled after.
+const STYLE_EXAMPLES: usize = 10;
+
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    templates: &TemplatesConfig,
+    scope_patterns: &HashMap<String, String>,
+) -> Result<()> {
+    let staged_diff = repo.get_staged_diff()?;
+    if staged_diff.trim().is_empty() {
+        return Err(anyhow::anyhow!("Nothing is staged"));
+    }
+    if repo.head_commit()?.is_none() {
+        return Err(anyhow::anyhow!(
+            "There's no commit to amend yet; the staged changes will make the first one"
+        ));
+    }
+    let head = repo.commit("HEAD")?;
+
+    let style_examples = repo.recent_messages(STYLE_EXAMPLES)?;
+    let (files, added, removed) = template::diff_stats(&staged_diff);
+    let scope = scopes::infer(scope_patterns, &files);
+    let mut advice = summarizer
+        .assess_amend(
+            &summary::condense(summarizer, staged_diff.clone()).await?,
+            &head,
+            &style_examples,
+            scope.as_deref(),
+        )
+        .await?;
+    if let (Some(template), Some(message)) = (&templates.commit_message, &advice.message) {
+        let branch = repo.get_status()?.branch.head.unwrap_or_default();
+        let (subject, body) = message.split_once('\n').unwrap_or((message, ""));
+        let vars = Vars::new()
+            .set("message", message.trim())
+            .set("subject", subject.trim())
+            .set("body", body.trim())
+            .set("branch", branch.as_str())
+            .set("scope", scope.unwrap_or_default())
+            .set(
+                "tickets",
+                template::tickets([branch.as_str(), message]).join(", "),
+            )
+            .list("files", &files)
+            .set(
+                "stats",
+                format!("{} file(s) changed, +{} -{}", files.len(), added, removed),
+            );
+        advice.message = Some(template::render(template, &vars)?);
+    }
+
+    let formatter = StatusFormatter::new();
+    formatter.display_amend_advice(&head, &advice)
+}
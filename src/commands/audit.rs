@@ -0,0 +1,39 @@
+use crate::audit;
+use crate::cli::AuditAction;
+use crate::display::StatusFormatter;
+use crate::prompt;
+use anyhow::Result;
+
+pub fn run(action: AuditAction) -> Result<()> {
+    match action {
+        AuditAction::Show { last } => show(last),
+        AuditAction::Purge { yes } => purge(yes),
+    }
+}
+
+fn show(last: usize) -> Result<()> {
+    let records = audit::read()?;
+    if records.is_empty() {
+        println!(
+            "The audit log is empty (enable it with `enabled = true` under [audit] in the config)"
+        );
+        return Ok(());
+    }
+
+    let formatter = StatusFormatter::new();
+    formatter.display_audit_records(&records[records.len().saturating_sub(last)..])
+}
+
+fn purge(yes: bool) -> Result<()> {
+    if !audit::path().is_some_and(|path| path.exists()) {
+        println!("There is no audit log to delete");
+        return Ok(());
+    }
+    if !yes && !prompt::confirm("Delete the audit log?")? {
+        return Ok(());
+    }
+    if audit::purge()? {
+        println!("Deleted the audit log");
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use crate::budget::Gate;
+use crate::commands::compare::{summarize_tree_diff, TreeDiffWithSummary};
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::summary::Summarizer;
+use anyhow::Result;
+
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    symptom: Option<&str>,
+) -> Result<()> {
+    let state = repo
+        .bisect_state()?
+        .ok_or_else(|| anyhow::anyhow!("No bisect is in progress"))?;
+    let good = state
+        .last_good()
+        .ok_or_else(|| anyhow::anyhow!("No commit has been marked good yet"))?;
+    let good = repo.commit(good)?;
+    let current = repo.commit("HEAD")?;
+
+    let entries = repo.diff_trees(&good.short_id, &current.short_id)?;
+    let files = summarize_tree_diff(summarizer, gate, entries).await?;
+    let notes: Vec<String> = files.iter().filter_map(TreeDiffWithSummary::note).collect();
+    let assessment = match notes.is_empty() {
+        true => String::new(),
+        false => summarizer.assess_bisect_suspect(&notes, symptom).await?,
+    };
+
+    let formatter = StatusFormatter::new();
+    println!(
+        "Bisecting: testing {} {}, last good is {} {}",
+        current.short_id, current.subject, good.short_id, good.subject
+    );
+    println!();
+    formatter.display_tree_diff(&good.short_id, &current.short_id, &files, &assessment)
+}
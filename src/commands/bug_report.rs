@@ -0,0 +1,16 @@
+use crate::crash::Report;
+use crate::display::StatusFormatter;
+use anyhow::Result;
+use std::path::Path;
+
+/// Prints a crash report as Markdown for an issue: the latest one, or the one at `path`.
+pub fn run(path: Option<&Path>) -> Result<()> {
+    let (report, path) = match path {
+        Some(path) => (Report::load(path)?, path.to_path_buf()),
+        None => Report::latest()?.ok_or_else(|| {
+            anyhow::anyhow!("No diagnostics have been saved; they're written when git hud crashes")
+        })?,
+    };
+    let formatter = StatusFormatter::new();
+    formatter.display_bug_report(&report, &path)
+}
@@ -0,0 +1,22 @@
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::summary::{Summarizer, SummaryRequest};
+use anyhow::Result;
+
+pub async fn run(repo: &Repository, summarizer: &dyn Summarizer, rev: &str) -> Result<()> {
+    let commit = repo.commit(rev)?;
+    let patch_id = repo.patch_id(rev)?;
+
+    let mut branches = Vec::new();
+    for branch in repo.local_branches()? {
+        let containment = repo.branch_containment(&branch, rev, patch_id.as_deref())?;
+        branches.push((branch, containment));
+    }
+
+    let summary = summarizer
+        .summarize(&SummaryRequest::new(&commit.diff))
+        .await?;
+
+    let formatter = StatusFormatter::new();
+    formatter.display_branch_containment(&commit, &summary.text, &branches)
+}
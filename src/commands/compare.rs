@@ -0,0 +1,91 @@
+use crate::budget::Gate;
+use crate::display::StatusFormatter;
+use crate::git::{Repository, TreeDiffEntry};
+use crate::language;
+use crate::summary::{self, Summarizer, SummaryRequest};
+use anyhow::Result;
+use futures::future::try_join_all;
+use std::path::Path;
+
+pub struct TreeDiffWithSummary {
+    pub entry: TreeDiffEntry,
+    pub summary: Option<String>,
+}
+
+impl TreeDiffWithSummary {
+    /// A "path: summary" line for feeding into an aggregate prompt.
+    pub fn note(&self) -> Option<String> {
+        self.summary
+            .as_ref()
+            .map(|summary| format!("{}: {}", self.entry.path, summary))
+    }
+}
+
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    from: &str,
+    to: &str,
+) -> Result<()> {
+    let entries = repo.diff_trees(from, to)?;
+    if entries.is_empty() {
+        println!("{} and {} have identical trees", from, to);
+        return Ok(());
+    }
+
+    let files = summarize_tree_diff(summarizer, gate, entries).await?;
+    let notes: Vec<String> = files.iter().filter_map(TreeDiffWithSummary::note).collect();
+    let overview = match notes.is_empty() {
+        true => String::new(),
+        false => {
+            summarizer
+                .summarize_changeset(
+                    &format!("file differences between {} and {}", from, to),
+                    &notes,
+                )
+                .await?
+        }
+    };
+
+    let formatter = StatusFormatter::new();
+    formatter.display_tree_diff(from, to, &files, &overview)
+}
+
+/// Summarizes every text entry of a tree-to-tree diff concurrently, once `gate` allows
+/// what that costs.
+pub async fn summarize_tree_diff(
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    entries: Vec<TreeDiffEntry>,
+) -> Result<Vec<TreeDiffWithSummary>> {
+    let requests: Vec<Option<SummaryRequest>> = entries
+        .iter()
+        .map(|entry| {
+            (!entry.is_binary && !entry.diff.is_empty()).then(|| SummaryRequest {
+                path: Some(entry.path.clone()),
+                language: language::detect(Path::new(&entry.path), ""),
+                status: Some(entry.status.clone()),
+                ..SummaryRequest::new(&entry.diff)
+            })
+        })
+        .collect();
+    if let Some(gate) = gate {
+        gate.require(&summary::prompts(summarizer, requests.iter().flatten()))?;
+    }
+    let summaries = try_join_all(requests.iter().map(|request| async {
+        match request {
+            Some(request) => {
+                Ok::<_, anyhow::Error>(Some(summarizer.summarize(request).await?.text))
+            }
+            None => Ok(None),
+        }
+    }))
+    .await?;
+
+    Ok(entries
+        .into_iter()
+        .zip(summaries)
+        .map(|(entry, summary)| TreeDiffWithSummary { entry, summary })
+        .collect())
+}
@@ -0,0 +1,54 @@
+use crate::cli::ConfigAction;
+use crate::config::{self, Config};
+use crate::display::StatusFormatter;
+use crate::summary::{self, Reply};
+use anyhow::Result;
+
+pub async fn run(action: ConfigAction, overrides: &[String]) -> Result<()> {
+    match action {
+        ConfigAction::Check { offline } => check(overrides, offline).await,
+        ConfigAction::Get { key } => get(overrides, &key),
+        ConfigAction::Set { key, value } => set(&key, &value),
+    }
+}
+
+fn get(overrides: &[String], key: &str) -> Result<()> {
+    let merged = config::merged(&Config::layers(overrides)?);
+    match config::lookup(&merged, key) {
+        Some(toml::Value::String(value)) => println!("{}", value),
+        Some(toml::Value::Table(table)) => print!("{}", toml::to_string(table)?),
+        Some(value) => println!("{}", value),
+        None => return Err(anyhow::anyhow!("`{}` isn't set", key)),
+    }
+    Ok(())
+}
+
+fn set(key: &str, value: &str) -> Result<()> {
+    let path = config::set_user_value(key, value)?;
+    println!("Set {} in {}", key, path.display());
+    Ok(())
+}
+
+async fn check(overrides: &[String], offline: bool) -> Result<()> {
+    let layers = Config::layers(overrides)?;
+    let config = Config::from_layers(&layers)?;
+    let formatter = StatusFormatter::new();
+    formatter.display_config(&layers, &config::merged(&layers), &config.unknown_keys())?;
+    if offline {
+        return Ok(());
+    }
+
+    // The cheapest request there is: one token back
+    let summarizer = summary::from_config(&config)?;
+    summarizer
+        .complete("Reply with OK.".to_string(), Reply::Text(1))
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "The {} summarizer didn't answer: {}",
+                summarizer.provider(),
+                e
+            )
+        })?;
+    formatter.display_provider_ok(summarizer.provider(), summarizer.model())
+}
@@ -0,0 +1,258 @@
+use crate::cache;
+use crate::config::{Backend, CacheConfig, Config};
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::strings;
+use crate::summary::{self, Reply};
+use anyhow::Result;
+use std::time::Duration;
+
+/// How long the network check waits for the API to answer at all.
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(5);
+const ANTHROPIC_API: &str = "https://api.anthropic.com";
+
+/// One thing `doctor` checked.
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+pub enum Outcome {
+    Ok(String),
+    /// Works, but probably not as intended; with what to do about it
+    Warning(String, String),
+    /// Won't work; with what to do about it
+    Failed(String, String),
+    /// Not checked, and why
+    Skipped(String),
+}
+
+impl Check {
+    fn new(name: &'static str, outcome: Outcome) -> Self {
+        Self { name, outcome }
+    }
+}
+
+/// Checks everything `git hud` needs, in the order it needs them, and fails if anything
+/// won't work. Unlike the other commands, it runs when the config doesn't load or
+/// there's no repo, since those are what it looks for.
+pub async fn run(overrides: &[String], offline: bool) -> Result<()> {
+    let mut checks = vec![check_git(), check_repo()];
+
+    let config = Config::layers(overrides).and_then(|layers| Config::from_layers(&layers));
+    checks.push(Check::new(
+        "config",
+        match &config {
+            Ok(config) => match config.unknown_keys() {
+                unknown if unknown.is_empty() => Outcome::Ok("loaded".to_string()),
+                unknown => Outcome::Warning(
+                    format!("unknown keys: {}", unknown.join(", ")),
+                    "Check them for typos with `git hud config check`".to_string(),
+                ),
+            },
+            Err(e) => Outcome::Failed(
+                one_line(e),
+                "Fix the file named above; `git hud config check` shows every layer".to_string(),
+            ),
+        },
+    ));
+    checks.push(check_cache());
+    if let Ok(config) = &config {
+        if config.cache.backend == Backend::Redis {
+            checks.push(check_shared_cache(&config.cache));
+        }
+    }
+
+    match &config {
+        Ok(config) => checks.extend(check_summarizer(config, offline).await),
+        Err(_) => checks.push(Check::new(
+            "summarizer",
+            Outcome::Skipped("the config didn't load".to_string()),
+        )),
+    }
+
+    let formatter = StatusFormatter::new();
+    formatter.display_doctor(&checks)?;
+    let failed = checks
+        .iter()
+        .filter(|check| matches!(check.outcome, Outcome::Failed(..)))
+        .count();
+    match failed {
+        0 => Ok(()),
+        failed => Err(anyhow::anyhow!("{} check(s) failed", failed)),
+    }
+}
+
+/// Errors like TOML parse errors span lines, which would break up the list.
+fn one_line(error: &anyhow::Error) -> String {
+    format!("{:#}", error)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn check_git() -> Check {
+    let output = std::process::Command::new("git").arg("--version").output();
+    Check::new(
+        "git",
+        match output {
+            Ok(output) if output.status.success() => {
+                Outcome::Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+            }
+            _ => Outcome::Failed(
+                "`git --version` didn't run".to_string(),
+                "Install git and make sure it's on your PATH".to_string(),
+            ),
+        },
+    )
+}
+
+fn check_repo() -> Check {
+    Check::new(
+        "repository",
+        match Repository::open_from_env() {
+            Ok(repo) => Outcome::Ok(repo.root().display().to_string()),
+            Err(e) => Outcome::Warning(
+                one_line(&e),
+                "Run `git hud` from inside a git repository".to_string(),
+            ),
+        },
+    )
+}
+
+/// Writes and removes a file where the cache goes.
+fn check_cache() -> Check {
+    let fix = format!(
+        "Set {} to a directory you can write to",
+        strings::GIT_HUD_CACHE_DIR
+    );
+    let Some(dir) = cache::dir() else {
+        return Check::new(
+            "cache",
+            Outcome::Failed("no cache directory (HOME isn't set)".to_string(), fix),
+        );
+    };
+    let probe = dir.join(".doctor");
+    let written = std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(&probe, b"ok"))
+        .and_then(|_| std::fs::remove_file(&probe));
+    Check::new(
+        "cache",
+        match written {
+            Ok(()) => Outcome::Ok(dir.display().to_string()),
+            Err(e) => Outcome::Failed(format!("can't write to {}: {}", dir.display(), e), fix),
+        },
+    )
+}
+
+/// Reads from the Redis server `cache.backend = "redis"` points at.
+fn check_shared_cache(config: &CacheConfig) -> Check {
+    let read = cache::backend(config).and_then(|backend| match backend {
+        Some(backend) => backend.load("doctor").map(|_| ()),
+        None => Ok(()),
+    });
+    Check::new(
+        "shared cache",
+        match read {
+            Ok(()) => Outcome::Ok("Redis answered".to_string()),
+            Err(e) => Outcome::Failed(
+                one_line(&e),
+                "Check `cache.redis-url`, or set `cache.backend` back to `file`".to_string(),
+            ),
+        },
+    )
+}
+
+/// Checks the configured summarizer: whether the API is reachable and has credentials,
+/// for the Anthropic API, and then whether it answers a one-token request.
+async fn check_summarizer(config: &Config, offline: bool) -> Vec<Check> {
+    let summarizer = match summary::from_config(config) {
+        Ok(summarizer) => summarizer,
+        Err(e) => {
+            return vec![Check::new(
+                "summarizer",
+                Outcome::Failed(
+                    one_line(&e),
+                    "Check the `summarizer` and `plugins` settings".to_string(),
+                ),
+            )]
+        }
+    };
+    let mut checks = Vec::new();
+    let remote = summarizer.provider() == "anthropic";
+    if remote {
+        checks.push(Check::new(
+            "credentials",
+            match std::env::var_os(strings::ANTHROPIC_API_KEY) {
+                Some(key) if !key.is_empty() => {
+                    Outcome::Ok(format!("{} is set", strings::ANTHROPIC_API_KEY))
+                }
+                _ => Outcome::Failed(
+                    format!("{} isn't set", strings::ANTHROPIC_API_KEY),
+                    format!(
+                        "Export {} with a key from console.anthropic.com, or set `summarizer` to a local command",
+                        strings::ANTHROPIC_API_KEY
+                    ),
+                ),
+            },
+        ));
+    }
+    if offline {
+        checks.push(Check::new(
+            "summarizer",
+            Outcome::Skipped("--offline".to_string()),
+        ));
+        return checks;
+    }
+
+    if remote {
+        let reachable = reqwest::Client::new()
+            .get(ANTHROPIC_API)
+            .timeout(REACHABILITY_TIMEOUT)
+            .send()
+            .await;
+        checks.push(Check::new(
+            "network",
+            match reachable {
+                // Any answer at all means the network path works
+                Ok(_) => Outcome::Ok(format!("{} answered", ANTHROPIC_API)),
+                Err(e) => Outcome::Failed(
+                    format!("{} didn't answer: {}", ANTHROPIC_API, e),
+                    "Check your connection, and HTTPS_PROXY if you're behind a proxy".to_string(),
+                ),
+            },
+        ));
+    }
+    // A request would only fail the same way
+    if checks
+        .iter()
+        .any(|check| matches!(check.outcome, Outcome::Failed(..)))
+    {
+        return checks;
+    }
+
+    // The same one-token request as `config check`
+    let answered = summarizer
+        .complete("Reply with OK.".to_string(), Reply::Text(1))
+        .await;
+    checks.push(Check::new(
+        "summarizer",
+        match answered {
+            Ok(_) => Outcome::Ok(format!(
+                "{} ({}) answered",
+                summarizer.provider(),
+                summarizer.model()
+            )),
+            Err(e) => Outcome::Failed(
+                format!("{} didn't answer: {}", summarizer.provider(), one_line(&e)),
+                match remote {
+                    true => "Check that the API key is valid and has credit".to_string(),
+                    false => "Run the command by hand to see what goes wrong".to_string(),
+                },
+            ),
+        },
+    ));
+    checks
+}
@@ -0,0 +1,47 @@
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::summary::Summarizer;
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    path: &Path,
+    compare_behavior: bool,
+) -> Result<()> {
+    let status = repo.get_status()?;
+    let entry = status
+        .entry_for_path(path)?
+        .ok_or_else(|| anyhow::anyhow!("No pending changes for {}", path.display()))?;
+
+    if entry.is_binary {
+        return Err(anyhow::anyhow!(
+            "{} is a binary file and can't be explained",
+            entry.display_path
+        ));
+    }
+
+    let diff = repo
+        .get_diff(entry)?
+        .ok_or_else(|| anyhow::anyhow!("Could not get a diff for {}", entry.display_path))?;
+
+    // The explanation is printed as it's written, so a long one doesn't look stuck
+    let formatter = StatusFormatter::new();
+    formatter.display_explanation_header(entry);
+    let mut stdout = std::io::stdout();
+    summarizer
+        .explain(
+            &entry.display_path,
+            &diff,
+            compare_behavior,
+            &mut |text: &str| {
+                let _ = write!(stdout, "{}", text);
+                let _ = stdout.flush();
+            },
+        )
+        .await?;
+    println!();
+    Ok(())
+}
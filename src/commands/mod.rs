@@ -0,0 +1,21 @@
+pub mod amend_check;
+pub mod audit;
+pub mod bisect;
+pub mod bug_report;
+pub mod cherry_targets;
+pub mod compare;
+pub mod config;
+pub mod doctor;
+pub mod explain;
+pub mod open;
+pub mod prepush;
+pub mod rescue;
+pub mod restore;
+pub mod review;
+pub mod show;
+pub mod snapshot;
+pub mod standup;
+pub mod stats;
+pub mod tag_notes;
+pub mod undo;
+pub mod why;
@@ -0,0 +1,28 @@
+use crate::editor;
+use crate::git::{Repository, StatusCode};
+use crate::hunks;
+use anyhow::Result;
+
+/// Opens a changed file in the editor git uses, at its first change. `target` is the
+/// file's number in the status view's list, its path, or part of it.
+pub fn run(repo: &Repository, target: &str) -> Result<()> {
+    let status = repo.get_status()?;
+    let entry = status.pick(target)?;
+    if entry.status() == &StatusCode::Deleted {
+        return Err(anyhow::anyhow!(
+            "{} was deleted, so there's nothing to open",
+            entry.display_path
+        ));
+    }
+    let line = match entry.abs_path.is_dir() {
+        true => None,
+        false => repo.get_diff(entry)?,
+    }
+    .and_then(|diff| {
+        hunks::changed_ranges(&diff)
+            .first()
+            .map(|range| range.start)
+    })
+    .unwrap_or(1);
+    editor::open(&repo.editor()?, &entry.abs_path, line)
+}
@@ -0,0 +1,81 @@
+use crate::budget::Gate;
+use crate::config::NotifyConfig;
+use crate::display::StatusFormatter;
+use crate::git::{CommitInfo, Repository};
+use crate::notify;
+use crate::summary::{self, Summarizer};
+use anyhow::Result;
+
+pub struct CommitWithSummary {
+    pub commit: CommitInfo,
+    pub summary: String,
+}
+
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    upstream: Option<String>,
+    notify: Option<&NotifyConfig>,
+) -> Result<()> {
+    let upstream = match upstream {
+        Some(upstream) => upstream,
+        None => repo.upstream_ref()?.ok_or_else(|| {
+            anyhow::anyhow!("The current branch has no upstream; pass --upstream <ref>")
+        })?,
+    };
+
+    let commits = repo.commits_in_range(&format!("{}..HEAD", upstream))?;
+    if commits.is_empty() {
+        println!(
+            "Nothing to push: HEAD has no commits that aren't in {}",
+            upstream
+        );
+        return Ok(());
+    }
+
+    let summaries = summary::summarize_commits(summarizer, gate, &commits).await?;
+    let notes: Vec<String> = commits
+        .iter()
+        .zip(&summaries)
+        .map(|(commit, summary)| format!("{}: {}", commit.subject, summary))
+        .collect();
+    let overview = summarizer
+        .summarize_changeset(
+            &format!("commits about to be pushed to {}", upstream),
+            &notes,
+        )
+        .await?;
+
+    let commits: Vec<CommitWithSummary> = commits
+        .into_iter()
+        .zip(summaries)
+        .map(|(commit, summary)| CommitWithSummary { commit, summary })
+        .collect();
+    let formatter = StatusFormatter::new();
+    formatter.display_commit_summaries(&upstream, &commits, &overview)?;
+
+    if let Some(notify) = notify {
+        notify::post(notify, "prepush", &report(&upstream, &commits, &overview)).await?;
+        formatter.display_posted()?;
+    }
+    Ok(())
+}
+
+/// The summary as plain text, for posting.
+fn report(upstream: &str, commits: &[CommitWithSummary], overview: &str) -> String {
+    let mut report = format!(
+        "{} commit(s) about to be pushed to {}:\n",
+        commits.len(),
+        upstream
+    );
+    for commit in commits {
+        report.push_str(&format!(
+            "- {} ({})\n",
+            commit.commit.subject, commit.summary
+        ));
+    }
+    report.push('\n');
+    report.push_str(overview.trim());
+    report
+}
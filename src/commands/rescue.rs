@@ -0,0 +1,36 @@
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::summary::{Summarizer, SummaryRequest};
+use anyhow::Result;
+use futures::future::try_join_all;
+use std::collections::HashMap;
+
+pub async fn run(repo: &Repository, summarizer: &dyn Summarizer, count: usize) -> Result<()> {
+    let entries = repo.reflog(count)?;
+    if entries.is_empty() {
+        println!("HEAD has no reflog entries");
+        return Ok(());
+    }
+
+    // The same commit often shows up several times in the reflog, so summarize each once
+    let mut dangling: Vec<&str> = entries
+        .iter()
+        .filter(|e| e.dangling)
+        .map(|e| e.id.as_str())
+        .collect();
+    dangling.sort_unstable();
+    dangling.dedup();
+
+    let summaries = try_join_all(dangling.iter().map(|id| async move {
+        let commit = repo.commit(id)?;
+        let summary = summarizer
+            .summarize(&SummaryRequest::new(&commit.diff))
+            .await?;
+        Ok::<_, anyhow::Error>((id.to_string(), summary.text))
+    }))
+    .await?;
+    let summaries: HashMap<String, String> = summaries.into_iter().collect();
+
+    let formatter = StatusFormatter::new();
+    formatter.display_reflog(&entries, &summaries)
+}
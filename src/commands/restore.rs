@@ -0,0 +1,42 @@
+use crate::display::StatusFormatter;
+use crate::git::{Repository, StatusCode};
+use crate::trash::Trash;
+use anyhow::Result;
+
+/// Throws away the unstaged changes to each of `targets`, picked the way `git hud open`
+/// picks a file, after copying them into the trash.
+pub fn run(repo: &Repository, targets: &[String]) -> Result<()> {
+    let status = repo.get_status()?;
+    let mut paths = Vec::new();
+    for target in targets {
+        let entry = status.pick(target)?;
+        match &entry.worktree {
+            None => {
+                return Err(anyhow::anyhow!(
+                    "{} has no unstaged changes to throw away",
+                    entry.display_path
+                ))
+            }
+            Some(StatusCode::Untracked) => {
+                return Err(anyhow::anyhow!(
+                    "{} is untracked, so git has nothing to restore it to",
+                    entry.display_path
+                ))
+            }
+            Some(StatusCode::Unmerged) => {
+                return Err(anyhow::anyhow!(
+                    "{} has merge conflicts; resolve them or abort the merge instead",
+                    entry.display_path
+                ))
+            }
+            Some(_) => {}
+        }
+        if !paths.contains(&entry.path) {
+            paths.push(entry.path.clone());
+        }
+    }
+    let mut batch = Trash::new(repo.git_dir()).save(repo.root(), &paths)?;
+    repo.discard_worktree(&paths)?;
+    batch.seal(repo.root())?;
+    StatusFormatter::new().display_discarded(&paths)
+}
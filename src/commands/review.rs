@@ -0,0 +1,50 @@
+use crate::budget::Gate;
+use crate::commands::compare;
+use crate::config::ReviewConfig;
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::review::{self, FileNote};
+use crate::summary::Summarizer;
+use anyhow::Result;
+
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    config: &ReviewConfig,
+    base: Option<String>,
+    change: Option<String>,
+) -> Result<()> {
+    // Find out where to post before spending anything on summaries
+    let review = review::from_config(config, repo, change)?;
+    let base = match base {
+        Some(base) => base,
+        None => repo.upstream_ref()?.ok_or_else(|| {
+            anyhow::anyhow!("The current branch has no upstream; pass --base <ref>")
+        })?,
+    };
+
+    // Only what the branch changes, not what's landed on the base since it forked
+    let fork_point = repo.merge_base(&base, "HEAD")?;
+    let entries = repo.diff_trees(&fork_point, "HEAD")?;
+    if entries.is_empty() {
+        println!("Nothing to review: HEAD changes no files since {}", base);
+        return Ok(());
+    }
+
+    let files = compare::summarize_tree_diff(summarizer, gate, entries).await?;
+    let notes: Vec<FileNote> = files
+        .iter()
+        .filter_map(|file| {
+            Some(FileNote {
+                path: file.entry.path.clone(),
+                summary: file.summary.clone()?,
+            })
+        })
+        .collect();
+
+    let formatter = StatusFormatter::new();
+    formatter.display_tree_diff(&base, "HEAD", &files, "")?;
+    review.post(&notes).await?;
+    formatter.display_posted_to(&review.target())
+}
@@ -0,0 +1,53 @@
+use crate::cache::SummaryCache;
+use crate::config::CacheConfig;
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::path_rules::PathRules;
+use crate::summary::Summarizer;
+use anyhow::Result;
+use std::path::Path;
+use tokio_util::sync::CancellationToken;
+
+/// Shows one file's summary over its full diff, summarized the same way as in the status
+/// view, so a cached summary is reused.
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    rules: &PathRules,
+    cache_config: &CacheConfig,
+    path: &Path,
+    word_diff: bool,
+) -> Result<()> {
+    let status = repo.get_status()?;
+    let entry = status.pick(&path.to_string_lossy())?;
+
+    // Only read: saving would drop every other file's cached summary
+    let cache = SummaryCache::load(repo, cache_config)?;
+    // Ctrl-C exits as usual, since there's nothing else to show
+    let cancel = CancellationToken::new();
+    let mut sides = Vec::new();
+    for (side, code) in entry.sides() {
+        let prepared = crate::prepare_side(repo, &cache, rules, summarizer, entry, side, code)?;
+        sides.push(crate::summarize_side(repo, &cache, prepared, true, &cancel).await?);
+    }
+
+    let formatter = StatusFormatter::new();
+    let files: Vec<_> = sides.iter().map(|side| &side.file).collect();
+    formatter.display_show_header(entry, &files);
+    if entry.is_binary {
+        println!("Binary file, no diff to show");
+        return Ok(());
+    }
+    let diffs: Vec<_> = sides
+        .iter()
+        .filter_map(|side| Some((&side.file, side.file.diff.as_deref()?)))
+        .collect();
+    for (file, diff) in &diffs {
+        // With both sides changed, say which diff is which
+        if diffs.len() > 1 {
+            formatter.display_diff_heading(file.staged);
+        }
+        formatter.display_diff(&file.path, diff, word_diff);
+    }
+    Ok(())
+}
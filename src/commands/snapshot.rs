@@ -0,0 +1,58 @@
+use crate::budget::{self, Budget, Gate};
+use crate::cache::SummaryCache;
+use crate::cli::SnapshotAction;
+use crate::config::CacheConfig;
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::path_rules::PathRules;
+use crate::snapshot::Snapshot;
+use crate::summary::Summarizer;
+use anyhow::Result;
+
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    rules: &PathRules,
+    cache_config: &CacheConfig,
+    action: SnapshotAction,
+) -> Result<()> {
+    let formatter = StatusFormatter::new();
+    match action {
+        SnapshotAction::Save { name } => {
+            let snapshot = current(repo, summarizer, gate, rules, cache_config, &name).await?;
+            snapshot.save(repo.root())?;
+            formatter.display_snapshot_saved(&snapshot)
+        }
+        SnapshotAction::Diff { name } => {
+            let then = Snapshot::load(repo.root(), &name)?;
+            let now = current(repo, summarizer, gate, rules, cache_config, &name).await?;
+            formatter.display_snapshot_changes(&then, &then.compare(&now))
+        }
+        SnapshotAction::List => formatter.display_snapshots(&Snapshot::list(repo.root())?),
+    }
+}
+
+/// The status as it is now, summarized as `git hud` would show it.
+async fn current(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    rules: &PathRules,
+    cache_config: &CacheConfig,
+    name: &str,
+) -> Result<Snapshot> {
+    let status = repo.get_status()?;
+    let mut cache = SummaryCache::load(repo, cache_config)?;
+    let budget = Budget {
+        gate,
+        ..Budget::default()
+    };
+    let (files, _) =
+        crate::summarize_status(repo, summarizer, rules, &status, &mut cache, &budget, None)
+            .await?;
+    if let Some(estimate) = gate.and_then(Gate::held_back) {
+        return Err(budget::over_threshold(estimate));
+    }
+    Ok(Snapshot::new(name, status.branch.head.clone(), &files))
+}
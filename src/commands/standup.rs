@@ -0,0 +1,98 @@
+use crate::budget::{self, Budget, Gate};
+use crate::cache::SummaryCache;
+use crate::config::Config;
+use crate::display::StatusFormatter;
+use crate::git::{BranchInfo, Repository};
+use crate::notify;
+use crate::path_rules::PathRules;
+use crate::summary::{self, Summarizer};
+use crate::template::{self, Vars};
+use anyhow::Result;
+
+/// Writes a standup update from the commits made since `since` and the work in progress,
+/// and posts it too when `post` is set.
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    rules: &PathRules,
+    since: &str,
+    config: &Config,
+    post: bool,
+) -> Result<()> {
+    let commits = repo.own_commits_since(since)?;
+    let summaries = summary::summarize_commits(summarizer, gate, &commits).await?;
+    let done: Vec<String> = commits
+        .iter()
+        .zip(&summaries)
+        .map(|(commit, summary)| format!("{}: {}", commit.subject, summary))
+        .collect();
+
+    // Work in progress is summarized like the status view, so cached summaries are reused
+    let status = repo.get_status()?;
+    let mut cache = SummaryCache::load(repo, &config.cache)?;
+    let budget = Budget {
+        gate,
+        ..Budget::default()
+    };
+    let (files, _) =
+        crate::summarize_status(repo, summarizer, rules, &status, &mut cache, &budget, None)
+            .await?;
+    if let Some(estimate) = gate.and_then(Gate::held_back) {
+        return Err(budget::over_threshold(estimate));
+    }
+    let in_progress: Vec<String> = files
+        .iter()
+        .map(|file| match &file.summary {
+            Some(summary) => format!("{}: {}", file.path, summary),
+            None => file.path.clone(),
+        })
+        .collect();
+
+    if done.is_empty() && in_progress.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No commits since {} and nothing in progress to report",
+            since
+        ));
+    }
+    let mut report = summarizer
+        .write_standup(&describe_branch(&status.branch), &done, &in_progress)
+        .await?;
+    if let Some(template) = &config.templates.standup {
+        let branch = status.branch.head.clone().unwrap_or_default();
+        let subjects = commits.iter().map(|commit| commit.subject.as_str());
+        let vars = Vars::new()
+            .set("update", report.trim())
+            .set("branch", branch.as_str())
+            .list("done", &done)
+            .list("in_progress", &in_progress)
+            .set(
+                "tickets",
+                template::tickets(std::iter::once(branch.as_str()).chain(subjects)).join(", "),
+            );
+        report = template::render(template, &vars)?;
+    }
+
+    let formatter = StatusFormatter::new();
+    formatter.display_standup(&report)?;
+
+    if post {
+        notify::post(&config.notify, "standup", report.trim()).await?;
+        formatter.display_posted()?;
+    }
+    Ok(())
+}
+
+fn describe_branch(branch: &BranchInfo) -> String {
+    let Some(head) = &branch.head else {
+        return "none (detached HEAD)".to_string();
+    };
+    match (&branch.upstream, branch.ahead_behind) {
+        (Some(upstream), Some((ahead, behind))) if ahead > 0 || behind > 0 => format!(
+            "{} ({} commit(s) ahead of and {} behind {})",
+            head, ahead, behind, upstream
+        ),
+        (Some(upstream), _) => format!("{} (tracking {})", head, upstream),
+        (None, _) => format!("{} (not pushed)", head),
+    }
+}
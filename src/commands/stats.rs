@@ -0,0 +1,14 @@
+use crate::audit;
+use crate::config::Config;
+use crate::display::StatusFormatter;
+use crate::stats::{self, Stats};
+use anyhow::Result;
+use chrono::{Days, Utc};
+
+pub fn run(config: &Config, days: u32) -> Result<()> {
+    let since = Utc::now().date_naive() - Days::new(days.saturating_sub(1) as u64);
+    let stats = Stats::collect(&stats::read_runs()?, &audit::read()?, config, since);
+
+    let formatter = StatusFormatter::new();
+    formatter.display_stats(&stats, days, config.audit.enabled)
+}
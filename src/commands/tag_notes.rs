@@ -0,0 +1,73 @@
+use crate::budget::Gate;
+use crate::config::TemplatesConfig;
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::prompt;
+use crate::summary::{self, Summarizer};
+use crate::template::{self, Vars};
+use anyhow::Result;
+
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    templates: &TemplatesConfig,
+    tag: &str,
+    target: &str,
+    create: bool,
+) -> Result<()> {
+    let exists = repo.tag_exists(tag);
+    if exists && create {
+        return Err(anyhow::anyhow!("Tag {} already exists", tag));
+    }
+
+    // An existing tag is described relative to the tag before it, a new one relative
+    // to the latest tag reachable from where it will point
+    let (end, previous) = match exists {
+        true => (tag, repo.nearest_tag(&format!("{}^", tag))?),
+        false => (target, repo.nearest_tag(target)?),
+    };
+    let range = match previous {
+        Some(ref previous) => format!("{}..{}", previous, end),
+        None => end.to_string(),
+    };
+
+    let commits = repo.commits_in_range(&range)?;
+    if commits.is_empty() {
+        return Err(anyhow::anyhow!("There are no commits in {}", range));
+    }
+
+    let summaries = summary::summarize_commits(summarizer, gate, &commits).await?;
+    let notes: Vec<String> = commits
+        .iter()
+        .zip(&summaries)
+        .map(|(commit, summary)| format!("{}: {}", commit.subject, summary))
+        .collect();
+    let mut release_notes = summarizer.write_release_notes(tag, &notes).await?;
+    if let Some(template) = &templates.release_notes {
+        let subjects: Vec<String> = commits
+            .iter()
+            .map(|commit| commit.subject.clone())
+            .collect();
+        let vars = Vars::new()
+            .set("notes", release_notes.trim())
+            .set("tag", tag)
+            .set("previous", previous.as_deref().unwrap_or_default())
+            .list("commits", &subjects)
+            .set("commit_count", commits.len().to_string())
+            .set(
+                "tickets",
+                template::tickets(subjects.iter().map(String::as_str)).join(", "),
+            );
+        release_notes = template::render(template, &vars)?;
+    }
+
+    let formatter = StatusFormatter::new();
+    formatter.display_release_notes(tag, previous.as_deref(), commits.len(), &release_notes)?;
+
+    if create && prompt::confirm(&format!("Create annotated tag {} at {}?", tag, target))? {
+        repo.create_annotated_tag(tag, target, &release_notes)?;
+        println!("Created tag {}", tag);
+    }
+    Ok(())
+}
@@ -0,0 +1,13 @@
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::trash::Trash;
+use anyhow::Result;
+
+/// Puts back the files the last `git hud restore` threw away.
+pub fn run(repo: &Repository, force: bool) -> Result<()> {
+    let Some(batch) = Trash::new(repo.git_dir()).latest()? else {
+        return Err(anyhow::anyhow!("Nothing to undo; the trash is empty"));
+    };
+    let paths = batch.restore(repo.root(), force)?;
+    StatusFormatter::new().display_undone(&paths)
+}
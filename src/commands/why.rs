@@ -0,0 +1,42 @@
+use crate::display::StatusFormatter;
+use crate::git::Repository;
+use crate::summary::Summarizer;
+use anyhow::Result;
+use std::path::Path;
+
+pub async fn run(
+    repo: &Repository,
+    summarizer: &dyn Summarizer,
+    path: &Path,
+    commit_count: usize,
+) -> Result<()> {
+    let status = repo.get_status()?;
+    let entry = status
+        .entry_for_path(path)?
+        .ok_or_else(|| anyhow::anyhow!("No pending changes for {}", path.display()))?;
+
+    if entry.is_binary {
+        return Err(anyhow::anyhow!(
+            "{} is a binary file and can't be explained",
+            entry.display_path
+        ));
+    }
+
+    let diff = repo
+        .get_diff(entry)?
+        .ok_or_else(|| anyhow::anyhow!("Could not get a diff for {}", entry.display_path))?;
+    let commits = repo.recent_commits_for_path(&entry.path, commit_count)?;
+    if commits.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{} has no commit history to compare against",
+            entry.display_path
+        ));
+    }
+
+    let explanation = summarizer
+        .relate_to_history(&entry.display_path, &diff, &commits)
+        .await?;
+
+    let formatter = StatusFormatter::new();
+    formatter.display_explanation(entry, &explanation)
+}
@@ -0,0 +1,877 @@
+use crate::display::CleanStyle;
+use crate::git::StatusBackend;
+use crate::notify::WebhookFormat;
+use crate::path_rules::PathRule;
+use crate::review::Forge;
+use crate::strings;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The repo-local config file, checked in at the root of the working tree.
+pub const REPO_CONFIG_FILE: &str = ".git-hud.toml";
+/// Environment variables starting with this set config keys, with `__` between levels:
+/// `GIT_HUD__STATUS__SHOW_HIDDEN=true` sets `status.show-hidden`.
+const ENV_PREFIX: &str = "GIT_HUD__";
+/// Keys a cloned repo mustn't be able to set, with why.
+const USER_ONLY_KEYS: [(&str, &str); 6] = [
+    ("summarizer", "it would run code from the repository"),
+    ("plugins", "it would run code from the repository"),
+    ("notify", "it would decide where your reports are sent"),
+    ("cache", "it would decide where your summaries are sent"),
+    (
+        "review",
+        "it would decide where your review credentials are sent",
+    ),
+    (
+        "privacy",
+        "it would decide which code is sent off the machine",
+    ),
+];
+
+/// Every config key with an example value and what it does, for `--help-long` and the
+/// man page. `<name>` stands for a provider name.
+pub const KEYS: &[(&str, &str, &str)] = &[
+    (
+        "summarizer",
+        "\"my-llm-cli --short\"",
+        "A shell command that reads a diff on stdin and prints its summary, used instead of the Anthropic API",
+    ),
+    ("plugins.dir", "\"/opt/git-hud/plugins\"", "Where plugins are loaded from"),
+    ("plugins.preprocessors", "[\"strip-lockfiles\"]", "Plugins that rewrite each diff before it's summarized"),
+    ("plugins.summarizer", "\"local-model\"", "A plugin that writes summaries"),
+    ("plugins.postprocessors", "[\"emoji\"]", "Plugins that rewrite each summary"),
+    ("privacy.cloud-remotes", "[\"github.com/my-org/*\"]", "Only repositories with a remote matching one of these may be sent to a cloud provider; others need a local `summarizer` or go without summaries"),
+    ("audit.enabled", "true", "Log every prompt and response to audit.jsonl in the cache dir"),
+    ("status.show-hidden", "true", "List edited files hidden by assume-unchanged or skip-worktree"),
+    ("status.backend", "\"libgit2\"", "How the status is collected: `git` runs `git status`; `libgit2` asks libgit2 in-process"),
+    ("status.clean-style", "\"full\"", "How a clean working tree is shown: `compact` on one line (the default), `full` like `git status`, or `hidden`"),
+    ("status.max-summaries", "100", "The most files to ask for summaries of in one run (40 by default); the rest are marked, and `--all` summarizes them too"),
+    ("status.max-files", "300", "Above this many changed files, like after a branch switch or a formatter run, nothing new is summarized unless you pass `--ai` or `--all` (100 by default); 0 never holds back"),
+    ("status.confirm-cost", "0.5", "Ask before sending summaries estimated to cost more than this many US dollars (0.10 by default), and send none when there's no terminal to ask on; `--yes` skips asking, and 0 never asks"),
+    ("status.progress-interval", "30", "Seconds between `summarized 10/37…` lines while waiting on summaries with stderr not a terminal, like in CI (10 by default); 0 turns them off"),
+    ("status.renames", "false", "Whether staged renames are found; leaving it unset goes by git's `status.renames`"),
+    ("status.untracked-cache", "true", "Have `git status` cache untracked directories in the index to skip unchanged ones; leaving it unset goes by git's `core.untrackedCache`"),
+    ("status.fsmonitor", "true", "Have `git status` ask git's built-in file system monitor what changed; leaving it unset goes by git's `core.fsmonitor`"),
+    ("status.timeout", "30", "Seconds to wait for `git status` before showing tracked changes without untracked files (10 by default); 0 waits as long as it takes"),
+    ("status.fast-path", "false", "Show the last run's output again when HEAD, the index, and the changed files are as they were, without collecting diffs or asking for summaries (on by default)"),
+    ("status.line-ranges", "true", "Put the changed lines in front of each summary, like `(L45–80: Rework the retry loop)`"),
+    ("status.hyperlinks", "\"vscode\"", "Make file paths links that open the file at its first change: `file`, `vscode`, or a URL template like `idea://open?file={{path}}&line={{line}}`"),
+    ("status.syntax-theme", "\"InspiredGitHub\"", "The theme code in `show` and `--patch` diffs is highlighted in: one of syntect's bundled themes, or `none` to turn highlighting off"),
+    ("status.incoming-summary", "true", "When the branch is behind its upstream, sum up in one line what pulling would bring in"),
+    ("strict.secrets", "false", "Whether --strict looks for credentials like API keys and private keys"),
+    ("strict.conflict-markers", "false", "Whether --strict looks for leftover conflict markers"),
+    ("strict.debug-statements", "false", "Whether --strict looks for calls like `dbg!` and `console.log`"),
+    ("strict.oversized-files", "false", "Whether --strict looks for files over `strict.max-file-kb`"),
+    ("strict.max-file-kb", "2048", "How big a changed file can be before --strict fails, in KB (default 1024)"),
+    ("cache.backend", "\"redis\"", "Where summaries are cached: `file` or `sqlite` in the cache dir, or `redis` to share them"),
+    ("cache.redis-url", "\"redis://:password@cache.internal:6379/0\"", "The Redis server for `cache.backend = \"redis\"`"),
+    ("cache.shared-url", "\"https://cache.internal/git-hud\"", "A remote cache shared with your team or CI, checked before each request"),
+    ("cache.shared-read-only", "true", "Only read from the shared cache, never upload to it"),
+    ("notify.webhook", "\"https://hooks.slack.com/services/...\"", "Where --post sends reports"),
+    ("review.forge", "\"gitlab\"", "Where `git hud review` posts: `gitlab` or `gerrit`"),
+    ("review.url", "\"https://gitlab.example.com\"", "The forge's server; GitLab defaults to CI_SERVER_URL, then https://gitlab.com"),
+    ("review.project", "\"my-group/app\"", "The GitLab project, by path or ID; defaults to CI_PROJECT_ID, then the first remote's path"),
+    ("review.user", "\"jdoe\"", "Your Gerrit username"),
+    ("notify.format", "\"slack\"", "What the webhook expects: `slack`, `discord`, or `json`; guessed from the URL by default"),
+    ("templates.commit-message", "\"{{subject}}\\n\\nRefs: {{tickets}}\"", "Lays out suggested commit messages"),
+    ("templates.release-notes", "\"## {{tag}}\\n\\n{{notes}}\"", "Lays out release notes from `tag-notes`"),
+    ("templates.standup", "\"*{{branch}}*\\n{{update}}\"", "Lays out standup updates"),
+    ("providers.<name>.requests-per-minute", "50", "Request rate limit"),
+    ("providers.<name>.tokens-per-minute", "50000", "Prompt and reply token rate limit"),
+    ("providers.<name>.max-concurrent", "8", "How many requests may be in flight at once"),
+    ("providers.<name>.max-retries", "3", "How many times a failed request is retried"),
+    ("providers.<name>.input-price", "0.25", "US dollars per million prompt tokens, for stats and `status.confirm-cost`"),
+    ("providers.<name>.output-price", "1.25", "US dollars per million reply tokens, for stats and `status.confirm-cost`"),
+    ("providers.<name>.token-factor", "1.2", "What local token estimates are multiplied by to match the provider's tokenizer, for rate limits, the audit log, `--dry-run`, and `status.confirm-cost`; the ratio of the tokens the provider reports using to `--dry-run`'s estimate"),
+    ("providers.<name>.summary-max-tokens", "48", "Caps the tokens of one-line summaries"),
+    ("providers.<name>.stop-sequences", "[\".\"]", "Where one-line summaries stop early"),
+    ("paths.\"<pattern>\".summarize", "false", "Whether files matching a gitignore-style pattern are summarized; they're listed either way"),
+    ("paths.\"<pattern>\".provider", "\"command\"", "Summarize matching files with this provider: `anthropic`, `command`, or `plugin`"),
+    ("paths.\"<pattern>\".risk", "\"always-high\"", "`always-high` has `--strict` report any change to matching files; `low` skips its detectors on them"),
+    ("scopes.\"<pattern>\"", "\"display\"", "The conventional-commit scope for changes to files matching a gitignore-style pattern, used when a suggested message's files all map to one"),
+];
+
+/// Configuration, resolved from layers that each override the last: defaults, the user
+/// config at `~/.config/git-hud/config.toml`, the repo's `.git-hud.toml`, `GIT_HUD__*`
+/// environment variables, and `-c key=value` flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// A shell command that reads a diff on stdin and prints its summary, used instead
+    /// of the Anthropic API
+    pub summarizer: Option<String>,
+
+    pub plugins: PluginsConfig,
+
+    pub audit: AuditConfig,
+
+    pub status: StatusConfig,
+
+    pub strict: StrictConfig,
+
+    pub notify: NotifyConfig,
+
+    pub review: ReviewConfig,
+
+    pub cache: CacheConfig,
+
+    pub templates: TemplatesConfig,
+
+    pub privacy: PrivacyConfig,
+
+    /// Per-provider settings, keyed by provider name (`anthropic`, `command`, or `plugin`)
+    pub providers: HashMap<String, ProviderConfig>,
+
+    /// Settings for the files matching each gitignore-style pattern
+    pub paths: HashMap<String, PathRule>,
+
+    /// The conventional-commit scope for the files matching each gitignore-style pattern
+    pub scopes: HashMap<String, String>,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ProviderConfig {
+    pub requests_per_minute: Option<u32>,
+    /// Counts both prompt and reply tokens
+    pub tokens_per_minute: Option<u32>,
+    /// How many requests may be in flight at once
+    pub max_concurrent: Option<usize>,
+    /// How many times a rate-limited or failed request is retried
+    pub max_retries: Option<u32>,
+    /// US dollars per million prompt tokens, for `git hud stats` and `status.confirm-cost`
+    pub input_price: Option<f64>,
+    /// US dollars per million response tokens, for `git hud stats` and
+    /// `status.confirm-cost`
+    pub output_price: Option<f64>,
+    /// Caps one-line summaries, which otherwise get room for about twice their asked-for
+    /// length
+    pub summary_max_tokens: Option<u32>,
+    /// Where one-line summaries stop early. Replies are cut to their first line either way, and
+    /// the API rejects sequences that are only whitespace.
+    pub stop_sequences: Option<Vec<String>>,
+    /// What local token estimates are multiplied by to match the provider's tokenizer
+    pub token_factor: Option<f64>,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct StatusConfig {
+    /// List edited files that git hides because they're marked assume-unchanged or
+    /// skip-worktree
+    pub show_hidden: bool,
+    pub backend: StatusBackend,
+    /// Sum up in one line what the upstream's new commits change when the branch is
+    /// behind it
+    pub incoming_summary: bool,
+    /// How to show a working tree with no changes
+    pub clean_style: CleanStyle,
+    /// The most files to ask for summaries of in one run, unless `--all` is given
+    pub max_summaries: usize,
+    /// Above this many changed files, nothing new is summarized unless `--ai` or `--all`
+    /// is given; 0 turns the check off
+    pub max_files: usize,
+    /// Above this estimated cost of a run's requests, in US dollars, ask before sending
+    /// them; 0 never asks
+    pub confirm_cost: f64,
+    /// How often, in seconds, to print how many summaries are done while waiting on them
+    /// when stderr isn't a terminal, like in CI logs; 0 turns it off
+    pub progress_interval: u64,
+    /// Whether staged renames are found; unset leaves it to git's `status.renames`
+    pub renames: Option<bool>,
+    /// Whether `git status` keeps a cache of untracked directories in the index, to skip
+    /// the ones that haven't changed; unset leaves it to git's `core.untrackedCache`
+    pub untracked_cache: Option<bool>,
+    /// Whether `git status` asks git's file system monitor what changed instead of
+    /// checking every file; unset leaves it to git's `core.fsmonitor`
+    pub fsmonitor: Option<bool>,
+    /// Seconds to wait for `git status` before showing the changes to tracked files
+    /// without untracked ones; 0 waits as long as it takes
+    pub timeout: u64,
+    /// Show the last run's output again when nothing has changed since, without
+    /// collecting diffs or asking for summaries
+    pub fast_path: bool,
+    /// Put the lines each summary's changes are at in front of it, like `(L45–80: …)`
+    pub line_ranges: bool,
+    /// Where file paths link to when output is colored: `file`, `vscode`, or a URL
+    /// template with `{{path}}` and `{{line}}`
+    pub hyperlinks: Option<String>,
+    /// The syntect theme code in diffs is highlighted in, or `none`; unset uses
+    /// `base16-ocean.dark`
+    pub syntax_theme: Option<String>,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        Self {
+            show_hidden: false,
+            backend: StatusBackend::default(),
+            incoming_summary: false,
+            clean_style: CleanStyle::default(),
+            max_summaries: 40,
+            max_files: 100,
+            confirm_cost: 0.10,
+            progress_interval: 10,
+            renames: None,
+            untracked_cache: None,
+            fsmonitor: None,
+            timeout: 10,
+            fast_path: true,
+            line_ranges: false,
+            hyperlinks: None,
+            syntax_theme: None,
+            unknown: toml::Table::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PrivacyConfig {
+    /// Remote URL patterns, like `github.com/my-org/*`, of the repositories whose code may
+    /// be sent to a cloud provider. When set, any other repository only gets summaries
+    /// from a local summarizer
+    pub cloud_remotes: Vec<String>,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AuditConfig {
+    /// Log every prompt and response to `audit.jsonl` in the cache dir
+    pub enabled: bool,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+/// Which detectors `--strict` runs; all of them by default.
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct StrictConfig {
+    pub secrets: bool,
+    pub conflict_markers: bool,
+    pub debug_statements: bool,
+    pub oversized_files: bool,
+    /// Changed files bigger than this many KB are oversized
+    pub max_file_kb: u64,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+impl Default for StrictConfig {
+    fn default() -> Self {
+        Self {
+            secrets: true,
+            conflict_markers: true,
+            debug_statements: true,
+            oversized_files: true,
+            max_file_kb: 1024,
+            unknown: toml::Table::new(),
+        }
+    }
+}
+
+/// Where `--post` sends generated reports.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NotifyConfig {
+    /// A Slack, Discord, or other webhook URL
+    pub webhook: Option<String>,
+    /// What the webhook expects; guessed from the URL when unset
+    pub format: Option<WebhookFormat>,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+/// Where `git hud review` posts summaries.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ReviewConfig {
+    pub forge: Option<Forge>,
+    /// The forge's server
+    pub url: Option<String>,
+    /// The GitLab project, by path or numeric ID
+    pub project: Option<String>,
+    /// The Gerrit username
+    pub user: Option<String>,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+/// Where summaries are cached.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CacheConfig {
+    pub backend: Backend,
+    /// A `redis://[user:password@]host[:port][/db]` URL, for the `redis` backend
+    pub redis_url: Option<String>,
+    /// A team or CI cache that's checked before making a request, as an HTTP base URL
+    pub shared_url: Option<String>,
+    /// Only read from the shared cache, never upload to it
+    pub shared_read_only: bool,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// A JSON file per repo in the cache dir
+    #[default]
+    File,
+    /// A hash per repo on a Redis server, shared by every machine that points at it
+    Redis,
+    /// A row per summary in a SQLite database in the cache dir; needs the `sqlite` feature
+    Sqlite,
+}
+
+/// Templates that generated text is laid out with, each filling in `{{name}}` variables
+/// from what was generated. See the README for the variables each one gets.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TemplatesConfig {
+    /// For the message `amend-check` suggests
+    pub commit_message: Option<String>,
+    /// For `tag-notes`, including the message of a tag it creates
+    pub release_notes: Option<String>,
+    pub standup: Option<String>,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+/// WASM plugins, named by file stem and loaded from `dir`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PluginsConfig {
+    /// Where plugins live; defaults to `plugins/` next to the config file
+    pub dir: Option<PathBuf>,
+    /// Plugins that rewrite each diff before it's summarized, applied in order
+    pub preprocessors: Vec<String>,
+    /// A plugin that writes summaries, used instead of any other summarizer
+    pub summarizer: Option<String>,
+    /// Plugins that rewrite each summary before it's displayed, applied in order
+    pub postprocessors: Vec<String>,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+/// Where a layer of configuration came from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    User(PathBuf),
+    Repo(PathBuf),
+    /// A `GIT_HUD__*` environment variable, by name
+    Env(String),
+    /// A `-c key=value` flag
+    Flag(String),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::User(path) | Source::Repo(path) => write!(f, "{}", path.display()),
+            Source::Env(name) => write!(f, "${}", name),
+            Source::Flag(assignment) => write!(f, "-c {}", assignment),
+        }
+    }
+}
+
+/// One layer of configuration, checked on its own so errors can name where they are.
+#[derive(Debug)]
+pub struct Layer {
+    pub source: Source,
+    pub table: toml::Table,
+}
+
+impl Config {
+    /// Resolves every layer; `overrides` are `key=value` pairs from the command line.
+    pub fn load(overrides: &[String]) -> Result<Self> {
+        Self::from_layers(&Self::layers(overrides)?)
+    }
+
+    /// Reads each layer, lowest precedence first.
+    pub fn layers(overrides: &[String]) -> Result<Vec<Layer>> {
+        let mut layers = Vec::new();
+        if let Some(path) = Self::path().filter(|path| path.exists()) {
+            layers.push(read_layer(Source::User(path))?);
+        }
+        if let Some(path) = Self::repo_path().filter(|path| path.exists()) {
+            layers.push(read_layer(Source::Repo(path))?);
+        }
+        layers.extend(env_layers(std::env::vars())?);
+        for assignment in overrides {
+            let (key, value) = assignment.split_once('=').ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Expected key=value for a config override, got `{}`",
+                    assignment
+                )
+            })?;
+            layers.push(check_layer(
+                Source::Flag(assignment.clone()),
+                layer_for(key, value),
+            )?);
+        }
+        Ok(layers)
+    }
+
+    pub fn from_layers(layers: &[Layer]) -> Result<Self> {
+        Ok(Self::deserialize(merged(layers))?)
+    }
+
+    /// Where the user config lives; `GIT_HUD_CONFIG` overrides the XDG location.
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os(strings::GIT_HUD_CONFIG) {
+            return Some(PathBuf::from(path));
+        }
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_dir.join("git-hud").join("config.toml"))
+    }
+
+    /// Where the repo-local config would be, if the current directory is in a working tree.
+    pub fn repo_path() -> Option<PathBuf> {
+        let work_tree = match std::env::var_os(strings::GIT_WORK_TREE) {
+            Some(work_tree) => PathBuf::from(work_tree),
+            // Honors GIT_DIR like git does
+            None => git2::Repository::open_from_env()
+                .ok()?
+                .workdir()?
+                .to_path_buf(),
+        };
+        Some(work_tree.join(REPO_CONFIG_FILE))
+    }
+
+    /// Dotted paths of keys this version doesn't know, which are otherwise ignored.
+    pub fn unknown_keys(&self) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut add = |prefix: &str, unknown: &toml::Table| {
+            keys.extend(unknown.keys().map(|key| format!("{}{}", prefix, key)));
+        };
+        add("", &self.unknown);
+        add("plugins.", &self.plugins.unknown);
+        add("audit.", &self.audit.unknown);
+        add("status.", &self.status.unknown);
+        add("privacy.", &self.privacy.unknown);
+        add("strict.", &self.strict.unknown);
+        add("notify.", &self.notify.unknown);
+        add("review.", &self.review.unknown);
+        add("cache.", &self.cache.unknown);
+        add("templates.", &self.templates.unknown);
+        for (pattern, rule) in &self.paths {
+            add(&format!("paths.\"{}\".", pattern), &rule.unknown);
+        }
+        for (name, provider) in &self.providers {
+            add(&format!("providers.{}.", name), &provider.unknown);
+        }
+        keys.sort();
+        keys
+    }
+}
+
+/// Sets a dotted key in the user config file, keeping the rest of the file, comments
+/// included, as it was. Returns the file's path.
+pub fn set_user_value(key: &str, value: &str) -> Result<PathBuf> {
+    let layer = layer_for(key, value);
+    let config = Config::deserialize(layer.clone())
+        .map_err(|e| anyhow::anyhow!("Invalid value for `{}`: {}", key, e))?;
+    if !config.unknown_keys().is_empty() {
+        return Err(anyhow::anyhow!("Unknown config key `{}`", key));
+    }
+    let value: toml_edit::Value = lookup(&layer, key)
+        .expect("the layer sets its key")
+        .to_string()
+        .parse()?;
+
+    let path = Config::path()
+        .ok_or_else(|| anyhow::anyhow!("Could not locate the config dir; set HOME"))?;
+    let mut document = match path.exists() {
+        true => std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?
+            .parse::<toml_edit::DocumentMut>()
+            .with_context(|| format!("Invalid config file {}", path.display()))?,
+        false => toml_edit::DocumentMut::new(),
+    };
+    set_in_document(&mut document, key, value)?;
+
+    // Written to a temporary file first so a failed write can't leave half a config
+    let dir = path.parent().expect("the config path has a parent");
+    std::fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    std::io::Write::write_all(&mut file, document.to_string().as_bytes())?;
+    if let Ok(metadata) = std::fs::metadata(&path) {
+        std::fs::set_permissions(file.path(), metadata.permissions())?;
+    }
+    file.persist(&path)
+        .with_context(|| format!("Failed to write config file {}", path.display()))?;
+    Ok(path)
+}
+
+fn set_in_document(
+    document: &mut toml_edit::DocumentMut,
+    key: &str,
+    value: toml_edit::Value,
+) -> Result<()> {
+    let mut parts = key_parts(key);
+    let last = parts.pop().expect("a key has at least one part");
+    let last = last.as_str();
+    let mut table: &mut dyn toml_edit::TableLike = document.as_table_mut();
+    for part in &parts {
+        table = table
+            .entry(part)
+            .or_insert_with(|| {
+                let mut table = toml_edit::Table::new();
+                // Only the innermost table gets a [header]
+                table.set_implicit(true);
+                toml_edit::Item::Table(table)
+            })
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow::anyhow!("`{}` is set to a value, not a table", part))?;
+    }
+    match table.get_mut(last).and_then(|item| item.as_value_mut()) {
+        // Replaced in place, so comments around the old value stay
+        Some(old) => {
+            let decor = old.decor().clone();
+            *old = value;
+            *old.decor_mut() = decor;
+        }
+        None => {
+            table.insert(last, toml_edit::value(value));
+        }
+    }
+    Ok(())
+}
+
+/// Every layer overlaid on the ones before it.
+pub fn merged(layers: &[Layer]) -> toml::Table {
+    let mut table = toml::Table::new();
+    for layer in layers {
+        merge(&mut table, layer.table.clone());
+    }
+    table
+}
+
+/// The layer that decides a dotted key's value, which is the last one to set it.
+pub fn source_of<'a>(layers: &'a [Layer], key: &str) -> Option<&'a Source> {
+    layers
+        .iter()
+        .rev()
+        .find(|layer| lookup(&layer.table, key).is_some())
+        .map(|layer| &layer.source)
+}
+
+/// Every value set in `table`, by dotted key, in key order.
+pub fn leaves(table: &toml::Table) -> Vec<(String, &toml::Value)> {
+    let mut leaves = Vec::new();
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(table) => leaves.extend(
+                self::leaves(table)
+                    .into_iter()
+                    .map(|(inner, value)| (format!("{}.{}", key_part(key), inner), value)),
+            ),
+            value => leaves.push((key_part(key), value)),
+        }
+    }
+    leaves
+}
+
+/// Looks up a dotted key like `providers.anthropic.max-retries`.
+pub fn lookup<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let parts = key_parts(key);
+    let (last, parts) = parts.split_last()?;
+    let mut table = table;
+    for part in parts {
+        table = table.get(part)?.as_table()?;
+    }
+    table.get(last)
+}
+
+/// The parts of a dotted key, where quoted parts like the pattern in
+/// `paths."*.sql".risk` can contain dots.
+fn key_parts(key: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quoted = false;
+    for c in key.trim().chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '.' if !quoted => parts.push(String::new()),
+            c => parts.last_mut().expect("never empty").push(c),
+        }
+    }
+    parts
+}
+
+/// A key part as it's written in a dotted key: quoted, unless it's a bare TOML key.
+fn key_part(part: &str) -> String {
+    let bare = !part.is_empty()
+        && part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    match bare {
+        true => part.to_string(),
+        false => format!("\"{}\"", part),
+    }
+}
+
+fn read_layer(source: Source) -> Result<Layer> {
+    let (Source::User(path) | Source::Repo(path)) = &source else {
+        unreachable!("only files are read")
+    };
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    let table = contents
+        .parse()
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    check_layer(source, table)
+}
+
+/// Checks that a layer's values have the right types on their own, and that a repo's
+/// config doesn't set anything only the user should.
+fn check_layer(source: Source, table: toml::Table) -> Result<Layer> {
+    if let Source::Repo(path) = &source {
+        if let Some((key, reason)) = USER_ONLY_KEYS
+            .iter()
+            .find(|(key, _)| table.contains_key(*key))
+        {
+            return Err(anyhow::anyhow!(
+                "{} can't set `{}`, since {}; set it in your user config instead",
+                path.display(),
+                key,
+                reason
+            ));
+        }
+    }
+    Config::deserialize(table.clone()).map_err(|e| match &source {
+        Source::User(path) | Source::Repo(path) => {
+            anyhow::anyhow!("Invalid config file {}: {}", path.display(), e)
+        }
+        Source::Env(name) => anyhow::anyhow!("Invalid {}: {}", name, e),
+        Source::Flag(assignment) => {
+            anyhow::anyhow!("Invalid config override `{}`: {}", assignment, e)
+        }
+    })?;
+    Ok(Layer { source, table })
+}
+
+/// Reads `GIT_HUD__*` variables, one layer each.
+fn env_layers(vars: impl IntoIterator<Item = (String, String)>) -> Result<Vec<Layer>> {
+    let mut vars: Vec<_> = vars
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .collect();
+    // The environment has no order, so keep the layers stable
+    vars.sort();
+    vars.into_iter()
+        .map(|(name, value)| {
+            let key = name[ENV_PREFIX.len()..]
+                .split("__")
+                .map(|part| part.to_ascii_lowercase().replace('_', "-"))
+                .collect::<Vec<_>>()
+                .join(".");
+            check_layer(Source::Env(name), layer_for(&key, &value))
+        })
+        .collect()
+}
+
+/// A layer setting one dotted key, like `providers.anthropic.max-concurrent`. The value
+/// is read as TOML when it parses, so numbers, booleans, and arrays work, and as a plain
+/// string otherwise.
+fn layer_for(key: &str, value: &str) -> toml::Table {
+    let value = format!("v = {}", value)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("v"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+    let mut parts = key_parts(key).into_iter().rev();
+    let last = parts.next().unwrap_or_default();
+    let mut table = toml::Table::from_iter([(last, value)]);
+    for part in parts {
+        table = toml::Table::from_iter([(part, toml::Value::Table(table))]);
+    }
+    table
+}
+
+/// Overlays `layer` onto `base`, merging tables key by key and replacing anything else.
+fn merge(base: &mut toml::Table, layer: toml::Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(layer)) => merge(base, layer),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path_rules::Risk;
+
+    #[test]
+    fn test_layers() -> Result<()> {
+        let user = PathBuf::from("config.toml");
+        let repo = PathBuf::from(".git-hud.toml");
+        let mut layers = vec![
+            check_layer(
+                Source::User(user.clone()),
+                "summarizer = \"cat\"\ncolour = true\n[providers.anthropic]\nmax-concurrent = 8\nmax-retries = 3\n"
+                    .parse()?,
+            )?,
+            check_layer(
+                Source::Repo(repo.clone()),
+                "[providers.anthropic]\nmax-concurrent = 2\n[status]\nshow-hidden = true\n"
+                    .parse()?,
+            )?,
+        ];
+        layers.extend(env_layers([
+            (
+                "GIT_HUD__PROVIDERS__ANTHROPIC__MAX_RETRIES".to_string(),
+                "5".to_string(),
+            ),
+            ("GIT_HUD_CONFIG".to_string(), "/elsewhere.toml".to_string()),
+        ])?);
+        let flag = "status.show-hidden=false".to_string();
+        layers.push(check_layer(
+            Source::Flag(flag.clone()),
+            layer_for("status.show-hidden", "false"),
+        )?);
+
+        let config = Config::from_layers(&layers)?;
+        assert_eq!(config.summarizer.as_deref(), Some("cat"));
+        let anthropic = &config.providers["anthropic"];
+        assert_eq!(anthropic.max_concurrent, Some(2));
+        assert_eq!(anthropic.max_retries, Some(5));
+        assert!(!config.status.show_hidden);
+        assert_eq!(config.unknown_keys(), ["colour"]);
+
+        assert_eq!(source_of(&layers, "summarizer"), Some(&Source::User(user)));
+        assert_eq!(
+            source_of(&layers, "providers.anthropic.max-concurrent"),
+            Some(&Source::Repo(repo))
+        );
+        assert_eq!(
+            source_of(&layers, "providers.anthropic.max-retries"),
+            Some(&Source::Env(
+                "GIT_HUD__PROVIDERS__ANTHROPIC__MAX_RETRIES".to_string()
+            ))
+        );
+        assert_eq!(
+            source_of(&layers, "status.show-hidden"),
+            Some(&Source::Flag(flag))
+        );
+        assert_eq!(source_of(&layers, "audit.enabled"), None);
+
+        // Values that aren't TOML are strings
+        assert_eq!(
+            layer_for("summarizer", "ollama run llama3"),
+            toml::Table::from_iter([("summarizer".to_string(), "ollama run llama3".into())])
+        );
+        let error = env_layers([(
+            "GIT_HUD__STATUS__SHOW_HIDDEN".to_string(),
+            "yes".to_string(),
+        )])
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Invalid GIT_HUD__STATUS__SHOW_HIDDEN"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_only_keys() -> Result<()> {
+        let privacy: toml::Table = "[privacy]\ncloud-remotes = [\"*\"]\n".parse()?;
+        let error = check_layer(Source::Repo(".git-hud.toml".into()), privacy.clone())
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with(".git-hud.toml can't set `privacy`"));
+        check_layer(Source::User("config.toml".into()), privacy)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_in_document() -> Result<()> {
+        let mut document: toml_edit::DocumentMut =
+            "# mine\nsummarizer = \"cat\" # local\n\n[status]\nshow-hidden = false\n".parse()?;
+        set_in_document(&mut document, "summarizer", "\"tac\"".parse()?)?;
+        set_in_document(&mut document, "status.show-hidden", "true".parse()?)?;
+        set_in_document(
+            &mut document,
+            "providers.anthropic.max-retries",
+            "5".parse()?,
+        )?;
+        assert_eq!(
+            document.to_string(),
+            "# mine\nsummarizer = \"tac\" # local\n\n[status]\nshow-hidden = true\n\n[providers.anthropic]\nmax-retries = 5\n"
+        );
+
+        assert!(set_in_document(&mut document, "summarizer.nested", "1".parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_quoted_keys() -> Result<()> {
+        let key = "paths.\"*.sql\".risk";
+        let layer = layer_for(key, "\"low\"");
+        let config = Config::deserialize(layer.clone())?;
+        assert_eq!(config.paths["*.sql"].risk, Some(Risk::Low));
+        assert!(lookup(&layer, key).is_some());
+        assert_eq!(leaves(&layer)[0].0, key);
+
+        let mut document = toml_edit::DocumentMut::new();
+        set_in_document(&mut document, key, "\"always-high\"".parse()?)?;
+        assert_eq!(
+            document.to_string(),
+            "[paths.\"*.sql\"]\nrisk = \"always-high\"\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_keys() -> Result<()> {
+        for (key, example, _) in KEYS {
+            let key = key.replace("<name>", "anthropic");
+            let config = Config::deserialize(layer_for(&key, example))?;
+            assert!(config.unknown_keys().is_empty(), "{} isn't a key", key);
+            assert!(
+                format!("v = {}", example).parse::<toml::Table>().is_ok(),
+                "{}'s example isn't TOML",
+                key
+            );
+        }
+        Ok(())
+    }
+}
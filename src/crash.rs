@@ -0,0 +1,266 @@
+//! Diagnostics for bug reports. When `git hud` panics, or fails on an error from git,
+//! the filesystem, or the API rather than a usage mistake, it writes a report to the
+//! cache dir: the version, platform, git version, command line, config with anything
+//! secret redacted, the error, and the last log lines. Diffs and summaries are never
+//! included. `git hud bug-report` prints the latest one, ready to paste into an issue.
+
+use crate::cache;
+use crate::config::{self, Config};
+use crate::error::HudError;
+use crate::log;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// How many reports are kept; older ones are deleted.
+const KEPT_REPORTS: usize = 10;
+/// Config keys whose values are replaced, since they can hold URLs with tokens or
+/// commands with credentials, and any key whose name contains one of these words.
+const SECRET_KEYS: &[&str] = &["summarizer", "webhook", "redis-url", "shared-url"];
+const SECRET_WORDS: &[&str] = &["key", "token", "secret", "password"];
+const REDACTED: &str = "<redacted>";
+
+/// The `-c` overrides, so a panic hook can resolve the config the same way the run did.
+static OVERRIDES: OnceLock<Vec<String>> = OnceLock::new();
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub timestamp: DateTime<Utc>,
+    pub version: String,
+    pub platform: String,
+    pub git: Option<String>,
+    pub args: Vec<String>,
+    /// Either `panic` or `error`
+    pub kind: String,
+    pub message: String,
+    /// The merged config, redacted, as TOML
+    pub config: String,
+    pub log: Vec<String>,
+}
+
+/// Writes a report whenever `git hud` panics, after the usual panic message.
+pub fn install(overrides: &[String]) {
+    let _ = OVERRIDES.set(overrides.to_vec());
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        let location = info
+            .location()
+            .map(|location| format!(" at {}:{}", location.file(), location.line()))
+            .unwrap_or_default();
+        let payload = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        report("panic", &format!("{}{}", payload, location));
+    }));
+}
+
+/// Writes a report for `error` if it came from git, the filesystem, or the API. Errors
+/// that say how the command was misused don't need one.
+pub fn report_error(error: &anyhow::Error) {
+    let fatal = error.chain().any(|cause| {
+        cause.is::<HudError>() || cause.is::<std::io::Error>() || cause.is::<git2::Error>()
+    });
+    if fatal {
+        report("error", &format!("{:#}", error));
+    }
+}
+
+/// Saves a report and says where, or why it couldn't be saved.
+fn report(kind: &str, message: &str) {
+    match Report::new(kind, message).save() {
+        Ok(path) => eprintln!(
+            "Diagnostics were saved to {}; run `git hud bug-report` to include them in an issue",
+            path.display()
+        ),
+        Err(e) => eprintln!("Couldn't save diagnostics: {:#}", e),
+    }
+}
+
+impl Report {
+    fn new(kind: &str, message: &str) -> Self {
+        let overrides = OVERRIDES.get().cloned().unwrap_or_default();
+        let config = match Config::layers(&overrides) {
+            Ok(layers) => {
+                let mut merged = config::merged(&layers);
+                redact(&mut merged);
+                toml::to_string(&merged).unwrap_or_default()
+            }
+            Err(e) => format!("# didn't load: {:#}", e),
+        };
+        let git = std::process::Command::new("git")
+            .arg("--version")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+        Self {
+            timestamp: Utc::now(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            platform: format!("{} ({})", std::env::consts::OS, std::env::consts::ARCH),
+            git,
+            args: redact_args(std::env::args().skip(1)),
+            kind: kind.to_string(),
+            message: message.to_string(),
+            config,
+            log: log::recent(),
+        }
+    }
+
+    fn save(&self) -> Result<PathBuf> {
+        let dir = dir().ok_or_else(|| anyhow::anyhow!("No cache directory to save it in"))?;
+        let path = dir.join(format!(
+            "{}.json",
+            self.timestamp.format("%Y%m%dT%H%M%S%.3fZ")
+        ));
+        cache::write_atomic(&path, &serde_json::to_string_pretty(self)?)?;
+        prune(&dir)?;
+        Ok(path)
+    }
+
+    /// The most recent report, and where it is.
+    pub fn latest() -> Result<Option<(Self, PathBuf)>> {
+        let Some(path) = dir().and_then(|dir| reports(&dir).pop()) else {
+            return Ok(None);
+        };
+        Ok(Some((Self::load(&path)?, path)))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("{} isn't a git-hud crash report", path.display()))
+    }
+}
+
+fn dir() -> Option<PathBuf> {
+    cache::dir().map(|dir| dir.join("crashes"))
+}
+
+/// Every report in `dir`, oldest first; names sort by time.
+fn reports(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn prune(dir: &Path) -> Result<()> {
+    let reports = reports(dir);
+    for path in &reports[..reports.len().saturating_sub(KEPT_REPORTS)] {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to delete {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn is_secret(key: &str) -> bool {
+    SECRET_KEYS.contains(&key) || SECRET_WORDS.iter().any(|word| key.contains(word))
+}
+
+/// Replaces values that could be secret, keeping the keys so the report still shows
+/// what was set.
+fn redact(table: &mut toml::Table) {
+    for (key, value) in table.iter_mut() {
+        match value {
+            toml::Value::Table(table) => redact(table),
+            _ if is_secret(key) => *value = toml::Value::String(REDACTED.to_string()),
+            _ => {}
+        }
+    }
+}
+
+/// Redacts secret `-c key=value` overrides in the command line.
+fn redact_args(args: impl Iterator<Item = String>) -> Vec<String> {
+    args.map(|arg| {
+        let (prefix, setting) = match arg.split_once("--config=") {
+            Some(("", setting)) => ("--config=", setting),
+            _ => ("", arg.as_str()),
+        };
+        match setting.split_once('=') {
+            Some((key, _)) if is_secret(key.rsplit('.').next().unwrap_or(key)) => {
+                format!("{}{}={}", prefix, key, REDACTED)
+            }
+            _ => arg,
+        }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            summarizer = "llm --api-key sk-123"
+            [notify]
+            webhook = "https://hooks.slack.com/services/T0/B0/secret"
+            [providers.anthropic]
+            max-retries = 3
+            api-token = 12345
+            "#,
+        )
+        .unwrap();
+        redact(&mut table);
+        assert_eq!(table["summarizer"].as_str(), Some(REDACTED));
+        assert_eq!(table["notify"]["webhook"].as_str(), Some(REDACTED));
+        assert_eq!(
+            table["providers"]["anthropic"]["max-retries"].as_integer(),
+            Some(3)
+        );
+        assert_eq!(
+            table["providers"]["anthropic"]["api-token"].as_str(),
+            Some(REDACTED)
+        );
+    }
+
+    #[test]
+    fn test_redact_args() {
+        let args = [
+            "-c",
+            "notify.webhook=https://x/y",
+            "--config=summarizer=llm",
+            "show",
+            "a=b",
+        ];
+        assert_eq!(
+            redact_args(args.iter().map(|arg| arg.to_string())),
+            vec![
+                "-c",
+                "notify.webhook=<redacted>",
+                "--config=summarizer=<redacted>",
+                "show",
+                "a=b",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prune() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for i in 0..KEPT_REPORTS + 2 {
+            std::fs::write(dir.path().join(format!("2026010{:02}.json", i)), "{}")?;
+        }
+        prune(dir.path())?;
+        let kept = reports(dir.path());
+        assert_eq!(kept.len(), KEPT_REPORTS);
+        assert!(kept[0].ends_with("202601002.json"));
+        Ok(())
+    }
+}
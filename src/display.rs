@@ -1,19 +1,182 @@
-use crate::git::{Status, StatusCode};
-use crate::FileWithSummary;
+use crate::audit::AuditRecord;
+use crate::budget;
+use crate::commands::compare::TreeDiffWithSummary;
+use crate::commands::doctor::{Check, Outcome};
+use crate::commands::prepush::CommitWithSummary;
+use crate::config::{self, Layer};
+use crate::crash::Report;
+use crate::detect::Finding;
+use crate::git::{
+    BranchInfo, CommitInfo, Containment, HiddenFile, IndexFlag, Operation, RebaseProgress,
+    ReflogEntry, Status, StatusCode, StatusEntry,
+};
+use crate::hunks::LineRange;
+use crate::hyperlinks::{self, Links};
+use crate::plan::PlannedSummary;
+use crate::snapshot::{Snapshot, SnapshotChanges, SnapshotEntry};
+use crate::stats::{Stats, SummarySource};
+use crate::summary::AmendAdvice;
+use crate::syntax::{self, Highlighter};
+use crate::template;
+use crate::width;
+use crate::word_diff::{self, Block, Change};
+use crate::{Conflict, FileWithSummary};
 use anyhow::Result;
+use chrono::{DateTime, TimeDelta, Utc};
 use colored::*;
-use std::process::Command;
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub struct StatusFormatter;
+/// When to color output, as in git's `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ColorChoice {
+    Always,
+    /// Only when stdout is a terminal, and `NO_COLOR` isn't set
+    Auto,
+    Never,
+}
+
+impl ColorChoice {
+    /// Turns color on or off for everything printed from here on.
+    pub fn apply(self) {
+        match self {
+            ColorChoice::Always => colored::control::set_override(true),
+            // `colored` already checks for a terminal and the usual variables
+            ColorChoice::Auto => colored::control::unset_override(),
+            ColorChoice::Never => colored::control::set_override(false),
+        }
+    }
+}
+
+/// What the status view looks like when there's nothing to show but the branch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CleanStyle {
+    /// The usual header, then `nothing to commit, working tree clean`
+    Full,
+    /// One line with the branch, its upstream, and its last commit
+    #[default]
+    Compact,
+    /// Nothing at all
+    Hidden,
+}
+
+/// Summaries are asked to fit in 50 characters; this leaves room for ones that don't
+/// quite, while keeping a runaway one from wrapping.
+const SUMMARY_MAX_WIDTH: usize = 72;
+
+/// The furthest right summaries are lined up; entries wider than this just get a space.
+const SUMMARY_COLUMN_MAX: usize = 48;
+
+/// How long after a fetch the header starts saying how old the remote info is.
+const STALE_AFTER: TimeDelta = TimeDelta::hours(1);
+
+static ACCESSIBLE: AtomicBool = AtomicBool::new(false);
+
+/// How output is laid out. The accessible profile is for screen readers and line-oriented
+/// tools: each record is one line that says in words what it is, rather than relying on
+/// color, indentation, or the section it's under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputProfile {
+    Standard,
+    Accessible,
+}
+
+impl OutputProfile {
+    /// Uses this profile for everything printed from here on. The accessible profile turns
+    /// color off too, since it never depends on it.
+    pub fn apply(self) {
+        ACCESSIBLE.store(self == OutputProfile::Accessible, Ordering::Relaxed);
+        if self == OutputProfile::Accessible {
+            colored::control::set_override(false);
+        }
+    }
+
+    fn current() -> Self {
+        match ACCESSIBLE.load(Ordering::Relaxed) {
+            true => OutputProfile::Accessible,
+            false => OutputProfile::Standard,
+        }
+    }
+}
+
+pub struct StatusFormatter {
+    profile: OutputProfile,
+    /// Whether to print each file's diff under it in the status view
+    patch: bool,
+    /// Where the status view is written: stdout, unless a test captures it
+    out: RefCell<Box<dyn Write>>,
+    /// One line on what the upstream's new commits bring in, under the branch
+    incoming: Option<String>,
+    clean_style: CleanStyle,
+    /// Whether summaries start with the lines their changes are at
+    line_ranges: bool,
+    /// Where file paths link to, if they're links
+    links: Option<Links>,
+}
 
 impl StatusFormatter {
     pub fn new() -> Self {
-        Self
+        Self {
+            profile: OutputProfile::current(),
+            patch: false,
+            out: RefCell::new(Box::new(std::io::stdout())),
+            incoming: None,
+            clean_style: CleanStyle::default(),
+            line_ranges: false,
+            links: None,
+        }
+    }
+
+    pub fn with_patch(self, patch: bool) -> Self {
+        Self { patch, ..self }
+    }
+
+    pub fn with_incoming(self, incoming: Option<String>) -> Self {
+        Self { incoming, ..self }
+    }
+
+    pub fn with_clean_style(self, clean_style: CleanStyle) -> Self {
+        Self {
+            clean_style,
+            ..self
+        }
+    }
+
+    pub fn with_line_ranges(self, line_ranges: bool) -> Self {
+        Self {
+            line_ranges,
+            ..self
+        }
+    }
+
+    pub fn with_links(self, links: Option<Links>) -> Self {
+        Self { links, ..self }
+    }
+
+    /// Writes the status view to `writer` instead of stdout.
+    #[cfg(test)]
+    pub fn with_writer(self, writer: impl Write + 'static) -> Self {
+        Self {
+            out: RefCell::new(Box::new(writer)),
+            ..self
+        }
+    }
+
+    fn out(&self) -> RefMut<'_, Box<dyn Write>> {
+        self.out.borrow_mut()
     }
 
+    #[allow(dead_code)]
     pub fn display(&self, status: &Status) -> Result<()> {
         // Get branch information
-        self.print_branch_status()?;
+        let dirty = status.entries.iter().any(|entry| {
+            entry.is_staged() || !matches!(entry.worktree, None | Some(StatusCode::Untracked))
+        });
+        self.print_branch_status(&status.branch, dirty)?;
 
         let mut has_staged = false;
         let mut has_unstaged = false;
@@ -21,10 +184,11 @@ impl StatusFormatter {
 
         // Categorize changes
         for entry in &status.entries {
-            match entry.status {
-                StatusCode::Untracked => has_untracked = true,
-                _ if entry.staged => has_staged = true,
-                _ => has_unstaged = true,
+            has_staged |= entry.is_staged();
+            match &entry.worktree {
+                Some(StatusCode::Untracked) => has_untracked = true,
+                Some(_) => has_unstaged = true,
+                None => {}
             }
         }
 
@@ -41,118 +205,1328 @@ impl StatusFormatter {
             self.print_untracked_files(status)?;
         }
 
-        // Print summary line if needed
-        if !has_staged && has_unstaged {
-            println!("\nno changes added to commit (use \"git add\" and/or \"git commit -a\")");
+        // Print summary line if needed
+        if !has_staged && has_unstaged {
+            writeln!(
+                self.out(),
+                "\nno changes added to commit (use \"git add\" and/or \"git commit -a\")"
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints the branch, any operation in progress and stashes, how it stands against
+    /// its upstream with a hint at what to do about it, and its last commit. `dirty` is
+    /// whether tracked files have changes, which a rebase would have to set aside.
+    fn print_branch_status(&self, branch: &BranchInfo, dirty: bool) -> Result<()> {
+        let now = Utc::now();
+        // A detached HEAD has no upstream, so there's no tracking line to print
+        let Some(head) = &branch.head else {
+            match &branch.commit {
+                Some(commit) => writeln!(
+                    self.out(),
+                    "{} {} ({}), {}",
+                    "HEAD detached at".red(),
+                    commit.short_id,
+                    commit.subject,
+                    age(commit.time, now)
+                )?,
+                None => writeln!(self.out(), "Not currently on any branch.")?,
+            }
+            self.print_in_progress(branch)?;
+            writeln!(self.out())?;
+            return Ok(());
+        };
+        writeln!(self.out(), "On branch {}", head)?;
+        self.print_in_progress(branch)?;
+        // Like `git status`, there's no tracking info or last commit to show yet
+        if branch.unborn {
+            writeln!(self.out())?;
+            writeln!(self.out(), "No commits yet")?;
+            writeln!(self.out())?;
+            return Ok(());
+        }
+
+        match (&branch.upstream, branch.ahead_behind) {
+            (None, _) => writeln!(self.out(), "Your branch is not tracking a remote branch.")?,
+            (Some(upstream), None) => {
+                writeln!(
+                    self.out(),
+                    "Your branch is based on '{}', but the upstream is gone.",
+                    upstream
+                )?;
+                writeln!(
+                    self.out(),
+                    "  (use \"git branch --unset-upstream\" to fixup)"
+                )?;
+            }
+            (Some(upstream), Some((0, 0))) => {
+                writeln!(self.out(), "Your branch is up to date with '{}'.", upstream)?
+            }
+            (Some(upstream), Some((ahead, 0))) => {
+                writeln!(
+                    self.out(),
+                    "Your branch is ahead of '{}' by {} commit{}.",
+                    upstream,
+                    ahead,
+                    plural(ahead)
+                )?;
+                writeln!(
+                    self.out(),
+                    "  (use \"git push\" to publish your local commits)"
+                )?;
+            }
+            (Some(upstream), Some((0, behind))) => {
+                writeln!(
+                    self.out(),
+                    "Your branch is behind '{}' by {} commit{}, and can be fast-forwarded.",
+                    upstream,
+                    behind,
+                    plural(behind)
+                )?;
+                writeln!(
+                    self.out(),
+                    "  (use \"git pull\" to update your local branch)"
+                )?;
+            }
+            (Some(upstream), Some((ahead, behind))) => {
+                writeln!(self.out(), "Your branch and '{}' have diverged,", upstream)?;
+                writeln!(
+                    self.out(),
+                    "and have {} and {} different commits each, respectively.",
+                    ahead,
+                    behind
+                )?;
+                writeln!(
+                    self.out(),
+                    "  ({} ahead / {} behind — consider \"git pull --rebase{}\" to replay your commits on top of theirs)",
+                    ahead,
+                    behind,
+                    // Rebasing refuses to start with uncommitted changes otherwise
+                    if dirty { " --autostash" } else { "" }
+                )?;
+            }
+        }
+        if let Some(staleness) = staleness(branch, now) {
+            writeln!(
+                self.out(),
+                "  ({}; use \"git hud --fetch\" to refresh)",
+                staleness
+            )?;
+        }
+        if let Some(incoming) = &self.incoming {
+            writeln!(self.out(), "Incoming: {}", incoming)?;
+        }
+        if let Some(commit) = &branch.commit {
+            writeln!(
+                self.out(),
+                "Last commit: {} {} — '{}'",
+                commit.short_id.yellow(),
+                age(commit.time, now),
+                commit.subject
+            )?;
+        }
+
+        writeln!(self.out())?;
+        Ok(())
+    }
+
+    /// The whole status view of a clean tree in one line, like
+    /// `✔ clean · main ↑0 ↓0 · last commit 1h ago`, keeping anything that needs attention.
+    fn print_clean(&self, branch: &BranchInfo) -> Result<()> {
+        let now = Utc::now();
+        let mut parts = vec![format!("{} clean", "✔".green())];
+        let mut head = match (&branch.head, &branch.commit) {
+            (Some(head), _) => head.clone(),
+            (None, Some(commit)) => format!("{} {}", "detached at".red(), commit.short_id),
+            (None, None) => "no branch".to_string(),
+        };
+        if branch.unborn {
+            head.push_str(" (no commits yet)");
+        }
+        match (&branch.upstream, branch.ahead_behind) {
+            (Some(_), Some((ahead, behind))) => head.push_str(&format!(" ↑{} ↓{}", ahead, behind)),
+            (Some(_), None) => head.push_str(" (upstream gone)"),
+            (None, _) => {}
+        }
+        parts.push(head);
+        if let Some(operation) = branch.operation {
+            parts.push(
+                format!("{} in progress", operation_name(operation))
+                    .yellow()
+                    .to_string(),
+            );
+        }
+        if branch.stashes > 0 {
+            parts.push(stashes(branch.stashes));
+        }
+        parts.extend(staleness(branch, now));
+        if let Some(commit) = &branch.commit {
+            parts.push(format!("last commit {}", age(commit.time, now)));
+        }
+        writeln!(self.out(), "{}", parts.join(" · "))?;
+        if let Some(incoming) = &self.incoming {
+            writeln!(self.out(), "Incoming: {}", incoming)?;
+        }
+        Ok(())
+    }
+
+    /// Prints the operation waiting to be finished and how many stashes there are, which
+    /// are easy to forget about.
+    fn print_in_progress(&self, branch: &BranchInfo) -> Result<()> {
+        if let Some(operation) = branch.operation {
+            writeln!(
+                self.out(),
+                "{}",
+                format!("You are in the middle of a {}.", operation_name(operation)).yellow()
+            )?;
+            let command = operation.command();
+            match operation {
+                Operation::Bisect => {
+                    writeln!(self.out(), "  (use \"git bisect reset\" when you're done)")?
+                }
+                Operation::Merge => writeln!(
+                    self.out(),
+                    "  (fix conflicts and run \"git commit\", or use \"git merge --abort\" to stop)"
+                )?,
+                _ => writeln!(
+                    self.out(),
+                    "  (use \"git {0} --continue\" to go on, or \"git {0} --abort\" to stop)",
+                    command
+                )?,
+            }
+        }
+        if branch.stashes > 0 {
+            writeln!(
+                self.out(),
+                "You have {} (use \"git stash list\" to see them)",
+                stashes(branch.stashes)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn print_staged_changes(&self, status: &Status) -> Result<()> {
+        writeln!(self.out(), "Changes to be committed:")?;
+        writeln!(
+            self.out(),
+            "  (use \"git restore --staged <file>...\" to unstage)"
+        )?;
+
+        for entry in &status.entries {
+            if let Some(code) = &entry.index {
+                let status_text = code.describe();
+                let path = &entry.display_path;
+
+                if let Some(orig_path) = &entry.original_display_path {
+                    writeln!(
+                        self.out(),
+                        "\t{}: {} -> {}",
+                        status_text.green(),
+                        orig_path,
+                        path
+                    )?;
+                } else {
+                    writeln!(self.out(), "\t{}: {}", status_text.green(), path)?;
+                }
+            }
+        }
+        writeln!(self.out())?;
+        Ok(())
+    }
+
+    fn print_unstaged_changes(&self, status: &Status) -> Result<()> {
+        writeln!(self.out(), "Changes not staged for commit:")?;
+        writeln!(
+            self.out(),
+            "  (use \"git add <file>...\" to update what will be committed)"
+        )?;
+        writeln!(
+            self.out(),
+            "  (use \"git restore <file>...\" to discard changes in working directory)"
+        )?;
+
+        for entry in &status.entries {
+            if let Some(code) = entry
+                .worktree
+                .as_ref()
+                .filter(|code| !matches!(code, StatusCode::Untracked))
+            {
+                let status_text = code.describe();
+                let path = &entry.display_path;
+
+                // Here we'd add the summary when implemented
+                writeln!(self.out(), "\t{}: {}", status_text.red(), path)?;
+            }
+        }
+        writeln!(self.out())?;
+        Ok(())
+    }
+
+    fn print_untracked_files(&self, status: &Status) -> Result<()> {
+        let untracked: Vec<_> = status
+            .entries
+            .iter()
+            .filter(|e| matches!(e.worktree, Some(StatusCode::Untracked)))
+            .collect();
+
+        if !untracked.is_empty() {
+            writeln!(self.out(), "Untracked files:")?;
+            writeln!(
+                self.out(),
+                "  (use \"git add <file>...\" to include in what will be committed)"
+            )?;
+
+            for entry in untracked {
+                writeln!(self.out(), "\t{}", entry.display_path.red())?;
+            }
+            writeln!(self.out())?;
+        }
+        Ok(())
+    }
+
+    pub fn display_explanation(&self, entry: &StatusEntry, explanation: &str) -> Result<()> {
+        self.display_explanation_header(entry);
+        println!("{}", explanation);
+        Ok(())
+    }
+
+    /// Prints the file an explanation is about, for explanations printed as they stream in.
+    pub fn display_explanation_header(&self, entry: &StatusEntry) {
+        println!("{}", self.format_entry_heading(entry));
+        if self.profile == OutputProfile::Standard {
+            println!();
+        }
+    }
+
+    fn format_entry_heading(&self, entry: &StatusEntry) -> String {
+        let status_text = entry.status().describe();
+        if self.profile == OutputProfile::Accessible {
+            let label = match entry.worktree {
+                None => "STAGED",
+                Some(_) => "UNSTAGED",
+            };
+            return format!("{}: {}: {}", label, status_text, entry.display_path);
+        }
+        // Green only when the whole change is staged, like `git status`'s sections
+        let status_text = if entry.worktree.is_none() {
+            status_text.green()
+        } else {
+            status_text.red()
+        };
+        format!("{}: {}", status_text, entry.display_path.bold())
+    }
+
+    /// Prints the top of `git hud show`: the file, then each changed side's summary and
+    /// notes, labeled since a file can have both.
+    pub fn display_show_header(&self, entry: &StatusEntry, sides: &[&FileWithSummary]) {
+        println!("{}", self.format_entry_heading(entry));
+        for file in sides {
+            let side = match file.staged {
+                true => "Staged",
+                false => "Unstaged",
+            };
+            let summary = self
+                .format_summary(file)
+                .unwrap_or_else(|| "(no summary)".dimmed().to_string());
+            println!("{}: {}", side, summary);
+        }
+        println!();
+    }
+
+    pub fn display_diff_heading(&self, staged: bool) {
+        match staged {
+            true => println!("{}", "Staged changes:".bold()),
+            // The unstaged diff always follows the staged one
+            false => println!("\n{}", "Unstaged changes:".bold()),
+        }
+    }
+
+    /// Prints a diff colored like `git diff`. With `word_diff`, replaced lines are shown
+    /// once with the words that changed marked inline, like `git diff --word-diff`.
+    pub fn display_diff(&self, path: &str, diff: &str, word_diff: bool) {
+        for line in self.format_diff(path, diff, word_diff) {
+            println!("{}", line);
+        }
+    }
+
+    /// A diff's lines, formatted; unchanged code is syntax highlighted when `path` is in a
+    /// language that's known.
+    fn format_diff(&self, path: &str, diff: &str, word_diff: bool) -> Vec<String> {
+        let mut highlighter = match colored::control::SHOULD_COLORIZE.should_colorize() {
+            true => syntax::for_path(Path::new(path)),
+            false => None,
+        };
+        let mut lines = Vec::new();
+        for block in word_diff::blocks(diff) {
+            if let (Block::Replaced { added, .. }, Some(highlighter)) = (&block, &mut highlighter) {
+                for line in added {
+                    highlighter.read(line);
+                }
+            }
+            match block {
+                Block::Line(line) => {
+                    lines.push(self.format_diff_line(line, word_diff, highlighter.as_mut()))
+                }
+                Block::Replaced { removed, added } if word_diff => {
+                    let (old, new) = (removed.join("\n"), added.join("\n"));
+                    let text: String = word_diff::diff_words(&old, &new)
+                        .iter()
+                        .map(|change| self.format_word_change(change))
+                        .collect();
+                    lines.extend(text.split('\n').map(str::to_string));
+                }
+                Block::Replaced { removed, added } => {
+                    lines.extend(self.highlight_replaced(&removed, &added))
+                }
+            }
+        }
+        lines
+    }
+
+    /// Replaced lines as `-` and `+` lines, with the words that changed standing out from
+    /// the rest of the line, like git's `diff-highlight`. When the two sides have nothing
+    /// in common, highlighting would cover everything, so the lines are left as they are.
+    fn highlight_replaced(&self, removed: &[&str], added: &[&str]) -> Vec<String> {
+        let (old, new) = (removed.join("\n"), added.join("\n"));
+        let changes = word_diff::diff_words(&old, &new);
+        let shared = changes
+            .iter()
+            .any(|change| matches!(change, Change::Same(text) if !text.trim().is_empty()));
+        if !shared {
+            let removed = removed.iter().map(|line| format!("-{}", line).red());
+            let added = added.iter().map(|line| format!("+{}", line).green());
+            return removed.chain(added).map(|line| line.to_string()).collect();
+        }
+
+        let (mut old_side, mut new_side) = (String::new(), String::new());
+        for change in &changes {
+            // Colored line by line, since the lines are split apart afterwards
+            let style = |text: &str, color: Color, emphasize: bool| {
+                text.split('\n')
+                    .map(|line| match emphasize {
+                        true => line.color(color).reversed().to_string(),
+                        false => line.color(color).to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            match change {
+                Change::Same(text) => {
+                    old_side.push_str(&style(text, Color::Red, false));
+                    new_side.push_str(&style(text, Color::Green, false));
+                }
+                Change::Removed(text) => old_side.push_str(&style(text, Color::Red, true)),
+                Change::Added(text) => new_side.push_str(&style(text, Color::Green, true)),
+            }
+        }
+        let sign = |sign: &str, color: Color| sign.color(color).to_string();
+        let old_lines = match removed.is_empty() {
+            true => Vec::new(),
+            false => old_side
+                .split('\n')
+                .map(|line| format!("{}{}", sign("-", Color::Red), line))
+                .collect(),
+        };
+        let new_lines = match added.is_empty() {
+            true => Vec::new(),
+            false => new_side
+                .split('\n')
+                .map(|line| format!("{}{}", sign("+", Color::Green), line))
+                .collect(),
+        };
+        old_lines.into_iter().chain(new_lines).collect()
+    }
+
+    /// Prints a file's diff under its entry in the status view, if `--patch` asked for it.
+    fn print_patch(&self, file: &FileWithSummary) -> Result<()> {
+        let Some(diff) = file.diff.as_deref().filter(|_| self.patch) else {
+            return Ok(());
+        };
+        let lines = self.format_diff(&file.path, diff, false);
+        match self.profile {
+            OutputProfile::Standard => {
+                for line in lines {
+                    writeln!(self.out(), "\t  {}", line)?;
+                }
+            }
+            // Diff lines already say what they are with their leading `+` or `-`
+            OutputProfile::Accessible => {
+                for line in lines {
+                    writeln!(self.out(), "{}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn format_diff_line(
+        &self,
+        line: &str,
+        word_diff: bool,
+        highlighter: Option<&mut Highlighter>,
+    ) -> String {
+        if line.starts_with("@@") {
+            if let Some(highlighter) = highlighter {
+                highlighter.reset();
+            }
+            return line.cyan().to_string();
+        }
+        let is_header = [
+            "diff ",
+            "index ",
+            "--- ",
+            "+++ ",
+            "new file",
+            "deleted file",
+        ]
+        .iter()
+        .any(|prefix| line.starts_with(prefix));
+        if is_header {
+            return line.bold().to_string();
+        }
+        let (marker, code) = match line.strip_prefix(' ') {
+            // Word diffs don't mark unchanged lines, since changes are marked inline
+            Some(code) if word_diff => ("", code),
+            Some(code) => (" ", code),
+            None => {
+                if let (Some(added), Some(highlighter)) = (line.strip_prefix('+'), highlighter) {
+                    highlighter.read(added);
+                }
+                return line.to_string();
+            }
+        };
+        match highlighter {
+            Some(highlighter) => format!("{}{}", marker, highlighter.highlight(code)),
+            None => format!("{}{}", marker, code),
+        }
+    }
+
+    /// A word diff's change, colored, or bracketed like `--word-diff=plain` when color is
+    /// off, so it isn't only color telling removals from additions. Lines are colored one
+    /// by one so they can be split apart.
+    fn format_word_change(&self, change: &Change) -> String {
+        let colorize = colored::control::SHOULD_COLORIZE.should_colorize();
+        let format_line = |line: &str| match (change, colorize) {
+            _ if line.is_empty() => String::new(),
+            (Change::Same(_), _) => line.to_string(),
+            (Change::Removed(_), true) => line.red().strikethrough().to_string(),
+            (Change::Added(_), true) => line.green().to_string(),
+            (Change::Removed(_), false) => format!("[-{}-]", line),
+            (Change::Added(_), false) => format!("{{+{}+}}", line),
+        };
+        let text = match change {
+            Change::Same(text) | Change::Removed(text) | Change::Added(text) => text,
+        };
+        text.split('\n')
+            .map(format_line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn display_amend_advice(&self, head: &CommitInfo, advice: &AmendAdvice) -> Result<()> {
+        println!("HEAD is {} {}", head.short_id.yellow(), head.subject);
+        println!();
+        if advice.should_amend {
+            println!("{}", "Amending looks right.".green());
+        } else {
+            println!("{}", "This looks like a separate change.".red());
+        }
+        if !advice.reason.is_empty() {
+            println!("  {}", advice.reason);
+        }
+
+        if let Some(ref message) = advice.message {
+            println!();
+            println!("Suggested commit message:");
+            println!("  (use \"git commit --amend\" to update HEAD)");
+            for line in message.lines() {
+                println!("\t{}", line);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn display_commit_summaries(
+        &self,
+        upstream: &str,
+        commits: &[CommitWithSummary],
+        overview: &str,
+    ) -> Result<()> {
+        println!("{} commit(s) ahead of {}:", commits.len(), upstream.bold());
+        for item in commits {
+            println!(
+                "\t{} {} ({})",
+                item.commit.short_id.yellow(),
+                item.commit.subject,
+                item.summary
+            );
+        }
+        println!();
+        println!("{}", overview);
+        Ok(())
+    }
+
+    pub fn display_tree_diff(
+        &self,
+        from: &str,
+        to: &str,
+        files: &[TreeDiffWithSummary],
+        overview: &str,
+    ) -> Result<()> {
+        println!("Changes from {} to {}:", from.bold(), to.bold());
+
+        for file in files {
+            let status_text = file.entry.status.describe();
+
+            if let Some(ref orig_path) = file.entry.original_path {
+                print!(
+                    "\t{}: {} -> {}",
+                    status_text.yellow(),
+                    orig_path,
+                    file.entry.path
+                );
+            } else {
+                print!("\t{}: {}", status_text.yellow(), file.entry.path);
+            }
+
+            if let Some(ref summary) = file.summary {
+                println!(" ({})", summary);
+            } else {
+                println!();
+            }
+        }
+        if !overview.is_empty() {
+            println!();
+            println!("{}", overview);
+        }
+        Ok(())
+    }
+
+    pub fn display_branch_containment(
+        &self,
+        commit: &CommitInfo,
+        summary: &str,
+        branches: &[(String, Containment)],
+    ) -> Result<()> {
+        println!(
+            "{} {} ({})",
+            commit.short_id.yellow(),
+            commit.subject,
+            summary
+        );
+        println!();
+
+        let mut present = Vec::new();
+        let mut missing = Vec::new();
+        for (branch, containment) in branches {
+            match containment {
+                Containment::Contains => present.push(branch.clone()),
+                Containment::Equivalent(commit) => {
+                    present.push(format!("{} (as {:.7})", branch, commit))
+                }
+                Containment::Missing => missing.push(branch.clone()),
+            }
+        }
+        if !present.is_empty() {
+            println!("Already in {}", present.join(", ").green());
+        }
+        if !missing.is_empty() {
+            println!("Missing from {}", missing.join(", ").red());
+        }
+        Ok(())
+    }
+
+    pub fn display_reflog(
+        &self,
+        entries: &[ReflogEntry],
+        summaries: &HashMap<String, String>,
+    ) -> Result<()> {
+        println!("Recent HEAD movements:");
+        if entries.iter().any(|e| e.dangling) {
+            println!("  (use \"git branch <name> <commit>\" to recover a dangling commit)");
+        }
+
+        for entry in entries {
+            print!(
+                "\t{} {} {} [{}]",
+                entry.selector,
+                entry.short_id.yellow(),
+                entry.subject,
+                entry.action.dimmed()
+            );
+            if entry.dangling {
+                print!(" {}", "[dangling]".red());
+            }
+
+            if let Some(summary) = summaries.get(&entry.id) {
+                println!(" ({})", summary);
+            } else {
+                println!();
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints a standup update by itself, so it can be copied as is.
+    pub fn display_standup(&self, report: &str) -> Result<()> {
+        println!("{}", report.trim());
+        Ok(())
+    }
+
+    /// Confirms a report went to the `notify.webhook` channel.
+    pub fn display_posted(&self) -> Result<()> {
+        println!();
+        println!("{}", "Posted to the webhook".dimmed());
+        Ok(())
+    }
+
+    pub fn display_posted_to(&self, target: &str) -> Result<()> {
+        println!();
+        println!("{}", format!("Posted to {}", target).dimmed());
+        Ok(())
+    }
+
+    pub fn display_release_notes(
+        &self,
+        tag: &str,
+        previous: Option<&str>,
+        commit_count: usize,
+        notes: &str,
+    ) -> Result<()> {
+        match previous {
+            Some(previous) => println!(
+                "Release notes for {} ({} commit(s) since {}):",
+                tag.bold(),
+                commit_count,
+                previous
+            ),
+            None => println!(
+                "Release notes for {} ({} commit(s)):",
+                tag.bold(),
+                commit_count
+            ),
+        }
+        println!();
+        println!("{}", notes);
+        println!();
+        Ok(())
+    }
+
+    pub fn display_audit_records(&self, records: &[AuditRecord]) -> Result<()> {
+        for record in records {
+            println!(
+                "{} {} {} (~{} tokens in, ~{} out, {}ms)",
+                record
+                    .timestamp
+                    .format("%Y-%m-%d %H:%M:%S UTC")
+                    .to_string()
+                    .yellow(),
+                record.provider.bold(),
+                match record.error {
+                    Some(_) => "failed".red(),
+                    None => "ok".green(),
+                },
+                record.prompt_tokens,
+                record.response_tokens,
+                record.duration_ms
+            );
+            println!("{}", "Prompt:".dimmed());
+            println!("{}", record.prompt);
+            match (&record.response, &record.error) {
+                (Some(response), _) => {
+                    println!("{}", "Response:".dimmed());
+                    println!("{}", response);
+                }
+                (None, Some(error)) => {
+                    println!("{}", "Error:".dimmed());
+                    println!("{}", error);
+                }
+                (None, None) => {}
+            }
+            println!();
+        }
+        Ok(())
+    }
+
+    pub fn display_config(
+        &self,
+        layers: &[Layer],
+        merged: &toml::Table,
+        unknown_keys: &[String],
+    ) -> Result<()> {
+        if layers.is_empty() {
+            println!("Nothing is configured; every setting has its default.");
+            return Ok(());
+        }
+        println!("Config layers, lowest precedence first:");
+        for layer in layers {
+            println!("\t{}", layer.source);
+        }
+
+        println!();
+        println!("Effective settings (anything else has its default):");
+        let settings: Vec<_> = config::leaves(merged)
+            .into_iter()
+            .filter(|(key, _)| {
+                !unknown_keys
+                    .iter()
+                    .any(|unknown| key == unknown || key.starts_with(&format!("{}.", unknown)))
+            })
+            .map(|(key, value)| (format!("{} = {}", key, value), key))
+            .collect();
+        let column = settings
+            .iter()
+            .map(|(setting, _)| width::width(setting))
+            .max()
+            .unwrap_or(0);
+        for (setting, key) in &settings {
+            let source = config::source_of(layers, key).map(|source| source.to_string());
+            println!(
+                "\t{}  {}",
+                width::pad(setting, column),
+                format!("({})", source.unwrap_or_default()).dimmed()
+            );
+        }
+
+        if !unknown_keys.is_empty() {
+            println!();
+            println!("{}", "Unknown keys, which are ignored:".yellow());
+            for key in unknown_keys {
+                let source = config::source_of(layers, key).map(|source| source.to_string());
+                println!(
+                    "\t{}\t{}",
+                    key.yellow(),
+                    format!("({})", source.unwrap_or_default()).dimmed()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn display_provider_ok(&self, provider: &str, model: &str) -> Result<()> {
+        println!();
+        println!("{} {} ({}) answered", "ok:".green(), provider, model);
+        Ok(())
+    }
+
+    /// Lists what `--strict` found.
+    pub fn display_findings(&self, findings: &[Finding]) -> Result<()> {
+        let location = |finding: &Finding| match finding.line {
+            Some(line) => format!("{}:{}", finding.path, line),
+            None => finding.path.clone(),
+        };
+        if self.profile == OutputProfile::Accessible {
+            for finding in findings {
+                println!(
+                    "SUSPICIOUS: {}: {}: {}",
+                    location(finding),
+                    finding.detector.label(),
+                    finding.detail.trim()
+                );
+            }
+            return Ok(());
+        }
+        println!("Suspicious changes:");
+        let column = findings
+            .iter()
+            .map(|finding| width::width(&location(finding)))
+            .max()
+            .unwrap_or(0);
+        for finding in findings {
+            println!(
+                "\t{}  {} {}",
+                width::pad(&location(finding), column).red(),
+                format!("{}:", finding.detector.label()).bold(),
+                width::ellipsize(finding.detail.trim(), SUMMARY_MAX_WIDTH)
+            );
+        }
+        Ok(())
+    }
+
+    /// Prints a crash report as Markdown, to paste into an issue.
+    pub fn display_bug_report(&self, report: &Report, path: &Path) -> Result<()> {
+        println!("<!-- From {} -->", path.display());
+        println!("**git-hud {}** {}", report.version, report.kind);
+        println!();
+        println!("- When: {}", report.timestamp.format("%Y-%m-%d %H:%M UTC"));
+        println!("- Platform: {}", report.platform);
+        println!("- Git: {}", report.git.as_deref().unwrap_or("not found"));
+        println!("- Command: `git hud {}`", report.args.join(" "));
+        println!();
+        println!("```");
+        println!("{}", report.message.trim());
+        println!("```");
+        println!();
+        println!("<details><summary>Config (secrets redacted)</summary>");
+        println!();
+        println!("```toml");
+        println!("{}", report.config.trim());
+        println!("```");
+        println!("</details>");
+        if !report.log.is_empty() {
+            println!();
+            println!("<details><summary>Recent log</summary>");
+            println!();
+            println!("```");
+            report.log.iter().for_each(|line| println!("{}", line));
+            println!("```");
+            println!("</details>");
+        }
+        Ok(())
+    }
+
+    pub fn display_doctor(&self, checks: &[Check]) -> Result<()> {
+        let column = checks
+            .iter()
+            .map(|check| check.name.len())
+            .max()
+            .unwrap_or(0);
+        for check in checks {
+            let (label, detail, fix) = match &check.outcome {
+                Outcome::Ok(detail) => ("ok".green(), detail, None),
+                Outcome::Warning(detail, fix) => ("warning".yellow(), detail, Some(fix)),
+                Outcome::Failed(detail, fix) => ("failed".red(), detail, Some(fix)),
+                Outcome::Skipped(reason) => ("skipped".dimmed(), reason, None),
+            };
+            match self.profile {
+                OutputProfile::Standard => println!(
+                    "{}  {:<7}  {}",
+                    width::pad(check.name, column),
+                    label,
+                    detail
+                ),
+                OutputProfile::Accessible => {
+                    println!("{}: {}: {}", label.to_uppercase(), check.name, detail)
+                }
+            }
+            if let Some(fix) = fix {
+                match self.profile {
+                    OutputProfile::Standard => {
+                        println!("{}  {:<7}  {}", " ".repeat(column), "", fix.dimmed())
+                    }
+                    OutputProfile::Accessible => println!("FIX: {}", fix),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn display_snapshot_saved(&self, snapshot: &Snapshot) -> Result<()> {
+        println!(
+            "Saved snapshot '{}' of {} changed file(s)",
+            snapshot.name.bold(),
+            snapshot.file_count()
+        );
+        Ok(())
+    }
+
+    pub fn display_snapshot_changes(
+        &self,
+        snapshot: &Snapshot,
+        changes: &SnapshotChanges,
+    ) -> Result<()> {
+        println!(
+            "Since snapshot '{}' ({}):",
+            snapshot.name.bold(),
+            snapshot
+                .timestamp
+                .format("%Y-%m-%d %H:%M UTC")
+                .to_string()
+                .yellow()
+        );
+        if changes.is_empty() {
+            println!("\t{}", "nothing has changed".dimmed());
+            return Ok(());
+        }
+
+        let sections = [
+            ("more file(s) changed", &changes.added),
+            ("file(s) changed further", &changes.changed),
+            ("file(s) no longer changed", &changes.gone),
+        ];
+        for (heading, entries) in sections {
+            if entries.is_empty() {
+                continue;
+            }
+            // Sides of the same file are listed together, but it's one file
+            let mut paths: Vec<_> = entries.iter().map(|entry| &entry.path).collect();
+            paths.dedup();
+            println!();
+            println!("{} {}:", paths.len(), heading);
+            for entry in entries.iter() {
+                println!("\t{}", self.format_snapshot_entry(entry));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn display_snapshots(&self, snapshots: &[Snapshot]) -> Result<()> {
+        if snapshots.is_empty() {
+            println!("No snapshots; save one with `git hud snapshot save <name>`");
+            return Ok(());
+        }
+        for snapshot in snapshots {
+            let branch = snapshot
+                .branch
+                .as_deref()
+                .map(|branch| format!(" on {}", branch))
+                .unwrap_or_default();
+            println!(
+                "{} {}{}, {} changed file(s)",
+                snapshot.name.bold(),
+                snapshot
+                    .timestamp
+                    .format("%Y-%m-%d %H:%M UTC")
+                    .to_string()
+                    .yellow(),
+                branch,
+                snapshot.file_count()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn display_discarded(&self, paths: &[PathBuf]) -> Result<()> {
+        println!(
+            "Discarded the changes to {} file(s); `git hud undo` brings them back",
+            paths.len()
+        );
+        self.display_paths(paths);
+        Ok(())
+    }
+
+    pub fn display_undone(&self, paths: &[PathBuf]) -> Result<()> {
+        println!("Brought back the changes to {} file(s)", paths.len());
+        self.display_paths(paths);
+        Ok(())
+    }
+
+    fn display_paths(&self, paths: &[PathBuf]) {
+        for path in paths {
+            match self.profile {
+                OutputProfile::Standard => println!("\t{}", path.display().to_string().yellow()),
+                OutputProfile::Accessible => println!("FILE: {}", path.display()),
+            }
+        }
+    }
+
+    fn format_snapshot_entry(&self, entry: &SnapshotEntry) -> String {
+        let status_text = entry.status.describe();
+        // Said in words as well as color, since a file can be listed once for each side
+        let (status_text, side) = match entry.staged {
+            true => (status_text.green(), " (staged)"),
+            false => (status_text.red(), ""),
+        };
+        let summary = entry
+            .summary
+            .as_deref()
+            .map(|summary| format!(" ({})", width::ellipsize(summary, SUMMARY_MAX_WIDTH)))
+            .unwrap_or_default();
+        format!("{}: {}{}{}", status_text, entry.path, side, summary)
+    }
+
+    pub fn display_stats(&self, stats: &Stats, days: u32, audit_enabled: bool) -> Result<()> {
+        println!("Runs in the last {} day(s):", days);
+        if stats.runs.is_empty() {
+            println!("\t{}", "no runs".dimmed());
+        }
+        for (day, runs) in &stats.runs {
+            println!(
+                "\t{} {} run(s), {} summaries ({} cached, {} updated, {} new), ~{} tokens spent, ~{} saved by the cache",
+                day.to_string().yellow(),
+                runs.runs,
+                runs.files(),
+                runs.cached,
+                runs.updated,
+                runs.summarized,
+                budget::format_tokens(runs.tokens),
+                budget::format_tokens(runs.saved_tokens)
+            );
+        }
+        if let Some(rate) = stats.cache_hit_rate() {
+            println!("Cache hit rate: {:.0}%", rate * 100.0);
+        }
+        println!();
+
+        println!("Usage by provider:");
+        if stats.usage.is_empty() {
+            match audit_enabled {
+                true => println!("\t{}", "no requests".dimmed()),
+                false => println!(
+                    "\t{}",
+                    "usage comes from the audit log; enable it with `enabled = true` under [audit]"
+                        .dimmed()
+                ),
+            }
+        }
+        for (provider, usage) in &stats.usage {
+            let total: f64 = usage.values().map(|day| day.cost).sum();
+            println!("\t{} (~${:.2} total)", provider.bold(), total);
+            for (day, day_usage) in usage {
+                print!(
+                    "\t  {} {} request(s), ~{} tokens in, ~{} out, ~${:.2}",
+                    day.to_string().yellow(),
+                    day_usage.requests,
+                    day_usage.input_tokens,
+                    day_usage.output_tokens,
+                    day_usage.cost
+                );
+                if day_usage.failures > 0 {
+                    print!(" {}", format!("[{} failed]", day_usage.failures).red());
+                }
+                println!();
+            }
         }
-
         Ok(())
     }
 
-    fn print_branch_status(&self) -> Result<()> {
-        // Get current branch name
-        let branch_output = Command::new("git")
-            .args(["branch", "--show-current"])
-            .output()?;
-
-        let branch_name = String::from_utf8(branch_output.stdout)?.trim().to_string();
-
-        println!("On branch {}", branch_name);
+    pub fn display_dry_run(
+        &self,
+        provider: &str,
+        model: &str,
+        planned: &[PlannedSummary],
+    ) -> Result<()> {
+        println!(
+            "Dry run: summaries would come from {} ({}); nothing was sent",
+            provider.bold(),
+            model
+        );
+        for entry in planned {
+            let plan = match entry.source {
+                Some(SummarySource::Cache) => "cached".green(),
+                Some(SummarySource::Local) => "described locally".green(),
+                Some(SummarySource::Update) => {
+                    format!("update cached summary, ~{} tokens", entry.prompt_tokens).yellow()
+                }
+                Some(SummarySource::Full) => {
+                    format!("summarize, ~{} tokens", entry.prompt_tokens).yellow()
+                }
+                None => "skipped".dimmed(),
+            };
+            println!(
+                "\t{} {} ({})",
+                entry.path,
+                entry.side.label().dimmed(),
+                plan
+            );
+        }
 
-        // Get remote tracking info
-        let remote_output = Command::new("git").args(["status", "-sb"]).output()?;
+        let requests = planned
+            .iter()
+            .filter(|entry| {
+                matches!(
+                    entry.source,
+                    Some(SummarySource::Update | SummarySource::Full)
+                )
+            })
+            .count();
+        let tokens: u32 = planned.iter().map(|entry| entry.prompt_tokens).sum();
+        println!("{} request(s), ~{} prompt tokens", requests, tokens);
+        Ok(())
+    }
 
-        let remote_status = String::from_utf8(remote_output.stdout)?;
+    /// Lists edits that `git status` hides because of index flags, if there are any.
+    pub fn display_hidden_files(&self, hidden: &[HiddenFile]) -> Result<()> {
+        if hidden.is_empty() {
+            return Ok(());
+        }
 
-        // Parse remote status line
-        if let Some(remote_line) = remote_status.lines().next() {
-            if remote_line.contains("[") {
-                let parts: Vec<&str> = remote_line.splitn(2, "[").collect();
-                if let Some(remote_info) = parts.get(1) {
-                    let remote_status = remote_info.trim_end_matches(']');
-                    println!("Your branch is {}", remote_status);
-                }
-            } else if !branch_name.is_empty() {
-                println!("Your branch is not tracking a remote branch.");
+        if self.profile == OutputProfile::Accessible {
+            for file in hidden {
+                println!("HIDDEN: {}: {}", index_flag(file.flag), file.display_path);
             }
+            return Ok(());
         }
 
+        println!("Edited but hidden from git status by index flags:");
+        println!("  (use \"git update-index --no-assume-unchanged <file>...\" or \"--no-skip-worktree\" to track changes again)");
+        for file in hidden {
+            println!(
+                "\t{}: {}",
+                index_flag(file.flag).yellow(),
+                file.display_path
+            );
+        }
         println!();
         Ok(())
     }
 
-    fn print_staged_changes(&self, status: &Status) -> Result<()> {
-        println!("Changes to be committed:");
-        println!("  (use \"git restore --staged <file>...\" to unstage)");
-
-        for entry in &status.entries {
-            if entry.staged {
-                let status_text = self.format_status(&entry.status);
-                let path = format!("{}", entry.display_path);
+    pub fn display_warning(&self, message: &str) {
+        if self.profile == OutputProfile::Accessible {
+            eprintln!("WARNING: {}", message);
+            return;
+        }
+        eprintln!("{} {}", "warning:".yellow(), message);
+    }
 
-                if let Some(orig_path) = &entry.original_path {
-                    println!("\t{}: {} -> {}", status_text.green(), orig_path, path);
-                } else {
-                    println!("\t{}: {}", status_text.green(), path);
-                }
+    /// A file's parenthesized summary and notes about it, like a mode change or that its
+    /// text had to be re-encoded.
+    fn format_summary(&self, file: &FileWithSummary) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(summary) = &file.summary {
+            let ranges = match self.line_ranges {
+                true => self.format_line_ranges(&file.line_ranges),
+                false => None,
+            };
+            parts.push(format!(
+                "({}{})",
+                ranges.map_or(String::new(), |ranges| format!("{}: ", ranges)),
+                width::ellipsize(summary, SUMMARY_MAX_WIDTH)
+            ));
+            if let Some(encoding) = file.encoding {
+                parts.push(
+                    format!("(re-encoded from {})", encoding)
+                        .dimmed()
+                        .to_string(),
+                );
             }
         }
-        println!();
-        Ok(())
+        if file.not_summarized {
+            parts.push(match self.profile {
+                OutputProfile::Standard => "(not summarized — run with --all)".dimmed().to_string(),
+                OutputProfile::Accessible => "(not summarized, run with --all)".to_string(),
+            });
+        }
+        if let Some((old, new)) = &file.mode_change {
+            match self.profile {
+                OutputProfile::Standard => parts.push(format!("(mode {} → {})", old, new)),
+                OutputProfile::Accessible => parts.push(format!("(mode {} to {})", old, new)),
+            }
+        }
+        (!parts.is_empty()).then(|| parts.join(" "))
     }
 
-    fn print_unstaged_changes(&self, status: &Status) -> Result<()> {
-        println!("Changes not staged for commit:");
-        println!("  (use \"git add <file>...\" to update what will be committed)");
-        println!("  (use \"git restore <file>...\" to discard changes in working directory)");
+    /// Where a file's changes are, like `L12, L45–80`, or `lines 12, 45 to 80` for screen
+    /// readers. Past the first few, the rest are only counted.
+    fn format_line_ranges(&self, ranges: &[LineRange]) -> Option<String> {
+        const SHOWN: usize = 3;
+        let (prefix, to) = match self.profile {
+            OutputProfile::Standard => ("L", "–"),
+            OutputProfile::Accessible => ("", " to "),
+        };
+        let mut shown: Vec<String> = ranges
+            .iter()
+            .take(SHOWN)
+            .map(|range| match range.start == range.end {
+                true => format!("{}{}", prefix, range.start),
+                false => format!("{}{}{}{}", prefix, range.start, to, range.end),
+            })
+            .collect();
+        if ranges.len() > SHOWN {
+            shown.push(format!("{} more", ranges.len() - SHOWN));
+        }
+        let shown = shown.join(", ");
+        match (self.profile, ranges) {
+            (_, []) => None,
+            (OutputProfile::Standard, _) => Some(shown),
+            (OutputProfile::Accessible, [range]) if range.start == range.end => {
+                Some(format!("line {}", shown))
+            }
+            (OutputProfile::Accessible, _) => Some(format!("lines {}", shown)),
+        }
+    }
 
-        for entry in &status.entries {
-            if !entry.staged && !matches!(entry.status, StatusCode::Untracked) {
-                let status_text = self.format_status(&entry.status);
-                let path = format!("{}", entry.display_path);
+    /// An entry's status and path as printed, uncolored, with the path part on its own.
+    fn entry_label(&self, file: &FileWithSummary) -> (String, String) {
+        let path = match &file.original_path {
+            Some(original) => format!("{} -> {}", original, file.path),
+            None => file.path.clone(),
+        };
+        (format!("{}: {}", file.status.describe(), path), path)
+    }
 
-                // Here we'd add the summary when implemented
-                println!("\t{}: {}", status_text.red(), path);
+    /// `text`, a file's path as printed, as a link to the file at its first change when
+    /// paths are links. Screen readers get plain text.
+    fn linked(&self, file: &FileWithSummary, text: &str) -> Result<String> {
+        match (&self.links, self.profile) {
+            (Some(links), OutputProfile::Standard) => {
+                let line = file.line_ranges.first().map_or(1, |range| range.start);
+                Ok(hyperlinks::link(text, &links.url(&file.abs_path, line)?))
             }
+            _ => Ok(text.to_string()),
         }
-        println!();
-        Ok(())
     }
 
-    fn print_untracked_files(&self, status: &Status) -> Result<()> {
-        let untracked: Vec<_> = status
-            .entries
+    /// The column summaries in a section line up at: just past its widest entry, unless
+    /// that's so wide it would push every summary off to the right.
+    fn summary_column(&self, files: &[&FileWithSummary]) -> usize {
+        files
             .iter()
-            .filter(|e| matches!(e.status, StatusCode::Untracked))
-            .collect();
-
-        if !untracked.is_empty() {
-            println!("Untracked files:");
-            println!("  (use \"git add <file>...\" to include in what will be committed)");
+            .map(|file| width::width(&self.entry_label(file).0))
+            .filter(|&columns| columns <= SUMMARY_COLUMN_MAX)
+            .max()
+            .unwrap_or(0)
+    }
 
-            for entry in untracked {
-                println!("\t{}", entry.display_path.red());
+    /// Finishes an entry's line with its summary, if it has one, lined up at `column`.
+    fn print_summary(
+        &self,
+        file: &FileWithSummary,
+        column: usize,
+        label: &(String, String),
+    ) -> Result<()> {
+        match self.format_summary(file) {
+            Some(summary) => {
+                let padding = column.saturating_sub(width::width(&label.0));
+                writeln!(self.out(), "{} {}", " ".repeat(padding), summary)?;
             }
-            println!();
+            None => writeln!(self.out())?,
         }
         Ok(())
     }
 
-    fn format_status(&self, status: &StatusCode) -> &'static str {
-        match status {
-            StatusCode::Modified => "modified",
-            StatusCode::Added => "new file",
-            StatusCode::Deleted => "deleted",
-            StatusCode::Renamed => "renamed",
-            StatusCode::Copied => "copied",
-            StatusCode::Unmerged => "unmerged",
-            StatusCode::Untracked => "untracked",
-            StatusCode::Ignored => "ignored",
+    /// The changed files inside a submodule, indented under its entry one level per
+    /// submodule deep. Staged ones say so, since they aren't in a section of their own.
+    fn print_submodule_files(&self, file: &FileWithSummary, depth: usize) -> Result<()> {
+        for nested in &file.submodule_files {
+            let status = match nested.staged {
+                true => format!("staged {}", nested.status.describe()).green(),
+                false => nested.status.describe().red(),
+            };
+            write!(
+                self.out(),
+                "\t{}{}: {}",
+                "  ".repeat(depth),
+                status,
+                self.linked(nested, &self.entry_label(nested).1)?
+            )?;
+            match self.format_summary(nested) {
+                Some(summary) => writeln!(self.out(), " {}", summary)?,
+                None => writeln!(self.out())?,
+            }
+            self.print_patch(nested)?;
+            self.print_submodule_files(nested, depth + 1)?;
         }
+        Ok(())
     }
 
-    pub fn display_with_summaries(&self, files: &[FileWithSummary]) -> Result<()> {
-        self.print_branch_status()?;
+    pub fn display_with_summaries(
+        &self,
+        branch: &BranchInfo,
+        files: &[FileWithSummary],
+    ) -> Result<()> {
+        if files.is_empty() {
+            match (self.clean_style, self.profile) {
+                (CleanStyle::Hidden, _) => return Ok(()),
+                // The accessible view of a clean tree is already one line
+                (CleanStyle::Compact, OutputProfile::Standard) => return self.print_clean(branch),
+                _ => {}
+            }
+        }
+        if self.profile == OutputProfile::Accessible {
+            return self.display_accessible(branch, files);
+        }
+        if files.is_empty() {
+            self.print_branch_status(branch, false)?;
+            writeln!(self.out(), "nothing to commit, working tree clean")?;
+            return Ok(());
+        }
+        let dirty = files
+            .iter()
+            .any(|file| file.status != StatusCode::Untracked);
+        self.print_branch_status(branch, dirty)?;
 
         let mut has_staged = false;
         let mut has_unstaged = false;
@@ -167,79 +1541,428 @@ impl StatusFormatter {
         }
 
         if has_staged {
-            println!("Changes to be committed:");
-            println!("  (use \"git restore --staged <file>...\" to unstage)");
+            let staged: Vec<_> = files.iter().filter(|file| file.staged).collect();
+            writeln!(
+                self.out(),
+                "Changes to be committed{}:",
+                section_counts(&staged)
+            )?;
+            // There's no commit to restore from yet
+            let unstage = match branch.unborn {
+                true => "git rm --cached <file>...",
+                false => "git restore --staged <file>...",
+            };
+            writeln!(self.out(), "  (use \"{}\" to unstage)", unstage)?;
 
-            for file in files {
-                if file.staged {
-                    let status_text = self.format_status(&file.status);
-
-                    if let Some(ref orig_path) = file.original_path {
-                        print!("\t{}: {} -> {}", status_text.green(), orig_path, file.path);
-                    } else {
-                        print!("\t{}: {}", status_text.green(), file.path);
-                    }
-
-                    // Add summary if available
-                    if let Some(ref summary) = file.summary {
-                        println!(" ({})", summary);
-                    } else {
-                        println!();
-                    }
-                }
+            let column = self.summary_column(&staged);
+            for file in staged {
+                let label = self.entry_label(file);
+                write!(
+                    self.out(),
+                    "\t{}: {}",
+                    file.status.describe().green(),
+                    self.linked(file, &label.1)?
+                )?;
+                self.print_summary(file, column, &label)?;
+                self.print_patch(file)?;
             }
-            println!();
+            writeln!(self.out())?;
         }
 
         if has_unstaged {
-            println!("Changes not staged for commit:");
-            println!("  (use \"git add <file>...\" to update what will be committed)");
-            println!("  (use \"git restore <file>...\" to discard changes in working directory)");
-
-            for file in files {
-                if !file.staged && !matches!(file.status, StatusCode::Untracked) {
-                    let status_text = self.format_status(&file.status);
-                    print!("\t{}: {}", status_text.red(), file.path);
-
-                    // Add summary if available
-                    if let Some(ref summary) = file.summary {
-                        println!(" ({})", summary);
-                    } else {
-                        println!();
-                    }
-                }
+            let unstaged: Vec<_> = files
+                .iter()
+                .filter(|file| !file.staged && !matches!(file.status, StatusCode::Untracked))
+                .collect();
+            writeln!(
+                self.out(),
+                "Changes not staged for commit{}:",
+                section_counts(&unstaged)
+            )?;
+            writeln!(
+                self.out(),
+                "  (use \"git add <file>...\" to update what will be committed)"
+            )?;
+            writeln!(
+                self.out(),
+                "  (use \"git restore <file>...\" to discard changes in working directory)"
+            )?;
+
+            let column = self.summary_column(&unstaged);
+            for file in unstaged {
+                let label = self.entry_label(file);
+                write!(
+                    self.out(),
+                    "\t{}: {}",
+                    file.status.describe().red(),
+                    self.linked(file, &label.1)?
+                )?;
+                self.print_summary(file, column, &label)?;
+                self.print_patch(file)?;
+                self.print_submodule_files(file, 1)?;
             }
-            println!();
+            writeln!(self.out())?;
         }
 
         if has_untracked {
-            println!("Untracked files:");
-            println!("  (use \"git add <file>...\" to include in what will be committed)");
-
-            for file in files {
-                if matches!(file.status, StatusCode::Untracked) {
-                    println!("\t{}", file.path.red());
-                    if let Some(ref summary) = file.summary {
-                        println!("\t  ({})", summary);
-                    }
+            let untracked: Vec<_> = files
+                .iter()
+                .filter(|file| matches!(file.status, StatusCode::Untracked))
+                .collect();
+            writeln!(self.out(), "Untracked files{}:", section_counts(&untracked))?;
+            writeln!(
+                self.out(),
+                "  (use \"git add <file>...\" to include in what will be committed)"
+            )?;
+
+            for file in untracked {
+                let path = file.path.red().to_string();
+                writeln!(self.out(), "\t{}", self.linked(file, &path)?)?;
+                if let Some(summary) = self.format_summary(file) {
+                    writeln!(self.out(), "\t  {}", summary)?;
                 }
+                self.print_patch(file)?;
             }
-            println!();
+            writeln!(self.out())?;
         }
 
         if !has_staged && has_unstaged {
-            println!("no changes added to commit (use \"git add\" and/or \"git commit -a\")");
+            writeln!(
+                self.out(),
+                "no changes added to commit (use \"git add\" and/or \"git commit -a\")"
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// One `path<TAB>summary` line per changed file, for picking one with a fuzzy finder
+    /// like fzf. A file with staged and unstaged changes gets both summaries.
+    pub fn display_pick_lines(&self, files: &[FileWithSummary]) -> Result<()> {
+        let mut lines: Vec<(&str, Vec<&str>)> = Vec::new();
+        for file in files {
+            let summary = file.summary.as_deref();
+            match lines.iter_mut().find(|(path, _)| *path == file.path) {
+                Some((_, summaries)) => summaries.extend(summary),
+                None => lines.push((&file.path, summary.into_iter().collect())),
+            }
+        }
+        for (path, summaries) in lines {
+            // A tab or line break in a summary would break up its line
+            let summary = summaries.join("; ").replace(['\t', '\n'], " ");
+            match writeln!(self.out(), "{}\t{}", path, summary) {
+                // The finder may stop reading once it has what it needs, like `head` does
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The accessible form of `display_with_summaries`: the branch, then one line per file,
+    /// conflicts first and otherwise in `git status`'s order.
+    fn display_accessible(&self, branch: &BranchInfo, files: &[FileWithSummary]) -> Result<()> {
+        writeln!(self.out(), "{}", branch_record(branch, Utc::now()))?;
+        if let Some(incoming) = &self.incoming {
+            writeln!(self.out(), "INCOMING: {}", incoming)?;
+        }
+        let mut files: Vec<_> = files.iter().collect();
+        files.sort_by_key(|file| match file.status {
+            StatusCode::Unmerged => 0,
+            StatusCode::Untracked => 3,
+            _ if file.staged => 1,
+            _ => 2,
+        });
+        for file in files {
+            self.print_record(file)?;
+        }
+        Ok(())
+    }
+
+    /// A file's record, then those of the changed files inside it if it's a submodule.
+    fn print_record(&self, file: &FileWithSummary) -> Result<()> {
+        writeln!(self.out(), "{}", self.file_record(file))?;
+        self.print_patch(file)?;
+        for nested in &file.submodule_files {
+            self.print_record(nested)?;
+        }
+        Ok(())
+    }
+
+    /// The status view in the middle of a rebase or merge: where it's at, the files left
+    /// to resolve, and how many other files changed, which aren't summarized.
+    pub fn display_operation(
+        &self,
+        branch: &BranchInfo,
+        progress: Option<&RebaseProgress>,
+        conflicts: &[Conflict],
+        others: usize,
+    ) -> Result<()> {
+        let operation = branch.operation.map_or("operation", operation_name);
+        let stopped = progress.map(|progress| {
+            let mut stopped = format!("commit {} of {}", progress.current, progress.total);
+            if let Some(commit) = &progress.stopped_at {
+                stopped.push_str(&format!(", {} {}", commit.short_id, commit.subject));
+            }
+            stopped
+        });
+        let regions = |conflict: &Conflict| match conflict.regions {
+            0 => "no conflict markers left".to_string(),
+            1 => "1 conflict".to_string(),
+            regions => format!("{} conflicts", regions),
+        };
+        let others =
+            (others > 0).then(|| format!("{} other changed file{}", others, plural(others as u32)));
+
+        if self.profile == OutputProfile::Accessible {
+            writeln!(self.out(), "{}", branch_record(branch, Utc::now()))?;
+            if let Some(stopped) = stopped {
+                writeln!(self.out(), "STOPPED: at {}", stopped)?;
+            }
+            for conflict in conflicts {
+                writeln!(
+                    self.out(),
+                    "CONFLICT: {}, {}",
+                    conflict.path,
+                    regions(conflict)
+                )?;
+            }
+            if let Some(others) = others {
+                writeln!(
+                    self.out(),
+                    "NOT SUMMARIZED: {} during the {}, run with --all to summarize them",
+                    others,
+                    operation
+                )?;
+            }
+            return Ok(());
         }
 
+        self.print_branch_status(branch, true)?;
+        if let Some(stopped) = stopped {
+            writeln!(self.out(), "Stopped at {}", stopped)?;
+            writeln!(self.out())?;
+        }
+        match conflicts.is_empty() {
+            true => {
+                writeln!(self.out(), "{}", "No conflicts left.".green())?;
+                writeln!(self.out())?;
+            }
+            false => {
+                writeln!(
+                    self.out(),
+                    "Unmerged paths ({} file{}):",
+                    conflicts.len(),
+                    plural(conflicts.len() as u32)
+                )?;
+                writeln!(
+                    self.out(),
+                    "  (fix the conflicts, then use \"git add <file>...\" to mark them resolved)"
+                )?;
+                for conflict in conflicts {
+                    writeln!(
+                        self.out(),
+                        "\t{}: {} {}",
+                        "unmerged".red(),
+                        conflict.path,
+                        format!("({})", regions(conflict)).dimmed()
+                    )?;
+                }
+                writeln!(self.out())?;
+            }
+        }
+        if let Some(others) = others {
+            writeln!(
+                self.out(),
+                "{}",
+                format!(
+                    "{} not summarized during the {} (use \"git hud --all\" to summarize them)",
+                    others, operation
+                )
+                .dimmed()
+            )?;
+        }
         Ok(())
     }
+
+    /// One file as a single line, led by a word for which part of the status it's in.
+    fn file_record(&self, file: &FileWithSummary) -> String {
+        let mut record = match file.status {
+            StatusCode::Unmerged => format!("CONFLICT: {}", file.path),
+            StatusCode::Untracked => format!("UNTRACKED: {}", file.path),
+            _ => format!(
+                "{}: {}: {}",
+                if file.staged { "STAGED" } else { "UNSTAGED" },
+                file.status.describe(),
+                file.path
+            ),
+        };
+        if let Some(original) = &file.original_path {
+            record.push_str(&format!(" (from {})", original));
+        }
+        if let Some(summary) = self.format_summary(file) {
+            record.push(' ');
+            record.push_str(&summary);
+        }
+        record
+    }
+}
+
+/// The branch, how it stands against its upstream, and its last commit, as a single line.
+fn branch_record(branch: &BranchInfo, now: DateTime<Utc>) -> String {
+    let Some(head) = &branch.head else {
+        let record = match &branch.commit {
+            Some(commit) => format!(
+                "BRANCH: detached at {} ({}), {}",
+                commit.short_id,
+                commit.subject,
+                age(commit.time, now)
+            ),
+            None => "BRANCH: none".to_string(),
+        };
+        return record + &in_progress(branch);
+    };
+    let tracking = match (&branch.upstream, branch.ahead_behind) {
+        (None, _) => "not tracking a remote branch".to_string(),
+        (Some(upstream), None) => format!("upstream '{}' is gone", upstream),
+        (Some(upstream), Some((0, 0))) => format!("up to date with '{}'", upstream),
+        (Some(upstream), Some((ahead, 0))) => format!(
+            "ahead of '{}' by {} commit{}",
+            upstream,
+            ahead,
+            plural(ahead)
+        ),
+        (Some(upstream), Some((0, behind))) => format!(
+            "behind '{}' by {} commit{}",
+            upstream,
+            behind,
+            plural(behind)
+        ),
+        (Some(upstream), Some((ahead, behind))) => format!(
+            "diverged from '{}', {} ahead and {} behind",
+            upstream, ahead, behind
+        ),
+    };
+    if branch.unborn {
+        return format!("BRANCH: {}, no commits yet", head) + &in_progress(branch);
+    }
+    let tracking = match staleness(branch, now) {
+        Some(staleness) => format!("{}, {}", tracking, staleness),
+        None => tracking,
+    } + &in_progress(branch);
+    match &branch.commit {
+        Some(commit) => format!(
+            "BRANCH: {}, {}, last commit {} {}: {}",
+            head,
+            tracking,
+            commit.short_id,
+            age(commit.time, now),
+            commit.subject
+        ),
+        None => format!("BRANCH: {}, {}", head, tracking),
+    }
+}
+
+/// How many files a section lists and the lines their diffs add and remove, like
+/// ` (3 files, +120 −13)`. Binary files and directories count as files with no lines.
+fn section_counts(files: &[&FileWithSummary]) -> String {
+    let mut lines = None;
+    for diff in files.iter().filter_map(|file| file.diff.as_deref()) {
+        let (_, added, removed) = template::diff_stats(diff);
+        let (total_added, total_removed) = lines.get_or_insert((0, 0));
+        *total_added += added;
+        *total_removed += removed;
+    }
+    let count = files.len();
+    let noun = match count {
+        1 => "file",
+        _ => "files",
+    };
+    match lines {
+        Some((added, removed)) => format!(" ({} {}, +{} −{})", count, noun, added, removed),
+        None => format!(" ({} {})", count, noun),
+    }
+}
+
+/// The operation in progress and stashes, each led by a comma, for a branch record.
+fn in_progress(branch: &BranchInfo) -> String {
+    let mut notes = String::new();
+    if let Some(operation) = branch.operation {
+        notes.push_str(&format!(", {} in progress", operation_name(operation)));
+    }
+    if branch.stashes > 0 {
+        notes.push_str(&format!(", {}", stashes(branch.stashes)));
+    }
+    notes
+}
+
+fn operation_name(operation: Operation) -> &'static str {
+    match operation {
+        Operation::ApplyMailbox => "git am session",
+        operation => operation.command(),
+    }
+}
+
+fn stashes(count: usize) -> String {
+    match count {
+        1 => "1 stash".to_string(),
+        count => format!("{} stashes", count),
+    }
+}
+
+/// How long ago `time` was, in the largest unit that fits, like `2h ago`.
+fn age(time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    match span(now - time) {
+        Some(span) => format!("{} ago", span),
+        None => "just now".to_string(),
+    }
+}
+
+/// A length of time in the largest unit that fits, like `3d`, or `None` when it's under a
+/// minute (or negative, from a clock that's ahead).
+fn span(elapsed: TimeDelta) -> Option<String> {
+    let (count, unit) = match elapsed.num_minutes() {
+        ..=0 => return None,
+        minutes @ ..60 => (minutes, "m"),
+        _ if elapsed.num_hours() < 24 => (elapsed.num_hours(), "h"),
+        _ if elapsed.num_days() < 14 => (elapsed.num_days(), "d"),
+        _ if elapsed.num_days() < 60 => (elapsed.num_weeks(), "w"),
+        _ if elapsed.num_days() < 365 => (elapsed.num_days() / 30, "mo"),
+        _ => (elapsed.num_days() / 365, "y"),
+    };
+    Some(format!("{}{}", count, unit))
+}
+
+/// How old the upstream's state is, if it was last fetched long enough ago to say so.
+fn staleness(branch: &BranchInfo, now: DateTime<Utc>) -> Option<String> {
+    let elapsed = now - branch.fetched?;
+    (elapsed >= STALE_AFTER)
+        .then(|| span(elapsed))
+        .flatten()
+        .map(|span| format!("remote info {} old", span))
+}
+
+fn index_flag(flag: IndexFlag) -> &'static str {
+    match flag {
+        IndexFlag::AssumeUnchanged => "assume-unchanged",
+        IndexFlag::SkipWorktree => "skip-worktree",
+    }
+}
+
+fn plural(count: u32) -> &'static str {
+    match count {
+        1 => "",
+        _ => "s",
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::git::Repository;
+    use crate::git::{HeadCommit, Repository};
     use std::fs;
+    use std::path::PathBuf;
+    use std::process::Command;
     use tempfile::TempDir;
 
     fn setup_test_repo() -> Result<(TempDir, Repository)> {
@@ -247,17 +1970,17 @@ mod tests {
 
         // Initialize git repo
         Command::new("git")
-            .args(&["init"])
+            .args(["init"])
             .current_dir(temp_dir.path())
             .output()?;
 
         // Configure git user for commits
         Command::new("git")
-            .args(&["config", "user.name", "test"])
+            .args(["config", "user.name", "test"])
             .current_dir(temp_dir.path())
             .output()?;
         Command::new("git")
-            .args(&["config", "user.email", "test@example.com"])
+            .args(["config", "user.email", "test@example.com"])
             .current_dir(temp_dir.path())
             .output()?;
 
@@ -265,6 +1988,486 @@ mod tests {
         Ok((temp_dir, repo))
     }
 
+    /// Collects what a formatter writes, shared so the test can read it afterwards.
+    #[derive(Clone, Default)]
+    struct Capture(std::rc::Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        /// What was written, without colors: other tests turn color on and off for the
+        /// whole process while these run.
+        fn raw(&self) -> String {
+            String::from_utf8_lossy(&self.0.borrow()).into_owned()
+        }
+
+        fn text(&self) -> String {
+            let text = self.raw();
+            let mut plain = String::with_capacity(text.len());
+            let mut chars = text.chars().peekable();
+            while let Some(c) = chars.next() {
+                if c == '\x1b' && chars.peek() == Some(&']') {
+                    // A link, up to the `ESC \` ending its URL
+                    chars.by_ref().find(|&c| c == '\\');
+                } else if c == '\x1b' {
+                    chars.by_ref().find(|c| c.is_ascii_alphabetic());
+                } else {
+                    plain.push(c);
+                }
+            }
+            plain
+        }
+    }
+
+    fn capturing(profile: OutputProfile) -> (StatusFormatter, Capture) {
+        let capture = Capture::default();
+        let formatter = StatusFormatter {
+            profile,
+            ..StatusFormatter::new()
+        }
+        .with_writer(capture.clone());
+        (formatter, capture)
+    }
+
+    /// Compares `output` with the snapshot saved as `src/snapshots/display__<name>.snap`.
+    /// A missing snapshot is written out for review; run with `UPDATE_SNAPSHOTS=1` to
+    /// accept changed output.
+    fn assert_snapshot(name: &str, output: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/snapshots")
+            .join(format!("display__{}.snap", name));
+        let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some();
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if !update => assert_eq!(
+                output,
+                expected,
+                "{} changed; rerun with UPDATE_SNAPSHOTS=1 to accept it",
+                path.display()
+            ),
+            _ => {
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, output).unwrap();
+            }
+        }
+    }
+
+    fn file(path: &str, status: StatusCode, staged: bool, summary: &str) -> FileWithSummary {
+        FileWithSummary {
+            path: path.to_string(),
+            abs_path: PathBuf::from("/repo").join(path),
+            status,
+            staged,
+            original_path: None,
+            summary: (!summary.is_empty()).then(|| summary.to_string()),
+            encoding: None,
+            mode_change: None,
+            not_summarized: false,
+            diff: None,
+            line_ranges: Vec::new(),
+            submodule_files: Vec::new(),
+        }
+    }
+
+    fn on_main(ahead_behind: Option<(u32, u32)>) -> BranchInfo {
+        BranchInfo {
+            oid: Some("0123456789abcdef0123456789abcdef01234567".to_string()),
+            head: Some("main".to_string()),
+            upstream: Some("origin/main".to_string()),
+            ahead_behind,
+            commit: Some(head_commit("0123456", "Fix status parsing", 2)),
+            fetched: Some(Utc::now() - TimeDelta::days(3)),
+            stashes: 0,
+            operation: None,
+            unborn: false,
+        }
+    }
+
+    /// A commit made `hours_ago` hours before now.
+    fn head_commit(short_id: &str, subject: &str, hours_ago: i64) -> HeadCommit {
+        HeadCommit {
+            short_id: short_id.to_string(),
+            subject: subject.to_string(),
+            time: Utc::now() - TimeDelta::hours(hours_ago),
+        }
+    }
+
+    fn staged_and_unstaged() -> Vec<FileWithSummary> {
+        vec![
+            file(
+                "src/parser.rs",
+                StatusCode::Modified,
+                true,
+                "Handle empty input",
+            ),
+            file(
+                "src/lexer.rs",
+                StatusCode::Added,
+                true,
+                "Add a lexer for tokens",
+            ),
+            file(
+                "src/parser.rs",
+                StatusCode::Modified,
+                false,
+                "Log parse errors",
+            ),
+            file("README.md", StatusCode::Deleted, false, "Remove the README"),
+            file("notes.txt", StatusCode::Untracked, false, "Meeting notes"),
+        ]
+    }
+
+    /// Renders the status view for each scenario in both profiles and checks it against
+    /// its snapshot.
+    #[test]
+    fn test_status_view_snapshots() -> Result<()> {
+        let mut renamed = file(
+            "src/new_name.rs",
+            StatusCode::Renamed,
+            true,
+            "Rename the module",
+        );
+        renamed.original_path = Some("src/old_name.rs".to_string());
+        let mut executable = file("build.sh", StatusCode::Modified, true, "");
+        executable.mode_change = Some(("100644".to_string(), "100755".to_string()));
+        let mut reencoded = file("legacy.txt", StatusCode::Modified, false, "Fix a typo");
+        reencoded.encoding = Some("windows-1252");
+        let mut patched = file(
+            "src/lib.rs",
+            StatusCode::Modified,
+            false,
+            "Export the parser",
+        );
+        patched.diff = Some("@@ -1 +1 @@\n-mod parser;\n+pub mod parser;\n".to_string());
+        let detached = BranchInfo {
+            oid: Some("abc1234".to_string()),
+            commit: Some(head_commit("abc1234", "Release 1.2", 30)),
+            // HEAD is detached while a rebase is stopped
+            operation: Some(Operation::Rebase),
+            stashes: 2,
+            ..BranchInfo::default()
+        };
+
+        let scenarios: Vec<(&str, BranchInfo, Vec<FileWithSummary>, bool)> = vec![
+            ("staged_and_unstaged", on_main(Some((0, 0))), staged_and_unstaged(), false),
+            (
+                "renames",
+                on_main(Some((2, 0))),
+                vec![renamed, executable, reencoded],
+                false,
+            ),
+            (
+                "conflicts",
+                on_main(Some((3, 2))),
+                vec![
+                    file("src/merge.rs", StatusCode::Unmerged, false, ""),
+                    file("src/clean.rs", StatusCode::Modified, true, "Resolve imports"),
+                ],
+                false,
+            ),
+            (
+                "untracked_only",
+                on_main(None),
+                vec![
+                    file("scratch/", StatusCode::Untracked, false, "Scratch files"),
+                    file("todo.md", StatusCode::Untracked, false, ""),
+                ],
+                false,
+            ),
+            ("detached_head", detached, staged_and_unstaged(), false),
+            (
+                // Entries too wide to line up with the rest, and paths whose characters
+                // each take two columns
+                "wide_and_narrow_entries",
+                BranchInfo {
+                    head: Some("feature/very-long-branch-name".to_string()),
+                    upstream: Some("origin/feature/very-long-branch-name".to_string()),
+                    ..BranchInfo::default()
+                },
+                vec![
+                    file("a.rs", StatusCode::Modified, false, "Short path"),
+                    file(
+                        "src/some/deeply/nested/module/with/a/long/path.rs",
+                        StatusCode::Modified,
+                        false,
+                        "Long path",
+                    ),
+                    file("文档/说明.md", StatusCode::Modified, false, "Wide characters"),
+                    file(
+                        "b.rs",
+                        StatusCode::Modified,
+                        false,
+                        "A summary that runs on far past the width summaries are given, so it's cut short",
+                    ),
+                ],
+                false,
+            ),
+            ("patch", on_main(Some((0, 1))), vec![patched], true),
+            ("clean", on_main(Some((1, 0))), vec![], false),
+            (
+                "no_commits_yet",
+                BranchInfo {
+                    head: Some("main".to_string()),
+                    unborn: true,
+                    ..BranchInfo::default()
+                },
+                vec![
+                    file("src/main.rs", StatusCode::Added, true, "Add the entry point"),
+                    file("notes.txt", StatusCode::Untracked, false, ""),
+                ],
+                false,
+            ),
+        ];
+        for (name, branch, files, patch) in scenarios {
+            for (profile, suffix) in [
+                (OutputProfile::Standard, ""),
+                (OutputProfile::Accessible, "_accessible"),
+            ] {
+                let (formatter, capture) = capturing(profile);
+                formatter
+                    .with_patch(patch)
+                    .display_with_summaries(&branch, &files)?;
+                assert_snapshot(&format!("{}{}", name, suffix), &capture.text());
+            }
+        }
+        Ok(())
+    }
+
+    /// Diverging with only untracked files around needs no stash to rebase, and a summary
+    /// of the incoming commits goes under the branch in both profiles.
+    #[test]
+    fn test_divergence_hint() -> Result<()> {
+        let branch = on_main(Some((3, 2)));
+        let files = [file("notes.txt", StatusCode::Untracked, false, "")];
+        for (profile, incoming) in [
+            (OutputProfile::Standard, "Incoming: Faster startup"),
+            (OutputProfile::Accessible, "INCOMING: Faster startup"),
+        ] {
+            let (formatter, capture) = capturing(profile);
+            formatter
+                .with_incoming(Some("Faster startup".to_string()))
+                .display_with_summaries(&branch, &files)?;
+            let output = capture.text();
+            assert!(output.contains(incoming), "{}", output);
+            assert!(!output.contains("--autostash"));
+        }
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter.display_with_summaries(&branch, &files)?;
+        assert!(capture
+            .text()
+            .contains("(3 ahead / 2 behind — consider \"git pull --rebase\" to replay"));
+        Ok(())
+    }
+
+    /// Mid-rebase, only the conflicts are listed, under where the rebase stopped.
+    #[test]
+    fn test_operation_view_snapshots() -> Result<()> {
+        let branch = BranchInfo {
+            oid: Some("abc1234".to_string()),
+            commit: Some(head_commit("abc1234", "Release 1.2", 30)),
+            operation: Some(Operation::Rebase),
+            ..BranchInfo::default()
+        };
+        let progress = RebaseProgress {
+            current: 3,
+            total: 7,
+            stopped_at: Some(head_commit("def5678", "Parse empty files", 5)),
+        };
+        let conflicts = [
+            Conflict {
+                path: "src/parser.rs".to_string(),
+                regions: 2,
+            },
+            Conflict {
+                path: "src/lexer.rs".to_string(),
+                regions: 0,
+            },
+        ];
+        for (profile, suffix) in [
+            (OutputProfile::Standard, ""),
+            (OutputProfile::Accessible, "_accessible"),
+        ] {
+            let (formatter, capture) = capturing(profile);
+            formatter.display_operation(&branch, Some(&progress), &conflicts, 14)?;
+            assert_snapshot(&format!("rebase_conflicts{}", suffix), &capture.text());
+        }
+
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter.display_operation(&branch, None, &[], 0)?;
+        let output = capture.text();
+        assert!(output.contains("No conflicts left."), "{}", output);
+        assert!(!output.contains("not summarized"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_styles() -> Result<()> {
+        let branch = on_main(Some((0, 0)));
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter
+            .with_clean_style(CleanStyle::Full)
+            .display_with_summaries(&branch, &[])?;
+        let output = capture.text();
+        assert!(output.starts_with("On branch main\n"));
+        assert!(output.ends_with("\nnothing to commit, working tree clean\n"));
+
+        for profile in [OutputProfile::Standard, OutputProfile::Accessible] {
+            let (formatter, capture) = capturing(profile);
+            formatter
+                .with_clean_style(CleanStyle::Hidden)
+                .display_with_summaries(&branch, &[])?;
+            assert_eq!(capture.text(), "");
+        }
+
+        let unborn = BranchInfo {
+            head: Some("main".to_string()),
+            unborn: true,
+            ..BranchInfo::default()
+        };
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter.display_with_summaries(&unborn, &[])?;
+        assert!(capture.text().contains("main (no commits yet)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_ranges() -> Result<()> {
+        let range = |start, end| LineRange { start, end };
+        let mut retry = file(
+            "src/retry.rs",
+            StatusCode::Modified,
+            false,
+            "Rework the retry loop",
+        );
+        retry.line_ranges = vec![range(45, 80)];
+        let mut many = file("src/lib.rs", StatusCode::Modified, false, "Rename things");
+        many.line_ranges = vec![range(3, 3), range(10, 12), range(40, 41), range(90, 95)];
+
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter
+            .with_line_ranges(true)
+            .display_with_summaries(&on_main(None), &[retry, many])?;
+        let output = capture.text();
+        assert!(
+            output.contains("(L45–80: Rework the retry loop)"),
+            "{}",
+            output
+        );
+        assert!(output.contains("(L3, L10–12, L40–41, 1 more: Rename things)"));
+
+        let mut deleted_line = file("a.rs", StatusCode::Modified, true, "Drop a line");
+        deleted_line.line_ranges = vec![range(7, 7)];
+        let (formatter, capture) = capturing(OutputProfile::Accessible);
+        formatter
+            .with_line_ranges(true)
+            .display_with_summaries(&on_main(None), &[deleted_line])?;
+        assert!(capture.text().contains("(line 7: Drop a line)"));
+
+        // Off unless asked for
+        let mut retry = file("src/retry.rs", StatusCode::Modified, false, "Rework");
+        retry.line_ranges = vec![range(45, 80)];
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter.display_with_summaries(&on_main(None), &[retry])?;
+        assert!(capture.text().contains("(Rework)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_links() -> Result<()> {
+        let mut retry = file("src/retry.rs", StatusCode::Modified, false, "Rework");
+        retry.line_ranges = vec![LineRange { start: 45, end: 80 }];
+        let untracked = file("notes.txt", StatusCode::Untracked, false, "Notes");
+        let files = [retry, untracked];
+
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter
+            .with_links(Some(Links::Vscode))
+            .display_with_summaries(&on_main(None), &files)?;
+        let output = capture.raw();
+        assert!(output.contains(
+            "modified: \x1b]8;;vscode://file/repo/src/retry.rs:45\x1b\\src/retry.rs\x1b]8;;\x1b\\"
+        ));
+        assert!(output.contains("\x1b]8;;vscode://file/repo/notes.txt:1\x1b\\"));
+        assert!(capture.text().contains("\tmodified: src/retry.rs (Rework)"));
+
+        let (formatter, capture) = capturing(OutputProfile::Accessible);
+        formatter
+            .with_links(Some(Links::Vscode))
+            .display_with_summaries(&on_main(None), &files)?;
+        assert!(!capture.raw().contains("\x1b]8"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pick_lines() -> Result<()> {
+        let files = [
+            file(
+                "src/main.rs",
+                StatusCode::Modified,
+                true,
+                "Add the entry point",
+            ),
+            file("src/main.rs", StatusCode::Modified, false, "Log\tmore"),
+            FileWithSummary {
+                summary: None,
+                not_summarized: true,
+                ..file("big.rs", StatusCode::Modified, false, "")
+            },
+            file("notes.txt", StatusCode::Untracked, false, "Notes"),
+        ];
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter.display_pick_lines(&files)?;
+        assert_eq!(
+            capture.text(),
+            "src/main.rs\tAdd the entry point; Log more\nbig.rs\t\nnotes.txt\tNotes\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_submodule_files() -> Result<()> {
+        let mut inner = file("lib/inner", StatusCode::Modified, false, "Bump");
+        inner.submodule_files = vec![file("lib/inner/a.rs", StatusCode::Modified, false, "Fix")];
+        let mut lib = file("lib", StatusCode::Modified, false, "Work in lib");
+        lib.submodule_files = vec![
+            file("lib/src/parse.rs", StatusCode::Added, true, "Add a parser"),
+            inner,
+        ];
+        let files = [lib, file("main.rs", StatusCode::Modified, false, "Use it")];
+
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter.display_with_summaries(&on_main(None), &files)?;
+        let output = capture.text();
+        assert!(
+            output.contains(
+                "\tmodified: lib     (Work in lib)\n\
+                 \t  staged new file: lib/src/parse.rs (Add a parser)\n\
+                 \t  modified: lib/inner (Bump)\n\
+                 \t    modified: lib/inner/a.rs (Fix)\n\
+                 \tmodified: main.rs (Use it)\n"
+            ),
+            "{}",
+            output
+        );
+
+        let (formatter, capture) = capturing(OutputProfile::Accessible);
+        formatter.display_with_summaries(&on_main(None), &files)?;
+        assert!(capture.text().contains(
+            "UNSTAGED: modified: lib (Work in lib)\n\
+             STAGED: new file: lib/src/parse.rs (Add a parser)\n\
+             UNSTAGED: modified: lib/inner (Bump)\n\
+             UNSTAGED: modified: lib/inner/a.rs (Fix)\n"
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_status_display() -> Result<()> {
         let (temp_dir, repo) = setup_test_repo()?;
@@ -280,25 +2483,14 @@ mod tests {
             .current_dir(temp_dir.path())
             .output()?;
 
-        let formatter = StatusFormatter::new();
-        let status = repo.get_status()?;
-
-        // Redirect stdout to capture output
-        let mut output = Vec::new();
-        {
-            use std::io::Write;
-            let _cursor = std::io::Cursor::new(&mut output);
-            std::io::stdout().flush()?;
-
-            formatter.display(&status)?;
-        }
-
-        let output = String::from_utf8(output)?;
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter.display(&repo.get_status()?)?;
+        let output = capture.text();
 
         // Verify output format
         assert!(output.contains("On branch"));
         assert!(output.contains("Changes to be committed:"));
-        assert!(output.contains("new file:   staged.txt"));
+        assert!(output.contains("new file: staged.txt"));
         assert!(output.contains("Untracked files:"));
         assert!(output.contains("untracked.txt"));
 
@@ -307,7 +2499,7 @@ mod tests {
 
     #[test]
     fn test_branch_status() -> Result<()> {
-        let (temp_dir, _) = setup_test_repo()?;
+        let (temp_dir, repo) = setup_test_repo()?;
 
         // Create and commit a file
         fs::write(temp_dir.path().join("test.txt"), "content\n")?;
@@ -320,21 +2512,169 @@ mod tests {
             .current_dir(temp_dir.path())
             .output()?;
 
-        let formatter = StatusFormatter::new();
-
-        // Capture output
-        let mut output = Vec::new();
-        {
-            let _cursor = std::io::Cursor::new(&mut output);
-            formatter.print_branch_status()?;
-        }
-
-        let output = String::from_utf8(output)?;
+        let (formatter, capture) = capturing(OutputProfile::Standard);
+        formatter.print_branch_status(&repo.get_status()?.branch, false)?;
 
         // Verify branch information
-        assert!(output.contains("On branch"));
+        assert!(capture.text().starts_with("On branch"));
         // Note: We don't check for specific branch name as it might vary
 
         Ok(())
     }
+
+    #[test]
+    fn test_branch_record() {
+        let now = Utc::now();
+        let mut branch = BranchInfo {
+            oid: None,
+            head: Some("main".to_string()),
+            upstream: Some("origin/main".to_string()),
+            ahead_behind: Some((2, 1)),
+            commit: None,
+            fetched: None,
+            stashes: 0,
+            operation: None,
+            unborn: false,
+        };
+        assert_eq!(
+            branch_record(&branch, now),
+            "BRANCH: main, diverged from 'origin/main', 2 ahead and 1 behind"
+        );
+        branch.ahead_behind = Some((1, 0));
+        branch.commit = Some(HeadCommit {
+            short_id: "abc1234".to_string(),
+            subject: "Fix it".to_string(),
+            time: now - TimeDelta::minutes(5),
+        });
+        assert_eq!(
+            branch_record(&branch, now),
+            "BRANCH: main, ahead of 'origin/main' by 1 commit, last commit abc1234 5m ago: Fix it"
+        );
+        branch.head = None;
+        assert_eq!(
+            branch_record(&branch, now),
+            "BRANCH: detached at abc1234 (Fix it), 5m ago"
+        );
+        branch.operation = Some(Operation::Bisect);
+        branch.stashes = 1;
+        assert_eq!(
+            branch_record(&branch, now),
+            "BRANCH: detached at abc1234 (Fix it), 5m ago, bisect in progress, 1 stash"
+        );
+    }
+
+    #[test]
+    fn test_age() {
+        let now = Utc::now();
+        for (elapsed, expected) in [
+            (TimeDelta::seconds(-30), "just now"),
+            (TimeDelta::seconds(59), "just now"),
+            (TimeDelta::minutes(59), "59m ago"),
+            (TimeDelta::hours(2), "2h ago"),
+            (TimeDelta::days(3), "3d ago"),
+            (TimeDelta::days(20), "2w ago"),
+            (TimeDelta::days(100), "3mo ago"),
+            (TimeDelta::days(800), "2y ago"),
+        ] {
+            assert_eq!(age(now - elapsed, now), expected);
+        }
+    }
+
+    #[test]
+    fn test_staleness() {
+        let now = Utc::now();
+        let mut branch = on_main(Some((0, 0)));
+        branch.fetched = Some(now - TimeDelta::minutes(20));
+        assert_eq!(staleness(&branch, now), None);
+        branch.fetched = Some(now - TimeDelta::days(3));
+        assert_eq!(
+            staleness(&branch, now).as_deref(),
+            Some("remote info 3d old")
+        );
+        branch.fetched = None;
+        assert_eq!(staleness(&branch, now), None);
+    }
+
+    #[test]
+    fn test_section_counts() {
+        let mut edited = file("a.rs", StatusCode::Modified, false, "");
+        edited.diff =
+            Some("--- a/a.rs\n+++ b/a.rs\n@@ -1,2 +1,3 @@\n-old\n+new\n+more\n same\n".to_string());
+        let mut added = file("b.rs", StatusCode::Added, false, "");
+        added.diff = Some("--- /dev/null\n+++ b/b.rs\n@@ -0,0 +1 @@\n+line\n".to_string());
+        let binary = file("logo.png", StatusCode::Modified, false, "");
+
+        assert_eq!(
+            section_counts(&[&edited, &added, &binary]),
+            " (3 files, +3 −1)"
+        );
+        assert_eq!(section_counts(&[&binary]), " (1 file)");
+    }
+
+    #[test]
+    fn test_file_record() {
+        let formatter = StatusFormatter {
+            profile: OutputProfile::Accessible,
+            ..StatusFormatter::new()
+        };
+        let file = |status, staged| FileWithSummary {
+            path: "src/new.rs".to_string(),
+            abs_path: PathBuf::from("/repo/src/new.rs"),
+            status,
+            staged,
+            original_path: None,
+            summary: Some("adds a parser".to_string()),
+            encoding: None,
+            mode_change: None,
+            not_summarized: false,
+            diff: None,
+            line_ranges: Vec::new(),
+            submodule_files: Vec::new(),
+        };
+
+        let mut renamed = file(StatusCode::Renamed, true);
+        renamed.original_path = Some("src/old.rs".to_string());
+        renamed.mode_change = Some(("100644".to_string(), "100755".to_string()));
+        assert_eq!(
+            formatter.file_record(&renamed),
+            "STAGED: renamed: src/new.rs (from src/old.rs) (adds a parser) (mode 100644 to 100755)"
+        );
+        assert_eq!(
+            formatter.file_record(&file(StatusCode::Modified, false)),
+            "UNSTAGED: modified: src/new.rs (adds a parser)"
+        );
+        assert_eq!(
+            formatter.file_record(&file(StatusCode::Untracked, false)),
+            "UNTRACKED: src/new.rs (adds a parser)"
+        );
+        let mut conflict = file(StatusCode::Unmerged, false);
+        conflict.summary = None;
+        assert_eq!(formatter.file_record(&conflict), "CONFLICT: src/new.rs");
+        let mut skipped = file(StatusCode::Modified, false);
+        skipped.summary = None;
+        skipped.not_summarized = true;
+        assert_eq!(
+            formatter.file_record(&skipped),
+            "UNSTAGED: modified: src/new.rs (not summarized, run with --all)"
+        );
+    }
+
+    #[test]
+    fn test_highlight_replaced() {
+        let formatter = StatusFormatter::new();
+        colored::control::set_override(true);
+        let lines = formatter.highlight_replaced(&["call(foo, 1);"], &["call(bar, 1);"]);
+        let unrelated = formatter.highlight_replaced(&["one"], &["two"]);
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(&"foo".red().reversed().to_string()));
+        assert!(!lines[0].contains(&"call(".red().reversed().to_string()));
+        assert!(lines[1].contains(&"bar".green().reversed().to_string()));
+        // Nothing in common, so nothing is highlighted
+        assert_eq!(
+            unrelated,
+            vec!["-one".red().to_string(), "+two".green().to_string()]
+        );
+        colored::control::unset_override();
+    }
 }
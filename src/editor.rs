@@ -0,0 +1,75 @@
+//! Opens files in the editor git uses, at a line for the editors that can be told one.
+
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+/// Opens `path` at `line` in `editor`, a shell command like git's `core.editor`, and
+/// waits for it to close.
+pub fn open(editor: &str, path: &Path, line: u32) -> Result<()> {
+    // Run like git runs it, so an editor setting with arguments works the same
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(editor)
+        .args(args(editor, path, line))
+        .status()
+        .with_context(|| format!("Failed to run {}", editor))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("{} exited with {}", editor, status));
+    }
+    Ok(())
+}
+
+/// The arguments that open `path` at `line`, which each editor takes its own way. Ones
+/// not known here get vi's `+<line>`, which most terminal editors understand.
+fn args(editor: &str, path: &Path, line: u32) -> Vec<OsString> {
+    let program = editor.split_whitespace().next().unwrap_or_default();
+    let name = Path::new(program)
+        .file_name()
+        .map_or(program.into(), |name| name.to_string_lossy());
+    let mut at_line = path.as_os_str().to_owned();
+    at_line.push(format!(":{}", line));
+    match name.as_ref() {
+        "code" | "code-insiders" | "codium" | "cursor" => vec!["--goto".into(), at_line],
+        "subl" | "hx" | "zed" => vec![at_line],
+        "idea" | "clion" | "goland" | "pycharm" | "rustrover" | "webstorm" => {
+            vec!["--line".into(), line.to_string().into(), path.into()]
+        }
+        _ => vec![format!("+{}", line).into(), path.into()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        let path = Path::new("/repo/src/main.rs");
+        let args = |editor| -> Vec<String> {
+            args(editor, path, 12)
+                .into_iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(args("vim"), ["+12", "/repo/src/main.rs"]);
+        assert_eq!(args("nano -w"), ["+12", "/repo/src/main.rs"]);
+        assert_eq!(args("code --wait"), ["--goto", "/repo/src/main.rs:12"]);
+        assert_eq!(args("/usr/local/bin/hx"), ["/repo/src/main.rs:12"]);
+        assert_eq!(args("idea"), ["--line", "12", "/repo/src/main.rs"]);
+    }
+
+    #[test]
+    fn test_open() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let log = dir.path().join("args");
+        // An editor that writes down what it was asked to open
+        let editor = format!("printf '%s\\n' >{}", log.display());
+        open(&editor, Path::new("my file.rs"), 3)?;
+        assert_eq!(std::fs::read_to_string(&log)?, "+3\nmy file.rs\n");
+        assert!(open("false", Path::new("a.rs"), 1).is_err());
+        Ok(())
+    }
+}
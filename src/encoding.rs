@@ -0,0 +1,61 @@
+//! Decodes file content that isn't UTF-8, so files in legacy encodings can still be
+//! diffed and summarized.
+//!
+//! `encoding_rs` only decodes, so the encoding is guessed: a byte order mark wins, then
+//! UTF-8, then Shift_JIS if it decodes cleanly into Japanese text, then Windows-1252
+//! (a superset of Latin-1), which accepts any bytes.
+
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_8, WINDOWS_1252};
+
+/// Guesses the encoding of text content, or returns `None` if it looks binary.
+pub fn detect(bytes: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return Some(encoding);
+    }
+    if bytes.contains(&0) {
+        return None;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return Some(UTF_8);
+    }
+    // Plenty of Latin-1 byte pairs are also valid Shift_JIS, but Japanese text nearly
+    // always has some kana in it
+    if let Some(text) = SHIFT_JIS.decode_without_bom_handling_and_without_replacement(bytes) {
+        if text.chars().any(|c| ('\u{3040}'..='\u{30ff}').contains(&c)) {
+            return Some(SHIFT_JIS);
+        }
+    }
+    Some(WINDOWS_1252)
+}
+
+/// Decodes `bytes` as `encoding`, replacing anything malformed.
+pub fn decode(bytes: &[u8], encoding: &'static Encoding) -> String {
+    encoding.decode(bytes).0.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"plain ascii"), Some(UTF_8));
+        assert_eq!(detect("caf\u{e9}".as_bytes()), Some(UTF_8));
+        assert_eq!(detect(b"\xef\xbb\xbfwith a bom"), Some(UTF_8));
+        assert_eq!(detect(b"\xff\xfeh\0i\0"), Some(encoding_rs::UTF_16LE));
+        assert_eq!(detect(b"bin\0ary"), None);
+
+        // "café naïve" in Latin-1; "ïv" alone would also pass as Shift_JIS
+        assert_eq!(detect(b"caf\xe9 na\xefve"), Some(WINDOWS_1252));
+        // "こんにちは" in Shift_JIS
+        let (sjis, _, _) = SHIFT_JIS.encode("こんにちは");
+        assert_eq!(detect(&sjis), Some(SHIFT_JIS));
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"caf\xe9", WINDOWS_1252), "caf\u{e9}");
+        let (sjis, _, _) = SHIFT_JIS.encode("こんにちは");
+        assert_eq!(decode(&sjis, SHIFT_JIS), "こんにちは");
+    }
+}
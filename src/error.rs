@@ -1,3 +1,4 @@
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,6 +9,14 @@ pub enum HudError {
     #[error("API error: {0}")]
     Api(String),
 
+    #[error("API error ({status}): {message}")]
+    ApiStatus {
+        status: u16,
+        message: String,
+        /// How long the provider asked us to wait before retrying
+        retry_after: Option<Duration>,
+    },
+
     // #[error("Cache error: {0}")]
     // Cache(String),
     #[error("IO error: {0}")]
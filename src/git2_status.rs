@@ -0,0 +1,258 @@
+//! Collects status through libgit2 instead of running `git status`, for
+//! `status.backend = "libgit2"`. It produces the same records and branch headers as
+//! `git status --porcelain=v2 --branch`, so everything after is shared with the default
+//! backend.
+
+use crate::git::{path_from_bytes, BranchInfo, StatusOptions, UntrackedFiles};
+use crate::porcelain::{Code, Modes, Porcelain, Record, XY};
+use anyhow::{Context, Result};
+use git2::{Delta, IndexEntryExtendedFlag, Status};
+use std::path::{absolute, Path};
+
+/// What libgit2 says about submodules isn't kept, like the porcelain field it stands for.
+const NOT_A_SUBMODULE: &str = "N...";
+
+pub fn collect(repo: &git2::Repository, root: &Path, options: &StatusOptions) -> Result<Porcelain> {
+    let untracked = match options.untracked_files {
+        Some(mode) => mode,
+        None => match repo
+            .config()
+            .and_then(|config| config.get_string("status.showUntrackedFiles"))
+            .as_deref()
+        {
+            Ok("no") => UntrackedFiles::No,
+            Ok("all") => UntrackedFiles::All,
+            _ => UntrackedFiles::Normal,
+        },
+    };
+    let mut status_options = git2::StatusOptions::new();
+    status_options
+        .include_untracked(untracked != UntrackedFiles::No)
+        .recurse_untracked_dirs(untracked == UntrackedFiles::All)
+        .include_ignored(false)
+        .renames_head_to_index(options.renames.unwrap_or_else(|| {
+            repo.config()
+                .and_then(|config| config.get_bool("status.renames"))
+                .unwrap_or(true)
+        }));
+    for pathspec in &options.pathspecs {
+        status_options.pathspec(repo_pathspec(root, pathspec)?);
+    }
+    let statuses = repo
+        .statuses(Some(&mut status_options))
+        .context("Failed to get the status from libgit2")?;
+
+    let index = repo.index()?;
+    let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+    let mut records = Vec::new();
+    for entry in statuses.iter() {
+        records.extend(records_for(&entry, &index, head.as_ref()));
+    }
+    Ok(Porcelain {
+        branch: branch(repo)?,
+        records,
+    })
+}
+
+/// The porcelain records for one status entry: usually one, but a file deleted from the
+/// index that's still in the working tree is both a change and an untracked file.
+fn records_for(
+    entry: &git2::StatusEntry,
+    index: &git2::Index,
+    head: Option<&git2::Tree>,
+) -> Vec<Record> {
+    let status = entry.status();
+    let staged = entry.head_to_index();
+    let unstaged = entry.index_to_workdir();
+    let path = match (&staged, &unstaged) {
+        (Some(delta), _) | (None, Some(delta)) => delta
+            .new_file()
+            .path_bytes()
+            .or(delta.old_file().path_bytes())
+            .unwrap_or_default()
+            .to_vec(),
+        (None, None) => entry.path_bytes().to_vec(),
+    };
+
+    if status.is_conflicted() {
+        return vec![Record::Unmerged {
+            xy: XY {
+                index: Code::Unmerged,
+                worktree: Code::Unmerged,
+            },
+            submodule: NOT_A_SUBMODULE.to_string(),
+            path,
+        }];
+    }
+    let index_entry = index.get_path(&path_from_bytes(&path), 0);
+    // `git add -N` leaves an empty placeholder in the index, which libgit2 reports as a
+    // staged new file; git reports the file as new in the working tree only
+    let intent_to_add = index_entry.as_ref().is_some_and(|entry| {
+        IndexEntryExtendedFlag::from_bits_truncate(entry.flags_extended).is_intent_to_add()
+    });
+    let xy = match intent_to_add {
+        true => XY {
+            index: Code::Unmodified,
+            worktree: Code::Added,
+        },
+        false => XY {
+            index: index_code(status),
+            worktree: worktree_code(status),
+        },
+    };
+    if xy.index == Code::Unmodified && xy.worktree == Code::Unmodified {
+        return match status.is_wt_new() {
+            true => vec![Record::Untracked { path }],
+            false => Vec::new(),
+        };
+    }
+
+    // Each side's modes come from its delta, and are the same as the side next to them
+    // when that side didn't change
+    let mode = |mode: git2::FileMode| format!("{:06o}", u32::from(mode));
+    let index_mode = index_entry.map(|entry| format!("{:06o}", entry.mode));
+    let head_mode = match &staged {
+        Some(delta) => mode(delta.old_file().mode()),
+        None => head
+            .and_then(|tree| tree.get_path(&path_from_bytes(&path)).ok())
+            .map(|entry| format!("{:06o}", entry.filemode()))
+            .or(index_mode.clone())
+            .unwrap_or_else(|| mode(git2::FileMode::Unreadable)),
+    };
+    let index_mode = match &staged {
+        // Like git, the placeholder has no mode
+        Some(_) if intent_to_add => mode(git2::FileMode::Unreadable),
+        Some(delta) => mode(delta.new_file().mode()),
+        None => index_mode.unwrap_or_else(|| head_mode.clone()),
+    };
+    let worktree_mode = match &unstaged {
+        Some(delta) => mode(delta.new_file().mode()),
+        None => index_mode.clone(),
+    };
+    let modes = Modes {
+        head: head_mode,
+        index: index_mode,
+        worktree: worktree_mode,
+    };
+
+    let mut records = vec![
+        match staged.filter(|delta| delta.status() == Delta::Renamed) {
+            Some(delta) => Record::Moved {
+                xy,
+                submodule: NOT_A_SUBMODULE.to_string(),
+                modes,
+                kind: Code::Renamed,
+                // libgit2 doesn't report how similar the files were
+                score: 0,
+                path,
+                original_path: delta.old_file().path_bytes().unwrap_or_default().to_vec(),
+            },
+            None => Record::Changed {
+                xy,
+                submodule: NOT_A_SUBMODULE.to_string(),
+                modes,
+                path: path.clone(),
+            },
+        },
+    ];
+    if status.is_wt_new() {
+        records.push(Record::Untracked {
+            path: entry.path_bytes().to_vec(),
+        });
+    }
+    records
+}
+
+fn index_code(status: Status) -> Code {
+    if status.is_index_new() {
+        Code::Added
+    } else if status.is_index_deleted() {
+        Code::Deleted
+    } else if status.is_index_renamed() {
+        Code::Renamed
+    } else if status.is_index_typechange() {
+        Code::TypeChanged
+    } else if status.is_index_modified() {
+        Code::Modified
+    } else {
+        Code::Unmodified
+    }
+}
+
+fn worktree_code(status: Status) -> Code {
+    if status.is_wt_deleted() {
+        Code::Deleted
+    } else if status.is_wt_typechange() {
+        Code::TypeChanged
+    } else if status.is_wt_modified() {
+        Code::Modified
+    } else {
+        Code::Unmodified
+    }
+}
+
+/// A pathspec given relative to the current directory, made relative to the repo root
+/// the way libgit2 takes it. Of the magic pathspecs only `:/`, the root, is understood.
+fn repo_pathspec(root: &Path, pathspec: &Path) -> Result<String> {
+    let text = pathspec.to_string_lossy();
+    if let Some(from_root) = text.strip_prefix(":/") {
+        return Ok(from_root.to_string());
+    }
+    if text.starts_with(':') {
+        return Err(anyhow::anyhow!(
+            "The pathspec `{}` needs `status.backend = \"git\"`",
+            text
+        ));
+    }
+    let path = absolute(pathspec)?;
+    let relative = path
+        .strip_prefix(root)
+        .map_err(|_| anyhow::anyhow!("{} is outside the repository", pathspec.display()))?;
+    Ok(relative.to_string_lossy().into_owned())
+}
+
+/// The branch headers `git status --branch` prints.
+fn branch(repo: &git2::Repository) -> Result<BranchInfo> {
+    let mut info = BranchInfo::default();
+    let head = match repo.head() {
+        Ok(head) => head,
+        // Before the first commit HEAD names a branch that doesn't exist yet
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => {
+            info.head = repo
+                .find_reference("HEAD")?
+                .symbolic_target()
+                .and_then(|target| target.strip_prefix("refs/heads/"))
+                .map(str::to_string);
+            return Ok(info);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    info.oid = head.target().map(|oid| oid.to_string());
+    if !head.is_branch() {
+        return Ok(info);
+    }
+    info.head = head.shorthand().map(str::to_string);
+
+    let Some(name) = head.name() else {
+        return Ok(info);
+    };
+    // The upstream is named by config, so it's known even when its ref is gone
+    let Ok(upstream) = repo.branch_upstream_name(name) else {
+        return Ok(info);
+    };
+    let Some(upstream) = upstream.as_str() else {
+        return Ok(info);
+    };
+    info.upstream = Some(
+        upstream
+            .strip_prefix("refs/remotes/")
+            .or(upstream.strip_prefix("refs/heads/"))
+            .unwrap_or(upstream)
+            .to_string(),
+    );
+    if let (Some(local), Ok(remote)) = (head.target(), repo.refname_to_id(upstream)) {
+        let (ahead, behind) = repo.graph_ahead_behind(local, remote)?;
+        info.ahead_behind = Some((ahead as u32, behind as u32));
+    }
+    Ok(info)
+}
@@ -0,0 +1,122 @@
+//! Where a diff's changes are in the new version of the file, worked out from its hunks,
+//! so output can point at the lines to look at.
+
+use serde::{Deserialize, Serialize};
+
+/// Lines `start` to `end` of the new version of a file, counting from 1. A hunk that only
+/// removes lines is placed at the line after the removal.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LineRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// The lines each hunk of a one-file diff changes, without the context around them.
+/// Empty for a diff that covers more than one file, like an untracked directory's.
+pub fn changed_ranges(diff: &str) -> Vec<LineRange> {
+    if diff.lines().filter(|line| line.starts_with("+++ ")).count() > 1 {
+        return Vec::new();
+    }
+    let mut ranges = Vec::new();
+    let mut hunk: Option<LineRange> = None;
+    // The new file's line number the next line of the hunk is at. An untracked file's
+    // diff is all added lines, with no hunk header
+    let mut line = 1;
+    for text in diff.lines() {
+        if let Some(start) = hunk_start(text) {
+            ranges.extend(hunk.take());
+            line = start;
+            continue;
+        }
+        match text.as_bytes().first() {
+            Some(b'+') if !text.starts_with("+++ ") => {
+                widen(&mut hunk, line);
+                line += 1;
+            }
+            Some(b'-') if !text.starts_with("--- ") => widen(&mut hunk, line.max(1)),
+            Some(b' ') => line += 1,
+            _ => {}
+        }
+    }
+    ranges.extend(hunk);
+    ranges
+}
+
+/// Where a hunk header says the new side starts, as in `@@ -1,3 +12,4 @@`. A hunk that
+/// adds no lines names the line before it, so that's moved on by one.
+fn hunk_start(line: &str) -> Option<u32> {
+    let rest = line.strip_prefix("@@ ")?;
+    let new = rest.split_whitespace().find(|part| part.starts_with('+'))?;
+    let mut parts = new[1..].split(',');
+    let start: u32 = parts.next()?.parse().ok()?;
+    match parts.next() {
+        Some("0") => Some(start + 1),
+        _ => Some(start),
+    }
+}
+
+fn widen(range: &mut Option<LineRange>, line: u32) {
+    match range {
+        Some(range) => {
+            range.start = range.start.min(line);
+            range.end = range.end.max(line);
+        }
+        None => {
+            *range = Some(LineRange {
+                start: line,
+                end: line,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u32, end: u32) -> LineRange {
+        LineRange { start, end }
+    }
+
+    #[test]
+    fn test_changed_ranges() {
+        let diff = "\
+diff --git a/src/retry.rs b/src/retry.rs
+--- a/src/retry.rs
++++ b/src/retry.rs
+@@ -1,4 +1,5 @@
+ use std::time::Duration;
++use std::thread;
+
+ fn retry() {
+     loop {
+@@ -42,7 +43,6 @@ fn retry() {
+         attempt += 1;
+         wait();
+-        log();
+-        log();
++        log_once();
+         done();
+         return;
+@@ -90,3 +90,2 @@
+ fn tail() {
+-    old();
+ }
+";
+        assert_eq!(
+            changed_ranges(diff),
+            [range(2, 2), range(45, 45), range(91, 91)]
+        );
+
+        // A new file is changed throughout
+        let added = "--- /dev/null\n+++ b/new.rs\n@@ -0,0 +1,3 @@\n+one\n+two\n+three\n";
+        assert_eq!(changed_ranges(added), [range(1, 3)]);
+        assert_eq!(changed_ranges("+one\n+two"), [range(1, 2)]);
+        // Emptying a file leaves nothing to point at but its start
+        let emptied = "--- a/old.rs\n+++ b/old.rs\n@@ -1,2 +0,0 @@\n-one\n-two\n";
+        assert_eq!(changed_ranges(emptied), [range(1, 1)]);
+
+        let directory = "+++ dir/a.txt\n@@ -0,0 +1 @@\n+a\n+++ dir/b.txt\n@@ -0,0 +1 @@\n+b\n";
+        assert!(changed_ranges(directory).is_empty());
+    }
+}
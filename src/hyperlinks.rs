@@ -0,0 +1,97 @@
+//! OSC 8 hyperlinks on file paths, so clicking one in a terminal that supports them opens
+//! the file, at its first change when the link can say where.
+
+use crate::template::{self, Vars};
+use anyhow::Result;
+use std::path::Path;
+
+/// Where `status.hyperlinks` points a file's link.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Links {
+    /// `file://` URLs, which open the file but can't say at which line
+    File,
+    /// `vscode://file` URLs, which open the file in VS Code at the line
+    Vscode,
+    /// A URL template, filled in with `{{path}}` and `{{line}}`
+    Template(String),
+}
+
+impl Links {
+    /// The links `status.hyperlinks` asks for: `file`, `vscode`, or a URL template.
+    pub fn new(setting: &str) -> Result<Self> {
+        let links = match setting {
+            "file" => Links::File,
+            "vscode" => Links::Vscode,
+            template => Links::Template(template.to_string()),
+        };
+        // A template with a typo fails now rather than on the first changed file
+        links
+            .url(Path::new("/"), 1)
+            .map_err(|e| anyhow::anyhow!("status.hyperlinks: {:#}", e))?;
+        Ok(links)
+    }
+
+    /// The URL to open `path`, an absolute path, at `line`.
+    pub fn url(&self, path: &Path, line: u32) -> Result<String> {
+        let path = encode(&path.to_string_lossy());
+        match self {
+            Links::File => Ok(format!("file://{}", path)),
+            Links::Vscode => Ok(format!("vscode://file{}:{}", path, line)),
+            Links::Template(template) => template::render(
+                template,
+                &Vars::new().set("path", path).set("line", line.to_string()),
+            ),
+        }
+    }
+}
+
+/// `text` as a link to `url`, which a terminal without OSC 8 support shows as just `text`.
+pub fn link(text: &str, url: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Percent-encodes everything in a path but letters, digits, `/`, and `-._~`.
+fn encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url() -> Result<()> {
+        let path = Path::new("/home/me/my repo/src/main.rs");
+        assert_eq!(
+            Links::new("file")?.url(path, 12)?,
+            "file:///home/me/my%20repo/src/main.rs"
+        );
+        assert_eq!(
+            Links::new("vscode")?.url(path, 12)?,
+            "vscode://file/home/me/my%20repo/src/main.rs:12"
+        );
+        assert_eq!(
+            Links::new("idea://open?file={{path}}&line={{line}}")?.url(path, 12)?,
+            "idea://open?file=/home/me/my%20repo/src/main.rs&line=12"
+        );
+        assert!(Links::new("idea://open?file={{file}}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_link() {
+        assert_eq!(
+            link("src/main.rs", "file:///src/main.rs"),
+            "\x1b]8;;file:///src/main.rs\x1b\\src/main.rs\x1b]8;;\x1b\\"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Coordinates `git hud` processes running at once, like a shell prompt integration and
+//! a run by hand, so the same diff isn't summarized twice. The first process to claim a
+//! diff holds a lock file in the cache dir while it asks for the summary, then leaves the
+//! result next to it; any other process that wants the same diff waits for the lock to
+//! go away and reuses the result.
+
+use crate::cache::{self, CachedSummary};
+use anyhow::Result;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often a waiting process checks whether the lock is gone.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Past this, a lock is taken to belong to a process that died without removing it, for
+/// locks that don't say which process holds them. Shorter than `MAX_WAIT`, so a waiting
+/// process gets to take it over rather than give up.
+const STALE_LOCK: Duration = Duration::from_secs(60);
+/// How long to wait on another process before making the request anyway.
+const MAX_WAIT: Duration = Duration::from_secs(90);
+/// How long results are kept for other processes to pick up.
+const RESULT_TTL: Duration = Duration::from_secs(600);
+
+/// Whether a summary was made here or taken from another process.
+pub enum Outcome {
+    Made(CachedSummary),
+    Reused(CachedSummary),
+}
+
+/// Runs `summarize` for the diff hashing to `diff_hash`, unless another process is
+/// already summarizing the same diff of the same file, in which case waits for its result.
+pub async fn coalesce<F>(
+    repo_root: &Path,
+    key: &str,
+    diff_hash: &str,
+    summarize: F,
+) -> Result<Outcome>
+where
+    F: Future<Output = Result<CachedSummary>>,
+{
+    match cache::dir() {
+        Some(dir) => {
+            coalesce_in(
+                &dir.join("inflight"),
+                &id(repo_root, key, diff_hash),
+                summarize,
+            )
+            .await
+        }
+        None => Ok(Outcome::Made(summarize.await?)),
+    }
+}
+
+/// Names a file's diff the same way in every process.
+fn id(repo_root: &Path, key: &str, diff_hash: &str) -> String {
+    cache::hash(&format!(
+        "{}\0{}\0{}",
+        repo_root.to_string_lossy(),
+        key,
+        diff_hash
+    ))[..32]
+        .to_string()
+}
+
+async fn coalesce_in<F>(dir: &Path, id: &str, summarize: F) -> Result<Outcome>
+where
+    F: Future<Output = Result<CachedSummary>>,
+{
+    let lock_path = dir.join(format!("{}.lock", id));
+    let result_path = dir.join(format!("{}.json", id));
+    let _ = std::fs::create_dir_all(dir);
+
+    let waited = SystemTime::now();
+    let lock = loop {
+        if let Some(result) = read_result(&result_path) {
+            return Ok(Outcome::Reused(result));
+        }
+        match Lock::claim(&lock_path) {
+            Some(lock) => break Some(lock),
+            // It was just released, so its result is there now; or the lock can't be
+            // created at all, and each process is on its own
+            None if !lock_path.exists() => match read_result(&result_path) {
+                Some(result) => return Ok(Outcome::Reused(result)),
+                None => break None,
+            },
+            None if holder_exited(&lock_path) || age(&lock_path) > STALE_LOCK => {
+                let _ = std::fs::remove_file(&lock_path);
+            }
+            None if age_since(waited) > MAX_WAIT => break None,
+            None => tokio::time::sleep(POLL_INTERVAL).await,
+        }
+    };
+
+    let summary = summarize.await?;
+    if lock.is_some() {
+        // Others only need the result while they're waiting, so failing to share it
+        // isn't an error
+        let _ = write_result(dir, &result_path, &summary);
+    }
+    Ok(Outcome::Made(summary))
+}
+
+/// A claimed lock file, removed when dropped, so an error or panic doesn't leave it
+/// for others to wait out. It holds the claiming process's ID, so a lock left by a
+/// process that was killed is taken over as soon as it's noticed.
+struct Lock(PathBuf);
+
+impl Lock {
+    fn claim(path: &Path) -> Option<Self> {
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .ok()?;
+        let _ = write!(file, "{}", std::process::id());
+        Some(Self(path.to_path_buf()))
+    }
+}
+
+/// Whether the process that claimed a lock has exited. A lock that doesn't name one,
+/// like one that's only just been created, isn't.
+fn holder_exited(path: &Path) -> bool {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+        .is_some_and(|pid| !is_running(pid))
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // Signal 0 checks the process exists without disturbing it. If `kill` itself can't be
+    // run, the process is taken to be running and the lock waits to go stale
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map_or(true, |status| status.success())
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn read_result(path: &Path) -> Option<CachedSummary> {
+    if age(path) > RESULT_TTL {
+        return None;
+    }
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+/// Writes the result atomically, so a waiting process never reads it half written, and
+/// clears out old results.
+fn write_result(dir: &Path, path: &Path, summary: &CachedSummary) -> Result<()> {
+    cache::write_atomic(path, &serde_json::to_string(summary)?)?;
+    for file in std::fs::read_dir(dir)?.flatten() {
+        let path = file.path();
+        let is_result = path
+            .extension()
+            .is_some_and(|extension| extension == "json");
+        if is_result && age(&path) > RESULT_TTL {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    Ok(())
+}
+
+/// How long ago a file was last written, or zero if it doesn't exist.
+fn age(path: &Path) -> Duration {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(age_since)
+        .unwrap_or_default()
+}
+
+fn age_since(time: SystemTime) -> Duration {
+    time.elapsed().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(text: &str) -> CachedSummary {
+        CachedSummary {
+            diff_hash: "hash".to_string(),
+            blob: None,
+            summary: text.to_string(),
+            provider: "command".to_string(),
+            model: "cat".to_string(),
+            tokens: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coalesce() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path();
+
+        // Someone else holds the lock, then finishes and leaves their result
+        let lock = Lock::claim(&dir.join("a.lock")).unwrap();
+        let finish = async {
+            tokio::time::sleep(POLL_INTERVAL * 3).await;
+            write_result(dir, &dir.join("a.json"), &summary("theirs")).unwrap();
+            drop(lock);
+        };
+        let waiting = coalesce_in(dir, "a", async { Ok(summary("mine")) });
+        let (outcome, ()) = tokio::join!(waiting, finish);
+        assert!(matches!(outcome?, Outcome::Reused(s) if s.summary == "theirs"));
+
+        // With nobody else at it, the summary is made here, shared, and unlocked
+        let outcome = coalesce_in(dir, "b", async { Ok(summary("mine")) }).await?;
+        assert!(matches!(outcome, Outcome::Made(s) if s.summary == "mine"));
+        assert!(!dir.join("b.lock").exists());
+        assert_eq!(read_result(&dir.join("b.json")).unwrap().summary, "mine");
+
+        // A lock that outlived its process is taken over
+        std::fs::write(dir.join("c.lock"), "")?;
+        let old = SystemTime::now() - STALE_LOCK * 2;
+        std::fs::File::options()
+            .write(true)
+            .open(dir.join("c.lock"))?
+            .set_modified(old)?;
+        let outcome = coalesce_in(dir, "c", async { Ok(summary("mine")) }).await?;
+        assert!(matches!(outcome, Outcome::Made(_)));
+
+        // So is a fresh one whose process is gone
+        let mut exited = std::process::Command::new("true").spawn()?;
+        exited.wait()?;
+        std::fs::write(dir.join("d.lock"), exited.id().to_string())?;
+        let outcome = tokio::time::timeout(
+            POLL_INTERVAL * 10,
+            coalesce_in(dir, "d", async { Ok(summary("mine")) }),
+        )
+        .await??;
+        assert!(matches!(outcome, Outcome::Made(_)));
+
+        // While one held by a running process is waited on
+        std::fs::write(dir.join("e.lock"), std::process::id().to_string())?;
+        assert!(!holder_exited(&dir.join("e.lock")));
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! Guesses a file's programming language from its name or shebang, so a new file's
+//! summary prompt can say what it's looking at.
+
+use std::path::Path;
+
+/// The language of a file, or `None` if nothing about it is recognized.
+pub fn detect(path: &Path, content: &str) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    by_name(name)
+        .or_else(|| {
+            let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+            by_extension(&extension)
+        })
+        .or_else(|| by_shebang(content.lines().next()?))
+}
+
+fn by_name(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "Dockerfile" | "Containerfile" => "Dockerfile",
+        "Makefile" | "GNUmakefile" => "Makefile",
+        "CMakeLists.txt" => "CMake",
+        "Justfile" | "justfile" => "Just",
+        "Gemfile" | "Rakefile" => "Ruby",
+        _ => return None,
+    })
+}
+
+fn by_extension(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        "rs" => "Rust",
+        "py" | "pyi" => "Python",
+        "js" | "mjs" | "cjs" => "JavaScript",
+        "jsx" => "JavaScript (JSX)",
+        "ts" | "mts" | "cts" => "TypeScript",
+        "tsx" => "TypeScript (TSX)",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "scala" => "Scala",
+        "swift" => "Swift",
+        "c" => "C",
+        "h" => "C header",
+        "cc" | "cpp" | "cxx" => "C++",
+        "hh" | "hpp" | "hxx" => "C++ header",
+        "cs" => "C#",
+        "m" => "Objective-C",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "pl" | "pm" => "Perl",
+        "lua" => "Lua",
+        "dart" => "Dart",
+        "ex" | "exs" => "Elixir",
+        "erl" => "Erlang",
+        "hs" => "Haskell",
+        "clj" | "cljs" => "Clojure",
+        "ml" | "mli" => "OCaml",
+        "zig" => "Zig",
+        "r" => "R",
+        "jl" => "Julia",
+        "sh" | "bash" | "zsh" => "shell script",
+        "ps1" => "PowerShell",
+        "sql" => "SQL",
+        "html" | "htm" => "HTML",
+        "css" => "CSS",
+        "scss" | "sass" => "Sass",
+        "vue" => "Vue",
+        "svelte" => "Svelte",
+        "md" | "markdown" => "Markdown",
+        "rst" => "reStructuredText",
+        "toml" => "TOML",
+        "yaml" | "yml" => "YAML",
+        "json" => "JSON",
+        "xml" => "XML",
+        "proto" => "Protocol Buffers",
+        "graphql" | "gql" => "GraphQL",
+        "tf" => "Terraform",
+        "nix" => "Nix",
+        _ => return None,
+    })
+}
+
+fn by_shebang(line: &str) -> Option<&'static str> {
+    let command = line.strip_prefix("#!")?;
+    // `#!/usr/bin/env python3` names the interpreter after env
+    let interpreter = command
+        .split_whitespace()
+        .find(|word| !word.ends_with("/env") && !word.starts_with('-'))?;
+    let interpreter = interpreter.rsplit('/').next()?;
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match interpreter {
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => "shell script",
+        "python" => "Python",
+        "node" | "deno" => "JavaScript",
+        "ruby" => "Ruby",
+        "perl" => "Perl",
+        "php" => "PHP",
+        "lua" => "Lua",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let detect_path = |path: &str| detect(Path::new(path), "");
+        assert_eq!(detect_path("src/cli.rs"), Some("Rust"));
+        assert_eq!(detect_path("web/App.TSX"), Some("TypeScript (TSX)"));
+        assert_eq!(detect_path("Dockerfile"), Some("Dockerfile"));
+        assert_eq!(detect_path("notes.txt"), None);
+        assert_eq!(detect_path("LICENSE"), None);
+
+        assert_eq!(
+            detect(
+                Path::new("bin/deploy"),
+                "#!/usr/bin/env python3\nimport sys\n"
+            ),
+            Some("Python")
+        );
+        assert_eq!(
+            detect(Path::new("bin/setup"), "#!/bin/bash -e\n"),
+            Some("shell script")
+        );
+        assert_eq!(
+            detect(Path::new("bin/run"), "#!/usr/bin/env -S weird\n"),
+            None
+        );
+        assert_eq!(detect(Path::new("script"), "no shebang"), None);
+    }
+}
@@ -0,0 +1,202 @@
+//! What the last status run showed, kept with a signature of the state it was made from,
+//! so a run when nothing has changed since can show it again straight away, without
+//! collecting diffs or asking for summaries.
+
+use crate::cache;
+use crate::git::{Repository, Status, StatusCode};
+use crate::hunks::LineRange;
+use crate::FileWithSummary;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LastRun {
+    /// What the files were shown from: see `signature`
+    pub signature: String,
+    pub files: Vec<ShownFile>,
+    /// The one-line summary of the upstream's new commits, if there was one
+    pub incoming: Option<String>,
+}
+
+/// One side of one changed file, as it was shown. Diffs aren't kept, so runs that need
+/// them, like `--patch`, don't reuse what was shown.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShownFile {
+    pub path: String,
+    pub abs_path: PathBuf,
+    pub status: StatusCode,
+    pub staged: bool,
+    pub original_path: Option<String>,
+    pub summary: Option<String>,
+    pub encoding: Option<String>,
+    pub mode_change: Option<(String, String)>,
+    pub not_summarized: bool,
+    pub line_ranges: Vec<LineRange>,
+}
+
+impl LastRun {
+    pub fn new(signature: String, files: &[FileWithSummary], incoming: Option<String>) -> Self {
+        Self {
+            signature,
+            files: files
+                .iter()
+                .map(|file| ShownFile {
+                    path: file.path.clone(),
+                    abs_path: file.abs_path.clone(),
+                    status: file.status.clone(),
+                    staged: file.staged,
+                    original_path: file.original_path.clone(),
+                    summary: file.summary.clone(),
+                    encoding: file.encoding.map(str::to_string),
+                    mode_change: file.mode_change.clone(),
+                    not_summarized: file.not_summarized,
+                    line_ranges: file.line_ranges.clone(),
+                })
+                .collect(),
+            incoming,
+        }
+    }
+
+    /// The files to show again.
+    pub fn into_files(self) -> Vec<FileWithSummary> {
+        self.files
+            .into_iter()
+            .map(|file| FileWithSummary {
+                path: file.path,
+                abs_path: file.abs_path,
+                status: file.status,
+                staged: file.staged,
+                original_path: file.original_path,
+                summary: file.summary,
+                encoding: file
+                    .encoding
+                    .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+                    .map(|encoding| encoding.name()),
+                mode_change: file.mode_change,
+                not_summarized: file.not_summarized,
+                diff: None,
+                line_ranges: file.line_ranges,
+                submodule_files: Vec::new(),
+            })
+            .collect()
+    }
+
+    /// The last run in this repo, if it was made from the state `signature` stands for.
+    pub fn load(repo_root: &Path, signature: &str) -> Option<Self> {
+        let contents = std::fs::read_to_string(path(repo_root)?).ok()?;
+        serde_json::from_str(&contents)
+            .ok()
+            .filter(|last: &Self| last.signature == signature)
+    }
+
+    pub fn save(&self, repo_root: &Path) -> Result<()> {
+        let Some(path) = path(repo_root) else {
+            return Ok(());
+        };
+        cache::write_atomic(&path, &serde_json::to_string(self)?)
+            .context("Failed to save the last run")
+    }
+}
+
+/// A hash of everything a run's output is made from: HEAD and its upstream, when the
+/// index was last written, each changed file's status, size, and modification time (for
+/// an untracked directory, those of every file in it), and `settings`, which stands for
+/// the config.
+pub fn signature(repo: &Repository, status: &Status, settings: &str) -> String {
+    let branch = &status.branch;
+    let mut text = format!(
+        "{:?} {:?} {:?} {:?}\n{}\n",
+        branch.oid,
+        branch.upstream,
+        branch.ahead_behind,
+        repo.index_modified().map(stamp),
+        settings
+    );
+    for entry in &status.entries {
+        text.push_str(&format!(
+            "{} {:?} {:?}",
+            entry.display_path, entry.index, entry.worktree
+        ));
+        let mut files = vec![entry.abs_path.clone()];
+        if entry.abs_path.is_dir() {
+            files = files_in(&entry.abs_path);
+            files.sort();
+        }
+        for file in files {
+            if let Ok(metadata) = std::fs::symlink_metadata(&file) {
+                let modified = metadata.modified().ok().map(stamp);
+                text.push_str(&format!(
+                    " {} {} {:?}",
+                    file.display(),
+                    metadata.len(),
+                    modified
+                ));
+            }
+        }
+        text.push('\n');
+    }
+    cache::hash(&text)
+}
+
+/// Nanoseconds since the epoch.
+fn stamp(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+/// Every file under `dir`, at any depth.
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let Ok(children) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    children
+        .flatten()
+        .flat_map(|child| match child.file_type() {
+            Ok(kind) if kind.is_dir() => files_in(&child.path()),
+            _ => vec![child.path()],
+        })
+        .collect()
+}
+
+/// `last-run/<repo hash>.json` in the cache dir.
+fn path(repo_root: &Path) -> Option<PathBuf> {
+    let name = &cache::hash(&repo_root.to_string_lossy())[..16];
+    Some(
+        cache::dir()?
+            .join("last-run")
+            .join(format!("{}.json", name)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::tests::{git, setup_test_repo};
+    use std::fs;
+
+    #[test]
+    fn test_signature() -> Result<()> {
+        let (temp_dir, repo) = setup_test_repo()?;
+        fs::create_dir_all(temp_dir.path().join("dir/nested"))?;
+        fs::write(temp_dir.path().join("dir/nested/file.txt"), "one\n")?;
+        fs::write(temp_dir.path().join("notes.txt"), "one\n")?;
+        let signature =
+            |settings| -> Result<String> { Ok(signature(&repo, &repo.get_status()?, settings)) };
+
+        let first = signature("")?;
+        assert_eq!(signature("")?, first);
+        assert_ne!(signature("summarizer = \"cat\"")?, first);
+
+        // A file deep in an untracked directory changes it
+        fs::write(temp_dir.path().join("dir/nested/file.txt"), "one\ntwo\n")?;
+        let second = signature("")?;
+        assert_ne!(second, first);
+
+        git(temp_dir.path(), &["add", "notes.txt"])?;
+        assert_ne!(signature("")?, second);
+        Ok(())
+    }
+}
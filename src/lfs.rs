@@ -0,0 +1,135 @@
+//! Recognizes diffs of Git LFS pointer files.
+//!
+//! A file tracked by LFS is stored as a short pointer naming the real object by hash and
+//! size, so its diff is a changed hash. There's nothing in that for a summarizer, so the
+//! change is described from the pointer instead.
+
+const VERSION_PREFIX: &str = "version https://git-lfs.github.com/spec/";
+
+/// Describes a diff of an LFS pointer, e.g. "LFS object updated, 45MB", or returns `None`
+/// if the diff touches anything other than pointer lines.
+pub fn describe_pointer_diff(diff: &str) -> Option<String> {
+    let mut old = Vec::new();
+    let mut new = Vec::new();
+    for line in diff.lines() {
+        if line.starts_with("+++ ") || line.starts_with("--- ") {
+            continue;
+        }
+        // Headers, hunk markers, and "\ No newline" notes don't start with these
+        let (in_old, in_new, text) = match line.as_bytes().first() {
+            Some(b'+') => (false, true, &line[1..]),
+            Some(b'-') => (true, false, &line[1..]),
+            Some(b' ') => (true, true, &line[1..]),
+            _ => continue,
+        };
+        if !is_pointer_line(text) {
+            return None;
+        }
+        if in_old {
+            old.push(text);
+        }
+        if in_new {
+            new.push(text);
+        }
+    }
+
+    match (pointer_size(&old), pointer_size(&new)) {
+        (Some(_), Some(size)) => Some(format!("LFS object updated, {}", format_size(size))),
+        (None, Some(size)) if old.is_empty() => {
+            Some(format!("LFS object added, {}", format_size(size)))
+        }
+        (Some(_), None) if new.is_empty() => Some("LFS object removed".to_string()),
+        _ => None,
+    }
+}
+
+fn is_pointer_line(line: &str) -> bool {
+    let Some((key, value)) = line.split_once(' ') else {
+        return false;
+    };
+    !value.is_empty() && (matches!(key, "version" | "oid" | "size") || key.starts_with("ext-"))
+}
+
+/// The object size a complete pointer names, or `None` if the lines aren't one.
+fn pointer_size(lines: &[&str]) -> Option<u64> {
+    if !lines.first()?.starts_with(VERSION_PREFIX) {
+        return None;
+    }
+    if !lines.iter().any(|line| line.starts_with("oid sha256:")) {
+        return None;
+    }
+    lines
+        .iter()
+        .find_map(|line| line.strip_prefix("size "))?
+        .parse()
+        .ok()
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match size < 10.0 {
+        true => format!("{:.1}{}", size, UNITS[unit]),
+        false => format!("{:.0}{}", size, UNITS[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATED: &str = "diff --git model.bin model.bin
+index 4d7a214..b3f5e1c 100644
+--- model.bin
++++ model.bin
+@@ -1,3 +1,3 @@
+ version https://git-lfs.github.com/spec/v1
+-oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393
+-size 12345
++oid sha256:b3f5e1c0a8f3e4d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b2a1f0e9d8
++size 47185920
+";
+
+    #[test]
+    fn test_describe_pointer_diff() {
+        assert_eq!(
+            describe_pointer_diff(UPDATED).as_deref(),
+            Some("LFS object updated, 45MB")
+        );
+
+        // Untracked files are diffed as all-added lines with no header
+        let added = "+version https://git-lfs.github.com/spec/v1\n+oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393\n+size 2048";
+        assert_eq!(
+            describe_pointer_diff(added).as_deref(),
+            Some("LFS object added, 2.0KB")
+        );
+
+        let removed = added.replace('+', "-");
+        assert_eq!(
+            describe_pointer_diff(&removed).as_deref(),
+            Some("LFS object removed")
+        );
+
+        let text = "@@ -1 +1 @@\n-size of the thing\n+size of the other thing\n";
+        assert_eq!(describe_pointer_diff(text), None);
+        let mixed = UPDATED.replace("+size 47185920", "+size 47185920\n+more text");
+        assert_eq!(describe_pointer_diff(&mixed), None);
+        assert_eq!(describe_pointer_diff(""), None);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 bytes");
+        assert_eq!(format_size(1536), "1.5KB");
+        assert_eq!(format_size(45 * 1024 * 1024), "45MB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0GB");
+    }
+}
@@ -0,0 +1,439 @@
+use crate::config::ProviderConfig;
+use crate::error::HudError;
+use crate::summary::{NewDirectory, NewFile, OnText, Reply, Summarizer, SummaryRequest};
+use crate::tokens::Estimator;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Semaphore};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+    pub max_concurrent: usize,
+    pub max_retries: u32,
+    /// What `tokens_per_minute` counts requests with
+    pub tokens: Estimator,
+}
+
+impl Limits {
+    /// Anthropic's entry-tier limits.
+    pub fn anthropic() -> Self {
+        Self {
+            requests_per_minute: Some(50),
+            tokens_per_minute: Some(50_000),
+            max_concurrent: 8,
+            max_retries: 3,
+            tokens: Estimator::default(),
+        }
+    }
+
+    /// Commands and plugins run locally, so they're only kept from running all at once.
+    pub fn local() -> Self {
+        Self {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_concurrent: 8,
+            max_retries: 0,
+            tokens: Estimator::default(),
+        }
+    }
+
+    /// Applies the limits set in a provider's config over these defaults.
+    pub fn with_config(self, config: Option<&ProviderConfig>) -> Self {
+        let Some(config) = config else {
+            return self;
+        };
+        Self {
+            requests_per_minute: config.requests_per_minute.or(self.requests_per_minute),
+            tokens_per_minute: config.tokens_per_minute.or(self.tokens_per_minute),
+            max_concurrent: config.max_concurrent.unwrap_or(self.max_concurrent).max(1),
+            max_retries: config.max_retries.unwrap_or(self.max_retries),
+            tokens: Estimator::new(Some(config)),
+        }
+    }
+}
+
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(limit: u32, now: Instant) -> Self {
+        Self {
+            capacity: limit as f64,
+            available: limit as f64,
+            per_second: limit as f64 / 60.0,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// How long until `amount` is available, or `None` if it already is.
+    fn wait_for(&self, amount: f64) -> Option<Duration> {
+        // A request bigger than the whole bucket only has to wait for a full bucket
+        let amount = amount.min(self.capacity);
+        (self.available < amount)
+            .then(|| Duration::from_secs_f64((amount - self.available) / self.per_second))
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+}
+
+/// Request and token buckets shared by every request to one provider.
+struct RateLimiter {
+    buckets: Mutex<(Option<TokenBucket>, Option<TokenBucket>)>,
+}
+
+impl RateLimiter {
+    fn new(limits: &Limits) -> Self {
+        let now = Instant::now();
+        Self {
+            buckets: Mutex::new((
+                limits
+                    .requests_per_minute
+                    .map(|limit| TokenBucket::per_minute(limit, now)),
+                limits
+                    .tokens_per_minute
+                    .map(|limit| TokenBucket::per_minute(limit, now)),
+            )),
+        }
+    }
+
+    /// Waits until both buckets allow a request of `tokens` tokens, then takes from both.
+    async fn acquire(&self, tokens: u32) {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().await;
+                let (requests, token_bucket) = &mut *buckets;
+                let now = Instant::now();
+                requests.iter_mut().for_each(|b| b.refill(now));
+                token_bucket.iter_mut().for_each(|b| b.refill(now));
+
+                let wait = [
+                    requests.as_ref().and_then(|b| b.wait_for(1.0)),
+                    token_bucket
+                        .as_ref()
+                        .and_then(|b| b.wait_for(tokens as f64)),
+                ]
+                .into_iter()
+                .flatten()
+                .max();
+                if wait.is_none() {
+                    requests.iter_mut().for_each(|b| b.take(1.0));
+                    token_bucket.iter_mut().for_each(|b| b.take(tokens as f64));
+                }
+                wait
+            };
+
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+}
+
+/// Keeps a provider within its rate limits: caps concurrent requests, spaces requests
+/// out with token buckets, and retries requests that were rate limited or failed
+/// transiently.
+pub struct LimitedSummarizer {
+    inner: Box<dyn Summarizer>,
+    limiter: RateLimiter,
+    concurrency: Semaphore,
+    max_retries: u32,
+    tokens: Estimator,
+}
+
+impl LimitedSummarizer {
+    pub fn new(inner: Box<dyn Summarizer>, limits: Limits) -> Self {
+        Self {
+            inner,
+            limiter: RateLimiter::new(&limits),
+            concurrency: Semaphore::new(limits.max_concurrent),
+            max_retries: limits.max_retries,
+            tokens: limits.tokens,
+        }
+    }
+
+    async fn limited<'a, F, Fut>(&'a self, tokens: u32, call: F) -> Result<String>
+    where
+        F: Fn() -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = Result<String>> + Send + 'a,
+    {
+        let mut attempt = 0;
+        loop {
+            let result = {
+                let _permit = self.concurrency.acquire().await?;
+                self.limiter.acquire(tokens).await;
+                call().await
+            };
+
+            match result {
+                Err(e) if attempt < self.max_retries && is_retryable(&e) => {
+                    tokio::time::sleep(backoff(attempt, retry_after(&e))).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Summarizer for LimitedSummarizer {
+    async fn complete(&self, prompt: String, reply: Reply) -> Result<String> {
+        let tokens = self.tokens.estimate(&prompt) + reply.max_tokens();
+        self.limited(tokens, || self.inner.complete(prompt.clone(), reply))
+            .await
+    }
+
+    async fn complete_streaming(
+        &self,
+        prompt: String,
+        reply: Reply,
+        on_text: &mut OnText<'_>,
+    ) -> Result<String> {
+        let tokens = self.tokens.estimate(&prompt) + reply.max_tokens();
+        let mut attempt = 0;
+        loop {
+            let mut streamed = false;
+            let result = {
+                let _permit = self.concurrency.acquire().await?;
+                self.limiter.acquire(tokens).await;
+                let mut on_text = |text: &str| {
+                    streamed = true;
+                    on_text(text);
+                };
+                self.inner
+                    .complete_streaming(prompt.clone(), reply, &mut on_text)
+                    .await
+            };
+
+            match result {
+                // Once part of the reply is shown, retrying would show it twice
+                Err(e) if !streamed && attempt < self.max_retries && is_retryable(&e) => {
+                    tokio::time::sleep(backoff(attempt, retry_after(&e))).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn estimator(&self) -> Estimator {
+        self.tokens
+    }
+
+    fn summarize_prompt(&self, request: &SummaryRequest) -> String {
+        self.inner.summarize_prompt(request)
+    }
+
+    fn update_summary_prompt(&self, previous: &str, delta: &str) -> String {
+        self.inner.update_summary_prompt(previous, delta)
+    }
+
+    fn summarize_sides_prompt(
+        &self,
+        staged: &SummaryRequest,
+        unstaged: &SummaryRequest,
+    ) -> Option<String> {
+        self.inner.summarize_sides_prompt(staged, unstaged)
+    }
+
+    fn summarize_new_file_prompt(&self, file: &NewFile) -> String {
+        self.inner.summarize_new_file_prompt(file)
+    }
+
+    fn summarize_new_directory_prompt(&self, directory: &NewDirectory) -> String {
+        self.inner.summarize_new_directory_prompt(directory)
+    }
+}
+
+fn is_retryable(error: &anyhow::Error) -> bool {
+    if let Some(HudError::ApiStatus { status, .. }) = error.downcast_ref::<HudError>() {
+        // 429 is rate limiting and 529 is Anthropic's "overloaded"
+        return *status == 429 || *status >= 500;
+    }
+    if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+        return error.is_timeout() || error.is_connect();
+    }
+    false
+}
+
+fn retry_after(error: &anyhow::Error) -> Option<Duration> {
+    match error.downcast_ref::<HudError>() {
+        Some(HudError::ApiStatus { retry_after, .. }) => *retry_after,
+        _ => None,
+    }
+}
+
+/// Exponential backoff starting at one second, unless the provider said how long to wait.
+fn backoff(attempt: u32, retry_after: Option<Duration>) -> Duration {
+    retry_after
+        .unwrap_or_else(|| Duration::from_secs(1) * 2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::per_minute(60, start);
+
+        assert_eq!(bucket.wait_for(60.0), None);
+        bucket.take(60.0);
+        assert_eq!(bucket.wait_for(1.0), Some(Duration::from_secs(1)));
+
+        bucket.refill(start + Duration::from_secs(10));
+        assert_eq!(bucket.wait_for(10.0), None);
+        assert_eq!(bucket.wait_for(15.0), Some(Duration::from_secs(5)));
+
+        // Oversized requests wait for a full bucket rather than forever
+        bucket.refill(start + Duration::from_secs(120));
+        assert_eq!(bucket.wait_for(1000.0), None);
+    }
+
+    #[test]
+    fn test_limits_with_config() {
+        let limits = Limits::anthropic().with_config(Some(&ProviderConfig {
+            requests_per_minute: Some(1000),
+            max_concurrent: Some(0),
+            ..Default::default()
+        }));
+        assert_eq!(
+            limits,
+            Limits {
+                requests_per_minute: Some(1000),
+                tokens_per_minute: Some(50_000),
+                max_concurrent: 1,
+                max_retries: 3,
+                tokens: Estimator::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let rate_limited = anyhow::Error::from(HudError::ApiStatus {
+            status: 429,
+            message: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(7)),
+        });
+        assert!(is_retryable(&rate_limited));
+        assert_eq!(
+            backoff(0, retry_after(&rate_limited)),
+            Duration::from_secs(7)
+        );
+
+        let bad_request = anyhow::Error::from(HudError::ApiStatus {
+            status: 400,
+            message: "bad request".to_string(),
+            retry_after: None,
+        });
+        assert!(!is_retryable(&bad_request));
+        assert!(!is_retryable(&anyhow::anyhow!("something else")));
+
+        assert_eq!(backoff(2, None), Duration::from_secs(4));
+        assert_eq!(backoff(10, None), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_retries() -> Result<()> {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        use std::sync::Arc;
+
+        /// Fails with an overloaded error every time, after streaming `partial` if set.
+        struct Overloaded {
+            partial: Option<&'static str>,
+            attempts: Arc<AtomicU32>,
+        }
+
+        #[async_trait]
+        impl Summarizer for Overloaded {
+            async fn complete(&self, _prompt: String, _reply: Reply) -> Result<String> {
+                unreachable!()
+            }
+
+            async fn complete_streaming(
+                &self,
+                _prompt: String,
+                _reply: Reply,
+                on_text: &mut OnText<'_>,
+            ) -> Result<String> {
+                self.attempts.fetch_add(1, Ordering::SeqCst);
+                if let Some(partial) = self.partial {
+                    on_text(partial);
+                }
+                Err(HudError::ApiStatus {
+                    status: 529,
+                    message: "overloaded".to_string(),
+                    retry_after: Some(Duration::ZERO),
+                }
+                .into())
+            }
+
+            fn provider(&self) -> &str {
+                "test"
+            }
+
+            fn model(&self) -> &str {
+                "test"
+            }
+        }
+
+        let limits = Limits {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_concurrent: 1,
+            max_retries: 2,
+            tokens: Estimator::default(),
+        };
+        for (partial, attempts) in [(None, 3), (Some("The change"), 1)] {
+            let counter = Arc::new(AtomicU32::new(0));
+            let summarizer = LimitedSummarizer::new(
+                Box::new(Overloaded {
+                    partial,
+                    attempts: counter.clone(),
+                }),
+                limits.clone(),
+            );
+            let mut shown = String::new();
+            let result = summarizer
+                .complete_streaming("prompt".to_string(), Reply::Text(10), &mut |text| {
+                    shown.push_str(text)
+                })
+                .await;
+            assert!(result.is_err());
+            assert_eq!(shown, partial.unwrap_or_default());
+            assert_eq!(counter.load(Ordering::SeqCst), attempts);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+//! Spots diffs that only convert line endings, which happen when `core.autocrlf` doesn't
+//! match how a file was committed. Every line shows up as changed, but nothing is worth
+//! summarizing.
+
+/// The summary shown for a diff that only changes line endings.
+pub const NORMALIZED: &str = "line endings normalized";
+
+/// Whether every changed line in a diff differs from its counterpart only by a trailing
+/// carriage return.
+pub fn only_line_endings(diff: &str) -> bool {
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    // `str::lines` would strip the carriage returns this is looking for
+    for line in diff.split('\n') {
+        if line.starts_with("+++ ") || line.starts_with("--- ") {
+            continue;
+        }
+        match line.as_bytes().first() {
+            Some(b'-') => removed.push(&line[1..]),
+            Some(b'+') => added.push(&line[1..]),
+            _ => {}
+        }
+    }
+
+    !removed.is_empty()
+        && removed.len() == added.len()
+        && removed.iter().zip(&added).all(|(old, new)| {
+            old != new && old.trim_end_matches('\r') == new.trim_end_matches('\r')
+        })
+}
+
+/// Advice for when line-ending churn is likely caused by `core.autocrlf`, given its
+/// value, or `None` if the setting looks right for this platform.
+pub fn autocrlf_advice(autocrlf: Option<&str>) -> Option<&'static str> {
+    let autocrlf = autocrlf.map(str::to_ascii_lowercase);
+    match (cfg!(windows), autocrlf.as_deref()) {
+        (true, Some("true")) => None,
+        (true, _) => Some(
+            "core.autocrlf isn't `true`, so files committed with LF are compared against CRLF \
+             copies; set it to `true` or add `* text=auto` to .gitattributes",
+        ),
+        (false, Some("true")) => Some(
+            "core.autocrlf is `true`, which checks files out with CRLF; on this platform it's \
+             usually `input` or unset",
+        ),
+        (false, _) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_line_endings() {
+        let crlf = "diff --git a.txt a.txt\n--- a.txt\n+++ a.txt\n@@ -1,2 +1,2 @@\n-one\n-two\n+one\r\n+two\r\n";
+        assert!(only_line_endings(crlf));
+        let lf = "@@ -1 +1 @@\n-one\r\n+one\n";
+        assert!(only_line_endings(lf));
+
+        let edited = "@@ -1,2 +1,2 @@\n-one\n-two\n+one\r\n+three\r\n";
+        assert!(!only_line_endings(edited));
+        let added = "@@ -1 +1,2 @@\n-one\n+one\r\n+two\r\n";
+        assert!(!only_line_endings(added));
+        assert!(!only_line_endings("+new file\r"));
+        assert!(!only_line_endings(""));
+    }
+
+    #[test]
+    fn test_autocrlf_advice() {
+        if cfg!(windows) {
+            assert!(autocrlf_advice(None).is_some());
+            assert_eq!(autocrlf_advice(Some("true")), None);
+        } else {
+            assert_eq!(autocrlf_advice(None), None);
+            assert_eq!(autocrlf_advice(Some("input")), None);
+            assert!(autocrlf_advice(Some("TRUE")).is_some());
+        }
+    }
+}
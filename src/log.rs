@@ -1,17 +1,108 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::{str::FromStr, time::Duration};
 
+use colored::*;
+
 use crate::strings;
 
-pub fn log_duration(log_line: &str, duration: &Duration) {
+// How many of the slowest files --verbose highlights
+const SLOWEST_ENTRIES: usize = 3;
+// How many log lines are kept for crash reports
+const RECENT_LINES: usize = 50;
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+/// The last log lines, printed or not, so a crash report can say what led up to it
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    if VERBOSE.load(Ordering::Relaxed) {
+        return true;
+    }
     let log_level = match std::env::var(strings::LOG_LEVEL) {
         Ok(val) => val,
         Err(_) => String::from_str("").unwrap(),
     };
-    if log_level == "debug" {
-        println!(
-            "{log_line} {duration:.2?}",
-            log_line = log_line,
-            duration = duration
-        )
+    log_level == "debug"
+}
+
+pub fn log_duration(log_line: &str, duration: &Duration) {
+    let line = format!(
+        "{log_line} {duration:.2?}",
+        log_line = log_line,
+        duration = duration
+    );
+    if enabled() {
+        println!("{}", line);
+    }
+    remember(line);
+}
+
+pub fn log_message(line: &str) {
+    if enabled() {
+        println!("{}", line);
+    }
+    remember(line.to_string());
+}
+
+fn remember(line: String) {
+    // A poisoned lock only means a panic happened while logging; the lines are still fine
+    let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    if recent.len() == RECENT_LINES {
+        recent.pop_front();
+    }
+    recent.push_back(line);
+}
+
+/// The last few log lines, oldest first.
+pub fn recent() -> Vec<String> {
+    let recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent.iter().cloned().collect()
+}
+
+/// Where the time went for one file in the status pipeline.
+#[derive(Debug, Default)]
+pub struct EntryTimings {
+    pub path: String,
+    pub binary_check: Duration,
+    pub diff: Duration,
+    pub cache_lookup: Duration,
+    /// Includes time spent waiting on rate limits and retries
+    pub request: Duration,
+}
+
+impl EntryTimings {
+    pub fn total(&self) -> Duration {
+        self.binary_check + self.diff + self.cache_lookup + self.request
+    }
+}
+
+/// Prints each file's timings, slowest first, highlighting the slowest few.
+pub fn log_entry_timings(mut timings: Vec<EntryTimings>) {
+    if !enabled() || timings.is_empty() {
+        return;
+    }
+
+    timings.sort_by_key(|timing| std::cmp::Reverse(timing.total()));
+    println!("Per-file timings, slowest first:");
+    for (i, timing) in timings.iter().enumerate() {
+        let line = format!(
+            "\t{} {:.2?} (binary check {:.2?}, diff {:.2?}, cache lookup {:.2?}, request {:.2?})",
+            timing.path,
+            timing.total(),
+            timing.binary_check,
+            timing.diff,
+            timing.cache_lookup,
+            timing.request
+        );
+        match i < SLOWEST_ENTRIES && timings.len() > 1 {
+            true => println!("{}", line.yellow()),
+            false => println!("{}", line),
+        }
     }
 }
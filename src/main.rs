@@ -1,81 +1,1093 @@
 use anyhow::Result;
+use clap::{CommandFactory, Parser};
 use futures::future::try_join_all;
-use std::time::Instant;
+use futures::{FutureExt, TryFutureExt};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
+mod audit;
+#[cfg(test)]
+mod bench;
+mod budget;
+mod cache;
+#[cfg(test)]
+mod cassette;
+mod cli;
+mod commands;
+mod config;
+mod crash;
+mod detect;
 mod display;
+mod editor;
+mod encoding;
 mod error;
 mod git;
+mod git2_status;
+mod hunks;
+mod hyperlinks;
+mod inflight;
+mod language;
+mod last_run;
+mod lfs;
+mod limits;
+mod line_endings;
 mod log;
+mod manual;
+mod notify;
+mod path_rules;
+mod plan;
+mod plugins;
+mod porcelain;
+mod privacy;
+mod progress;
+mod prompt;
+mod redis;
+mod review;
+mod scopes;
+mod shared_cache;
+mod snapshot;
+mod stats;
 mod strings;
+mod submodules;
 mod summary;
+mod syntax;
+mod template;
+mod tokens;
+mod trash;
+mod width;
+mod word_diff;
 
-use crate::summary::Summarizer;
-use git::StatusCode;
-use summary::ClaudeSummarizer;
+use budget::Budget;
+use cache::{CachedSummary, SummaryCache};
+use cli::{Cli, Command};
+use config::Config;
+use git::{Side, StatusCode, StatusEntry};
+use last_run::LastRun;
+use log::EntryTimings;
+use path_rules::{PathRules, Risk};
+use plan::{PlannedSummary, SummaryPlan};
+use progress::Progress;
+use shared_cache::SharedCache;
+use stats::{RunRecord, SummarySource};
+use summary::Summarizer;
+use tokens::Estimator;
+use tokio_util::sync::CancellationToken;
+
+/// How long `--fetch` waits for the remote before going ahead without it.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Only the newest incoming commits are summarized for the header.
+const INCOMING_MAX_COMMITS: usize = 10;
 
 struct FileWithSummary {
     path: String,
+    /// Where the file is, for links to it
+    abs_path: PathBuf,
     status: StatusCode,
     staged: bool,
     original_path: Option<String>,
     summary: Option<String>,
+    /// The encoding the file was decoded from to summarize it, if it isn't UTF-8
+    encoding: Option<&'static str>,
+    /// The file mode before and after, if this side changed it
+    mode_change: Option<(String, String)>,
+    /// Whether the file was left unsummarized to keep to `status.max-summaries`
+    not_summarized: bool,
+    /// The diff the summary was written from, kept for `--patch`
+    diff: Option<String>,
+    /// Where the changes are in the file, for `status.line-ranges`
+    line_ranges: Vec<hunks::LineRange>,
+    /// The changed files inside this submodule, with `--recurse-submodules`
+    submodule_files: Vec<FileWithSummary>,
+}
+
+/// A file left unmerged by a conflict.
+struct Conflict {
+    path: String,
+    /// How many conflicted regions are left in it
+    regions: usize,
 }
 
 #[tokio::main]
-async fn run() -> Result<()> {
-    // Ensure we have the API key
-    let _api_key = std::env::var(strings::ANTHROPIC_API_KEY)
-        .map_err(|_| anyhow::anyhow!("ANTHROPIC_API_KEY environment variable not set"))?;
+async fn run(cli: Cli) -> Result<()> {
+    log::set_verbose(cli.verbose);
+    cli.color.apply();
+    if cli.accessible {
+        display::OutputProfile::Accessible.apply();
+    }
+    // Shell aliases like `!git-hud` run from the top of the work tree, with where they
+    // were run from in GIT_PREFIX; going back there makes paths resolve as typed
+    if let Some(prefix) = std::env::var_os(strings::GIT_PREFIX).filter(|p| !p.is_empty()) {
+        std::env::set_current_dir(&prefix)?;
+    }
+    if cli.help_long {
+        print!("{}", manual::long_help(&Cli::command()));
+        return Ok(());
+    }
+    if cli.man {
+        print!("{}", manual::man_page(&Cli::command()));
+        return Ok(());
+    }
+    // Checking the config has to work when it doesn't load
+    match cli.command {
+        Some(Command::Config { action }) => {
+            return commands::config::run(action, &cli.config_overrides).await
+        }
+        Some(Command::Doctor { offline }) => {
+            return commands::doctor::run(&cli.config_overrides, offline).await
+        }
+        Some(Command::BugReport { path }) => return commands::bug_report::run(path.as_deref()),
+        _ => {}
+    }
+    let config = Config::load(&cli.config_overrides)?;
+    syntax::use_theme(
+        config
+            .status
+            .syntax_theme
+            .as_deref()
+            .unwrap_or(syntax::DEFAULT_THEME),
+    )?;
+
+    // These only read local state, so they work outside a repo and without a summarizer
+    let command = match cli.command {
+        Some(Command::Audit { action }) => return commands::audit::run(action),
+        Some(Command::Stats { days }) => return commands::stats::run(&config, days),
+        command => command,
+    };
 
     let t0 = Instant::now();
     // Initialize repositories and services
-    let repo = git::Repository::open_current_directory(None)?;
+    let mut repo = git::Repository::open_from_env()?;
+    repo.set_status_backend(config.status.backend);
     log::log_duration("Open repo", &t0.elapsed());
+    let remotes = repo.remote_urls()?;
+    // Submodules are checked against their own remotes, so the unguarded one is kept
+    let unguarded = summary::from_config(&config)?;
+    let withheld = privacy::withhold(unguarded.as_ref(), &config.privacy, &remotes);
+    let summarizer = withheld
+        .as_ref()
+        .map_or(unguarded.as_ref(), |withheld| withheld as &dyn Summarizer);
+    let rules = PathRules::new(&config.paths)
+        .with_summarizers(&config, summarizer, &remotes)?
+        .with_focus(&cli.focus);
+    // `--yes` is for scripts, which have no one to ask
+    let gate = (config.status.confirm_cost > 0.0 && !cli.yes)
+        .then(|| budget::Gate::new(config.status.confirm_cost, &config.providers));
+    let gate = gate.as_ref();
+
+    if let Some(command) = command {
+        return match command {
+            Command::Explain { path, compare } => {
+                commands::explain::run(&repo, summarizer, &path, compare).await
+            }
+            Command::Show { path, word_diff } => {
+                commands::show::run(&repo, summarizer, &rules, &config.cache, &path, word_diff)
+                    .await
+            }
+            Command::Open { target } => commands::open::run(&repo, &target),
+            Command::Restore { files } => commands::restore::run(&repo, &files),
+            Command::Undo { force } => commands::undo::run(&repo, force),
+            Command::Why { path, commits } => {
+                commands::why::run(&repo, summarizer, &path, commits).await
+            }
+            Command::AmendCheck => {
+                commands::amend_check::run(&repo, summarizer, &config.templates, &config.scopes)
+                    .await
+            }
+            Command::Prepush { upstream, post } => {
+                let notify = post.then_some(&config.notify);
+                commands::prepush::run(&repo, summarizer, gate, upstream, notify).await
+            }
+            Command::Review { base, change } => {
+                commands::review::run(&repo, summarizer, gate, &config.review, base, change).await
+            }
+            Command::Compare { from, to } => {
+                commands::compare::run(&repo, summarizer, gate, &from, &to).await
+            }
+            Command::Where { commit } => {
+                commands::cherry_targets::run(&repo, summarizer, &commit).await
+            }
+            Command::Rescue { count } => commands::rescue::run(&repo, summarizer, count).await,
+            Command::Bisect { symptom } => {
+                commands::bisect::run(&repo, summarizer, gate, symptom.as_deref()).await
+            }
+            Command::Snapshot { action } => {
+                commands::snapshot::run(&repo, summarizer, gate, &rules, &config.cache, action)
+                    .await
+            }
+            Command::Standup { since, post } => {
+                commands::standup::run(&repo, summarizer, gate, &rules, &since, &config, post).await
+            }
+            Command::TagNotes {
+                tag,
+                target,
+                create,
+            } => {
+                commands::tag_notes::run(
+                    &repo,
+                    summarizer,
+                    gate,
+                    &config.templates,
+                    &tag,
+                    &target,
+                    create,
+                )
+                .await
+            }
+            Command::Config { .. }
+            | Command::Doctor { .. }
+            | Command::BugReport { .. }
+            | Command::Audit { .. }
+            | Command::Stats { .. } => {
+                unreachable!("handled before opening the repo")
+            }
+        };
+    }
+
+    if cli.fetch {
+        let t = Instant::now();
+        if let Err(e) = repo.fetch(FETCH_TIMEOUT) {
+            display::StatusFormatter::new()
+                .display_warning(&format!("{:#}; showing the remote info as it was", e));
+        }
+        log::log_duration("Fetch", &t.elapsed());
+    }
+
+    // Links are escape codes like colors, so they're only added where colors are
+    let links = config
+        .status
+        .hyperlinks
+        .as_deref()
+        .map(hyperlinks::Links::new)
+        .transpose()?
+        .filter(|_| colored::control::SHOULD_COLORIZE.should_colorize());
+
     let t1 = Instant::now();
-    let status = repo.get_status()?;
+    let status_options = git::StatusOptions {
+        untracked_files: cli.untracked_files,
+        pathspecs: cli.pathspecs,
+        renames: config.status.renames,
+        untracked_cache: config.status.untracked_cache,
+        fsmonitor: config.status.fsmonitor,
+        timeout: (config.status.timeout > 0).then(|| Duration::from_secs(config.status.timeout)),
+    };
+    let status = repo.get_status_with(&status_options)?;
     log::log_duration("Get status", &t1.elapsed());
-    let summarizer = ClaudeSummarizer::new()?;
+    if status.untracked_skipped {
+        display::StatusFormatter::new().display_warning(&format!(
+            "git status took longer than {}s, so untracked files aren't listed; raise `status.timeout` or try `status.untracked-cache` and `status.fsmonitor`",
+            config.status.timeout
+        ));
+    }
 
-    let t3 = Instant::now();
-    // Process each file and generate summaries
-    let summary_futures: Vec<_> = status
-        .entries
-        .iter()
-        .map(|entry| async {
-            let summary = match entry.is_binary {
-                true => None,
-                false => match repo.get_diff(entry)? {
-                    Some(diff) => Some(summarizer.summarize(&diff).await?),
-                    None => None,
-                },
-            };
-            Ok::<_, anyhow::Error>(FileWithSummary {
+    // Mid-rebase or merge, most changes are ones being replayed rather than work in
+    // progress, so only what needs resolving is shown, and nothing is summarized
+    let operation = status.branch.operation.filter(|op| op.replays_changes());
+    if operation.is_some() && !cli.all {
+        let (conflicts, others): (Vec<_>, Vec<_>) = status
+            .entries
+            .iter()
+            .partition(|entry| entry.worktree == Some(StatusCode::Unmerged));
+        let conflicts: Vec<Conflict> = conflicts
+            .into_iter()
+            .map(|entry| Conflict {
                 path: entry.display_path.clone(),
-                status: entry.status.clone(),
-                staged: entry.staged,
-                original_path: entry.original_path.clone(),
-                summary,
+                regions: std::fs::read(&entry.abs_path)
+                    .map_or(0, |content| detect::conflict_regions(&content)),
             })
+            .collect();
+        return display::StatusFormatter::new().display_operation(
+            &status.branch,
+            repo.rebase_progress().as_ref(),
+            &conflicts,
+            others.len(),
+        );
+    }
+
+    // A branch switch or a formatter run can change so many files that summarizing them
+    // all costs far more than anyone meant to spend, so only cached summaries are shown
+    let changed = status.entries.len();
+    let too_many =
+        config.status.max_files > 0 && changed > config.status.max_files && !(cli.ai || cli.all);
+    let budget = Budget {
+        max_requests: match too_many {
+            true => Some(0),
+            false => (!cli.all).then_some(config.status.max_summaries),
+        },
+        gate,
+        cancel: CancellationToken::new(),
+        cancel_on_ctrl_c: true,
+    };
+
+    // Runs that need the diffs can't use what was shown last time
+    let signature = match config.status.fast_path
+        && !(cli.all || cli.patch || cli.strict || cli.dry_run || cli.recurse_submodules)
+    {
+        true => {
+            let mut settings =
+                toml::to_string(&config::merged(&Config::layers(&cli.config_overrides)?))?;
+            // Which files get summaries is as much a setting as the config
+            settings.push_str(&format!(
+                "focus = {:?}\ntoo-many = {}\n",
+                cli.focus, too_many
+            ));
+            Some(last_run::signature(&repo, &status, &settings))
+        }
+        false => None,
+    };
+    let last = signature
+        .as_deref()
+        .and_then(|signature| LastRun::load(repo.root(), signature));
+
+    let (files_with_summaries, incoming, entry_timings) = match last {
+        Some(last) => {
+            log::log_message("Nothing changed since the last run; showing it again");
+            let incoming = last.incoming.clone();
+            (last.into_files(), incoming, Vec::new())
+        }
+        None => {
+            let t2 = Instant::now();
+            let mut cache = SummaryCache::load(&repo, &config.cache)?;
+            log::log_duration("Load cache", &t2.elapsed());
+
+            if cli.dry_run {
+                return dry_run(
+                    &repo,
+                    summarizer,
+                    &rules,
+                    &status,
+                    &cache,
+                    budget.max_requests,
+                    Estimator::new(config.providers.get(summarizer.provider())),
+                );
+            }
+
+            let incoming = async {
+                match config.status.incoming_summary {
+                    true => incoming_summary(&repo, summarizer, &status.branch).await,
+                    false => None,
+                }
+            };
+            let (summaries, incoming) = futures::join!(
+                summarize_status(
+                    &repo,
+                    summarizer,
+                    &rules,
+                    &status,
+                    &mut cache,
+                    &budget,
+                    progress::interval(config.status.progress_interval)
+                ),
+                incoming
+            );
+            let (mut files, entry_timings) = summaries?;
+            if cli.recurse_submodules {
+                let recursion = submodules::Recursion {
+                    summarizer: unguarded.as_ref(),
+                    config: &config,
+                    focus: &cli.focus,
+                    options: git::StatusOptions {
+                        pathspecs: Vec::new(),
+                        ..status_options
+                    },
+                    budget: &budget,
+                };
+                recursion.nest(&repo, &mut files).await?;
+            }
+            let over_cost = budget.gate.and_then(budget::Gate::held_back);
+            let interrupted = budget.cancel.is_cancelled();
+            if too_many || over_cost.is_some() || interrupted {
+                // The hint below says why, once, rather than each file
+                unmark_not_summarized(&mut files);
+            }
+            // Held back for its cost or cut short, a run is shown again only once it's
+            // been allowed to finish
+            if let Some(signature) = signature.filter(|_| over_cost.is_none() && !interrupted) {
+                LastRun::new(signature, &files, incoming.clone()).save(repo.root())?;
+            }
+            (files, incoming, entry_timings)
+        }
+    };
+
+    let t5 = Instant::now();
+    // Display the results
+    let formatter = display::StatusFormatter::new()
+        .with_patch(cli.patch)
+        .with_incoming(incoming)
+        .with_clean_style(config.status.clean_style)
+        .with_line_ranges(config.status.line_ranges)
+        .with_links(links);
+    if cli.pick {
+        formatter.display_pick_lines(&files_with_summaries)?;
+    } else {
+        formatter.display_with_summaries(&status.branch, &files_with_summaries)?;
+        if config.status.show_hidden {
+            formatter.display_hidden_files(&repo.hidden_edits()?)?;
+        }
+    }
+    if too_many {
+        formatter.display_warning(&format!(
+            "{} files changed; run with --ai to summarize anyway",
+            changed
+        ));
+    }
+    if let Some(estimate) = budget.gate.and_then(budget::Gate::held_back) {
+        formatter.display_warning(&format!(
+            "not summarized: sending ~{} tokens (~${:.2}) is over `status.confirm-cost`; run with --yes to send them",
+            budget::format_tokens(estimate.tokens),
+            estimate.cost
+        ));
+    }
+    if budget.cancel.is_cancelled() {
+        formatter.display_warning(
+            "interrupted: summaries that hadn't come back yet weren't waited for; run again to get them",
+        );
+    }
+    if summarizer.withheld() && !files_with_summaries.is_empty() {
+        formatter.display_warning(&format!(
+            "no summaries: this repository's remotes aren't in privacy.cloud-remotes, so its code isn't sent to {}",
+            summarizer.provider()
+        ));
+    }
+    let churn = files_with_summaries
+        .iter()
+        .any(|file| file.summary.as_deref() == Some(line_endings::NORMALIZED));
+    if churn {
+        if let Some(advice) = line_endings::autocrlf_advice(repo.autocrlf().as_deref()) {
+            formatter.display_warning(advice);
+        }
+    }
+
+    log::log_duration("Display", &t5.elapsed());
+    log::log_entry_timings(entry_timings);
+
+    if cli.strict {
+        let findings = detect_suspicious(&status, &files_with_summaries, &config.strict, &rules);
+        if !findings.is_empty() {
+            formatter.display_findings(&findings)?;
+            return Err(anyhow::anyhow!(
+                "--strict: {} suspicious change(s)",
+                findings.len()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Clears the not-summarized mark from `files` and the files inside submodules.
+fn unmark_not_summarized(files: &mut [FileWithSummary]) {
+    for file in files {
+        file.not_summarized = false;
+        unmark_not_summarized(&mut file.submodule_files);
+    }
+}
+
+/// Runs the `--strict` detectors over the diffs that were summarized and the changed
+/// files' sizes. Files `[paths]` marks as low risk are left out, and any change to ones
+/// it marks as always high risk is reported.
+fn detect_suspicious(
+    status: &git::Status,
+    files: &[FileWithSummary],
+    config: &config::StrictConfig,
+    rules: &PathRules,
+) -> Vec<detect::Finding> {
+    let risks: HashMap<&str, Option<Risk>> = status
+        .entries
+        .iter()
+        .map(|entry| (entry.display_path.as_str(), rules.rule(&entry.path).risk))
+        .collect();
+    let risk = |path: &str| risks.get(path).copied().flatten();
+    let mut findings: Vec<detect::Finding> = Vec::new();
+    let found = status
+        .entries
+        .iter()
+        .filter(|entry| risk(&entry.display_path) == Some(Risk::AlwaysHigh))
+        .map(detect::high_risk)
+        .chain(
+            files
+                .iter()
+                .filter(|file| risk(&file.path) != Some(Risk::Low))
+                .filter_map(|file| {
+                    Some(detect::scan_diff(&file.path, file.diff.as_deref()?, config))
+                })
+                .flatten(),
+        )
+        .chain(
+            status
+                .entries
+                .iter()
+                .filter(|entry| risk(&entry.display_path) != Some(Risk::Low))
+                .filter_map(|entry| detect::check_size(entry, config)),
+        );
+    // Staged and unstaged diffs of one file can both add the same line
+    for finding in found {
+        if !findings.contains(&finding) {
+            findings.push(finding);
+        }
+    }
+    findings
+}
+
+/// Each side of each entry that changed, so a file with both staged and unstaged changes
+/// gets a summary for each like it gets a line in each of `git status`'s sections.
+fn entry_sides(status: &git::Status) -> impl Iterator<Item = (&StatusEntry, Side, &StatusCode)> {
+    status
+        .entries
+        .iter()
+        .flat_map(|entry| entry.sides().map(move |(side, code)| (entry, side, code)))
+}
+
+/// Summarizes each changed side of each entry, then replaces the cache with the results
+/// and records the run. At most the budget's `max_requests` sides are sent for a summary,
+/// the most recently modified first, and only if its gate allows what they'd cost; the
+/// rest are marked as not summarized.
+async fn summarize_status(
+    repo: &git::Repository,
+    summarizer: &dyn Summarizer,
+    rules: &PathRules,
+    status: &git::Status,
+    cache: &mut SummaryCache,
+    budget: &Budget<'_>,
+    progress_interval: Option<Duration>,
+) -> Result<(Vec<FileWithSummary>, Vec<EntryTimings>)> {
+    let t3 = Instant::now();
+    // Process each file and generate summaries
+    let previous = &*cache;
+    let prepared = entry_sides(status)
+        .map(|(entry, side, code)| {
+            prepare_side(repo, previous, rules, summarizer, entry, side, code)
         })
+        .collect::<Result<Vec<_>>>()?;
+    let mut sent = select_requests(&prepared, budget.max_requests);
+    if budget.cancel.is_cancelled() {
+        // Cancelled during an earlier repository's requests, like a submodule's parent's
+        sent = select_requests(&prepared, Some(0));
+    } else if let Some(gate) = budget.gate {
+        let requests: Vec<_> = prepared
+            .iter()
+            .zip(&sent)
+            .filter(|(side, &sent)| sent && side.needs_request())
+            .filter_map(|(side, _)| Some((side.summarizer.provider(), side.prompt()?)))
+            .collect();
+        if !gate.allows(&requests)? {
+            sent = select_requests(&prepared, Some(0));
+        }
+    }
+    if budget.cancel_on_ctrl_c {
+        budget::cancel_on_ctrl_c(&budget.cancel);
+    }
+    let pairs = paired_sides(&prepared, &sent);
+    let order = dispatch_order(&prepared);
+    // The sides that actually wait on a request, for progress reports
+    let requested: Vec<bool> = prepared
+        .iter()
+        .zip(&sent)
+        .map(|(side, &sent)| sent && side.needs_request())
         .collect();
+    let requested = &requested;
+    let progress = Progress::new(requested.iter().filter(|&&requested| requested).count());
+    let progress = &progress;
+    let mut prepared: Vec<_> = prepared.into_iter().map(Some).collect();
+    let mut summary_futures = Vec::with_capacity(order.len());
+    for i in order {
+        // The unstaged side of a pair was taken with its staged side, which comes first
+        let Some(side) = prepared[i].take() else {
+            continue;
+        };
+        match pairs.get(&i) {
+            Some(&j) => {
+                let unstaged = prepared[j].take().expect("each side is dispatched once");
+                summary_futures.push(
+                    summarize_pair(repo, previous, side, unstaged, &budget.cancel)
+                        .map_ok(move |(staged, unstaged)| {
+                            progress.advance(usize::from(requested[i]) + usize::from(requested[j]));
+                            vec![(i, staged), (j, unstaged)]
+                        })
+                        .boxed_local(),
+                );
+            }
+            None => summary_futures.push(
+                summarize_side(repo, previous, side, sent[i], &budget.cancel)
+                    .map_ok(move |side| {
+                        progress.advance(usize::from(requested[i]));
+                        vec![(i, side)]
+                    })
+                    .boxed_local(),
+            ),
+        }
+    }
     log::log_duration("Create requests", &t3.elapsed());
 
     let t4 = Instant::now();
-    let files_with_summaries = try_join_all(summary_futures).await?;
+    let summaries = try_join_all(summary_futures);
+    let summaries = match progress_interval {
+        Some(interval) => {
+            progress::report(summaries, progress, interval, |line| eprintln!("{}", line)).await
+        }
+        None => summaries.await,
+    };
+    let mut results: Vec<_> = summaries?.into_iter().flatten().collect();
+    // Back in the status's order
+    results.sort_by_key(|(i, _)| *i);
     log::log_duration("Join requests", &t4.elapsed());
 
-    let t5 = Instant::now();
-    // Display the results
-    let formatter = display::StatusFormatter::new();
-    formatter.display_with_summaries(&files_with_summaries)?;
+    let mut entries = HashMap::new();
+    let mut sources = Vec::new();
+    let mut entry_timings = Vec::new();
+    let mut files_with_summaries = Vec::with_capacity(results.len());
+    for (_, side) in results {
+        if let Some((cached, source)) = side.summarized {
+            sources.push((source, cached.tokens));
+            entries.insert(side.key, cached);
+        }
+        files_with_summaries.push(side.file);
+        entry_timings.push(side.timings);
+    }
+    // Files left out by `--focus` keep their summaries for a run that shows them
+    for (entry, side, _) in entry_sides(status).filter(|(entry, ..)| !rules.in_focus(&entry.path)) {
+        let key = cache_key(entry, side);
+        if let Some(cached) = previous.get(&key) {
+            entries.entry(key).or_insert_with(|| cached.clone());
+        }
+    }
+    cache.save(entries);
+    stats::record_run(&RunRecord::new(&sources))?;
+    Ok((files_with_summaries, entry_timings))
+}
 
-    log::log_duration("Display", &t5.elapsed());
-    Ok(())
+/// Which of the prepared sides may make a request, keeping to `limit` of them. Sides
+/// that don't need one, like those with a cached summary, are free and always allowed.
+/// Otherwise the most recently modified files go first, then the smallest diffs, so what
+/// was just worked on gets summarized and as many files as possible do.
+fn select_requests(prepared: &[PreparedSide], limit: Option<usize>) -> Vec<bool> {
+    let mut sent: Vec<bool> = prepared.iter().map(|side| !side.needs_request()).collect();
+    let mut requests: Vec<_> = (0..prepared.len()).filter(|&i| !sent[i]).collect();
+    requests.sort_by_key(|&i| {
+        let side = &prepared[i];
+        let modified = std::fs::metadata(&side.entry.abs_path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        (
+            std::cmp::Reverse(modified),
+            side.diff.as_ref().map_or(0, String::len),
+        )
+    });
+    for i in requests.into_iter().take(limit.unwrap_or(usize::MAX)) {
+        sent[i] = true;
+    }
+    sent
+}
+
+/// The files edited again after they were staged whose two sides can be summarized in
+/// one request, as the index of the staged side to that of the unstaged one. Both sides
+/// must be summarized from their whole diff, and the summarizer must be able to do both
+/// at once.
+fn paired_sides(prepared: &[PreparedSide], sent: &[bool]) -> HashMap<usize, usize> {
+    let request = |i: usize| prepared[i].plan.as_ref()?.full_request();
+    // An entry's sides are next to each other, staged first
+    (1..prepared.len())
+        .filter(|&i| {
+            let (staged, unstaged) = (&prepared[i - 1], &prepared[i]);
+            std::ptr::eq(staged.entry, unstaged.entry) && sent[i - 1] && sent[i]
+        })
+        .filter(|&i| {
+            request(i - 1)
+                .zip(request(i))
+                .is_some_and(|(staged, unstaged)| {
+                    prepared[i]
+                        .summarizer
+                        .summarize_sides_prompt(staged, unstaged)
+                        .is_some()
+                })
+        })
+        .map(|i| (i - 1, i))
+        .collect()
+}
+
+/// The order to start summarizing the prepared sides in: the order they're displayed,
+/// staged then unstaged then untracked, and the smallest diffs first within each
+/// section. Requests wait their turn for the provider's concurrency limit in the order
+/// they're started, so the top of the output is ready first.
+fn dispatch_order(prepared: &[PreparedSide]) -> Vec<usize> {
+    let mut order: Vec<_> = (0..prepared.len()).collect();
+    order.sort_by_key(|&i| {
+        let side = &prepared[i];
+        let section = match (side.side, side.code) {
+            (Side::Staged, _) => 0,
+            (Side::Unstaged, StatusCode::Untracked) => 2,
+            (Side::Unstaged, _) => 1,
+        };
+        (section, side.diff.as_ref().map_or(0, String::len))
+    });
+    order
+}
+
+/// One side of an entry with its diff and how it'll be summarized, worked out before
+/// any request is made.
+struct PreparedSide<'a> {
+    entry: &'a StatusEntry,
+    side: Side,
+    code: &'a StatusCode,
+    /// Who summarizes it, which `[paths]` may have changed
+    summarizer: &'a dyn Summarizer,
+    key: String,
+    diff: Option<String>,
+    plan: Option<SummaryPlan<'a>>,
+    timings: EntryTimings,
+}
+
+impl PreparedSide<'_> {
+    fn needs_request(&self) -> bool {
+        self.plan
+            .as_ref()
+            .is_some_and(|plan| plan.request_hash().is_some())
+    }
+
+    /// The prompt the side's request sends, if it makes one.
+    fn prompt(&self) -> Option<String> {
+        self.plan.as_ref()?.prompt(self.summarizer)
+    }
+
+    /// The side as it's displayed and cached, with its summary if it got one. Unless
+    /// `sent`, it's marked as not summarized.
+    fn summarized(
+        self,
+        summarized: Option<(CachedSummary, SummarySource)>,
+        sent: bool,
+    ) -> SummarizedSide {
+        let (entry, side) = (self.entry, self.side);
+        let file = FileWithSummary {
+            path: entry.display_path.clone(),
+            abs_path: entry.abs_path.clone(),
+            status: self.code.clone(),
+            staged: side == Side::Staged,
+            // Only the staged side is the rename; later edits are to the new path
+            original_path: match side {
+                Side::Staged => entry.original_display_path.clone(),
+                Side::Unstaged => None,
+            },
+            summary: summarized
+                .as_ref()
+                .map(|(cached, _)| cached.summary.clone()),
+            encoding: entry.encoding.map(|encoding| encoding.name()),
+            mode_change: entry
+                .mode_change(side)
+                .map(|(old, new)| (old.to_string(), new.to_string())),
+            not_summarized: !sent,
+            // A deleted file or an untracked directory has no lines to go to
+            line_ranges: match (&self.diff, &self.code) {
+                (Some(_), StatusCode::Deleted) => Vec::new(),
+                (Some(_), _) if entry.abs_path.is_dir() => Vec::new(),
+                (Some(diff), _) => hunks::changed_ranges(diff),
+                (None, _) => Vec::new(),
+            },
+            submodule_files: Vec::new(),
+            diff: self.diff,
+        };
+        SummarizedSide {
+            key: self.key,
+            file,
+            summarized,
+            timings: self.timings,
+        }
+    }
+}
+
+/// One side of an entry with its summary.
+struct SummarizedSide {
+    key: String,
+    file: FileWithSummary,
+    summarized: Option<(CachedSummary, SummarySource)>,
+    timings: EntryTimings,
+}
+
+/// Gets one side of an entry's diff and plans its summary, reusing the one in `previous`
+/// when it still applies. A file `[paths]` says not to summarize gets no plan.
+fn prepare_side<'a>(
+    repo: &git::Repository,
+    previous: &'a SummaryCache,
+    rules: &'a PathRules,
+    summarizer: &'a dyn Summarizer,
+    entry: &'a StatusEntry,
+    side: Side,
+    code: &'a StatusCode,
+) -> Result<PreparedSide<'a>> {
+    let rule = rules.rule(&entry.path);
+    let key = cache_key(entry, side);
+    let mut timings = EntryTimings {
+        path: key.clone(),
+        binary_check: entry.binary_check_time,
+        ..Default::default()
+    };
+    let diff = match entry.is_binary {
+        true => None,
+        false => {
+            let t = Instant::now();
+            let diff = repo
+                .get_side_diff(entry, side)?
+                .filter(|diff| !plan::is_mode_only(diff));
+            timings.diff = t.elapsed();
+            diff
+        }
+    };
+    let summarizer = rules.summarizer(&rule, summarizer);
+    let plan = match &diff {
+        Some(diff) if rule.summarize != Some(false) && !summarizer.withheld() => {
+            let t = Instant::now();
+            let plan = SummaryPlan::new(repo, entry, side, diff, previous.get(&key), summarizer)?;
+            timings.cache_lookup = t.elapsed();
+            Some(plan)
+        }
+        _ => None,
+    };
+    Ok(PreparedSide {
+        entry,
+        side,
+        code,
+        summarizer,
+        key,
+        diff,
+        plan,
+        timings,
+    })
+}
+
+/// Summarizes one prepared side of an entry. Unless `send`, a side that needs a request
+/// is left without a summary and marked as not summarized.
+async fn summarize_side(
+    repo: &git::Repository,
+    previous: &SummaryCache,
+    prepared: PreparedSide<'_>,
+    send: bool,
+    cancel: &CancellationToken,
+) -> Result<SummarizedSide> {
+    let PreparedSide {
+        entry,
+        side,
+        code,
+        summarizer,
+        key,
+        diff,
+        plan,
+        mut timings,
+    } = prepared;
+    let mut cancelled = false;
+    let summarized = match plan.filter(|_| send) {
+        Some(plan) => {
+            let t = Instant::now();
+            let summarize = async {
+                Ok::<_, anyhow::Error>(match plan.request_hash().map(str::to_string) {
+                    // Another git hud running at the same time may already be asking
+                    Some(diff_hash) => {
+                        let source = plan.source();
+                        // Someone on the team may have made the same request already
+                        let shared = shared_entry(previous, summarizer, &plan);
+                        let found = match &shared {
+                            Some((shared, key)) => shared.get(key).await,
+                            None => None,
+                        };
+                        match found {
+                            Some(summary) => {
+                                (plan.with_summary(summary, summarizer), SummarySource::Cache)
+                            }
+                            None => {
+                                let request = async {
+                                    let (cached, _) = plan.execute(summarizer).await?;
+                                    if let Some((shared, key)) = &shared {
+                                        shared.put(key, &cached.summary).await;
+                                    }
+                                    Ok(cached)
+                                };
+                                match inflight::coalesce(repo.root(), &key, &diff_hash, request)
+                                    .await?
+                                {
+                                    inflight::Outcome::Made(cached) => (cached, source),
+                                    inflight::Outcome::Reused(cached) => {
+                                        (cached, SummarySource::Cache)
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    None => plan.execute(summarizer).await?,
+                })
+            };
+            // A request cut short is dropped, which stops it
+            let summarized = cancel.run_until_cancelled(summarize).await.transpose()?;
+            cancelled = summarized.is_none();
+            timings.request = t.elapsed();
+            summarized
+        }
+        None => None,
+    };
+    Ok(PreparedSide {
+        entry,
+        side,
+        code,
+        summarizer,
+        key,
+        diff,
+        plan: None,
+        timings,
+    }
+    .summarized(summarized, send && !cancelled))
+}
+
+/// Summarizes both sides of a file edited again after it was staged with one request
+/// instead of two. If a teammate already shared either summary, there's only one
+/// request left to make, so each side is summarized on its own.
+async fn summarize_pair(
+    repo: &git::Repository,
+    previous: &SummaryCache,
+    mut staged: PreparedSide<'_>,
+    mut unstaged: PreparedSide<'_>,
+    cancel: &CancellationToken,
+) -> Result<(SummarizedSide, SummarizedSide)> {
+    // The same path, so the same rules
+    let summarizer = staged.summarizer;
+    let (Some(staged_plan), Some(unstaged_plan)) = (staged.plan.take(), unstaged.plan.take())
+    else {
+        unreachable!("only sides with a plan are paired");
+    };
+    let staged_shared = shared_entry(previous, summarizer, &staged_plan);
+    let unstaged_shared = shared_entry(previous, summarizer, &unstaged_plan);
+    for (shared, key) in staged_shared.iter().chain(&unstaged_shared) {
+        if shared.get(key).await.is_some() {
+            staged.plan = Some(staged_plan);
+            unstaged.plan = Some(unstaged_plan);
+            return futures::try_join!(
+                summarize_side(repo, previous, staged, true, cancel),
+                summarize_side(repo, previous, unstaged, true, cancel)
+            );
+        }
+    }
+
+    let (Some(staged_request), Some(unstaged_request)) =
+        (staged_plan.full_request(), unstaged_plan.full_request())
+    else {
+        unreachable!("only sides summarized from their whole diff are paired");
+    };
+    let t = Instant::now();
+    let Some(summaries) = cancel
+        .run_until_cancelled(summarizer.summarize_sides(staged_request, unstaged_request))
+        .await
+    else {
+        return Ok((
+            staged.summarized(None, false),
+            unstaged.summarized(None, false),
+        ));
+    };
+    let (staged_summary, unstaged_summary) = summaries?;
+    for ((shared, key), summary) in [
+        (&staged_shared, &staged_summary),
+        (&unstaged_shared, &unstaged_summary),
+    ]
+    .into_iter()
+    .filter_map(|(shared, summary)| Some((shared.as_ref()?, summary)))
+    {
+        shared.put(key, &summary.text).await;
+    }
+    let staged_cached = staged_plan.with_response(staged_summary, summarizer);
+    let unstaged_cached = unstaged_plan.with_response(unstaged_summary, summarizer);
+    staged.timings.request = t.elapsed();
+    unstaged.timings.request = t.elapsed();
+    Ok((
+        staged.summarized(Some((staged_cached, SummarySource::Full)), true),
+        unstaged.summarized(Some((unstaged_cached, SummarySource::Full)), true),
+    ))
+}
+
+/// The shared cache and the key a plan's summary is shared under, if there's a shared
+/// cache and the plan makes a request.
+fn shared_entry<'c>(
+    previous: &'c SummaryCache,
+    summarizer: &dyn Summarizer,
+    plan: &SummaryPlan,
+) -> Option<(&'c SharedCache, String)> {
+    let prompt = plan.prompt(summarizer)?;
+    let shared = previous.shared()?;
+    Some((shared, shared_cache::key(plan.blob(), &prompt, summarizer)))
+}
+
+/// One line on what the upstream's commits that HEAD doesn't have yet change, worked out
+/// the way `prepush` sums up outgoing ones: a summary of each commit, then of them all.
+/// It's an extra, so a failure is only logged.
+async fn incoming_summary(
+    repo: &git::Repository,
+    summarizer: &dyn Summarizer,
+    branch: &git::BranchInfo,
+) -> Option<String> {
+    let upstream = branch.upstream.as_deref()?;
+    if branch.ahead_behind?.1 == 0 {
+        return None;
+    }
+    let summary = async {
+        let mut commits = repo.commits_in_range(&format!("HEAD..{}", upstream))?;
+        commits.truncate(INCOMING_MAX_COMMITS);
+        // Held to INCOMING_MAX_COMMITS rather than asked about, since it's an extra
+        let summaries = summary::summarize_commits(summarizer, None, &commits).await?;
+        let notes: Vec<String> = commits
+            .iter()
+            .zip(&summaries)
+            .map(|(commit, summary)| format!("{}: {}", commit.subject, summary))
+            .collect();
+        summarizer.summarize_incoming(upstream, &notes).await
+    };
+    match summary.await {
+        Ok(summary) => Some(summary),
+        Err(e) => {
+            log::log_message(&format!("Couldn't summarize incoming commits: {:#}", e));
+            None
+        }
+    }
+}
+
+fn cache_key(entry: &StatusEntry, side: Side) -> String {
+    format!("{}:{}", side.label(), entry.display_path)
+}
+
+/// Reports what each file's summary would cost without sending anything.
+fn dry_run(
+    repo: &git::Repository,
+    summarizer: &dyn Summarizer,
+    rules: &PathRules,
+    status: &git::Status,
+    cache: &SummaryCache,
+    limit: Option<usize>,
+    tokens: Estimator,
+) -> Result<()> {
+    let prepared = entry_sides(status)
+        .map(|(entry, side, code)| prepare_side(repo, cache, rules, summarizer, entry, side, code))
+        .collect::<Result<Vec<_>>>()?;
+    let sent = select_requests(&prepared, limit);
+    let planned: Vec<_> = prepared
+        .iter()
+        .zip(sent)
+        .map(|(prepared, sent)| {
+            // Sides left out by the limit are skipped, like binary files
+            let plan = prepared.plan.as_ref().filter(|_| sent);
+            PlannedSummary {
+                path: prepared.entry.display_path.clone(),
+                side: prepared.side,
+                source: plan.map(SummaryPlan::source),
+                prompt_tokens: plan
+                    .and(prepared.prompt())
+                    .map_or(0, |prompt| tokens.estimate(&prompt)),
+            }
+        })
+        .collect();
+
+    let formatter = display::StatusFormatter::new();
+    formatter.display_dry_run(summarizer.provider(), summarizer.model(), &planned)
 }
 
 fn main() -> Result<()> {
-    if let Err(e) = run() {
+    let cli = Cli::parse();
+    crash::install(&cli.config_overrides);
+    if let Err(e) = run(cli) {
         eprintln!("Error: {}", e);
+        crash::report_error(&e);
         std::process::exit(1);
     }
     Ok(())
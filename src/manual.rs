@@ -0,0 +1,227 @@
+//! Extended help and a man page, both generated from the clap command tree so they can't
+//! drift from the real flags. `git hud --help` goes through `git help`, which looks for a
+//! `git-hud(1)` man page, so `--man` writes one to install.
+
+use crate::config::KEYS;
+use crate::strings;
+use clap::Command;
+
+/// Environment variables read, besides `GIT_HUD__*` config overrides.
+const ENV_VARS: &[(&str, &str)] = &[
+    (
+        strings::ANTHROPIC_API_KEY,
+        "API key for the default summarizer",
+    ),
+    (
+        strings::GIT_HUD_CONFIG,
+        "The user config file, instead of ~/.config/git-hud/config.toml",
+    ),
+    (
+        strings::GIT_HUD_CACHE_DIR,
+        "Where summaries, the audit log, and run stats are kept, instead of ~/.cache/git-hud",
+    ),
+    (strings::GITLAB_TOKEN, "GitLab access token for `review`"),
+    (
+        strings::GERRIT_HTTP_PASSWORD,
+        "Gerrit HTTP password for `review`",
+    ),
+    (
+        strings::LOG_LEVEL,
+        "Set to `debug` to print timings, like --verbose",
+    ),
+];
+
+/// Every command's full help, then the config keys and environment variables.
+pub fn long_help(command: &Command) -> String {
+    let mut help = command.clone().render_long_help().to_string();
+    for (path, subcommand) in subcommands(command, "git hud") {
+        help.push_str(&format!("\n\n{}\n\n", heading(&path)));
+        help.push_str(&subcommand.clone().render_long_help().to_string());
+    }
+
+    help.push_str(&format!("\n\n{}\n\n", heading("Configuration")));
+    help.push_str(
+        "Set in ~/.config/git-hud/config.toml, a repo's .git-hud.toml, GIT_HUD__<KEY> variables \
+         (`__` between levels), or -c <key>=<value>, each overriding the last.\n\n",
+    );
+    for (key, example, description) in KEYS {
+        help.push_str(&format!("  {} = {}\n      {}\n", key, example, description));
+    }
+
+    help.push_str(&format!("\n{}\n\n", heading("Environment")));
+    for (name, description) in ENV_VARS {
+        help.push_str(&format!("  {}\n      {}\n", name, description));
+    }
+    help
+}
+
+/// A `git-hud(1)` man page in roff.
+pub fn man_page(command: &Command) -> String {
+    let mut page = format!(
+        ".TH GIT-HUD 1 \"\" \"git-hud {}\" \"Git Manual\"\n",
+        command.get_version().unwrap_or_default()
+    );
+    page.push_str(&format!(
+        ".SH NAME\ngit-hud \\- {}\n",
+        roff(
+            &command
+                .get_about()
+                .map(|about| about.to_string())
+                .unwrap_or_default()
+        )
+    ));
+    page.push_str(&format!(".SH SYNOPSIS\n{}", synopsis(command, "git hud")));
+    if let Some(about) = command.get_long_about() {
+        page.push_str(&format!(".SH DESCRIPTION\n{}\n", roff(&about.to_string())));
+    }
+    page.push_str(".SH OPTIONS\n");
+    page.push_str(&options(command));
+
+    page.push_str(".SH COMMANDS\n");
+    for (path, subcommand) in subcommands(command, "git hud") {
+        page.push_str(&format!(".SS {}\n", roff(&path)));
+        page.push_str(&synopsis(subcommand, &path));
+        let about = subcommand
+            .get_long_about()
+            .or(subcommand.get_about())
+            .map(|about| about.to_string())
+            .unwrap_or_default();
+        page.push_str(&format!(".PP\n{}\n", roff(&about)));
+        page.push_str(&options(subcommand));
+    }
+
+    page.push_str(".SH CONFIGURATION\n");
+    page.push_str(
+        "Settings are read from ~/.config/git\\-hud/config.toml, then a repository's \
+         \\fI.git\\-hud.toml\\fR, then \\fBGIT_HUD__\\fR\\fIKEY\\fR environment variables \
+         (with \\fB__\\fR between levels), then \\fB\\-c\\fR \\fIkey\\fR=\\fIvalue\\fR \
+         flags, each overriding the last.\n",
+    );
+    for (key, example, description) in KEYS {
+        page.push_str(&format!(
+            ".TP\n\\fB{}\\fR = {}\n{}\n",
+            roff(key),
+            roff(example),
+            roff(description)
+        ));
+    }
+
+    page.push_str(".SH ENVIRONMENT\n");
+    for (name, description) in ENV_VARS {
+        page.push_str(&format!(
+            ".TP\n\\fB{}\\fR\n{}\n",
+            roff(name),
+            roff(description)
+        ));
+    }
+    page
+}
+
+/// Every visible subcommand under `command`, depth first, with its full invocation.
+fn subcommands<'a>(command: &'a Command, path: &str) -> Vec<(String, &'a Command)> {
+    let mut found = Vec::new();
+    for subcommand in command.get_subcommands().filter(|c| !c.is_hide_set()) {
+        let path = format!("{} {}", path, subcommand.get_name());
+        let nested = subcommands(subcommand, &path);
+        found.push((path, subcommand));
+        found.extend(nested);
+    }
+    found
+}
+
+fn heading(text: &str) -> String {
+    format!("{}\n{}", text, "=".repeat(text.len()))
+}
+
+fn synopsis(command: &Command, path: &str) -> String {
+    let usage = command.clone().render_usage().to_string();
+    let usage = usage.strip_prefix("Usage: ").unwrap_or(&usage);
+    // The usage starts with the bare command name, which doesn't say how it's reached
+    let usage = usage.split_once(' ').map_or("", |(_, arguments)| arguments);
+    format!("\\fB{}\\fR {}\n", roff(path), roff(usage))
+}
+
+fn options(command: &Command) -> String {
+    let mut options = String::new();
+    for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        let mut names = Vec::new();
+        if let Some(short) = arg.get_short() {
+            names.push(format!("\\fB\\-{}\\fR", short));
+        }
+        if let Some(long) = arg.get_long() {
+            names.push(format!("\\fB\\-\\-{}\\fR", roff(long)));
+        }
+        let value = arg
+            .get_value_names()
+            .map(|values| values.join(" "))
+            .filter(|_| arg.get_action().takes_values());
+        let mut term = names.join(", ");
+        match (names.is_empty(), value) {
+            (true, value) => {
+                term = format!(
+                    "\\fI{}\\fR",
+                    roff(&value.unwrap_or_else(|| arg.get_id().to_string()))
+                )
+            }
+            (false, Some(value)) => term.push_str(&format!(" \\fI{}\\fR", roff(&value))),
+            (false, None) => {}
+        }
+        let help = arg
+            .get_long_help()
+            .or(arg.get_help())
+            .map(|help| help.to_string())
+            .unwrap_or_default();
+        options.push_str(&format!(".TP\n{}\n{}\n", term, roff(&help)));
+    }
+    options
+}
+
+/// Escapes text for roff: backslashes, hyphens (which would otherwise become dashes),
+/// and lines that would read as requests.
+fn roff(text: &str) -> String {
+    text.replace('\\', "\\e")
+        .replace('-', "\\-")
+        .lines()
+        .map(|line| match line.starts_with(['.', '\'']) {
+            true => format!("\\&{}", line),
+            false => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_long_help() {
+        let help = long_help(&Cli::command());
+        assert!(help.contains("git hud explain\n===="));
+        assert!(help.contains("git hud config set\n===="));
+        assert!(help.contains("  status.show-hidden = true\n"));
+        assert!(help.contains("  GIT_HUD_CACHE_DIR\n"));
+    }
+
+    #[test]
+    fn test_man_page() {
+        let page = man_page(&Cli::command());
+        assert!(page.starts_with(".TH GIT-HUD 1 "));
+        assert!(page.contains(".SS git hud audit show\n\\fBgit hud audit show\\fR [OPTIONS]\n"));
+        assert!(page.contains(".TP\n\\fB\\-u\\fR, \\fB\\-\\-untracked\\-files\\fR \\fIMODE\\fR\n"));
+        assert!(page.contains(".TP\n\\fBproviders.<name>.max\\-retries\\fR = 3\n"));
+        // No line may start a roff request by accident
+        assert!(page
+            .lines()
+            .filter(|line| line.starts_with('.'))
+            .all(|line| line[1..3].chars().all(|c| c.is_ascii_uppercase())));
+    }
+
+    #[test]
+    fn test_roff() {
+        assert_eq!(roff("a-b \\n"), "a\\-b \\en");
+        assert_eq!(roff("one\n.two"), "one\n\\&.two");
+    }
+}
@@ -0,0 +1,124 @@
+//! Posts generated reports, like `standup` and `prepush` output, to a chat webhook when
+//! they're run with `--post`.
+
+use crate::config::NotifyConfig;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Discord rejects messages longer than this.
+const DISCORD_MAX_CHARS: usize = 2000;
+
+/// The body a webhook expects.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// `{"text": ...}`
+    Slack,
+    /// `{"content": ...}`
+    Discord,
+    /// `{"source": "git-hud", "command": ..., "text": ...}`, for anything else
+    Json,
+}
+
+impl WebhookFormat {
+    /// Slack and Discord webhooks can be told apart by their host.
+    fn guess(url: &str) -> Self {
+        if url.contains("hooks.slack.com") {
+            WebhookFormat::Slack
+        } else if url.contains("discord.com/api/webhooks") || url.contains("discordapp.com") {
+            WebhookFormat::Discord
+        } else {
+            WebhookFormat::Json
+        }
+    }
+
+    fn body(self, command: &str, text: &str) -> serde_json::Value {
+        match self {
+            WebhookFormat::Slack => json!({ "text": text }),
+            WebhookFormat::Discord => {
+                let text: String = match text.chars().count() > DISCORD_MAX_CHARS {
+                    true => text
+                        .chars()
+                        .take(DISCORD_MAX_CHARS - 1)
+                        .chain(['…'])
+                        .collect(),
+                    false => text.to_string(),
+                };
+                json!({ "content": text })
+            }
+            WebhookFormat::Json => json!({
+                "source": "git-hud",
+                "command": command,
+                "text": text,
+            }),
+        }
+    }
+}
+
+/// Posts `text`, the output of `command`, to the configured webhook.
+pub async fn post(config: &NotifyConfig, command: &str, text: &str) -> Result<()> {
+    let url = config.webhook.as_deref().ok_or_else(|| {
+        anyhow::anyhow!(
+            "--post needs a webhook; set `notify.webhook` with `git hud config set notify.webhook <url>`"
+        )
+    })?;
+    let format = config.format.unwrap_or_else(|| WebhookFormat::guess(url));
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(&format.body(command, text))
+        .send()
+        .await
+        // Webhook URLs carry their token, so errors don't repeat them
+        .map_err(reqwest::Error::without_url)
+        .context("Failed to post to the webhook")?;
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!(
+            "The webhook answered {}: {}",
+            status,
+            error_text.trim()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess() {
+        assert_eq!(
+            WebhookFormat::guess("https://hooks.slack.com/services/T0/B0/x"),
+            WebhookFormat::Slack
+        );
+        assert_eq!(
+            WebhookFormat::guess("https://discord.com/api/webhooks/1/abc"),
+            WebhookFormat::Discord
+        );
+        assert_eq!(
+            WebhookFormat::guess("https://example.com/hooks/standup"),
+            WebhookFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_body() {
+        assert_eq!(
+            WebhookFormat::Slack.body("standup", "Done: x"),
+            json!({ "text": "Done: x" })
+        );
+        assert_eq!(
+            WebhookFormat::Json.body("prepush", "3 commits"),
+            json!({ "source": "git-hud", "command": "prepush", "text": "3 commits" })
+        );
+        let long = "a".repeat(DISCORD_MAX_CHARS + 10);
+        let body = WebhookFormat::Discord.body("standup", &long);
+        assert_eq!(
+            body["content"].as_str().unwrap().chars().count(),
+            DISCORD_MAX_CHARS
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Settings attached to files by gitignore-style patterns in the `[paths]` config table,
+//! like not summarizing anything under `vendor/` or sending `docs/` to another provider.
+
+use crate::config::Config;
+use crate::privacy;
+use crate::summary::{self, Summarizer};
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// What a `[paths]` pattern sets for the files it matches. A setting it leaves out is up
+/// to other patterns, or the default.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct PathRule {
+    /// Whether matching files are summarized; they're listed either way
+    pub summarize: Option<bool>,
+    /// The provider that summarizes matching files instead of the usual one: `anthropic`,
+    /// `command`, or `plugin`
+    pub provider: Option<String>,
+    /// How `--strict` treats matching files
+    pub risk: Option<Risk>,
+
+    /// Keys this version doesn't know, reported by `git hud config check`
+    #[serde(flatten)]
+    pub unknown: toml::Table,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Risk {
+    /// `--strict` reports any change to matching files, like migrations or CI config
+    AlwaysHigh,
+    /// `--strict` doesn't run its detectors on matching files, like test fixtures full
+    /// of fake secrets
+    Low,
+}
+
+/// The `[paths]` patterns, and a summarizer for each provider they send files to.
+#[derive(Default)]
+pub struct PathRules {
+    /// Least specific first, so later ones win
+    rules: Vec<(Pattern, PathRule)>,
+    summarizers: HashMap<String, Box<dyn Summarizer>>,
+    /// `--focus` patterns; when there are any, files matching none aren't summarized
+    focus: Vec<Pattern>,
+}
+
+impl PathRules {
+    pub fn new(patterns: &HashMap<String, PathRule>) -> Self {
+        let mut rules: Vec<_> = patterns
+            .iter()
+            .map(|(pattern, rule)| (Pattern::new(pattern), rule.clone()))
+            .collect();
+        // Config layers are merged into tables that don't keep their order, so unlike in
+        // .gitignore, the longer pattern wins rather than the later one
+        rules.sort_by(|(a, _), (b, _)| (a.text.len(), &a.text).cmp(&(b.text.len(), &b.text)));
+        Self {
+            rules,
+            summarizers: HashMap::new(),
+            focus: Vec::new(),
+        }
+    }
+
+    /// Summarizes only files matching one of `patterns`, for `--focus`. The rest are
+    /// listed without a summary, whatever their `[paths]` rules say.
+    pub fn with_focus(mut self, patterns: &[String]) -> Self {
+        self.focus = patterns
+            .iter()
+            .map(|pattern| Pattern::new(pattern))
+            .collect();
+        self
+    }
+
+    /// Whether `--focus` leaves a path, relative to the repo root, to be summarized.
+    pub fn in_focus(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        self.focus.is_empty() || self.focus.iter().any(|pattern| pattern.matches(&path))
+    }
+
+    /// Sets up a summarizer for each provider the patterns name, other than `default`'s.
+    /// Cloud providers are withheld unless one of `remotes` may be sent to them.
+    pub fn with_summarizers(
+        mut self,
+        config: &Config,
+        default: &dyn Summarizer,
+        remotes: &[String],
+    ) -> Result<Self> {
+        for (pattern, rule) in &self.rules {
+            let Some(provider) = &rule.provider else {
+                continue;
+            };
+            if provider == default.provider() || self.summarizers.contains_key(provider) {
+                continue;
+            }
+            let summarizer = summary::for_provider(config, provider)
+                .map_err(|e| anyhow::anyhow!("paths.\"{}\".provider: {:#}", pattern.text, e))?;
+            let summarizer = privacy::guard(summarizer, &config.privacy, remotes);
+            self.summarizers.insert(provider.clone(), summarizer);
+        }
+        Ok(self)
+    }
+
+    /// The settings for a path relative to the repo root, each from the most specific
+    /// pattern that matches and sets it. Outside the `--focus` patterns, nothing is
+    /// summarized.
+    pub fn rule(&self, path: &Path) -> PathRule {
+        let in_focus = self.in_focus(path);
+        let path = path.to_string_lossy();
+        let mut rule = PathRule::default();
+        for (pattern, matched) in &self.rules {
+            if !pattern.matches(&path) {
+                continue;
+            }
+            rule.summarize = matched.summarize.or(rule.summarize);
+            rule.provider = matched.provider.clone().or(rule.provider);
+            rule.risk = matched.risk.or(rule.risk);
+        }
+        if !in_focus {
+            rule.summarize = Some(false);
+        }
+        rule
+    }
+
+    /// Who summarizes the files `rule` applies to.
+    pub fn summarizer<'s>(
+        &'s self,
+        rule: &PathRule,
+        default: &'s dyn Summarizer,
+    ) -> &'s dyn Summarizer {
+        rule.provider
+            .as_ref()
+            .and_then(|provider| self.summarizers.get(provider))
+            .map_or(default, |summarizer| summarizer.as_ref())
+    }
+}
+
+/// Whether `path` matches a gitignore-style `pattern`.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    Pattern::new(pattern).matches(path)
+}
+
+/// A pattern as `.gitignore` reads them: `*` and `?` match within a path component and
+/// `**` matches any number of components. A pattern with no `/` except at the end matches
+/// at any depth, others only from the repo root, and one ending in `/` only matches
+/// directories. A pattern that matches a directory matches everything in it.
+#[derive(Debug)]
+struct Pattern {
+    text: String,
+    components: Vec<String>,
+    dir_only: bool,
+}
+
+impl Pattern {
+    fn new(text: &str) -> Self {
+        let dir_only = text.ends_with('/');
+        let trimmed = text.trim_end_matches('/');
+        let anchored = trimmed.contains('/');
+        let mut components: Vec<String> = trimmed
+            .trim_start_matches('/')
+            .split('/')
+            .map(str::to_string)
+            .collect();
+        if !anchored {
+            components.insert(0, "**".to_string());
+        }
+        Self {
+            text: text.to_string(),
+            components,
+            dir_only,
+        }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        // Untracked directories are listed with a trailing slash
+        let components: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        // The path itself, or a directory it's in
+        (1..=components.len())
+            .filter(|&len| !self.dir_only || len < components.len() || path.ends_with('/'))
+            .any(|len| match_components(&self.components, &components[..len]))
+    }
+}
+
+fn match_components(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skip| match_components(rest, &path[skip..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((component, path)) => {
+                wildcard(first.as_bytes(), component.as_bytes()) && match_components(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Matches one path component against `*`, `?`, and literal characters.
+fn wildcard(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => (0..=text.len()).any(|skip| wildcard(rest, &text[skip..])),
+        (Some((b'?', rest)), Some((_, text))) => wildcard(rest, text),
+        (Some((p, rest)), Some((t, text))) if p == t => wildcard(rest, text),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(patterns: &[(&str, PathRule)]) -> PathRules {
+        PathRules::new(
+            &patterns
+                .iter()
+                .map(|(pattern, rule)| (pattern.to_string(), rule.clone()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_pattern() {
+        assert!(matches("*.sql", "schema.sql"));
+        assert!(matches("*.sql", "db/migrations/001.sql"));
+        assert!(!matches("*.sql", "schema.sql.bak"));
+        assert!(matches("docs/**", "docs/guide/intro.md"));
+        assert!(!matches("docs/**", "src/docs/intro.md"));
+        assert!(matches("**/docs/**", "src/docs/intro.md"));
+        assert!(matches("/Cargo.lock", "Cargo.lock"));
+        assert!(!matches("/Cargo.lock", "crates/foo/Cargo.lock"));
+        // A directory name matches everything in it
+        assert!(matches("vendor", "third_party/vendor/lib.c"));
+        assert!(matches("vendor/", "vendor/lib.c"));
+        assert!(!matches("vendor/", "vendor"));
+        assert!(matches("vendor/", "vendor/"));
+        assert!(matches("src/*/mod.rs", "src/git/mod.rs"));
+        assert!(!matches("src/*/mod.rs", "src/a/b/mod.rs"));
+        assert!(matches("a/**/b", "a/b"));
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(!matches("file?.txt", "file/.txt"));
+    }
+
+    #[test]
+    fn test_rule() {
+        let skip = PathRule {
+            summarize: Some(false),
+            ..PathRule::default()
+        };
+        let rules = rules(&[
+            ("vendor/**", skip.clone()),
+            (
+                "vendor/ours/**",
+                PathRule {
+                    summarize: Some(true),
+                    risk: Some(Risk::AlwaysHigh),
+                    ..PathRule::default()
+                },
+            ),
+            (
+                "*.sql",
+                PathRule {
+                    risk: Some(Risk::Low),
+                    ..PathRule::default()
+                },
+            ),
+        ]);
+
+        assert_eq!(rules.rule(Path::new("src/main.rs")), PathRule::default());
+        assert_eq!(rules.rule(Path::new("vendor/lib.c")), skip);
+        // The longer pattern wins, and settings it leaves out come from the others
+        assert_eq!(
+            rules.rule(Path::new("vendor/ours/seed.sql")),
+            PathRule {
+                summarize: Some(true),
+                risk: Some(Risk::AlwaysHigh),
+                ..PathRule::default()
+            }
+        );
+        assert_eq!(
+            rules.rule(Path::new("vendor/seed.sql")),
+            PathRule {
+                summarize: Some(false),
+                risk: Some(Risk::Low),
+                ..PathRule::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_focus() {
+        let rules = rules(&[(
+            "*.sql",
+            PathRule {
+                risk: Some(Risk::Low),
+                ..PathRule::default()
+            },
+        )])
+        .with_focus(&["src/api/**".to_string(), "*.toml".to_string()]);
+
+        assert!(rules.in_focus(Path::new("src/api/routes.rs")));
+        assert!(rules.in_focus(Path::new("Cargo.toml")));
+        assert!(!rules.in_focus(Path::new("src/main.rs")));
+        assert_eq!(
+            rules.rule(Path::new("src/api/routes.rs")),
+            PathRule::default()
+        );
+        // Left out of the summaries, while its other settings still apply
+        assert_eq!(
+            rules.rule(Path::new("db/seed.sql")),
+            PathRule {
+                summarize: Some(false),
+                risk: Some(Risk::Low),
+                ..PathRule::default()
+            }
+        );
+        assert!(PathRules::default().in_focus(Path::new("src/main.rs")));
+    }
+}
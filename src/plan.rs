@@ -0,0 +1,519 @@
+use crate::cache::{self, CachedSummary};
+use crate::git::{self, Repository, Side, StatusCode, StatusEntry};
+use crate::stats::SummarySource;
+use crate::summary::{NewDirectory, NewFile, Summarizer, SummaryRequest, SummaryResponse};
+use crate::{encoding, language, lfs, line_endings, log};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// What `--dry-run` reports for one file.
+pub struct PlannedSummary {
+    pub path: String,
+    pub side: Side,
+    /// `None` for files that aren't summarized, like binary files
+    pub source: Option<SummarySource>,
+    pub prompt_tokens: u32,
+}
+
+/// A file's content when its summary was planned, by blob id. Working tree content
+/// isn't in the object store, so once it's been summarized, it's kept in the cache dir.
+pub struct Blob {
+    id: String,
+    /// The working tree file it was read from, for unstaged changes
+    worktree: Option<PathBuf>,
+}
+
+impl Blob {
+    /// The id to cache a summary of the content with, once the content is somewhere a
+    /// later run can read it. Content that's changed since, or that can't be kept, leaves
+    /// the summary without one.
+    fn keep(self) -> Option<String> {
+        let Some(path) = &self.worktree else {
+            return Some(self.id);
+        };
+        let contents = std::fs::read(path).ok()?;
+        if git::blob_id(&contents) != self.id {
+            return None;
+        }
+        match cache::store_snapshot(&self.id, &contents) {
+            Ok(()) => Some(self.id),
+            Err(e) => {
+                log::log_message(&format!("Not keeping {}: {:#}", path.display(), e));
+                None
+            }
+        }
+    }
+}
+
+/// How a file's summary will be produced, decided before any request is made.
+pub enum SummaryPlan<'a> {
+    /// The diff hasn't changed, so the cached summary is reused
+    Cached(&'a CachedSummary),
+    /// The cached summary is updated from just the changes made since it was written
+    Update {
+        previous: &'a CachedSummary,
+        delta: String,
+        diff_hash: String,
+        blob: Option<Blob>,
+    },
+    /// The whole diff is summarized
+    Full {
+        request: SummaryRequest,
+        diff_hash: String,
+        blob: Option<Blob>,
+    },
+    /// An untracked file is summarized by what it is, from its content
+    New {
+        file: NewFile,
+        diff_hash: String,
+        blob: Option<Blob>,
+    },
+    /// An untracked directory is summarized as a whole, from its files
+    NewDirectory {
+        directory: NewDirectory,
+        diff_hash: String,
+        blob: Option<Blob>,
+    },
+    /// The change is described without asking the summarizer
+    Local { summary: String, diff_hash: String },
+}
+
+impl<'a> SummaryPlan<'a> {
+    /// Reuses the cached summary when a file's diff hasn't changed. When it has, the
+    /// cached summary is updated from just the changes made since, falling back to
+    /// summarizing the whole diff when that's no smaller. A summary another provider or
+    /// model wrote isn't used at all, since `summarizer` is the one to ask now.
+    pub fn new(
+        repo: &Repository,
+        entry: &StatusEntry,
+        side: Side,
+        diff: &str,
+        previous: Option<&'a CachedSummary>,
+        summarizer: &dyn Summarizer,
+    ) -> Result<Self> {
+        let diff_hash = cache::hash(diff);
+        if let Some(summary) = local_summary(diff) {
+            return Ok(Self::Local { summary, diff_hash });
+        }
+        let previous = previous.filter(|previous| previous.written_by(summarizer));
+        if let Some(previous) = previous.filter(|previous| previous.diff_hash == diff_hash) {
+            return Ok(Self::Cached(previous));
+        }
+
+        let blob = match side {
+            Side::Staged => repo
+                .index_blob(&entry.path)?
+                .map(|id| Blob { id, worktree: None }),
+            Side::Unstaged => repo.worktree_blob(&entry.abs_path)?.map(|id| Blob {
+                id,
+                worktree: Some(entry.abs_path.clone()),
+            }),
+        };
+        if let (Some(previous), Some(new)) = (previous, &blob) {
+            if let Some(old) = &previous.blob {
+                if let Some(delta) = delta(repo, entry, old, new)? {
+                    if !delta.is_empty() && delta.len() < diff.len() {
+                        return Ok(Self::Update {
+                            previous,
+                            delta,
+                            diff_hash,
+                            blob,
+                        });
+                    }
+                }
+            }
+        }
+        if side == Side::Unstaged && matches!(entry.worktree, Some(StatusCode::Untracked)) {
+            if entry.abs_path.is_file() {
+                let file = new_file(entry)?;
+                return Ok(Self::New {
+                    file,
+                    diff_hash,
+                    blob,
+                });
+            }
+            if entry.abs_path.is_dir() {
+                let directory = new_directory(repo, entry)?;
+                return Ok(Self::NewDirectory {
+                    directory,
+                    diff_hash,
+                    blob,
+                });
+            }
+        }
+        Ok(Self::Full {
+            request: SummaryRequest::for_entry(entry, side, diff),
+            diff_hash,
+            blob,
+        })
+    }
+
+    pub fn source(&self) -> SummarySource {
+        match self {
+            Self::Cached(_) => SummarySource::Cache,
+            Self::Update { .. } => SummarySource::Update,
+            Self::Full { .. } | Self::New { .. } | Self::NewDirectory { .. } => SummarySource::Full,
+            Self::Local { .. } => SummarySource::Local,
+        }
+    }
+
+    /// The request, for a plan that summarizes the whole diff, which is what a file's
+    /// staged and unstaged sides can share one request for.
+    pub fn full_request(&self) -> Option<&SummaryRequest> {
+        match self {
+            Self::Full { request, .. } => Some(request),
+            _ => None,
+        }
+    }
+
+    /// The hash of the diff, for plans that make a request.
+    pub fn request_hash(&self) -> Option<&str> {
+        match self {
+            Self::Cached(_) | Self::Local { .. } => None,
+            Self::Update { diff_hash, .. }
+            | Self::Full { diff_hash, .. }
+            | Self::New { diff_hash, .. }
+            | Self::NewDirectory { diff_hash, .. } => Some(diff_hash),
+        }
+    }
+
+    /// The file's blob when it was summarized, for plans that store one.
+    pub fn blob(&self) -> Option<&str> {
+        match self {
+            Self::Cached(previous) => previous.blob.as_deref(),
+            Self::Update { blob, .. }
+            | Self::Full { blob, .. }
+            | Self::New { blob, .. }
+            | Self::NewDirectory { blob, .. } => blob.as_ref().map(|blob| blob.id.as_str()),
+            Self::Local { .. } => None,
+        }
+    }
+
+    /// Caches `summary` as this plan's result without making the request, for a summary
+    /// `summarizer` wrote that was found elsewhere, so it took no tokens here.
+    pub fn with_summary(self, summary: String, summarizer: &dyn Summarizer) -> CachedSummary {
+        let response = SummaryResponse {
+            text: summary,
+            tokens: 0,
+            model: summarizer.model().to_string(),
+        };
+        self.with_response(response, summarizer)
+    }
+
+    /// Caches what `summarizer` replied as this plan's result.
+    pub fn with_response(
+        self,
+        response: SummaryResponse,
+        summarizer: &dyn Summarizer,
+    ) -> CachedSummary {
+        let (diff_hash, blob) = match self {
+            Self::Cached(previous) => {
+                return CachedSummary {
+                    summary: response.text,
+                    ..previous.clone()
+                }
+            }
+            Self::Update {
+                diff_hash, blob, ..
+            }
+            | Self::Full {
+                diff_hash, blob, ..
+            }
+            | Self::New {
+                diff_hash, blob, ..
+            }
+            | Self::NewDirectory {
+                diff_hash, blob, ..
+            } => (diff_hash, blob.and_then(Blob::keep)),
+            Self::Local { diff_hash, .. } => (diff_hash, None),
+        };
+        CachedSummary {
+            summary: response.text,
+            diff_hash,
+            blob,
+            provider: summarizer.provider().to_string(),
+            model: response.model,
+            tokens: response.tokens,
+        }
+    }
+
+    /// The prompt this plan sends, or `None` if it doesn't make a request.
+    pub fn prompt(&self, summarizer: &dyn Summarizer) -> Option<String> {
+        match self {
+            Self::Cached(_) | Self::Local { .. } => None,
+            Self::Update {
+                previous, delta, ..
+            } => Some(summarizer.update_summary_prompt(&previous.summary, delta)),
+            Self::Full { request, .. } => Some(summarizer.summarize_prompt(request)),
+            Self::New { file, .. } => Some(summarizer.summarize_new_file_prompt(file)),
+            Self::NewDirectory { directory, .. } => {
+                Some(summarizer.summarize_new_directory_prompt(directory))
+            }
+        }
+    }
+
+    pub async fn execute(
+        self,
+        summarizer: &dyn Summarizer,
+    ) -> Result<(CachedSummary, SummarySource)> {
+        let source = self.source();
+        let response = match &self {
+            Self::Cached(previous) => return Ok(((*previous).clone(), source)),
+            Self::Update {
+                previous, delta, ..
+            } => summarizer.update_summary(&previous.summary, delta).await?,
+            Self::Full { request, .. } => summarizer.summarize(request).await?,
+            Self::New { file, .. } => summarizer.summarize_new_file(file).await?,
+            Self::NewDirectory { directory, .. } => {
+                summarizer.summarize_new_directory(directory).await?
+            }
+            Self::Local { summary, .. } => {
+                let summary = summary.clone();
+                return Ok((self.with_summary(summary, summarizer), source));
+            }
+        };
+        Ok((self.with_response(response, summarizer), source))
+    }
+}
+
+/// The changes from the content a file's cached summary was made of to `new`, or `None`
+/// if that content isn't around anymore.
+fn delta(repo: &Repository, entry: &StatusEntry, old: &str, new: &Blob) -> Result<Option<String>> {
+    let Some(path) = &new.worktree else {
+        return repo.diff_blobs(old, &new.id);
+    };
+    let Some(old) = cache::snapshot(old) else {
+        return Ok(None);
+    };
+    Ok(Some(repo.diff_contents(
+        &entry.path,
+        &old,
+        &std::fs::read(path)?,
+    )?))
+}
+
+/// Reads an untracked file for a new-file summary, in whatever encoding it's in.
+fn new_file(entry: &StatusEntry) -> Result<NewFile> {
+    let bytes = std::fs::read(&entry.abs_path)?;
+    let content = encoding::decode(&bytes, entry.encoding.unwrap_or(encoding_rs::UTF_8));
+    Ok(NewFile::new(
+        &entry.display_path,
+        language::detect(&entry.path, &content),
+        &content,
+    ))
+}
+
+/// Lists an untracked directory's files and reads the first few text ones, for a
+/// summary of the directory as a whole.
+fn new_directory(repo: &Repository, entry: &StatusEntry) -> Result<NewDirectory> {
+    let paths = repo.untracked_files_in(&entry.abs_path)?;
+    let relative = |path: &Path| {
+        path.strip_prefix(&entry.path)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    };
+    let files = paths.iter().map(|path| relative(path)).collect();
+    // Files are only read as samples are needed
+    let samples = paths.iter().filter_map(|path| {
+        let abs_path = repo.root().join(path);
+        let text_encoding = match repo.text_encoding(&abs_path) {
+            Ok(Some(text_encoding)) => text_encoding,
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(std::fs::read(&abs_path).map_err(Into::into).map(|bytes| {
+            let content = encoding::decode(&bytes, text_encoding);
+            (relative(path), language::detect(path, &content), content)
+        }))
+    });
+    NewDirectory::new(&entry.display_path, files, samples)
+}
+
+/// Whether a diff only changes a file's mode, which is shown without a summary.
+pub fn is_mode_only(diff: &str) -> bool {
+    diff.lines().any(|line| line.starts_with("new mode "))
+        && !diff.lines().any(|line| {
+            (line.starts_with('+') && !line.starts_with("+++ "))
+                || (line.starts_with('-') && !line.starts_with("--- "))
+        })
+}
+
+/// Describes changes that a summary wouldn't help with, without a request.
+fn local_summary(diff: &str) -> Option<String> {
+    lfs::describe_pointer_diff(diff).or_else(|| {
+        line_endings::only_line_endings(diff).then(|| line_endings::NORMALIZED.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::tests::git;
+    use crate::summary::CommandSummarizer;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_summary_plan() -> Result<()> {
+        let summarizer = CommandSummarizer::new("cat");
+        let temp_dir = TempDir::new()?;
+        git(temp_dir.path(), &["init"])?;
+        let repo = Repository::open_current_directory(temp_dir.path().to_str())?;
+
+        let content: String = (0..50).map(|i| format!("line {}\n", i)).collect();
+        fs::write(temp_dir.path().join("notes.txt"), &content)?;
+        let entry = repo.get_status()?.entries.remove(0);
+        let diff = repo.get_side_diff(&entry, Side::Unstaged)?.unwrap();
+
+        let plan = SummaryPlan::new(&repo, &entry, Side::Unstaged, &diff, None, &summarizer)?;
+        assert_eq!(plan.source(), SummarySource::Full);
+        let SummaryPlan::New {
+            file,
+            diff_hash,
+            blob,
+        } = plan
+        else {
+            unreachable!()
+        };
+        assert_eq!(file.path, "notes.txt");
+        assert_eq!(file.content, content);
+        let cached = CachedSummary {
+            diff_hash,
+            blob: blob.map(|blob| blob.id),
+            summary: "Add notes".to_string(),
+            provider: summarizer.provider().to_string(),
+            model: summarizer.model().to_string(),
+            tokens: 100,
+        };
+        let plan = SummaryPlan::new(
+            &repo,
+            &entry,
+            Side::Unstaged,
+            &diff,
+            Some(&cached),
+            &summarizer,
+        )?;
+        assert_eq!(plan.source(), SummarySource::Cache);
+        // Another provider's summary is no use to this one
+        let other = CommandSummarizer::new("head -1");
+        let plan = SummaryPlan::new(&repo, &entry, Side::Unstaged, &diff, Some(&cached), &other)?;
+        assert_eq!(plan.source(), SummarySource::Full);
+
+        // Working tree content is only kept once it's been summarized, so a plan alone
+        // leaves nothing to update from
+        fs::write(
+            temp_dir.path().join("notes.txt"),
+            content.clone() + "one more\n",
+        )?;
+        let diff = repo.get_side_diff(&entry, Side::Unstaged)?.unwrap();
+        let plan = SummaryPlan::new(
+            &repo,
+            &entry,
+            Side::Unstaged,
+            &diff,
+            Some(&cached),
+            &summarizer,
+        )?;
+        assert_eq!(plan.source(), SummarySource::Full);
+
+        // Staged content is in the object store already
+        git(temp_dir.path(), &["add", "notes.txt"])?;
+        let entry = repo.get_status()?.entries.remove(0);
+        let diff = repo.get_side_diff(&entry, Side::Staged)?.unwrap();
+        let plan = SummaryPlan::new(&repo, &entry, Side::Staged, &diff, None, &summarizer)?;
+        let cached = plan.with_summary("Add notes".to_string(), &summarizer);
+        fs::write(
+            temp_dir.path().join("notes.txt"),
+            content + "one more\nand another\n",
+        )?;
+        git(temp_dir.path(), &["add", "notes.txt"])?;
+        let entry = repo.get_status()?.entries.remove(0);
+        let diff = repo.get_side_diff(&entry, Side::Staged)?.unwrap();
+        let plan = SummaryPlan::new(
+            &repo,
+            &entry,
+            Side::Staged,
+            &diff,
+            Some(&cached),
+            &summarizer,
+        )?;
+        assert_eq!(plan.source(), SummarySource::Update);
+        let SummaryPlan::Update { delta, .. } = plan else {
+            unreachable!()
+        };
+        assert!(delta.contains("+and another"));
+        assert!(!delta.contains("+line 0"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_directory() -> Result<()> {
+        let summarizer = CommandSummarizer::new("cat");
+        let temp_dir = TempDir::new()?;
+        git(temp_dir.path(), &["init"])?;
+        let dir = temp_dir.path().join("migrations/2024_06");
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("001_users.sql"), "CREATE TABLE users (id INT);\n")?;
+        fs::write(dir.join("002_logo.png"), b"\x89PNG\r\n\x1a\n\0\0")?;
+        fs::write(dir.join("README.md"), "June schema changes\n")?;
+
+        let repo = Repository::open_current_directory(temp_dir.path().to_str())?;
+        let entry = repo.get_status()?.entries.remove(0);
+        assert_eq!(entry.display_path, "migrations/");
+        let diff = repo.get_side_diff(&entry, Side::Unstaged)?.unwrap();
+
+        let plan = SummaryPlan::new(&repo, &entry, Side::Unstaged, &diff, None, &summarizer)?;
+        assert_eq!(plan.source(), SummarySource::Full);
+        let SummaryPlan::NewDirectory { directory, .. } = plan else {
+            unreachable!()
+        };
+        assert_eq!(directory.path, "migrations/");
+        assert_eq!(
+            directory.files,
+            [
+                "2024_06/001_users.sql",
+                "2024_06/002_logo.png",
+                "2024_06/README.md"
+            ]
+        );
+        // The image is listed but not sampled
+        let samples: Vec<_> = directory
+            .samples
+            .iter()
+            .map(|sample| (sample.path.as_str(), sample.language))
+            .collect();
+        assert_eq!(
+            samples,
+            [
+                ("2024_06/001_users.sql", Some("SQL")),
+                ("2024_06/README.md", Some("Markdown"))
+            ]
+        );
+        assert!(directory.describe().contains("CREATE TABLE users"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_ending_churn() -> Result<()> {
+        let summarizer = CommandSummarizer::new("cat");
+        let temp_dir = TempDir::new()?;
+        git(temp_dir.path(), &["init"])?;
+        git(temp_dir.path(), &["config", "core.autocrlf", "false"])?;
+        fs::write(temp_dir.path().join("notes.txt"), "one\ntwo\n")?;
+        git(temp_dir.path(), &["add", "notes.txt"])?;
+        git(temp_dir.path(), &["commit", "-m", "notes"])?;
+        fs::write(temp_dir.path().join("notes.txt"), "one\r\ntwo\r\n")?;
+
+        let repo = Repository::open_current_directory(temp_dir.path().to_str())?;
+        let entry = repo.get_status()?.entries.remove(0);
+        let diff = repo.get_side_diff(&entry, Side::Unstaged)?.unwrap();
+        let plan = SummaryPlan::new(&repo, &entry, Side::Unstaged, &diff, None, &summarizer)?;
+        assert_eq!(plan.source(), SummarySource::Local);
+        assert!(plan.prompt(&summarizer).is_none());
+
+        Ok(())
+    }
+}
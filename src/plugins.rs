@@ -0,0 +1,385 @@
+use crate::config::PluginsConfig;
+use crate::summary::{
+    NewDirectory, NewFile, OnText, Reply, Summarizer, SummaryRequest, SummaryResponse,
+};
+use crate::tokens::Estimator;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+
+/// A plugin is a WASI command module (`.wasm`) that reads its input on stdin and writes
+/// its result to stdout. Plugins run sandboxed, without filesystem, network, or
+/// environment access.
+pub struct Plugin {
+    name: String,
+    #[cfg(feature = "wasm-plugins")]
+    engine: wasmtime::Engine,
+    #[cfg(feature = "wasm-plugins")]
+    module: wasmtime::Module,
+}
+
+// Plugin output is a diff or a summary, so anything bigger than this is a runaway plugin
+#[cfg(feature = "wasm-plugins")]
+const MAX_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+
+impl Plugin {
+    #[cfg(feature = "wasm-plugins")]
+    pub fn load(name: &str, path: &Path) -> Result<Self> {
+        use anyhow::Context;
+
+        let engine = wasmtime::Engine::default();
+        let module = wasmtime::Module::from_file(&engine, path)
+            .map_err(anyhow::Error::from)
+            .with_context(|| format!("Failed to load plugin {}", path.display()))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            engine,
+            module,
+        })
+    }
+
+    #[cfg(not(feature = "wasm-plugins"))]
+    pub fn load(name: &str, _path: &Path) -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "Can't load plugin {}: git-hud was built without the `wasm-plugins` feature",
+            name
+        ))
+    }
+
+    #[cfg(feature = "wasm-plugins")]
+    pub async fn run(&self, input: &str) -> Result<String> {
+        use wasmtime_wasi::p2::pipe::{MemoryInputPipe, MemoryOutputPipe};
+
+        let stdout = MemoryOutputPipe::new(MAX_OUTPUT_BYTES);
+        let wasi = wasmtime_wasi::WasiCtxBuilder::new()
+            .stdin(MemoryInputPipe::new(input.to_string()))
+            .stdout(stdout.clone())
+            .inherit_stderr()
+            .arg(&self.name)
+            .build_p1();
+
+        let mut linker = wasmtime::Linker::new(&self.engine);
+        wasmtime_wasi::p1::add_to_linker_async(&mut linker, |ctx| ctx)?;
+        let mut store = wasmtime::Store::new(&self.engine, wasi);
+        let instance = linker.instantiate_async(&mut store, &self.module).await?;
+        let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+
+        if let Err(e) = start.call_async(&mut store, ()).await {
+            // Calling `exit` surfaces as an error even when the exit code is 0
+            match e.downcast_ref::<wasmtime_wasi::I32Exit>() {
+                Some(wasmtime_wasi::I32Exit(0)) => {}
+                Some(wasmtime_wasi::I32Exit(code)) => {
+                    return Err(anyhow::anyhow!(
+                        "Plugin {} exited with code {}",
+                        self.name,
+                        code
+                    ))
+                }
+                None => {
+                    return Err(
+                        anyhow::Error::from(e).context(format!("Plugin {} failed", self.name))
+                    )
+                }
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&stdout.contents())
+            .trim()
+            .to_string())
+    }
+
+    #[cfg(not(feature = "wasm-plugins"))]
+    pub async fn run(&self, _input: &str) -> Result<String> {
+        Err(anyhow::anyhow!(
+            "Can't run plugin {}: git-hud was built without the `wasm-plugins` feature",
+            self.name
+        ))
+    }
+}
+
+/// The plugins named in the config, loaded from the plugins directory.
+pub struct Plugins {
+    pub preprocessors: Vec<Plugin>,
+    pub summarizer: Option<Plugin>,
+    pub postprocessors: Vec<Plugin>,
+}
+
+impl Plugins {
+    pub fn load(config: &PluginsConfig) -> Result<Self> {
+        let dir = config.dir.clone().unwrap_or_else(default_dir);
+        let load = |name: &String| Plugin::load(name, &dir.join(format!("{}.wasm", name)));
+
+        Ok(Self {
+            preprocessors: config
+                .preprocessors
+                .iter()
+                .map(load)
+                .collect::<Result<_>>()?,
+            summarizer: config.summarizer.as_ref().map(load).transpose()?,
+            postprocessors: config
+                .postprocessors
+                .iter()
+                .map(load)
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.preprocessors.is_empty() && self.summarizer.is_none() && self.postprocessors.is_empty()
+    }
+}
+
+fn default_dir() -> PathBuf {
+    crate::config::Config::path()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+        .unwrap_or_default()
+        .join("plugins")
+}
+
+/// Uses a plugin as the model backend.
+pub struct PluginSummarizer {
+    plugin: Plugin,
+}
+
+impl PluginSummarizer {
+    pub fn new(plugin: Plugin) -> Self {
+        Self { plugin }
+    }
+}
+
+#[async_trait]
+impl Summarizer for PluginSummarizer {
+    async fn complete(&self, prompt: String, _reply: Reply) -> Result<String> {
+        self.plugin.run(&prompt).await
+    }
+
+    fn provider(&self) -> &str {
+        "plugin"
+    }
+
+    fn model(&self) -> &str {
+        &self.plugin.name
+    }
+
+    // Like command summarizers, plugins get the bare diff
+    fn summarize_prompt(&self, request: &SummaryRequest) -> String {
+        request.diff.clone()
+    }
+
+    fn summarize_sides_prompt(
+        &self,
+        _staged: &SummaryRequest,
+        _unstaged: &SummaryRequest,
+    ) -> Option<String> {
+        None
+    }
+
+    fn summarize_new_file_prompt(&self, file: &NewFile) -> String {
+        file.content.clone()
+    }
+
+    fn summarize_new_directory_prompt(&self, directory: &NewDirectory) -> String {
+        directory.describe()
+    }
+}
+
+/// Runs each diff through the preprocessors before summarizing it, and each summary
+/// through the post-processors before it's displayed.
+pub struct ProcessingSummarizer {
+    inner: Box<dyn Summarizer>,
+    preprocessors: Vec<Plugin>,
+    postprocessors: Vec<Plugin>,
+}
+
+impl ProcessingSummarizer {
+    pub fn new(
+        inner: Box<dyn Summarizer>,
+        preprocessors: Vec<Plugin>,
+        postprocessors: Vec<Plugin>,
+    ) -> Self {
+        Self {
+            inner,
+            preprocessors,
+            postprocessors,
+        }
+    }
+}
+
+#[async_trait]
+impl Summarizer for ProcessingSummarizer {
+    async fn complete(&self, prompt: String, reply: Reply) -> Result<String> {
+        self.inner.complete(prompt, reply).await
+    }
+
+    async fn complete_streaming(
+        &self,
+        prompt: String,
+        reply: Reply,
+        on_text: &mut OnText<'_>,
+    ) -> Result<String> {
+        self.inner.complete_streaming(prompt, reply, on_text).await
+    }
+
+    fn provider(&self) -> &str {
+        self.inner.provider()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn estimator(&self) -> Estimator {
+        self.inner.estimator()
+    }
+
+    fn summarize_prompt(&self, request: &SummaryRequest) -> String {
+        self.inner.summarize_prompt(request)
+    }
+
+    fn update_summary_prompt(&self, previous: &str, delta: &str) -> String {
+        self.inner.update_summary_prompt(previous, delta)
+    }
+
+    fn summarize_sides_prompt(
+        &self,
+        staged: &SummaryRequest,
+        unstaged: &SummaryRequest,
+    ) -> Option<String> {
+        self.inner.summarize_sides_prompt(staged, unstaged)
+    }
+
+    fn summarize_new_file_prompt(&self, file: &NewFile) -> String {
+        self.inner.summarize_new_file_prompt(file)
+    }
+
+    fn summarize_new_directory_prompt(&self, directory: &NewDirectory) -> String {
+        self.inner.summarize_new_directory_prompt(directory)
+    }
+
+    async fn summarize(&self, request: &SummaryRequest) -> Result<SummaryResponse> {
+        let mut request = request.clone();
+        for plugin in &self.preprocessors {
+            request.diff = plugin.run(&request.diff).await?;
+        }
+
+        let mut summary = self.inner.summarize(&request).await?;
+        for plugin in &self.postprocessors {
+            summary.text = plugin.run(&summary.text).await?;
+        }
+        Ok(summary)
+    }
+
+    async fn summarize_sides(
+        &self,
+        staged: &SummaryRequest,
+        unstaged: &SummaryRequest,
+    ) -> Result<(SummaryResponse, SummaryResponse)> {
+        let (mut staged, mut unstaged) = (staged.clone(), unstaged.clone());
+        for plugin in &self.preprocessors {
+            staged.diff = plugin.run(&staged.diff).await?;
+            unstaged.diff = plugin.run(&unstaged.diff).await?;
+        }
+
+        let (mut staged, mut unstaged) = self.inner.summarize_sides(&staged, &unstaged).await?;
+        for plugin in &self.postprocessors {
+            staged.text = plugin.run(&staged.text).await?;
+            unstaged.text = plugin.run(&unstaged.text).await?;
+        }
+        Ok((staged, unstaged))
+    }
+
+    async fn summarize_new_file(&self, file: &NewFile) -> Result<SummaryResponse> {
+        let mut file = file.clone();
+        for plugin in &self.preprocessors {
+            file.content = plugin.run(&file.content).await?;
+        }
+
+        let mut summary = self.inner.summarize_new_file(&file).await?;
+        for plugin in &self.postprocessors {
+            summary.text = plugin.run(&summary.text).await?;
+        }
+        Ok(summary)
+    }
+
+    async fn summarize_new_directory(&self, directory: &NewDirectory) -> Result<SummaryResponse> {
+        let mut directory = directory.clone();
+        for sample in &mut directory.samples {
+            for plugin in &self.preprocessors {
+                sample.content = plugin.run(&sample.content).await?;
+            }
+        }
+
+        let mut summary = self.inner.summarize_new_directory(&directory).await?;
+        for plugin in &self.postprocessors {
+            summary.text = plugin.run(&summary.text).await?;
+        }
+        Ok(summary)
+    }
+
+    async fn update_summary(&self, previous: &str, delta: &str) -> Result<SummaryResponse> {
+        let mut delta = delta.to_string();
+        for plugin in &self.preprocessors {
+            delta = plugin.run(&delta).await?;
+        }
+
+        let mut summary = self.inner.update_summary(previous, &delta).await?;
+        for plugin in &self.postprocessors {
+            summary.text = plugin.run(&summary.text).await?;
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(all(test, feature = "wasm-plugins"))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    // Copies up to 1 KiB of stdin to stdout
+    const ECHO_PLUGIN: &str = r#"
+        (module
+          (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (func (export "_start")
+            (i32.store (i32.const 0) (i32.const 64))
+            (i32.store (i32.const 4) (i32.const 1024))
+            (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 16)))
+            (i32.store (i32.const 4) (i32.load (i32.const 16)))
+            (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 20)))))
+    "#;
+
+    #[tokio::test]
+    async fn test_plugin_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("echo.wasm"), ECHO_PLUGIN)?;
+
+        let plugins = Plugins::load(&PluginsConfig {
+            dir: Some(temp_dir.path().to_path_buf()),
+            preprocessors: vec!["echo".to_string()],
+            summarizer: Some("echo".to_string()),
+            ..Default::default()
+        })?;
+        assert_eq!(plugins.preprocessors.len(), 1);
+
+        let summarizer = PluginSummarizer::new(plugins.summarizer.unwrap());
+        assert_eq!(
+            summarizer
+                .summarize(&SummaryRequest::new("+a line\n"))
+                .await?
+                .text,
+            "+a line"
+        );
+
+        assert!(Plugins::load(&PluginsConfig {
+            dir: Some(temp_dir.path().to_path_buf()),
+            postprocessors: vec!["missing".to_string()],
+            ..Default::default()
+        })
+        .is_err());
+
+        Ok(())
+    }
+}
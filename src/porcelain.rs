@@ -0,0 +1,830 @@
+//! Parses `git status --porcelain=v2 --branch -z` output.
+//!
+//! Records are NUL-separated. Their header fields are ASCII, but paths can hold any
+//! bytes except NUL, so paths are kept as raw bytes.
+
+use crate::git::BranchInfo;
+use anyhow::{Context, Result};
+
+/// One side of an XY status pair.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Code {
+    Unmodified,
+    Modified,
+    TypeChanged,
+    Added,
+    Deleted,
+    Renamed,
+    Copied,
+    /// Updated but unmerged
+    Unmerged,
+}
+
+impl Code {
+    fn parse(code: u8) -> Result<Self> {
+        match code {
+            b'.' => Ok(Code::Unmodified),
+            b'M' => Ok(Code::Modified),
+            b'T' => Ok(Code::TypeChanged),
+            b'A' => Ok(Code::Added),
+            b'D' => Ok(Code::Deleted),
+            b'R' => Ok(Code::Renamed),
+            b'C' => Ok(Code::Copied),
+            b'U' => Ok(Code::Unmerged),
+            _ => Err(anyhow::anyhow!("Invalid status code: {}", code as char)),
+        }
+    }
+}
+
+/// The index (staged) and worktree (unstaged) sides of a change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XY {
+    pub index: Code,
+    pub worktree: Code,
+}
+
+impl XY {
+    fn parse(field: &str) -> Result<Self> {
+        match field.as_bytes() {
+            [x, y] => Ok(XY {
+                index: Code::parse(*x)?,
+                worktree: Code::parse(*y)?,
+            }),
+            _ => Err(anyhow::anyhow!("Invalid XY field: {}", field)),
+        }
+    }
+}
+
+/// Octal file modes, like `100644`, on each side of a change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modes {
+    pub head: String,
+    pub index: String,
+    pub worktree: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    /// An ordinary changed entry (`1`)
+    Changed {
+        xy: XY,
+        /// `N...` for files, `S<c><m><u>` for submodules
+        submodule: String,
+        modes: Modes,
+        path: Vec<u8>,
+    },
+    /// A renamed or copied entry (`2`)
+    Moved {
+        xy: XY,
+        submodule: String,
+        modes: Modes,
+        /// `Renamed` or `Copied`
+        kind: Code,
+        /// How similar the source and destination are, as a percentage
+        score: u8,
+        path: Vec<u8>,
+        original_path: Vec<u8>,
+    },
+    /// An unmerged entry (`u`)
+    Unmerged {
+        xy: XY,
+        submodule: String,
+        path: Vec<u8>,
+    },
+    /// An untracked file (`?`)
+    Untracked { path: Vec<u8> },
+    /// An ignored file (`!`), only listed with `--ignored`
+    Ignored { path: Vec<u8> },
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Porcelain {
+    pub branch: BranchInfo,
+    pub records: Vec<Record>,
+}
+
+/// Parses the full output of `git status --porcelain=v2 --branch -z`.
+pub fn parse(output: &[u8]) -> Result<Porcelain> {
+    let mut porcelain = Porcelain::default();
+    let mut fields = output.split(|b| *b == b'\0');
+
+    while let Some(line) = fields.next() {
+        if line.is_empty() {
+            continue;
+        }
+        let record = match line[0] {
+            b'#' => {
+                parse_branch_header(&mut porcelain.branch, &String::from_utf8_lossy(line))?;
+                continue;
+            }
+            // With -z, a rename's original path is the next NUL-separated field
+            b'2' => fields
+                .next()
+                .filter(|original_path| !original_path.is_empty())
+                .map(|original_path| parse_record(line, Some(original_path))),
+            _ => Some(parse_record(line, None)),
+        };
+
+        let record = record
+            .ok_or_else(|| anyhow::anyhow!("Missing original path"))
+            .and_then(|record| record)
+            .with_context(|| {
+                format!(
+                    "Failed to parse status line: {}",
+                    String::from_utf8_lossy(line)
+                )
+            })?;
+        if let Some(record) = record {
+            porcelain.records.push(record);
+        }
+    }
+    Ok(porcelain)
+}
+
+fn parse_record(line: &[u8], original_path: Option<&[u8]>) -> Result<Option<Record>> {
+    let record = match line[0] {
+        b'1' => {
+            let (fields, path) = split_fields(line, 8)?;
+            Record::Changed {
+                xy: XY::parse(fields[1])?,
+                submodule: fields[2].to_string(),
+                modes: modes(&fields[3..6]),
+                path,
+            }
+        }
+        b'2' => {
+            let (fields, path) = split_fields(line, 9)?;
+            // The similarity field is the kind of move followed by a score, like `R100`
+            let kind = Code::parse(*fields[8].as_bytes().first().unwrap_or(&b' '))?;
+            Record::Moved {
+                xy: XY::parse(fields[1])?,
+                submodule: fields[2].to_string(),
+                modes: modes(&fields[3..6]),
+                kind,
+                score: fields[8][1..]
+                    .parse()
+                    .with_context(|| format!("Invalid similarity score: {}", fields[8]))?,
+                path,
+                original_path: original_path.unwrap_or_default().to_vec(),
+            }
+        }
+        b'u' => {
+            let (fields, path) = split_fields(line, 10)?;
+            Record::Unmerged {
+                xy: XY::parse(fields[1])?,
+                submodule: fields[2].to_string(),
+                path,
+            }
+        }
+        b'?' => Record::Untracked {
+            path: split_fields(line, 1)?.1,
+        },
+        b'!' => Record::Ignored {
+            path: split_fields(line, 1)?.1,
+        },
+        // Newer versions of git may add record types; skipping them beats failing
+        _ => return Ok(None),
+    };
+    Ok(Some(record))
+}
+
+/// Splits the first `count` space-separated header fields off a record. The rest of the
+/// record is the path, which may itself contain spaces.
+fn split_fields(line: &[u8], count: usize) -> Result<(Vec<&str>, Vec<u8>)> {
+    let mut parts = line.splitn(count + 1, |b| *b == b' ');
+    let fields = parts
+        .by_ref()
+        .take(count)
+        .map(std::str::from_utf8)
+        .collect::<Result<Vec<_>, _>>()
+        .context("Status header fields were not valid UTF-8")?;
+    if fields.len() < count {
+        return Err(anyhow::anyhow!("Expected {} header fields", count));
+    }
+    let path = parts
+        .next()
+        .filter(|path| !path.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Missing path"))?;
+    Ok((fields, path.to_vec()))
+}
+
+fn modes(fields: &[&str]) -> Modes {
+    Modes {
+        head: fields[0].to_string(),
+        index: fields[1].to_string(),
+        worktree: fields[2].to_string(),
+    }
+}
+
+/// Splits renames git found in the working tree back into a deleted file and a new one.
+/// They only come from files added with `git add -N`, which git pairs with a deleted file
+/// they resemble; split, the new file is summarized in full like an untracked one.
+pub fn split_worktree_renames(records: Vec<Record>) -> Vec<Record> {
+    const NO_MODE: &str = "000000";
+    let mut split = Vec::with_capacity(records.len());
+    let mut deleted = Vec::new();
+    for record in records {
+        match record {
+            Record::Moved {
+                xy:
+                    XY {
+                        index: Code::Unmodified,
+                        worktree: Code::Renamed,
+                    },
+                submodule,
+                modes,
+                path,
+                original_path,
+                ..
+            } => {
+                split.push(Record::Changed {
+                    xy: XY {
+                        index: Code::Unmodified,
+                        worktree: Code::Added,
+                    },
+                    submodule: submodule.clone(),
+                    modes: Modes {
+                        head: NO_MODE.to_string(),
+                        index: NO_MODE.to_string(),
+                        worktree: modes.worktree,
+                    },
+                    path,
+                });
+                deleted.push((submodule, modes.head, modes.index, original_path));
+            }
+            record => split.push(record),
+        }
+    }
+    for (submodule, head_mode, index_mode, original_path) in deleted {
+        // The deleted file has its own record when its staged side changed too
+        let existing = split.iter_mut().find_map(|record| match record {
+            Record::Changed {
+                xy, modes, path, ..
+            }
+            | Record::Moved {
+                xy, modes, path, ..
+            } if *path == original_path => Some((xy, modes)),
+            _ => None,
+        });
+        match existing {
+            Some((xy, modes)) => {
+                xy.worktree = Code::Deleted;
+                modes.worktree = NO_MODE.to_string();
+            }
+            None => {
+                // Tracked files are listed in path order, before untracked ones
+                let at = split
+                    .iter()
+                    .position(|record| match record {
+                        Record::Changed { path, .. }
+                        | Record::Moved { path, .. }
+                        | Record::Unmerged { path, .. } => *path > original_path,
+                        Record::Untracked { .. } | Record::Ignored { .. } => true,
+                    })
+                    .unwrap_or(split.len());
+                let record = Record::Changed {
+                    xy: XY {
+                        index: Code::Unmodified,
+                        worktree: Code::Deleted,
+                    },
+                    submodule,
+                    modes: Modes {
+                        head: head_mode,
+                        index: index_mode,
+                        worktree: NO_MODE.to_string(),
+                    },
+                    path: original_path,
+                };
+                split.insert(at, record);
+            }
+        }
+    }
+    split
+}
+
+/// Applies one header line, like `# branch.ab +1 -0`. Unknown headers are ignored.
+fn parse_branch_header(branch: &mut BranchInfo, line: &str) -> Result<()> {
+    let Some(header) = line.strip_prefix("# ") else {
+        return Ok(());
+    };
+    let (key, value) = header.split_once(' ').unwrap_or((header, ""));
+    match key {
+        "branch.oid" => branch.oid = (value != "(initial)").then(|| value.to_string()),
+        "branch.head" => branch.head = (value != "(detached)").then(|| value.to_string()),
+        "branch.upstream" => branch.upstream = Some(value.to_string()),
+        "branch.ab" => {
+            let (ahead, behind) = value
+                .split_once(' ')
+                .ok_or_else(|| anyhow::anyhow!("Invalid branch.ab header: {}", line))?;
+            let count = |n: &str, sign: char| {
+                n.strip_prefix(sign)
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| anyhow::anyhow!("Invalid branch.ab header: {}", line))
+            };
+            branch.ahead_behind = Some((count(ahead, '+')?, count(behind, '-')?));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Captured from `git status --porcelain=v2 --branch -z --ignored` mid-merge, with
+    // every kind of change present
+    const MIXED: &[u8] = b"# branch.oid 9aa4c6218750635737ef8587a6cfb4e88936c6a7\0\
+        # branch.head main\0\
+        1 .A N... 000000 000000 100644 0000000000000000000000000000000000000000 0000000000000000000000000000000000000000 added.txt\0\
+        1 MM N... 100644 100644 100644 61780798228d17af2d34fce4cfbdf35556832472 5ab4d2b67babef5b15983c499a5f476d1a44ce8d both.txt\0\
+        1 D. N... 100644 000000 000000 f2ad6c76f0115a6ba5b00456a849810e7ec0af20 0000000000000000000000000000000000000000 del.txt\0\
+        1 .T N... 120000 120000 100644 d5507555ed64e2276ff1d5d91a87c3c45a978c6f d5507555ed64e2276ff1d5d91a87c3c45a978c6f link\0\
+        1 M. N... 100644 100644 100644 78981922613b2afb6025042ff6bd878ac1994e85 6670a6874dfd39d3724f7d880926d90f3a80c8c3 mod.txt\0\
+        2 R. N... 100644 100644 100644 dc06bc039927393c8c41c565d6c123618e029f45 dc06bc039927393c8c41c565d6c123618e029f45 R100 new name.txt\0\
+        old.txt\0\
+        u UU N... 100644 100644 100644 100644 587be6b4c3f93f93c489c0111bba5596147a26cb ba2906d0666cf726c7eaadd2cd3db615dedfdf3a e45c9c2666d44e0327c1f9c239a74c508336053e conflict.txt\0\
+        ? .gitignore\0\
+        ? untracked.txt\0\
+        ! ignored.log\0";
+
+    // Captured with `-c status.renames=copies` on a branch one commit ahead of its upstream
+    const COPIED: &[u8] = b"# branch.oid 7d7980eda5f424baa19d335c083eb2c08852f96d\0\
+        # branch.head master\0\
+        # branch.upstream origin/master\0\
+        # branch.ab +1 -0\0\
+        2 C. N... 100644 100644 100644 ac9837cd58bc91b0837922be433d758edb362434 4355ac18f4ea9986f2786e54f246a370442a993c C97 copy.txt\0\
+        orig.txt\0\
+        1 M. N... 100644 100644 100644 ac9837cd58bc91b0837922be433d758edb362434 8e0ccbf0eda857d58b2596d298df0b9a1237ea63 orig.txt\0";
+
+    fn xy(index: Code, worktree: Code) -> XY {
+        XY { index, worktree }
+    }
+
+    fn file_modes(head: &str, index: &str, worktree: &str) -> Modes {
+        Modes {
+            head: head.to_string(),
+            index: index.to_string(),
+            worktree: worktree.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_mixed_status() -> Result<()> {
+        let porcelain = parse(MIXED)?;
+        assert_eq!(
+            porcelain.branch,
+            BranchInfo {
+                oid: Some("9aa4c6218750635737ef8587a6cfb4e88936c6a7".to_string()),
+                head: Some("main".to_string()),
+                ..Default::default()
+            }
+        );
+
+        use Code::*;
+        let changed = |xy, modes, path: &str| Record::Changed {
+            xy,
+            submodule: "N...".to_string(),
+            modes,
+            path: path.as_bytes().to_vec(),
+        };
+        assert_eq!(
+            porcelain.records,
+            [
+                changed(
+                    xy(Unmodified, Added),
+                    file_modes("000000", "000000", "100644"),
+                    "added.txt"
+                ),
+                changed(
+                    xy(Modified, Modified),
+                    file_modes("100644", "100644", "100644"),
+                    "both.txt"
+                ),
+                changed(
+                    xy(Deleted, Unmodified),
+                    file_modes("100644", "000000", "000000"),
+                    "del.txt"
+                ),
+                changed(
+                    xy(Unmodified, TypeChanged),
+                    file_modes("120000", "120000", "100644"),
+                    "link"
+                ),
+                changed(
+                    xy(Modified, Unmodified),
+                    file_modes("100644", "100644", "100644"),
+                    "mod.txt"
+                ),
+                Record::Moved {
+                    xy: xy(Renamed, Unmodified),
+                    submodule: "N...".to_string(),
+                    modes: file_modes("100644", "100644", "100644"),
+                    kind: Renamed,
+                    score: 100,
+                    path: b"new name.txt".to_vec(),
+                    original_path: b"old.txt".to_vec(),
+                },
+                Record::Unmerged {
+                    xy: xy(Unmerged, Unmerged),
+                    submodule: "N...".to_string(),
+                    path: b"conflict.txt".to_vec(),
+                },
+                Record::Untracked {
+                    path: b".gitignore".to_vec()
+                },
+                Record::Untracked {
+                    path: b"untracked.txt".to_vec()
+                },
+                Record::Ignored {
+                    path: b"ignored.log".to_vec()
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    /// A file added with `git add -N` that resembles a deleted one is reported as renamed
+    /// from it in the working tree, and split back apart.
+    #[test]
+    fn test_split_worktree_renames() -> Result<()> {
+        use Code::*;
+        let changed = |xy, modes, path: &str| Record::Changed {
+            xy,
+            submodule: "N...".to_string(),
+            modes,
+            path: path.as_bytes().to_vec(),
+        };
+        let porcelain = parse(
+            b"1 M. N... 100644 100644 100644 5626abf0f72e58d7a153368ba57db4c673c0e171 f719efd430d52bcfc8566a43b2eb655688d38871 staged.txt\0\
+            2 .R N... 100644 100644 100644 f719efd430d52bcfc8566a43b2eb655688d38871 f719efd430d52bcfc8566a43b2eb655688d38871 R50 intent.txt\0\
+            staged.txt\0\
+            2 .R N... 100644 100644 100755 ac9837cd58bc91b0837922be433d758edb362434 ac9837cd58bc91b0837922be433d758edb362434 R90 other.sh\0\
+            gone.sh\0",
+        )?;
+        assert_eq!(
+            split_worktree_renames(porcelain.records),
+            [
+                changed(
+                    xy(Unmodified, Deleted),
+                    file_modes("100644", "100644", "000000"),
+                    "gone.sh"
+                ),
+                changed(
+                    xy(Modified, Deleted),
+                    file_modes("100644", "100644", "000000"),
+                    "staged.txt"
+                ),
+                changed(
+                    xy(Unmodified, Added),
+                    file_modes("000000", "000000", "100644"),
+                    "intent.txt"
+                ),
+                changed(
+                    xy(Unmodified, Added),
+                    file_modes("000000", "000000", "100755"),
+                    "other.sh"
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_copies_and_upstream() -> Result<()> {
+        let porcelain = parse(COPIED)?;
+        assert_eq!(porcelain.branch.upstream.as_deref(), Some("origin/master"));
+        assert_eq!(porcelain.branch.ahead_behind, Some((1, 0)));
+
+        assert_eq!(porcelain.records.len(), 2);
+        let Record::Moved {
+            kind,
+            score,
+            path,
+            original_path,
+            ..
+        } = &porcelain.records[0]
+        else {
+            panic!("expected a copy, got {:?}", porcelain.records[0]);
+        };
+        assert_eq!(*kind, Code::Copied);
+        assert_eq!(*score, 97);
+        assert_eq!(path, b"copy.txt");
+        assert_eq!(original_path, b"orig.txt");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_unusual_paths() -> Result<()> {
+        let porcelain = parse(b"? with  two spaces\0? latin1-\xe9.txt\0")?;
+        assert_eq!(
+            porcelain.records,
+            [
+                Record::Untracked {
+                    path: b"with  two spaces".to_vec()
+                },
+                Record::Untracked {
+                    path: b"latin1-\xe9.txt".to_vec()
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse(b"1 .M N... 100644 100644 100644 file.txt\0").is_err());
+        assert!(parse(b"1 .X N... 100644 100644 100644 a b file.txt\0").is_err());
+        assert!(parse(b"2 R. N... 100644 100644 100644 a b R100 new.txt\0").is_err());
+        assert!(parse(b"2 R. N... 100644 100644 100644 a b  new.txt\0old.txt\0").is_err());
+        assert!(parse(b"# branch.ab 3 1\0").is_err());
+        assert_eq!(parse(b"~ future record\0").unwrap().records, []);
+    }
+
+    #[test]
+    fn test_parse_branch_headers() -> Result<()> {
+        let mut branch = BranchInfo::default();
+        for line in [
+            "# branch.oid 1234abcd",
+            "# branch.head feature/x",
+            "# branch.upstream origin/feature/x",
+            "# branch.ab +3 -1",
+            "# stash 2",
+        ] {
+            parse_branch_header(&mut branch, line)?;
+        }
+        assert_eq!(
+            branch,
+            BranchInfo {
+                oid: Some("1234abcd".to_string()),
+                head: Some("feature/x".to_string()),
+                upstream: Some("origin/feature/x".to_string()),
+                ahead_behind: Some((3, 1)),
+                commit: None,
+                fetched: None,
+                stashes: 0,
+                operation: None,
+                unborn: false,
+            }
+        );
+
+        let mut branch = BranchInfo::default();
+        parse_branch_header(&mut branch, "# branch.oid (initial)")?;
+        parse_branch_header(&mut branch, "# branch.head (detached)")?;
+        assert_eq!(branch, BranchInfo::default());
+
+        Ok(())
+    }
+
+    /// A small xorshift generator, so generated cases are the same on every run.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+            items[self.below(items.len())]
+        }
+
+        /// A path of any bytes git allows: anything but NUL, weighted towards the
+        /// characters that trip up parsers.
+        fn path(&mut self) -> Vec<u8> {
+            let tricky = b" \t\n\r\"\\#?!:12RUu.";
+            (0..1 + self.below(12))
+                .map(|_| match self.below(4) {
+                    0 => self.pick(tricky),
+                    1 => 1 + self.below(255) as u8,
+                    _ => self.pick(b"abcxyz/_-."),
+                })
+                .collect()
+        }
+
+        fn xy(&mut self) -> XY {
+            XY {
+                index: self.pick(CODES),
+                worktree: self.pick(CODES),
+            }
+        }
+
+        fn modes(&mut self) -> Modes {
+            let mut mode = || self.pick(&["000000", "100644", "100755", "120000", "160000"]);
+            file_modes(mode(), mode(), mode())
+        }
+
+        fn submodule(&mut self) -> String {
+            self.pick(&["N...", "SC..", "S.M.", "S..U", "SCMU"])
+                .to_string()
+        }
+
+        fn record(&mut self) -> Record {
+            match self.below(5) {
+                0 => Record::Changed {
+                    xy: self.xy(),
+                    submodule: self.submodule(),
+                    modes: self.modes(),
+                    path: self.path(),
+                },
+                1 => Record::Moved {
+                    xy: self.xy(),
+                    submodule: self.submodule(),
+                    modes: self.modes(),
+                    kind: self.pick(&[Code::Renamed, Code::Copied]),
+                    score: self.below(101) as u8,
+                    path: self.path(),
+                    original_path: self.path(),
+                },
+                2 => Record::Unmerged {
+                    xy: self.xy(),
+                    submodule: self.submodule(),
+                    path: self.path(),
+                },
+                3 => Record::Untracked { path: self.path() },
+                _ => Record::Ignored { path: self.path() },
+            }
+        }
+    }
+
+    const CODES: &[Code] = &[
+        Code::Unmodified,
+        Code::Modified,
+        Code::TypeChanged,
+        Code::Added,
+        Code::Deleted,
+        Code::Renamed,
+        Code::Copied,
+        Code::Unmerged,
+    ];
+    const OID: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn code_char(code: Code) -> char {
+        match code {
+            Code::Unmodified => '.',
+            Code::Modified => 'M',
+            Code::TypeChanged => 'T',
+            Code::Added => 'A',
+            Code::Deleted => 'D',
+            Code::Renamed => 'R',
+            Code::Copied => 'C',
+            Code::Unmerged => 'U',
+        }
+    }
+
+    /// How git would print `record`.
+    fn render(record: &Record) -> Vec<u8> {
+        let xy = |xy: &XY| format!("{}{}", code_char(xy.index), code_char(xy.worktree));
+        let modes = |m: &Modes| format!("{} {} {}", m.head, m.index, m.worktree);
+        let (header, path, original_path) = match record {
+            Record::Changed {
+                xy: x,
+                submodule,
+                modes: m,
+                path,
+            } => (
+                format!("1 {} {} {} {} {} ", xy(x), submodule, modes(m), OID, OID),
+                path,
+                None,
+            ),
+            Record::Moved {
+                xy: x,
+                submodule,
+                modes: m,
+                kind,
+                score,
+                path,
+                original_path,
+            } => (
+                format!(
+                    "2 {} {} {} {} {} {}{} ",
+                    xy(x),
+                    submodule,
+                    modes(m),
+                    OID,
+                    OID,
+                    code_char(*kind),
+                    score
+                ),
+                path,
+                Some(original_path),
+            ),
+            Record::Unmerged {
+                xy: x,
+                submodule,
+                path,
+            } => (
+                format!(
+                    "u {} {} 100644 100644 100644 100644 {} {} {} ",
+                    xy(x),
+                    submodule,
+                    OID,
+                    OID,
+                    OID
+                ),
+                path,
+                None,
+            ),
+            Record::Untracked { path } => ("? ".to_string(), path, None),
+            Record::Ignored { path } => ("! ".to_string(), path, None),
+        };
+        let mut line = header.into_bytes();
+        line.extend_from_slice(path);
+        line.push(b'\0');
+        if let Some(original_path) = original_path {
+            line.extend_from_slice(original_path);
+            line.push(b'\0');
+        }
+        line
+    }
+
+    /// Any list of records, printed the way git prints them, parses back to the same
+    /// records, whatever bytes the paths hold.
+    #[test]
+    fn test_parse_generated_records() -> Result<()> {
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for case in 0..2000 {
+            let branch = BranchInfo {
+                oid: Some(OID.to_string()),
+                head: Some(String::from_utf8_lossy(&random.path()).replace(['\n', '\r', ' '], "-")),
+                upstream: None,
+                ahead_behind: Some((random.below(1000) as u32, random.below(1000) as u32)),
+                commit: None,
+                fetched: None,
+                stashes: 0,
+                operation: None,
+                unborn: false,
+            };
+            let records: Vec<Record> = (0..random.below(6)).map(|_| random.record()).collect();
+
+            let (ahead, behind) = branch.ahead_behind.unwrap();
+            let mut output = format!(
+                "# branch.oid {}\0# branch.head {}\0# branch.ab +{} -{}\0",
+                OID,
+                branch.head.as_deref().unwrap(),
+                ahead,
+                behind
+            )
+            .into_bytes();
+            records
+                .iter()
+                .for_each(|record| output.extend(render(record)));
+
+            let porcelain = parse(&output)
+                .with_context(|| format!("case {}: {}", case, String::from_utf8_lossy(&output)))?;
+            assert_eq!(porcelain.records, records, "case {}", case);
+            assert_eq!(porcelain.branch, branch, "case {}", case);
+        }
+        Ok(())
+    }
+
+    /// Cutting a record short or corrupting any of its bytes makes parsing fail, or
+    /// succeed, but never panic.
+    #[test]
+    fn test_parse_damaged_records() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        for case in 0..2000 {
+            let mut output: Vec<u8> = (0..1 + random.below(3))
+                .flat_map(|_| render(&random.record()))
+                .collect();
+            match random.below(3) {
+                0 => output.truncate(random.below(output.len())),
+                1 => {
+                    let at = random.below(output.len());
+                    output[at] = random.below(256) as u8;
+                }
+                _ => {
+                    let at = random.below(output.len());
+                    output.insert(at, random.pick(b" \0#2"));
+                }
+            }
+            let result = std::panic::catch_unwind(|| parse(&output));
+            assert!(
+                result.is_ok(),
+                "case {} panicked on {:?}",
+                case,
+                String::from_utf8_lossy(&output)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_every_xy() -> Result<()> {
+        for &index in CODES {
+            for &worktree in CODES {
+                let field = format!("{}{}", code_char(index), code_char(worktree));
+                assert_eq!(XY::parse(&field)?, xy(index, worktree));
+            }
+        }
+        for field in ["", ".", "...", "M ", "m.", "é"] {
+            assert!(XY::parse(field).is_err(), "{:?}", field);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+//! Plain progress lines for runs no one watches in a terminal, like CI logs, so a long
+//! wait for summaries doesn't look like a hang.
+
+use std::future::Future;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// How many of a run's summary requests have come back.
+pub struct Progress {
+    done: AtomicUsize,
+    total: usize,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Self {
+            done: AtomicUsize::new(0),
+            total,
+        }
+    }
+
+    /// Counts `count` more requests as done.
+    pub fn advance(&self, count: usize) {
+        self.done.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// `summarized 10/37…`
+    pub fn line(&self) -> String {
+        format!(
+            "summarized {}/{}…",
+            self.done.load(Ordering::Relaxed),
+            self.total
+        )
+    }
+}
+
+/// How often to report progress, given `status.progress-interval` in seconds. Never when
+/// stderr is a terminal, where the wait can be seen, or when the interval is 0.
+pub fn interval(seconds: u64) -> Option<Duration> {
+    (seconds > 0 && !std::io::stderr().is_terminal()).then(|| Duration::from_secs(seconds))
+}
+
+/// Waits for `work`, passing `progress`'s line to `print` every `interval` until it's done.
+pub async fn report<T>(
+    work: impl Future<Output = T>,
+    progress: &Progress,
+    interval: Duration,
+    mut print: impl FnMut(&str),
+) -> T {
+    tokio::pin!(work);
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        tokio::select! {
+            output = &mut work => return output,
+            _ = ticks.tick() => print(&progress.line()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line() {
+        let progress = Progress::new(37);
+        assert_eq!(progress.line(), "summarized 0/37…");
+        progress.advance(2);
+        progress.advance(8);
+        assert_eq!(progress.line(), "summarized 10/37…");
+    }
+
+    #[tokio::test]
+    async fn test_report() {
+        let progress = Progress::new(2);
+        let mut lines = Vec::new();
+        let work = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            progress.advance(1);
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            "done"
+        };
+        let output = report(work, &progress, Duration::from_millis(100), |line| {
+            lines.push(line.to_string())
+        })
+        .await;
+
+        assert_eq!(output, "done");
+        assert!(!lines.is_empty());
+        assert!(lines.iter().all(|line| line == "summarized 1/2…"));
+    }
+
+    #[tokio::test]
+    async fn test_report_quick_work() {
+        // Nothing is printed for work that's done before the first interval
+        let progress = Progress::new(1);
+        let mut lines = Vec::new();
+        report(async {}, &progress, Duration::from_secs(10), |line| {
+            lines.push(line.to_string())
+        })
+        .await;
+        assert!(lines.is_empty());
+    }
+}
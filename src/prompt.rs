@@ -0,0 +1,12 @@
+use anyhow::Result;
+use std::io::{self, BufRead, Write};
+
+/// Asks a yes/no question on the terminal. Anything but "y" or "yes" means no.
+pub fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
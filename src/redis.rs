@@ -0,0 +1,303 @@
+//! Just enough of a Redis client for the shared cache backend: connects to a
+//! `redis://[user:password@]host[:port][/db]` URL and sends commands in the RESP
+//! protocol over a plain TCP connection.
+
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const DEFAULT_PORT: u16 = 6379;
+/// Reading and writing the cache should never hold up the status view for long.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Where a Redis server is and how to log in to it.
+#[derive(Debug, PartialEq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub db: u32,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("redis://")
+            .ok_or_else(|| anyhow::anyhow!("A Redis URL starts with redis://"))?;
+        let (login, rest) = match rest.rsplit_once('@') {
+            Some((login, rest)) => (Some(login), rest),
+            None => (None, rest),
+        };
+        let (user, password) = match login.map(|login| login.split_once(':')) {
+            Some(Some((user, password))) => (
+                (!user.is_empty()).then(|| user.to_string()),
+                Some(password.to_string()),
+            ),
+            // `redis://password@host` is how some hosts hand out URLs
+            Some(None) => (None, login.map(str::to_string)),
+            None => (None, None),
+        };
+        let (address, db) = match rest.split_once('/') {
+            Some((address, "")) => (address, 0),
+            Some((address, db)) => (
+                address,
+                db.parse()
+                    .with_context(|| format!("`{}` isn't a Redis database number", db))?,
+            ),
+            None => (rest, 0),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .with_context(|| format!("`{}` isn't a port", port))?,
+            ),
+            None => (address, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(anyhow::anyhow!("The Redis URL has no host"));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            user,
+            password,
+            db,
+        })
+    }
+}
+
+/// A reply from the server.
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Status(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+/// An open, logged-in connection.
+pub struct Connection<S: Read + Write> {
+    stream: BufReader<S>,
+}
+
+impl Connection<TcpStream> {
+    pub fn open(url: &Url) -> Result<Self> {
+        let address = format!("{}:{}", url.host, url.port);
+        let stream = std::net::ToSocketAddrs::to_socket_addrs(&address)
+            .with_context(|| format!("Couldn't resolve {}", address))?
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Couldn't resolve {}", address))
+            .and_then(|socket| {
+                TcpStream::connect_timeout(&socket, TIMEOUT)
+                    .with_context(|| format!("Couldn't connect to Redis at {}", address))
+            })?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut connection = Self {
+            stream: BufReader::new(stream),
+        };
+        if let Some(password) = &url.password {
+            let login = match &url.user {
+                Some(user) => connection.command(&[b"AUTH", user.as_bytes(), password.as_bytes()]),
+                None => connection.command(&[b"AUTH", password.as_bytes()]),
+            };
+            login.context("Redis didn't accept the login")?;
+        }
+        if url.db != 0 {
+            connection.command(&[b"SELECT", url.db.to_string().as_bytes()])?;
+        }
+        Ok(connection)
+    }
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Sends one command and reads its reply; an error reply is an `Err`.
+    pub fn command(&mut self, args: &[&[u8]]) -> Result<Reply> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend_from_slice(arg);
+            request.extend_from_slice(b"\r\n");
+        }
+        let stream = self.stream.get_mut();
+        stream.write_all(&request)?;
+        stream.flush()?;
+        read_reply(&mut self.stream)
+    }
+
+    /// Every field of the hash at `key` and its value; none if it isn't set.
+    pub fn hgetall(&mut self, key: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let replies = match self.command(&[b"HGETALL", key.as_bytes()])? {
+            Reply::Array(replies) => replies,
+            reply => return Err(anyhow::anyhow!("Unexpected reply to HGETALL: {:?}", reply)),
+        };
+        let mut fields = Vec::with_capacity(replies.len() / 2);
+        let mut replies = replies.into_iter();
+        while let Some(field) = replies.next() {
+            match (field, replies.next()) {
+                (Reply::Bulk(Some(field)), Some(Reply::Bulk(Some(value)))) => {
+                    fields.push((String::from_utf8_lossy(&field).into_owned(), value))
+                }
+                (field, value) => {
+                    return Err(anyhow::anyhow!(
+                        "Unexpected field in reply to HGETALL: {:?} {:?}",
+                        field,
+                        value
+                    ))
+                }
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Sets `fields` in the hash at `key`, leaving its other fields as they are.
+    pub fn hset(&mut self, key: &str, fields: &[(&str, &[u8])]) -> Result<()> {
+        if fields.is_empty() {
+            return Ok(());
+        }
+        let mut args = vec![b"HSET".as_slice(), key.as_bytes()];
+        for (field, value) in fields {
+            args.extend([field.as_bytes(), *value]);
+        }
+        self.command(&args).map(|_| ())
+    }
+
+    /// Has `key` deleted once it goes `seconds` without being set again.
+    pub fn expire(&mut self, key: &str, seconds: u64) -> Result<()> {
+        self.command(&[b"EXPIRE", key.as_bytes(), seconds.to_string().as_bytes()])
+            .map(|_| ())
+    }
+}
+
+fn read_reply(reader: &mut impl BufRead) -> Result<Reply> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(anyhow::anyhow!("Redis closed the connection"));
+    }
+    let line = line.trim_end_matches("\r\n");
+    let (kind, rest) = line.split_at(line.len().min(1));
+    let number = || -> Result<i64> {
+        rest.parse()
+            .with_context(|| format!("Malformed Redis reply `{}`", line))
+    };
+    match kind {
+        "+" => Ok(Reply::Status(rest.to_string())),
+        "-" => Err(anyhow::anyhow!("Redis: {}", rest)),
+        ":" => Ok(Reply::Integer(number()?)),
+        "$" => match number()? {
+            length if length < 0 => Ok(Reply::Bulk(None)),
+            length => {
+                // The value, then its trailing CRLF
+                let mut value = vec![0; length as usize + 2];
+                reader.read_exact(&mut value)?;
+                value.truncate(length as usize);
+                Ok(Reply::Bulk(Some(value)))
+            }
+        },
+        "*" => match number()? {
+            length if length < 0 => Ok(Reply::Array(Vec::new())),
+            length => (0..length)
+                .map(|_| read_reply(reader))
+                .collect::<Result<_>>()
+                .map(Reply::Array),
+        },
+        _ => Err(anyhow::anyhow!("Malformed Redis reply `{}`", line)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Plays back canned replies and records what was sent.
+    struct Fake {
+        replies: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl Read for Fake {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for Fake {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_commands() -> Result<()> {
+        let fake = Fake {
+            replies: Cursor::new(
+                b":2\r\n*4\r\n$1\r\na\r\n$4\r\nx\r\ny\r\n$1\r\nb\r\n$1\r\n2\r\n*0\r\n*1\r\n$1\r\na\r\n-ERR wrong\r\n"
+                    .to_vec(),
+            ),
+            sent: Vec::new(),
+        };
+        let mut connection = Connection {
+            stream: BufReader::new(fake),
+        };
+        connection.hset("h", &[("a", b"x\r\ny"), ("b", b"2")])?;
+        // Nothing to set sends nothing
+        connection.hset("h", &[])?;
+        // Values are read by length, so they can hold line breaks
+        assert_eq!(
+            connection.hgetall("h")?,
+            [
+                ("a".to_string(), b"x\r\ny".to_vec()),
+                ("b".to_string(), b"2".to_vec())
+            ]
+        );
+        assert_eq!(connection.hgetall("missing")?, []);
+        // A field without its value
+        assert!(connection.hgetall("h").is_err());
+        assert!(connection.hgetall("h").is_err());
+        assert!(connection.stream.get_ref().sent.starts_with(
+            b"*6\r\n$4\r\nHSET\r\n$1\r\nh\r\n$1\r\na\r\n$4\r\nx\r\ny\r\n$1\r\nb\r\n$1\r\n2\r\n*2\r\n$7\r\nHGETALL\r\n"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_array() -> Result<()> {
+        let mut reader = Cursor::new(b"*2\r\n:1\r\n$1\r\nx\r\n".to_vec());
+        assert_eq!(
+            read_reply(&mut reader)?,
+            Reply::Array(vec![Reply::Integer(1), Reply::Bulk(Some(b"x".to_vec()))])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_url() -> Result<()> {
+        assert_eq!(
+            Url::parse("redis://cache.internal")?,
+            Url {
+                host: "cache.internal".to_string(),
+                port: DEFAULT_PORT,
+                user: None,
+                password: None,
+                db: 0,
+            }
+        );
+        let url = Url::parse("redis://ci:s3cr@t@10.0.0.5:6380/2")?;
+        assert_eq!(url.user.as_deref(), Some("ci"));
+        assert_eq!(url.password.as_deref(), Some("s3cr@t"));
+        assert_eq!((url.host.as_str(), url.port, url.db), ("10.0.0.5", 6380, 2));
+        assert_eq!(Url::parse("redis://:pw@h")?.password.as_deref(), Some("pw"));
+        assert!(Url::parse("http://h").is_err());
+        assert!(Url::parse("redis://h:port").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+//! Posts per-file summaries to the code review a branch is up for, on whichever forge the
+//! `review.forge` config names: a GitLab merge request or a Gerrit change.
+
+use crate::config::ReviewConfig;
+use crate::git::Repository;
+use crate::strings;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Where reviews happen.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Forge {
+    /// Merge requests, commented on with discussion notes
+    Gitlab,
+    /// Changes, commented on with file comments in a review
+    Gerrit,
+}
+
+/// A changed file and its summary.
+pub struct FileNote {
+    pub path: String,
+    pub summary: String,
+}
+
+/// A code review that summaries can be posted to.
+#[async_trait]
+pub trait Review {
+    /// What's being reviewed, for messages, like `merge request !12`.
+    fn target(&self) -> String;
+
+    /// Posts a summary of each file.
+    async fn post(&self, notes: &[FileNote]) -> Result<()>;
+}
+
+/// The review set up in `config`. `change` names the merge request or change, which
+/// otherwise comes from the CI job or the commit being reviewed.
+pub fn from_config(
+    config: &ReviewConfig,
+    repo: &Repository,
+    change: Option<String>,
+) -> Result<Box<dyn Review>> {
+    let forge = config.forge.ok_or_else(|| {
+        anyhow::anyhow!(
+            "No forge to post to; set `review.forge` to `gitlab` or `gerrit` with `git hud config set review.forge gitlab`"
+        )
+    })?;
+    match forge {
+        Forge::Gitlab => Ok(Box::new(GitLab::new(config, repo, change)?)),
+        Forge::Gerrit => Ok(Box::new(Gerrit::new(config, repo, change)?)),
+    }
+}
+
+/// A GitLab merge request.
+pub struct GitLab {
+    url: String,
+    project: String,
+    merge_request: String,
+    token: String,
+}
+
+impl GitLab {
+    fn new(config: &ReviewConfig, repo: &Repository, change: Option<String>) -> Result<Self> {
+        // GitLab CI sets these for merge request pipelines
+        let url = config
+            .url
+            .clone()
+            .or_else(|| std::env::var(strings::CI_SERVER_URL).ok())
+            .unwrap_or_else(|| "https://gitlab.com".to_string());
+        let project = match config.project.clone() {
+            Some(project) => project,
+            None => match std::env::var(strings::CI_PROJECT_ID) {
+                Ok(id) => id,
+                Err(_) => repo
+                    .remote_urls()?
+                    .first()
+                    .and_then(|remote| project_path(remote))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Couldn't tell the GitLab project from the remotes; set `review.project`"
+                        )
+                    })?,
+            },
+        };
+        let merge_request = change
+            .or_else(|| std::env::var(strings::CI_MERGE_REQUEST_IID).ok())
+            .ok_or_else(|| {
+                anyhow::anyhow!("Which merge request? Pass --change with its number, like 12")
+            })?;
+        let token = std::env::var(strings::GITLAB_TOKEN).map_err(|_| {
+            anyhow::anyhow!(
+                "Set {} to a GitLab access token with the api scope",
+                strings::GITLAB_TOKEN
+            )
+        })?;
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            project,
+            merge_request: merge_request.trim_start_matches('!').to_string(),
+            token,
+        })
+    }
+
+    /// The discussion note: a list of the files, each with its summary.
+    fn body(notes: &[FileNote]) -> String {
+        let mut body = String::from("Summaries from git-hud:\n\n");
+        for note in notes {
+            body.push_str(&format!("- `{}`: {}\n", note.path, note.summary));
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl Review for GitLab {
+    fn target(&self) -> String {
+        format!("merge request !{}", self.merge_request)
+    }
+
+    async fn post(&self, notes: &[FileNote]) -> Result<()> {
+        let url = format!(
+            "{}/api/v4/projects/{}/merge_requests/{}/discussions",
+            self.url,
+            // A project can be named by its path, with its slashes encoded
+            self.project.replace('/', "%2F"),
+            self.merge_request
+        );
+        let request = reqwest::Client::new()
+            .post(url)
+            .header("PRIVATE-TOKEN", &self.token)
+            .json(&json!({ "body": Self::body(notes) }));
+        send(request, "GitLab").await
+    }
+}
+
+/// A Gerrit change.
+pub struct Gerrit {
+    url: String,
+    change: String,
+    user: String,
+    password: String,
+}
+
+impl Gerrit {
+    fn new(config: &ReviewConfig, repo: &Repository, change: Option<String>) -> Result<Self> {
+        let url = config.url.clone().ok_or_else(|| {
+            anyhow::anyhow!(
+                "Set `review.url` to your Gerrit server, like https://review.example.com"
+            )
+        })?;
+        let change = match change {
+            Some(change) => change,
+            None => change_id(&repo.commit("HEAD")?.body).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "HEAD has no Change-Id trailer; pass --change with the change's number or ID"
+                )
+            })?,
+        };
+        let user = config
+            .user
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Set `review.user` to your Gerrit username"))?;
+        let password = std::env::var(strings::GERRIT_HTTP_PASSWORD).map_err(|_| {
+            anyhow::anyhow!(
+                "Set {} to your Gerrit HTTP password",
+                strings::GERRIT_HTTP_PASSWORD
+            )
+        })?;
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            change,
+            user,
+            password,
+        })
+    }
+
+    /// The review: a comment on each file, marked resolved since it needs no reply.
+    fn body(notes: &[FileNote]) -> serde_json::Value {
+        let comments: BTreeMap<&str, _> = notes
+            .iter()
+            .map(|note| {
+                let comment = json!({ "message": note.summary, "unresolved": false });
+                (note.path.as_str(), vec![comment])
+            })
+            .collect();
+        json!({
+            "message": format!("Summaries of {} file(s) from git-hud", notes.len()),
+            "comments": comments,
+        })
+    }
+}
+
+#[async_trait]
+impl Review for Gerrit {
+    fn target(&self) -> String {
+        format!("change {}", self.change)
+    }
+
+    async fn post(&self, notes: &[FileNote]) -> Result<()> {
+        let url = format!(
+            "{}/a/changes/{}/revisions/current/review",
+            self.url, self.change
+        );
+        let request = reqwest::Client::new()
+            .post(url)
+            .basic_auth(&self.user, Some(&self.password))
+            .json(&Self::body(notes));
+        send(request, "Gerrit").await
+    }
+}
+
+async fn send(request: reqwest::RequestBuilder, forge: &str) -> Result<()> {
+    let response = request
+        .send()
+        .await
+        .with_context(|| format!("Failed to post to {}", forge))?;
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!(
+            "{} answered {}: {}",
+            forge,
+            status,
+            error_text.trim()
+        ));
+    }
+    Ok(())
+}
+
+/// The project path in a remote URL, like `my-group/app` from
+/// `git@gitlab.com:my-group/app.git`.
+fn project_path(remote: &str) -> Option<String> {
+    let normalized = crate::privacy::normalize(remote);
+    let (_, path) = normalized.split_once('/')?;
+    (!path.is_empty()).then(|| path.to_string())
+}
+
+/// The `Change-Id` trailer Gerrit's commit-msg hook adds to a commit message.
+fn change_id(message: &str) -> Option<String> {
+    message
+        .lines()
+        .rev()
+        .filter_map(|line| line.strip_prefix("Change-Id:"))
+        .map(|id| id.trim().to_string())
+        .find(|id| !id.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes() -> Vec<FileNote> {
+        vec![
+            FileNote {
+                path: "src/main.rs".to_string(),
+                summary: "Add a review command".to_string(),
+            },
+            FileNote {
+                path: "README.md".to_string(),
+                summary: "Document it".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_gitlab_body() {
+        assert_eq!(
+            GitLab::body(&notes()),
+            "Summaries from git-hud:\n\n- `src/main.rs`: Add a review command\n- `README.md`: Document it\n"
+        );
+    }
+
+    #[test]
+    fn test_gerrit_body() {
+        assert_eq!(
+            Gerrit::body(&notes()),
+            json!({
+                "message": "Summaries of 2 file(s) from git-hud",
+                "comments": {
+                    "README.md": [{ "message": "Document it", "unresolved": false }],
+                    "src/main.rs": [{ "message": "Add a review command", "unresolved": false }],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_project_path() {
+        assert_eq!(
+            project_path("git@gitlab.com:my-group/sub/app.git").as_deref(),
+            Some("my-group/sub/app")
+        );
+        assert_eq!(
+            project_path("https://gitlab.example.com/my-group/app").as_deref(),
+            Some("my-group/app")
+        );
+        assert_eq!(project_path("https://gitlab.com"), None);
+    }
+
+    #[test]
+    fn test_change_id() {
+        let message = "Body text\n\nChange-Id: I8473b95934b5732ac55d26311a706c9c2bde9940\n";
+        assert_eq!(
+            change_id(message).as_deref(),
+            Some("I8473b95934b5732ac55d26311a706c9c2bde9940")
+        );
+        assert_eq!(change_id("No trailer here"), None);
+    }
+}
@@ -0,0 +1,74 @@
+//! Conventional-commit scopes for generated commit messages, from the `[scopes]` config
+//! table mapping gitignore-style patterns to names, like `"crates/foo/**" = "foo"`.
+
+use crate::path_rules;
+use std::collections::HashMap;
+
+/// The scope of a change to `files`: the one their patterns map them to, taking each
+/// file's longest matching pattern. Files no pattern matches don't count. `None` when the
+/// files map to more than one scope, or to none, which leaves it to the model.
+pub fn infer(scopes: &HashMap<String, String>, files: &[String]) -> Option<String> {
+    let mut found: Option<&str> = None;
+    for file in files {
+        let scope = scopes
+            .iter()
+            .filter(|(pattern, _)| path_rules::matches(pattern, file))
+            .max_by(|(a, _), (b, _)| (a.len(), a).cmp(&(b.len(), b)))
+            .map(|(_, scope)| scope.as_str());
+        match (found, scope) {
+            (_, None) => {}
+            (None, Some(scope)) => found = Some(scope),
+            (Some(found), Some(scope)) if found != scope => return None,
+            (Some(_), Some(_)) => {}
+        }
+    }
+    found.map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer() {
+        let scopes: HashMap<String, String> = [
+            ("src/display.rs", "display"),
+            ("crates/foo/**", "foo"),
+            ("crates/foo/src/ui/**", "foo-ui"),
+            ("crates/bar/**", "bar"),
+        ]
+        .into_iter()
+        .map(|(pattern, scope)| (pattern.to_string(), scope.to_string()))
+        .collect();
+        let infer = |files: &[&str]| {
+            infer(
+                &scopes,
+                &files
+                    .iter()
+                    .map(|file| file.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(infer(&["src/display.rs"]).as_deref(), Some("display"));
+        assert_eq!(
+            infer(&["crates/foo/src/lib.rs", "crates/foo/Cargo.toml"]).as_deref(),
+            Some("foo")
+        );
+        // The longer pattern wins
+        assert_eq!(
+            infer(&["crates/foo/src/ui/menu.rs"]).as_deref(),
+            Some("foo-ui")
+        );
+        // Files no pattern matches don't count
+        assert_eq!(
+            infer(&["crates/bar/src/lib.rs", "Cargo.lock"]).as_deref(),
+            Some("bar")
+        );
+        assert_eq!(
+            infer(&["crates/foo/src/lib.rs", "crates/bar/src/lib.rs"]),
+            None
+        );
+        assert_eq!(infer(&["README.md"]), None);
+    }
+}
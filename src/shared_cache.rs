@@ -0,0 +1,114 @@
+//! A remote cache of summaries shared by a team or CI, read through before asking the
+//! summarizer. Entries are addressed by what produced them, the file's blob, the prompt,
+//! and the model, so a summary made anywhere is reused for the same request anywhere,
+//! whatever the repo path or branch. The server is any HTTP store that answers
+//! `GET <url>/<key>` with the summary text, or 404, and accepts `PUT <url>/<key>`.
+
+use crate::cache;
+use crate::config::CacheConfig;
+use crate::log;
+use crate::strings;
+use crate::summary::Summarizer;
+use std::time::Duration;
+
+/// Lookups happen before every request, so a slow server shouldn't hold things up.
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+pub struct SharedCache {
+    client: reqwest::Client,
+    url: String,
+    /// Sent as a bearer token, from `GIT_HUD_SHARED_CACHE_TOKEN`
+    token: Option<String>,
+    read_only: bool,
+}
+
+impl SharedCache {
+    /// The shared cache `cache.shared-url` points at, if any.
+    pub fn from_config(config: &CacheConfig) -> Option<Self> {
+        let url = config.shared_url.as_deref()?;
+        Some(Self {
+            client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            token: std::env::var(strings::GIT_HUD_SHARED_CACHE_TOKEN)
+                .ok()
+                .filter(|token| !token.is_empty()),
+            read_only: config.shared_read_only,
+        })
+    }
+
+    /// The summary stored under `key`. A server that can't be reached or refuses the
+    /// token is treated as a miss, so the summary is just made locally.
+    pub async fn get(&self, key: &str) -> Option<String> {
+        let response = self
+            .authorized(self.client.get(self.entry_url(key)))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match response {
+            Ok(response) => response.text().await.ok(),
+            Err(e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => None,
+            Err(e) => {
+                log::log_message(&format!("Shared cache lookup failed: {}", e.without_url()));
+                None
+            }
+        }
+    }
+
+    /// Stores `summary` under `key` for others, unless the cache is read-only.
+    pub async fn put(&self, key: &str, summary: &str) {
+        if self.read_only {
+            return;
+        }
+        let response = self
+            .authorized(self.client.put(self.entry_url(key)))
+            .body(summary.to_string())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = response {
+            log::log_message(&format!("Shared cache upload failed: {}", e.without_url()));
+        }
+    }
+
+    fn entry_url(&self, key: &str) -> String {
+        format!("{}/{}", self.url, key)
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let request = request.timeout(TIMEOUT);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+/// Where a request's summary is stored: the hash of the file's blob, the prompt, and
+/// the model it's sent to. The prompt covers the diff and the instructions, so a new
+/// prompt or model never reuses summaries made with the old one.
+pub fn key(blob: Option<&str>, prompt: &str, summarizer: &dyn Summarizer) -> String {
+    cache::hash(&format!(
+        "{}\0{}\0{}/{}",
+        blob.unwrap_or(""),
+        cache::hash(prompt),
+        summarizer.provider(),
+        summarizer.model()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::CommandSummarizer;
+
+    #[test]
+    fn test_key() {
+        let haiku = CommandSummarizer::new("summarize --model haiku");
+        let sonnet = CommandSummarizer::new("summarize --model sonnet");
+        let key = key(Some("abc123"), "prompt", &haiku);
+        assert_eq!(key, super::key(Some("abc123"), "prompt", &haiku));
+        assert_ne!(key, super::key(Some("abc123"), "prompt", &sonnet));
+        assert_ne!(key, super::key(Some("def456"), "prompt", &haiku));
+        assert_ne!(key, super::key(Some("abc123"), "other prompt", &haiku));
+    }
+}
@@ -0,0 +1,242 @@
+//! Named snapshots of a repo's annotated status, so `git hud snapshot diff` can say what
+//! changed since one was saved: which files were changed since, which changed further,
+//! and which aren't changed anymore.
+
+use crate::cache;
+use crate::git::StatusCode;
+use crate::FileWithSummary;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub timestamp: DateTime<Utc>,
+    /// The branch that was checked out, if any
+    pub branch: Option<String>,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+/// One side of one changed file, as it was shown.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub path: String,
+    pub status: StatusCode,
+    pub staged: bool,
+    pub summary: Option<String>,
+    /// A hash of the diff, which tells whether the change moved on even when the summary
+    /// reads the same
+    pub diff_hash: Option<String>,
+}
+
+/// How the current status differs from a snapshot's.
+#[derive(Debug, Default)]
+pub struct SnapshotChanges<'a> {
+    /// Changed now, but not when the snapshot was saved
+    pub added: Vec<&'a SnapshotEntry>,
+    /// Changed then, and changed differently now
+    pub changed: Vec<&'a SnapshotEntry>,
+    /// Changed then, but committed, stashed, or reverted since
+    pub gone: Vec<&'a SnapshotEntry>,
+}
+
+impl SnapshotChanges<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.gone.is_empty()
+    }
+}
+
+impl Snapshot {
+    pub fn new(name: &str, branch: Option<String>, files: &[FileWithSummary]) -> Self {
+        Self {
+            name: name.to_string(),
+            timestamp: Utc::now(),
+            branch,
+            entries: files
+                .iter()
+                .map(|file| SnapshotEntry {
+                    path: file.path.clone(),
+                    status: file.status.clone(),
+                    staged: file.staged,
+                    summary: file.summary.clone(),
+                    diff_hash: file.diff.as_deref().map(cache::hash),
+                })
+                .collect(),
+        }
+    }
+
+    /// How many files were changed, counting a file with staged and unstaged changes once.
+    pub fn file_count(&self) -> usize {
+        let mut paths: Vec<_> = self.entries.iter().map(|entry| &entry.path).collect();
+        paths.sort();
+        paths.dedup();
+        paths.len()
+    }
+
+    /// What's different in `current` from this snapshot. Entries are matched by path and
+    /// by whether they're staged, like the sections of `git status`.
+    pub fn compare<'a>(&'a self, current: &'a Snapshot) -> SnapshotChanges<'a> {
+        let find = |entries: &'a [SnapshotEntry], entry: &SnapshotEntry| {
+            entries
+                .iter()
+                .find(|other| other.path == entry.path && other.staged == entry.staged)
+        };
+        let mut changes = SnapshotChanges::default();
+        for entry in &current.entries {
+            match find(&self.entries, entry) {
+                None => changes.added.push(entry),
+                Some(then) if then.diff_hash != entry.diff_hash || then.status != entry.status => {
+                    changes.changed.push(entry)
+                }
+                Some(_) => {}
+            }
+        }
+        changes.gone = self
+            .entries
+            .iter()
+            .filter(|entry| find(&current.entries, entry).is_none())
+            .collect();
+        changes
+    }
+
+    pub fn save(&self, repo_root: &Path) -> Result<()> {
+        let path = path(repo_root, &self.name)?;
+        cache::write_atomic(&path, &serde_json::to_string_pretty(self)?)
+            .context("Failed to save snapshot")
+    }
+
+    pub fn load(repo_root: &Path, name: &str) -> Result<Self> {
+        let path = path(repo_root, name)?;
+        let contents = std::fs::read_to_string(&path).map_err(|_| {
+            anyhow::anyhow!(
+                "No snapshot named '{}'; save one with `git hud snapshot save {}`",
+                name,
+                name
+            )
+        })?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to read snapshot {}", path.display()))
+    }
+
+    /// Every snapshot saved for the repo, oldest first.
+    pub fn list(repo_root: &Path) -> Result<Vec<Self>> {
+        let Some(dir) = dir(repo_root) else {
+            return Ok(Vec::new());
+        };
+        let Ok(files) = std::fs::read_dir(&dir) else {
+            return Ok(Vec::new());
+        };
+        let mut snapshots = Vec::new();
+        for file in files {
+            let path = file?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                let contents = std::fs::read_to_string(&path)?;
+                // One unreadable snapshot shouldn't hide the rest
+                if let Ok(snapshot) = serde_json::from_str::<Self>(&contents) {
+                    snapshots.push(snapshot);
+                }
+            }
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.timestamp);
+        Ok(snapshots)
+    }
+}
+
+/// Snapshots are kept per repo, like the summary cache.
+fn dir(repo_root: &Path) -> Option<PathBuf> {
+    cache::dir().map(|dir| {
+        dir.join("snapshots")
+            .join(&cache::hash(&repo_root.to_string_lossy())[..16])
+    })
+}
+
+fn path(repo_root: &Path, name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Snapshot names can only have letters, digits, '-', '_', and '.', like `morning`"
+        ));
+    }
+    let dir =
+        dir(repo_root).ok_or_else(|| anyhow::anyhow!("No cache directory to keep snapshots in"))?;
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, staged: bool, diff: &str) -> SnapshotEntry {
+        SnapshotEntry {
+            path: path.to_string(),
+            status: StatusCode::Modified,
+            staged,
+            summary: None,
+            diff_hash: Some(cache::hash(diff)),
+        }
+    }
+
+    fn snapshot(entries: Vec<SnapshotEntry>) -> Snapshot {
+        Snapshot {
+            name: "test".to_string(),
+            timestamp: Utc::now(),
+            branch: None,
+            entries,
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let then = snapshot(vec![
+            entry("same.rs", false, "a"),
+            entry("edited.rs", false, "b"),
+            entry("committed.rs", true, "c"),
+        ]);
+        let now = snapshot(vec![
+            entry("same.rs", false, "a"),
+            entry("edited.rs", false, "b and more"),
+            // Staging a file is a new entry on the staged side
+            entry("same.rs", true, "a"),
+            entry("new.rs", false, "d"),
+        ]);
+
+        let changes = then.compare(&now);
+        let paths = |entries: &[&SnapshotEntry]| {
+            entries
+                .iter()
+                .map(|entry| (entry.path.clone(), entry.staged))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            paths(&changes.added),
+            vec![("same.rs".to_string(), true), ("new.rs".to_string(), false)]
+        );
+        assert_eq!(
+            paths(&changes.changed),
+            vec![("edited.rs".to_string(), false)]
+        );
+        assert_eq!(
+            paths(&changes.gone),
+            vec![("committed.rs".to_string(), true)]
+        );
+        assert!(then.compare(&then).is_empty());
+    }
+
+    #[test]
+    fn test_names() {
+        let root = Path::new("/repo");
+        assert!(path(root, "morning").is_ok() || cache::dir().is_none());
+        assert!(path(root, "../escape").is_err());
+        assert!(path(root, "a/b").is_err());
+        assert!(path(root, "").is_err());
+    }
+}
@@ -0,0 +1,267 @@
+use crate::audit::AuditRecord;
+use crate::cache;
+use crate::config::{Config, ProviderConfig};
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Where each summary in a run came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SummarySource {
+    /// The diff hadn't changed since the cached summary
+    Cache,
+    /// The cached summary was updated from the changes made since
+    Update,
+    /// The whole diff was summarized
+    Full,
+    /// The change was described without the summarizer, like an LFS pointer update
+    Local,
+}
+
+/// One `git hud` status run, kept so `git hud stats` can report cache effectiveness.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub timestamp: DateTime<Utc>,
+    pub cached: usize,
+    pub updated: usize,
+    pub summarized: usize,
+    /// Estimated tokens the run's summaries took
+    #[serde(default)]
+    pub tokens: u64,
+    /// Estimated tokens the cached summaries took when they were written
+    #[serde(default)]
+    pub saved_tokens: u64,
+}
+
+impl RunRecord {
+    /// Each summary's source and the tokens writing it took.
+    pub fn new(summaries: &[(SummarySource, u32)]) -> Self {
+        let count = |source| summaries.iter().filter(|(s, _)| *s == source).count();
+        let tokens = |sources: &[SummarySource]| {
+            summaries
+                .iter()
+                .filter(|(source, _)| sources.contains(source))
+                .map(|(_, tokens)| *tokens as u64)
+                .sum()
+        };
+        Self {
+            timestamp: Utc::now(),
+            cached: count(SummarySource::Cache),
+            updated: count(SummarySource::Update),
+            summarized: count(SummarySource::Full),
+            tokens: tokens(&[SummarySource::Update, SummarySource::Full]),
+            saved_tokens: tokens(&[SummarySource::Cache]),
+        }
+    }
+}
+
+pub fn runs_path() -> Option<PathBuf> {
+    cache::dir().map(|dir| dir.join("runs.jsonl"))
+}
+
+pub fn record_run(record: &RunRecord) -> Result<()> {
+    match runs_path() {
+        Some(path) => cache::append_jsonl(&path, record),
+        None => Ok(()),
+    }
+}
+
+pub fn read_runs() -> Result<Vec<RunRecord>> {
+    match runs_path() {
+        Some(path) => cache::read_jsonl(&path),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Prices in US dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pricing {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl Pricing {
+    /// Anthropic defaults to Claude 3 Haiku's list price; local providers are free.
+    pub fn for_provider(provider: &str, config: Option<&ProviderConfig>) -> Self {
+        let default = match provider {
+            "anthropic" => Self {
+                input: 0.25,
+                output: 1.25,
+            },
+            _ => Self {
+                input: 0.0,
+                output: 0.0,
+            },
+        };
+        Self {
+            input: config.and_then(|c| c.input_price).unwrap_or(default.input),
+            output: config
+                .and_then(|c| c.output_price)
+                .unwrap_or(default.output),
+        }
+    }
+
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input + output_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DayRuns {
+    pub runs: usize,
+    pub cached: usize,
+    pub updated: usize,
+    pub summarized: usize,
+    pub tokens: u64,
+    pub saved_tokens: u64,
+}
+
+impl DayRuns {
+    pub fn files(&self) -> usize {
+        self.cached + self.updated + self.summarized
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DayUsage {
+    pub requests: usize,
+    pub failures: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+}
+
+/// Local usage totals, grouped by day.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub runs: BTreeMap<NaiveDate, DayRuns>,
+    /// Keyed by provider, then day
+    pub usage: BTreeMap<String, BTreeMap<NaiveDate, DayUsage>>,
+}
+
+impl Stats {
+    /// Totals runs and audited requests made on or after `since`.
+    pub fn collect(
+        runs: &[RunRecord],
+        records: &[AuditRecord],
+        config: &Config,
+        since: NaiveDate,
+    ) -> Self {
+        let mut stats = Self::default();
+        for run in runs
+            .iter()
+            .filter(|run| run.timestamp.date_naive() >= since)
+        {
+            let day = stats.runs.entry(run.timestamp.date_naive()).or_default();
+            day.runs += 1;
+            day.cached += run.cached;
+            day.updated += run.updated;
+            day.summarized += run.summarized;
+            day.tokens += run.tokens;
+            day.saved_tokens += run.saved_tokens;
+        }
+
+        for record in records
+            .iter()
+            .filter(|record| record.timestamp.date_naive() >= since)
+        {
+            let pricing =
+                Pricing::for_provider(&record.provider, config.providers.get(&record.provider));
+            let day = stats
+                .usage
+                .entry(record.provider.clone())
+                .or_default()
+                .entry(record.timestamp.date_naive())
+                .or_default();
+            day.requests += 1;
+            if record.error.is_some() {
+                day.failures += 1;
+            }
+            day.input_tokens += record.prompt_tokens as u64;
+            day.output_tokens += record.response_tokens as u64;
+            day.cost += pricing.cost(record.prompt_tokens as u64, record.response_tokens as u64);
+        }
+        stats
+    }
+
+    /// The share of summaries that didn't need the whole diff sent.
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let files: usize = self.runs.values().map(DayRuns::files).sum();
+        let cached: usize = self.runs.values().map(|day| day.cached).sum();
+        (files > 0).then(|| cached as f64 / files as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn audit_record(provider: &str, day: u32, prompt_tokens: u32) -> AuditRecord {
+        AuditRecord {
+            timestamp: Utc.with_ymd_and_hms(2026, 3, day, 12, 0, 0).unwrap(),
+            provider: provider.to_string(),
+            prompt: String::new(),
+            response: Some(String::new()),
+            error: None,
+            prompt_tokens,
+            response_tokens: 1000,
+            duration_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_collect_stats() {
+        let run = |day, cached, summarized| RunRecord {
+            timestamp: Utc.with_ymd_and_hms(2026, 3, day, 9, 0, 0).unwrap(),
+            cached,
+            updated: 0,
+            summarized,
+            tokens: summarized as u64 * 500,
+            saved_tokens: cached as u64 * 500,
+        };
+        let runs = [run(1, 0, 4), run(2, 3, 1), run(2, 4, 0)];
+        let records = [
+            audit_record("anthropic", 1, 1_000_000),
+            audit_record("anthropic", 2, 1000),
+            audit_record("command", 2, 1000),
+        ];
+        let since = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+
+        let stats = Stats::collect(&runs, &records, &Config::default(), since);
+        assert_eq!(
+            stats.runs.values().collect::<Vec<_>>(),
+            [&DayRuns {
+                runs: 2,
+                cached: 7,
+                updated: 0,
+                summarized: 1,
+                tokens: 500,
+                saved_tokens: 3500,
+            }]
+        );
+        assert_eq!(stats.cache_hit_rate(), Some(7.0 / 8.0));
+
+        let anthropic = &stats.usage["anthropic"][&since];
+        assert_eq!(anthropic.requests, 1);
+        assert!((anthropic.cost - 0.0015).abs() < 1e-9);
+        assert_eq!(stats.usage["command"][&since].cost, 0.0);
+    }
+
+    #[test]
+    fn test_pricing_override() {
+        let config = ProviderConfig {
+            output_price: Some(4.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            Pricing::for_provider("anthropic", Some(&config)),
+            Pricing {
+                input: 0.25,
+                output: 4.0
+            }
+        );
+    }
+}
@@ -1,2 +1,19 @@
 pub const ANTHROPIC_API_KEY: &str = "ANTHROPIC_API_KEY";
 pub const LOG_LEVEL: &str = "LOG_LEVEL";
+pub const GIT_HUD_CONFIG: &str = "GIT_HUD_CONFIG";
+pub const GIT_HUD_CACHE_DIR: &str = "GIT_HUD_CACHE_DIR";
+pub const GIT_HUD_SHARED_CACHE_TOKEN: &str = "GIT_HUD_SHARED_CACHE_TOKEN";
+pub const GITLAB_TOKEN: &str = "GITLAB_TOKEN";
+pub const GERRIT_HTTP_PASSWORD: &str = "GERRIT_HTTP_PASSWORD";
+// Set by GitLab CI
+pub const CI_SERVER_URL: &str = "CI_SERVER_URL";
+pub const CI_PROJECT_ID: &str = "CI_PROJECT_ID";
+pub const CI_MERGE_REQUEST_IID: &str = "CI_MERGE_REQUEST_IID";
+// Set by git for subcommands and aliases
+pub const GIT_DIR: &str = "GIT_DIR";
+pub const GIT_WORK_TREE: &str = "GIT_WORK_TREE";
+pub const GIT_PREFIX: &str = "GIT_PREFIX";
+// Set to keep git from asking for credentials on a terminal we don't show
+pub const GIT_TERMINAL_PROMPT: &str = "GIT_TERMINAL_PROMPT";
+// Set to make git read paths as names rather than glob patterns
+pub const GIT_LITERAL_PATHSPECS: &str = "GIT_LITERAL_PATHSPECS";
@@ -0,0 +1,115 @@
+//! `--recurse-submodules`: the status and summaries of each submodule with changes in it,
+//! nested under the submodule's own entry in the status view.
+
+use crate::budget::Budget;
+use crate::cache::SummaryCache;
+use crate::config::Config;
+use crate::git::{Repository, StatusCode, StatusOptions};
+use crate::path_rules::PathRules;
+use crate::summary::Summarizer;
+use crate::{log, privacy, FileWithSummary};
+use anyhow::{Context, Result};
+use futures::future::{FutureExt, LocalBoxFuture};
+use std::path::PathBuf;
+
+/// How deep submodules of submodules are followed.
+const MAX_DEPTH: usize = 8;
+
+/// What each submodule's status is read and summarized with.
+pub struct Recursion<'a> {
+    /// Before `privacy.cloud-remotes` is applied, which each submodule is checked against
+    /// with its own remotes
+    pub summarizer: &'a dyn Summarizer,
+    pub config: &'a Config,
+    /// `--focus` patterns
+    pub focus: &'a [String],
+    /// The parent's options; pathspecs, which name the parent's files, are dropped
+    pub options: StatusOptions,
+    pub budget: &'a Budget<'a>,
+}
+
+impl Recursion<'_> {
+    /// Fills in `submodule_files` for each of `files` that's a submodule with changes in
+    /// its working tree, and for their submodules in turn.
+    pub async fn nest(&self, repo: &Repository, files: &mut [FileWithSummary]) -> Result<()> {
+        let mut ancestors = vec![git_dir(repo)];
+        self.nest_in(repo, files, &mut ancestors).await
+    }
+
+    /// `ancestors` are the git dirs of the repositories being recursed through, so a
+    /// submodule that leads back to one of them isn't entered again.
+    fn nest_in<'b>(
+        &'b self,
+        repo: &'b Repository,
+        files: &'b mut [FileWithSummary],
+        ancestors: &'b mut Vec<PathBuf>,
+    ) -> LocalBoxFuture<'b, Result<()>> {
+        async move {
+            for path in repo.submodule_paths()? {
+                let abs_path = repo.root().join(&path);
+                // A submodule whose own files changed is modified in the working tree
+                let Some(file) = files.iter_mut().find(|file| {
+                    !file.staged && file.status == StatusCode::Modified && file.abs_path == abs_path
+                }) else {
+                    continue;
+                };
+                let submodule = repo.open_submodule(&path)?;
+                let dir = git_dir(&submodule);
+                if ancestors.contains(&dir) || ancestors.len() >= MAX_DEPTH {
+                    log::log_message(&format!("Not recursing into {} again", file.path));
+                    continue;
+                }
+                let status = submodule
+                    .get_status_with(&self.options)
+                    .with_context(|| format!("Failed to read submodule {}'s status", file.path))?;
+                let remotes = submodule.remote_urls()?;
+                let withheld = privacy::withhold(self.summarizer, &self.config.privacy, &remotes);
+                let summarizer = withheld
+                    .as_ref()
+                    .map_or(self.summarizer, |withheld| withheld as &dyn Summarizer);
+                let rules = PathRules::new(&self.config.paths)
+                    .with_summarizers(self.config, summarizer, &remotes)?
+                    .with_focus(self.focus);
+                let mut cache = SummaryCache::load(&submodule, &self.config.cache)?;
+                let (mut nested, _) = crate::summarize_status(
+                    &submodule,
+                    summarizer,
+                    &rules,
+                    &status,
+                    &mut cache,
+                    self.budget,
+                    None,
+                )
+                .await?;
+                ancestors.push(dir);
+                let result = self.nest_in(&submodule, &mut nested, ancestors).await;
+                ancestors.pop();
+                result?;
+                for nested in &mut nested {
+                    under(&file.path, nested);
+                }
+                file.submodule_files = nested;
+            }
+            Ok(())
+        }
+        .boxed_local()
+    }
+}
+
+/// The git dir, resolved so the same repository reached two ways compares equal.
+fn git_dir(repo: &Repository) -> PathBuf {
+    repo.git_dir()
+        .canonicalize()
+        .unwrap_or_else(|_| repo.git_dir().to_path_buf())
+}
+
+/// Makes a submodule's file's paths, and its nested files', relative to the parent's root.
+fn under(submodule: &str, file: &mut FileWithSummary) {
+    file.path = format!("{}/{}", submodule, file.path);
+    if let Some(original) = &mut file.original_path {
+        *original = format!("{}/{}", submodule, original);
+    }
+    for nested in &mut file.submodule_files {
+        under(submodule, nested);
+    }
+}
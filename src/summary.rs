@@ -1,29 +1,1030 @@
+use crate::audit::AuditedSummarizer;
+use crate::budget::Gate;
+use crate::config::{Config, ProviderConfig};
 use crate::error::HudError;
-use crate::strings;
-use anyhow::Result;
+use crate::git::{self, CommitInfo, Side, StatusCode, StatusEntry};
+use crate::limits::{LimitedSummarizer, Limits};
+use crate::plugins::{PluginSummarizer, Plugins, ProcessingSummarizer};
+use crate::tokens::Estimator;
+use crate::{language, strings};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
-use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use futures::future::try_join_all;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, RETRY_AFTER};
 use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 
+const CLAUDE_MODEL: &str = "claude-3-haiku-20240307";
+const ANTHROPIC_API: &str = "https://api.anthropic.com";
+// One-line summaries are asked to stay under this many characters
+const SUMMARY_MAX_CHARS: usize = 50;
+const EXPLAIN_MAX_TOKENS: u32 = 2048;
+const WHY_MAX_TOKENS: u32 = 512;
+const AMEND_MAX_TOKENS: u32 = 512;
+const CHANGESET_MAX_TOKENS: u32 = 1024;
+const BISECT_MAX_TOKENS: u32 = 512;
+const RELEASE_NOTES_MAX_TOKENS: u32 = 2048;
+const STANDUP_MAX_TOKENS: u32 = 512;
+const SIDES_MAX_TOKENS: u32 = 128;
+// Older commits only provide context, so their diffs are capped to keep prompts small
+const HISTORY_DIFF_MAX_CHARS: usize = 4000;
+// Past this, a changeset is summarized file by file before the model sees it as a whole
+const CHANGESET_DIFF_MAX_CHARS: usize = 60_000;
+// Style examples only need to show how messages are written, not all of a long one
+const STYLE_EXAMPLE_MAX_CHARS: usize = 600;
+// The start of a new file is enough to tell what it's for
+const NEW_FILE_MAX_CHARS: usize = 8 * 1024;
+// A new directory is summarized from its file names and the start of a few files
+const NEW_DIRECTORY_MAX_FILES: usize = 100;
+const NEW_DIRECTORY_SAMPLES: usize = 5;
+const NEW_DIRECTORY_SAMPLE_CHARS: usize = 1024;
+
+/// A model backend. Providers only need to implement `complete`; every kind of
+/// summary is built on top of it.
 #[async_trait]
-pub trait Summarizer {
-    async fn summarize(&self, diff: &str) -> Result<String>;
+pub trait Summarizer: Send + Sync {
+    /// Sends a prompt to the model and returns its reply.
+    async fn complete(&self, prompt: String, reply: Reply) -> Result<String>;
+
+    /// Like `complete`, but hands the reply to `on_text` in pieces as it arrives.
+    /// Providers that can't stream hand it over all at once; decorators should delegate.
+    async fn complete_streaming(
+        &self,
+        prompt: String,
+        reply: Reply,
+        on_text: &mut OnText<'_>,
+    ) -> Result<String> {
+        let text = self.complete(prompt, reply).await?;
+        on_text(&text);
+        Ok(text)
+    }
+
+    /// The provider's name as used in the config, like `anthropic`.
+    fn provider(&self) -> &str;
+
+    /// The model, command, or plugin that writes the replies.
+    fn model(&self) -> &str;
+
+    /// Whether every request is refused, for a cloud provider this repository's code may
+    /// not be sent to. Decorators don't need to delegate this, since that guard is the
+    /// outermost one.
+    fn withheld(&self) -> bool {
+        false
+    }
+
+    /// What `summarize` sends for a request. Decorators should delegate this to the
+    /// provider they wrap.
+    fn summarize_prompt(&self, request: &SummaryRequest) -> String {
+        format!(
+            "Summarize this git diff in ONE SHORT LINE (max {} chars). Focus on the semantic changes, not the mechanical ones. For a copied or renamed file, describe how it differs from the original. {}Here's the diff:\n\n{}",
+            request.constraints.max_chars,
+            request.describe("The diff is", true),
+            request.diff
+        )
+    }
+
+    /// How the provider's tokens are estimated, calibrated by its `token-factor`.
+    /// Decorators should delegate this, unless they hold the estimator themselves.
+    fn estimator(&self) -> Estimator {
+        Estimator::default()
+    }
+
+    async fn summarize(&self, request: &SummaryRequest) -> Result<SummaryResponse> {
+        summary_line(
+            self,
+            self.summarize_prompt(request),
+            request.constraints.max_chars,
+        )
+        .await
+    }
+
+    /// What `summarize_sides` sends, or `None` if the provider can only summarize one
+    /// diff at a time. Decorators should delegate this too.
+    fn summarize_sides_prompt(
+        &self,
+        staged: &SummaryRequest,
+        unstaged: &SummaryRequest,
+    ) -> Option<String> {
+        let status = |request: &SummaryRequest| match &request.status {
+            Some(status) => format!(" ({})", status.describe()),
+            None => String::new(),
+        };
+        Some(format!(
+            "A file has staged changes and, on top of them, unstaged changes. {}Summarize each in ONE SHORT LINE (max {} chars). Focus on the semantic changes, not the mechanical ones. For a copied or renamed file, describe how it differs from the original. Answer in exactly this format:\nSTAGED: <summary of the staged diff>\nUNSTAGED: <summary of the unstaged diff>\n\nStaged diff{}:\n\n{}\n\nUnstaged diff{}:\n\n{}",
+            staged.describe("The diffs are", false),
+            staged.constraints.max_chars.min(unstaged.constraints.max_chars),
+            status(staged),
+            staged.diff,
+            status(unstaged),
+            unstaged.diff
+        ))
+    }
+
+    /// Summarizes a file's staged and unstaged diffs in one request, for a file edited
+    /// again after it was staged. Returns the staged summary, then the unstaged one, with
+    /// the request's tokens split between them. Falls back to a request for each when the
+    /// provider can't do both at once or the reply can't be read.
+    async fn summarize_sides(
+        &self,
+        staged: &SummaryRequest,
+        unstaged: &SummaryRequest,
+    ) -> Result<(SummaryResponse, SummaryResponse)> {
+        let mut spent = 0;
+        if let Some(prompt) = self.summarize_sides_prompt(staged, unstaged) {
+            let estimator = self.estimator();
+            spent = estimator.estimate(&prompt);
+            let reply = self.complete(prompt, Reply::Text(SIDES_MAX_TOKENS)).await?;
+            spent += estimator.estimate(&reply);
+            if let Some((staged, unstaged)) = parse_sides(&reply) {
+                let response = |text, tokens| SummaryResponse {
+                    text,
+                    tokens,
+                    model: self.model().to_string(),
+                };
+                return Ok((
+                    response(staged, spent - spent / 2),
+                    response(unstaged, spent / 2),
+                ));
+            }
+        }
+        // A reply that couldn't be read was paid for all the same
+        let mut staged = self.summarize(staged).await?;
+        staged.tokens += spent;
+        Ok((staged, self.summarize(unstaged).await?))
+    }
+
+    /// What `summarize_new_file` sends. Decorators should delegate this too.
+    fn summarize_new_file_prompt(&self, file: &NewFile) -> String {
+        let language = file
+            .language
+            .map(|language| format!("{} ", language))
+            .unwrap_or_default();
+        let truncated = match file.truncated {
+            true => " Only the start of the file is shown.",
+            false => "",
+        };
+        format!(
+            "{} is a new {}file. Say what the file is or what it adds in ONE SHORT LINE (max {} chars), like \"adds a CLI arg parser module\". Reply with only the summary.{} Here's its content:\n\n{}",
+            file.path, language, SUMMARY_MAX_CHARS, truncated, file.content
+        )
+    }
+
+    /// Summarizes an untracked file by what it is, rather than as a diff adding every line.
+    async fn summarize_new_file(&self, file: &NewFile) -> Result<SummaryResponse> {
+        summary_line(
+            self,
+            self.summarize_new_file_prompt(file),
+            SUMMARY_MAX_CHARS,
+        )
+        .await
+    }
+
+    /// What `summarize_new_directory` sends. Decorators should delegate this too.
+    fn summarize_new_directory_prompt(&self, directory: &NewDirectory) -> String {
+        format!(
+            "{} is a new directory. Say what it's for in ONE SHORT LINE (max {} chars), like \"adds database migrations for June\". Reply with only the summary. Here are its files and the start of a few of them:\n\n{}",
+            directory.path,
+            SUMMARY_MAX_CHARS,
+            directory.describe()
+        )
+    }
+
+    /// Summarizes a whole untracked directory in one request, from its file names and a
+    /// sample of its contents.
+    async fn summarize_new_directory(&self, directory: &NewDirectory) -> Result<SummaryResponse> {
+        summary_line(
+            self,
+            self.summarize_new_directory_prompt(directory),
+            SUMMARY_MAX_CHARS,
+        )
+        .await
+    }
+
+    /// Updates a file's earlier summary from just the changes made since it was written,
+    /// which is cheaper than re-reading the whole diff and keeps the wording stable.
+    async fn update_summary(&self, previous: &str, delta: &str) -> Result<SummaryResponse> {
+        summary_line(
+            self,
+            self.update_summary_prompt(previous, delta),
+            SUMMARY_MAX_CHARS,
+        )
+        .await
+    }
+
+    /// What `update_summary` sends. Decorators should delegate this too.
+    fn update_summary_prompt(&self, previous: &str, delta: &str) -> String {
+        format!(
+            "A file's pending changes were previously summarized as \"{}\". The file has changed again since then. Write an updated summary of ALL its pending changes in ONE SHORT LINE (max {} chars), keeping the previous wording where it's still accurate. Reply with only the summary. Here's what changed since the previous summary:\n\n{}",
+            previous, SUMMARY_MAX_CHARS, delta
+        )
+    }
+
+    /// Produces a longer, multi-paragraph explanation of a single file's diff, streaming
+    /// it to `on_text` as it's written.
+    async fn explain(
+        &self,
+        path: &str,
+        diff: &str,
+        compare_behavior: bool,
+        on_text: &mut OnText<'_>,
+    ) -> Result<String> {
+        let mut prompt = format!(
+            "Explain the pending changes to {} in a few short paragraphs. Describe what was changed, why it was likely changed, and anything that looks unfinished. Write plain prose without headings.",
+            path
+        );
+        if compare_behavior {
+            prompt.push_str(
+                " End with a paragraph comparing the behavior before and after the change.",
+            );
+        }
+        prompt.push_str(&format!(" Here's the diff:\n\n{}", diff));
+        self.complete_streaming(prompt, Reply::Text(EXPLAIN_MAX_TOKENS), on_text)
+            .await
+    }
+
+    /// Explains how a file's pending diff relates to the file's recent commits.
+    async fn relate_to_history(
+        &self,
+        path: &str,
+        diff: &str,
+        commits: &[CommitInfo],
+    ) -> Result<String> {
+        let mut prompt = format!(
+            "Below are the pending changes to {} followed by its most recent commits, newest first. In two or three sentences, explain how the pending change relates to that recent work, referring to commits by their short SHA (for example \"continues the refactor started in abc1234\"). If it looks unrelated, say so.\n\nPending diff:\n\n{}\n",
+            path, diff
+        );
+        for commit in commits {
+            prompt.push_str(&format!(
+                "\nCommit {}: {}\n{}\n\n{}\n",
+                commit.short_id,
+                commit.subject,
+                commit.body,
+                truncate_chars(&commit.diff, HISTORY_DIFF_MAX_CHARS)
+            ));
+        }
+        self.complete(prompt, Reply::Text(WHY_MAX_TOKENS)).await
+    }
+
+    /// Decides whether the staged diff belongs in the `head` commit or in a new one.
+    /// `style_examples` are recent commit messages, which a suggested message should be
+    /// written like, and `scope` is the conventional-commit scope it should use, if known.
+    async fn assess_amend(
+        &self,
+        staged: &Changes,
+        head: &CommitInfo,
+        style_examples: &[String],
+        scope: Option<&str>,
+    ) -> Result<AmendAdvice> {
+        let style = match style_examples.is_empty() {
+            true => String::new(),
+            false => format!(
+                "Write any message in the same style as these recent commit messages from the repository, matching their tense, prefixes, scope naming, and length:\n\n{}\n\n",
+                style_examples
+                    .iter()
+                    .map(|message| truncate_chars(message.trim(), STYLE_EXAMPLE_MAX_CHARS))
+                    .collect::<Vec<_>>()
+                    .join("\n---\n")
+            ),
+        };
+        let style = match scope {
+            Some(scope) => format!(
+                "{}If the message uses a conventional-commit scope, like `fix(scope): ...`, the scope is `{}`.\n\n",
+                style, scope
+            ),
+            None => style,
+        };
+        let prompt = format!(
+            "{}Decide whether the staged changes below should be amended into the previous commit or committed separately. Amend only if they finish, fix, or polish the previous commit's change; otherwise recommend a separate commit.\n\nAnswer in exactly this format:\nVERDICT: AMEND or SEPARATE\nREASON: <one sentence>\nMESSAGE:\n<when amending, the updated commit message covering both changes; otherwise nothing>\n\nPrevious commit {}: {}\n{}\n\n{}\n\nStaged changes:\n\n{}",
+            style,
+            head.short_id,
+            head.subject,
+            head.body,
+            truncate_chars(&head.diff, HISTORY_DIFF_MAX_CHARS),
+            staged.describe()
+        );
+        let response = self.complete(prompt, Reply::Text(AMEND_MAX_TOKENS)).await?;
+        parse_amend_advice(&response)
+    }
+
+    /// Writes a short overall description of a set of changes from their one-line notes.
+    /// `context` says what the changes are, e.g. "commits about to be pushed".
+    async fn summarize_changeset(&self, context: &str, notes: &[String]) -> Result<String> {
+        let prompt = format!(
+            "Each line below describes one of the {}. Write a short paragraph (at most four sentences) describing what they do as a whole. Don't list the changes one by one.\n\n{}",
+            context,
+            notes.join("\n")
+        );
+        self.complete(prompt, Reply::Text(CHANGESET_MAX_TOKENS))
+            .await
+    }
+
+    /// Sums up in one line what a branch's incoming commits change, from their notes.
+    async fn summarize_incoming(&self, upstream: &str, notes: &[String]) -> Result<String> {
+        let prompt = format!(
+            "Each line below describes one of the commits on {} that the current branch doesn't have yet. Say in ONE SHORT LINE (max {} chars) what pulling them would bring in.\n\n{}",
+            upstream,
+            SUMMARY_MAX_CHARS,
+            notes.join("\n")
+        );
+        self.complete(prompt, Reply::Line(SUMMARY_MAX_CHARS)).await
+    }
+
+    /// Guesses whether the changes since the last good bisect commit could cause a bug.
+    async fn assess_bisect_suspect(
+        &self,
+        notes: &[String],
+        symptom: Option<&str>,
+    ) -> Result<String> {
+        let symptom = match symptom {
+            Some(symptom) => format!("The bug being hunted: {}", symptom),
+            None => "The bug being hunted wasn't described.".to_string(),
+        };
+        let prompt = format!(
+            "During a git bisect, these are the changes between the last known-good commit and the commit being tested, one file per line. {} In two or three sentences, say which of these changes could plausibly introduce the bug and how likely that seems.\n\n{}",
+            symptom,
+            notes.join("\n")
+        );
+        self.complete(prompt, Reply::Text(BISECT_MAX_TOKENS)).await
+    }
+
+    /// Writes release notes for `tag`, formatted to be used as an annotated tag message.
+    async fn write_release_notes(&self, tag: &str, notes: &[String]) -> Result<String> {
+        let prompt = format!(
+            "Write release notes for {} from the commits below, one per line as \"subject: summary\". The notes will be used as an annotated git tag message, so write plain text: a one-line title, a blank line, then \"- \" bullet points grouping related commits. Leave out purely internal changes. Reply with only the release notes.\n\n{}",
+            tag,
+            notes.join("\n")
+        );
+        self.complete(prompt, Reply::Text(RELEASE_NOTES_MAX_TOKENS))
+            .await
+    }
+
+    /// Writes a standup update from recent commits and work in progress, to be pasted
+    /// into a chat.
+    async fn write_standup(
+        &self,
+        branch: &str,
+        done: &[String],
+        in_progress: &[String],
+    ) -> Result<String> {
+        let list = |notes: &[String]| match notes.is_empty() {
+            true => "(none)".to_string(),
+            false => notes.join("\n"),
+        };
+        let prompt = format!(
+            "Write a short standup update, in the first person, from the work below. It will be pasted into a team chat, so write plain text: a line starting \"Done:\" and a line starting \"In progress:\", each a sentence or two in plain language rather than a list of files, and mention the branch. Leave out a section with nothing in it. Reply with only the update.\n\nBranch: {}\n\nCommits since the last standup, as \"subject: summary\":\n{}\n\nUncommitted changes, one file per line:\n{}",
+            branch,
+            list(done),
+            list(in_progress)
+        );
+        self.complete(prompt, Reply::Text(STANDUP_MAX_TOKENS)).await
+    }
+}
+
+/// A diff to summarize in one line, with what's known about the file it changes so the
+/// prompt can mention it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SummaryRequest {
+    pub diff: String,
+    /// The file the diff is for, when it's one file's
+    pub path: Option<String>,
+    pub language: Option<&'static str>,
+    pub status: Option<StatusCode>,
+    /// Anything else the model should know, said in a sentence or two
+    pub context: Option<String>,
+    pub constraints: Constraints,
+}
+
+impl SummaryRequest {
+    /// A request for just a diff, like a commit's, with nothing else known about it.
+    pub fn new(diff: &str) -> Self {
+        Self {
+            diff: diff.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// A request for one side of a file in the status.
+    pub fn for_entry(entry: &StatusEntry, side: Side, diff: &str) -> Self {
+        Self {
+            path: Some(entry.display_path.clone()),
+            language: language::detect(&entry.path, ""),
+            status: entry.code(side).cloned(),
+            ..Self::new(diff)
+        }
+    }
+
+    /// What's known about the file, as sentences for a prompt starting with `subject`,
+    /// like "The diff is". The file's status is left out when each diff is given its own.
+    fn describe(&self, subject: &str, with_status: bool) -> String {
+        let mut about = String::new();
+        if let Some(path) = &self.path {
+            about.push_str(&format!("{} for {}", subject, path));
+            if let Some(language) = self.language {
+                about.push_str(&format!(", a {} file", language));
+            }
+            if let Some(status) = self.status.as_ref().filter(|_| with_status) {
+                about.push_str(&format!(" ({})", status.describe()));
+            }
+            about.push_str(". ");
+        }
+        if let Some(context) = &self.context {
+            about.push_str(&format!("{} ", context));
+        }
+        about
+    }
+}
+
+/// What a summary has to fit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constraints {
+    pub max_chars: usize,
+}
+
+impl Default for Constraints {
+    fn default() -> Self {
+        Self {
+            max_chars: SUMMARY_MAX_CHARS,
+        }
+    }
+}
+
+/// A summary, with what it took to write.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryResponse {
+    pub text: String,
+    /// The prompt's and reply's tokens, estimated locally with the provider's estimator
+    pub tokens: u32,
+    pub model: String,
+}
+
+/// Asks `summarizer` for a one-line summary with `prompt`, counting what it took.
+async fn summary_line<S: Summarizer + ?Sized>(
+    summarizer: &S,
+    prompt: String,
+    max_chars: usize,
+) -> Result<SummaryResponse> {
+    let estimator = summarizer.estimator();
+    let prompt_tokens = estimator.estimate(&prompt);
+    let text = summarizer.complete(prompt, Reply::Line(max_chars)).await?;
+    Ok(SummaryResponse {
+        tokens: prompt_tokens + estimator.estimate(&text),
+        text,
+        model: summarizer.model().to_string(),
+    })
+}
+
+/// An untracked file to summarize, with its content capped to the first few KB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewFile {
+    pub path: String,
+    pub language: Option<&'static str>,
+    pub content: String,
+    /// Whether `content` stops short of the whole file
+    pub truncated: bool,
+}
+
+impl NewFile {
+    pub fn new(path: &str, language: Option<&'static str>, content: &str) -> Self {
+        Self::capped(path, language, content, NEW_FILE_MAX_CHARS)
+    }
+
+    fn capped(path: &str, language: Option<&'static str>, content: &str, max_chars: usize) -> Self {
+        let capped = truncate_chars(content, max_chars);
+        Self {
+            path: path.to_string(),
+            language,
+            content: capped.to_string(),
+            truncated: capped.len() < content.len(),
+        }
+    }
+}
+
+/// An untracked directory to summarize as a whole, from its file names and the start of
+/// a few of its files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewDirectory {
+    pub path: String,
+    /// Paths relative to the directory, capped to the first hundred
+    pub files: Vec<String>,
+    /// How many files there are in all
+    pub file_count: usize,
+    pub samples: Vec<NewFile>,
+}
+
+impl NewDirectory {
+    /// `files` lists every file in the directory, and `samples` yields the text ones with
+    /// their content; only the first few are read.
+    pub fn new(
+        path: &str,
+        files: Vec<String>,
+        samples: impl IntoIterator<Item = Result<(String, Option<&'static str>, String)>>,
+    ) -> Result<Self> {
+        let file_count = files.len();
+        let files = files.into_iter().take(NEW_DIRECTORY_MAX_FILES).collect();
+        let samples = samples
+            .into_iter()
+            .take(NEW_DIRECTORY_SAMPLES)
+            .map(|sample| {
+                let (path, language, content) = sample?;
+                Ok(NewFile::capped(
+                    &path,
+                    language,
+                    &content,
+                    NEW_DIRECTORY_SAMPLE_CHARS,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            path: path.to_string(),
+            files,
+            file_count,
+            samples,
+        })
+    }
+
+    /// The file listing followed by each sample, as plain text.
+    pub fn describe(&self) -> String {
+        let mut text = self.files.join("\n");
+        if self.file_count > self.files.len() {
+            text.push_str(&format!(
+                "\n... and {} more",
+                self.file_count - self.files.len()
+            ));
+        }
+        for sample in &self.samples {
+            let language = sample
+                .language
+                .map(|language| format!(" ({})", language))
+                .unwrap_or_default();
+            let truncated = if sample.truncated { "\n..." } else { "" };
+            text.push_str(&format!(
+                "\n\n--- {}{}\n{}{}",
+                sample.path, language, sample.content, truncated
+            ));
+        }
+        text
+    }
+}
+
+/// Receives a streamed reply as it's written.
+pub type OnText<'a> = dyn FnMut(&str) + Send + 'a;
+
+/// How long a reply may be, which sets a request's `max_tokens`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reply {
+    /// One line of about this many characters. Anything after the first line is dropped.
+    Line(usize),
+    /// Free-form text of up to this many tokens
+    Text(u32),
+}
+
+impl Reply {
+    pub fn max_tokens(self) -> u32 {
+        match self {
+            // Room for twice the asked-for length, since replies often run over it and a
+            // summary cut off mid-word is worse than a long one
+            Reply::Line(chars) => (chars as u32).div_ceil(4) * 2 + 8,
+            Reply::Text(max_tokens) => max_tokens,
+        }
+    }
+
+    /// The part of `text` that's kept: all of it, or for a one-line reply, the first line.
+    pub fn keep(self, text: &str) -> &str {
+        match self {
+            Reply::Line(_) => text.split('\n').next().unwrap_or_default(),
+            Reply::Text(_) => text,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AmendAdvice {
+    pub should_amend: bool,
+    pub reason: String,
+    /// Updated commit message covering both changes, only given when amending
+    pub message: Option<String>,
+}
+
+/// A set of changes for a prompt that looks at them as a whole.
+#[derive(Debug, PartialEq)]
+pub enum Changes {
+    Diff(String),
+    /// A one-line note on each file, for a diff too large to send whole
+    Notes(Vec<String>),
+}
+
+impl Changes {
+    fn describe(&self) -> String {
+        match self {
+            Changes::Diff(diff) => diff.clone(),
+            Changes::Notes(notes) => format!(
+                "(The diff is too large to include, so here is a note on each file's changes.)\n{}",
+                notes.join("\n")
+            ),
+        }
+    }
+}
+
+/// Makes a diff fit a prompt about the whole changeset. A diff over the limit is split
+/// by file and each file is summarized on its own, so the model sees every file's notes
+/// rather than a diff cut off partway.
+pub async fn condense(summarizer: &dyn Summarizer, diff: String) -> Result<Changes> {
+    if diff.len() <= CHANGESET_DIFF_MAX_CHARS {
+        return Ok(Changes::Diff(diff));
+    }
+    let files = split_diff(&diff);
+    let summaries = try_join_all(files.iter().map(|(path, part)| async move {
+        let request = SummaryRequest {
+            path: Some(path.to_string()),
+            ..SummaryRequest::new(truncate_chars(part, CHANGESET_DIFF_MAX_CHARS))
+        };
+        summarizer.summarize(&request).await
+    }))
+    .await?;
+    Ok(Changes::Notes(
+        files
+            .iter()
+            .zip(summaries)
+            .map(|((path, _), summary)| format!("{}: {}", path, summary.text.trim()))
+            .collect(),
+    ))
+}
+
+/// Summarizes each commit's diff, all at once, in the commits' order.
+pub async fn summarize_commits(
+    summarizer: &dyn Summarizer,
+    gate: Option<&Gate<'_>>,
+    commits: &[CommitInfo],
+) -> Result<Vec<String>> {
+    let requests: Vec<SummaryRequest> = commits
+        .iter()
+        .map(|commit| SummaryRequest::new(&commit.diff))
+        .collect();
+    if let Some(gate) = gate {
+        gate.require(&prompts(summarizer, &requests))?;
+    }
+    let summaries =
+        try_join_all(requests.iter().map(|request| summarizer.summarize(request))).await?;
+    Ok(summaries.into_iter().map(|summary| summary.text).collect())
+}
+
+/// Each of `requests` as its provider and what `summarize` would send, for a `Gate`.
+pub fn prompts<'a, 'r>(
+    summarizer: &'a dyn Summarizer,
+    requests: impl IntoIterator<Item = &'r SummaryRequest>,
+) -> Vec<(&'a str, String)> {
+    requests
+        .into_iter()
+        .map(|request| (summarizer.provider(), summarizer.summarize_prompt(request)))
+        .collect()
+}
+
+/// Splits a multi-file diff into each file's path and part of the diff.
+fn split_diff(diff: &str) -> Vec<(&str, &str)> {
+    git::file_sections(diff)
+        .into_iter()
+        .map(|part| (diff_path(part), part))
+        .collect()
+}
+
+/// The path a file's part of a diff is for, from `+++`, or `---` for a deleted file, or
+/// the `diff --git` header when there are neither, as for binary files.
+fn diff_path(part: &str) -> &str {
+    let named = |prefix: &str| {
+        part.lines()
+            .find_map(|line| line.strip_prefix(prefix))
+            .filter(|path| *path != "/dev/null")
+    };
+    if let Some(path) = named("+++ ").or_else(|| named("--- ")) {
+        return path;
+    }
+    let header = part
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches("diff --git ");
+    // Without prefixes, both sides of the header name the same path
+    let half = header.len() / 2;
+    match header.is_char_boundary(half) && header[..half] == header[(half + 1).min(header.len())..]
+    {
+        true => &header[..half],
+        false => header,
+    }
+}
+
+/// Picks the summarizer the user configured, defaulting to Claude, and wraps it
+/// with any configured plugins.
+pub fn from_config(config: &Config) -> Result<Box<dyn Summarizer>> {
+    build(config, None)
+}
+
+/// A summarizer for `provider` rather than the one the config picks, for files a
+/// `[paths]` pattern sends to it.
+pub fn for_provider(config: &Config, provider: &str) -> Result<Box<dyn Summarizer>> {
+    build(config, Some(provider))
+}
+
+fn build(config: &Config, provider: Option<&str>) -> Result<Box<dyn Summarizer>> {
+    let mut plugins = Plugins::load(&config.plugins)?;
+
+    let (summarizer, limits): (Box<dyn Summarizer>, _) =
+        match (provider, plugins.summarizer.take(), &config.summarizer) {
+            (None | Some("plugin"), Some(plugin), _) => {
+                (Box::new(PluginSummarizer::new(plugin)), Limits::local())
+            }
+            (None | Some("command"), _, Some(command)) => {
+                (Box::new(CommandSummarizer::new(command)), Limits::local())
+            }
+            (None | Some("anthropic"), _, _) => (
+                Box::new(ClaudeSummarizer::new(config.providers.get("anthropic"))),
+                Limits::anthropic(),
+            ),
+            (Some("plugin"), None, _) => {
+                return Err(anyhow::anyhow!("no summarizer plugin is set up"))
+            }
+            (Some("command"), _, None) => {
+                return Err(anyhow::anyhow!("no summarizer command is set up"))
+            }
+            (Some(provider), _, _) => {
+                return Err(anyhow::anyhow!(
+                    "unknown provider `{}`; expected `anthropic`, `command`, or `plugin`",
+                    provider
+                ))
+            }
+        };
+    let limits = limits.with_config(config.providers.get(summarizer.provider()));
+    let summarizer: Box<dyn Summarizer> = match config.audit.enabled {
+        true => Box::new(AuditedSummarizer::new(summarizer, limits.tokens)?),
+        false => summarizer,
+    };
+    let summarizer = Box::new(LimitedSummarizer::new(summarizer, limits));
+
+    if plugins.is_empty() {
+        return Ok(summarizer);
+    }
+    Ok(Box::new(ProcessingSummarizer::new(
+        summarizer,
+        plugins.preprocessors,
+        plugins.postprocessors,
+    )))
 }
 
 pub struct ClaudeSummarizer {
     client: reqwest::Client,
-    api_key: String,
+    /// Where the Messages API is, which tests point at a recorded server
+    base_url: String,
+    // Checked on the first request, so runs that make none (like --dry-run) don't need it
+    api_key: Option<String>,
+    /// Overrides the `max_tokens` of one-line replies
+    line_max_tokens: Option<u32>,
+    /// Where one-line replies stop
+    line_stop_sequences: Vec<String>,
 }
 
 impl ClaudeSummarizer {
-    pub fn new() -> Result<Self> {
-        let api_key = std::env::var(strings::ANTHROPIC_API_KEY)
-            .map_err(|_| HudError::Api("ANTHROPIC_API_KEY not set".to_string()))?;
-
-        Ok(Self {
+    pub fn new(config: Option<&ProviderConfig>) -> Self {
+        Self {
             client: reqwest::Client::new(),
-            api_key,
-        })
+            base_url: ANTHROPIC_API.to_string(),
+            api_key: std::env::var(strings::ANTHROPIC_API_KEY).ok(),
+            line_max_tokens: config.and_then(|config| config.summary_max_tokens),
+            line_stop_sequences: config
+                .and_then(|config| config.stop_sequences.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Sends requests to `base_url` with `api_key` instead.
+    #[cfg(test)]
+    pub fn with_base_url(self, base_url: &str, api_key: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            api_key: Some(api_key.to_string()),
+            ..self
+        }
+    }
+
+    /// The request's `max_tokens` and `stop_sequences`.
+    fn reply_limits(&self, reply: Reply) -> (u32, &[String]) {
+        match reply {
+            Reply::Line(_) => (
+                self.line_max_tokens.unwrap_or(reply.max_tokens()),
+                &self.line_stop_sequences,
+            ),
+            Reply::Text(max_tokens) => (max_tokens, &[]),
+        }
+    }
+
+    /// Sends a Messages API request, turning error statuses into errors.
+    async fn send(&self, prompt: String, reply: Reply, stream: bool) -> Result<reqwest::Response> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let api_key = self
+            .api_key
+            .as_deref()
+            .ok_or_else(|| HudError::Api("ANTHROPIC_API_KEY not set".to_string()))?;
+        headers.insert("x-api-key", HeaderValue::from_str(api_key)?);
+        headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+
+        let (max_tokens, stop_sequences) = self.reply_limits(reply);
+        let mut request_body = serde_json::json!({
+            "model": CLAUDE_MODEL,
+            "max_tokens": max_tokens,
+            "messages": [{
+                "role": "user",
+                "content": prompt
+            }]
+        });
+        if !stop_sequences.is_empty() {
+            request_body["stop_sequences"] = serde_json::json!(stop_sequences);
+        }
+        if stream {
+            request_body["stream"] = serde_json::json!(true);
+        }
+        let response = self
+            .client
+            .post(format!("{}/v1/messages", self.base_url))
+            .headers(headers)
+            .json(&request_body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs);
+            let error_text = response.text().await?;
+            return Err(HudError::ApiStatus {
+                status,
+                message: format!("Claude API error: {}", error_text),
+                retry_after,
+            }
+            .into());
+        }
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl Summarizer for ClaudeSummarizer {
+    async fn complete(&self, prompt: String, reply: Reply) -> Result<String> {
+        let response = self.send(prompt, reply, false).await?;
+        let response = response.json::<AnthropicAPIResponse>().await?;
+
+        // Extract the content from the response
+        let content = response
+            .content
+            .first()
+            .map(|c| reply.keep(c.text.trim()).trim_end())
+            .ok_or_else(|| anyhow::anyhow!("Unexpected API response format"))?;
+
+        Ok(content.to_string())
+    }
+
+    async fn complete_streaming(
+        &self,
+        prompt: String,
+        reply: Reply,
+        on_text: &mut OnText<'_>,
+    ) -> Result<String> {
+        let mut response = self.send(prompt, reply, true).await?;
+        let mut events = EventStream::default();
+        let mut content = String::new();
+        let mut ended = false;
+        while let Some(chunk) = response.chunk().await? {
+            for data in events.push(&chunk) {
+                match serde_json::from_str(&data)? {
+                    StreamEvent::ContentBlockDelta { delta } => {
+                        // Leading whitespace would be trimmed from a complete reply too
+                        let text = match content.is_empty() {
+                            true => delta.text.trim_start(),
+                            false => &delta.text,
+                        };
+                        // Once a one-line reply's line has ended, the rest is dropped
+                        if ended {
+                            continue;
+                        }
+                        let kept = reply.keep(text);
+                        ended = kept.len() < text.len();
+                        let text = kept;
+                        on_text(text);
+                        content.push_str(text);
+                    }
+                    StreamEvent::Error { error } => {
+                        return Err(
+                            HudError::Api(format!("Claude API error: {}", error.message)).into(),
+                        )
+                    }
+                    StreamEvent::Other => {}
+                }
+            }
+        }
+        Ok(content.trim_end().to_string())
+    }
+
+    fn provider(&self) -> &str {
+        "anthropic"
+    }
+
+    fn model(&self) -> &str {
+        CLAUDE_MODEL
+    }
+}
+
+/// Pipes input to a user-configured shell command and uses its stdout as the reply.
+pub struct CommandSummarizer {
+    command: String,
+}
+
+impl CommandSummarizer {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+        }
+    }
+
+    async fn run(&self, input: &str) -> Result<String> {
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", &self.command])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // A request that's cancelled is dropped, and shouldn't leave the command running
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to run summarizer command `{}`", self.command))?;
+
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("Failed to open summarizer command stdin"))?;
+        // Write while reading so a command that streams its output can't deadlock on full pipes
+        let write = async move {
+            let result = stdin.write_all(input.as_bytes()).await;
+            drop(stdin);
+            result
+        };
+        let (write_result, output) = tokio::join!(write, child.wait_with_output());
+        let output = output?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Summarizer command `{}` failed: {}",
+                self.command,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        // Commands that stop reading early close the pipe, which is fine if they succeeded
+        if let Err(e) = write_result {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                return Err(e.into());
+            }
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+#[async_trait]
+impl Summarizer for CommandSummarizer {
+    async fn complete(&self, prompt: String, _reply: Reply) -> Result<String> {
+        self.run(&prompt).await
+    }
+
+    fn provider(&self) -> &str {
+        "command"
+    }
+
+    fn model(&self) -> &str {
+        &self.command
+    }
+
+    // The command gets the bare diff, so it can be any tool that summarizes diffs
+    fn summarize_prompt(&self, request: &SummaryRequest) -> String {
+        request.diff.clone()
+    }
+
+    fn summarize_sides_prompt(
+        &self,
+        _staged: &SummaryRequest,
+        _unstaged: &SummaryRequest,
+    ) -> Option<String> {
+        None
+    }
+
+    fn summarize_new_file_prompt(&self, file: &NewFile) -> String {
+        file.content.clone()
+    }
+
+    fn summarize_new_directory_prompt(&self, directory: &NewDirectory) -> String {
+        directory.describe()
     }
 }
 
@@ -44,55 +1045,582 @@ struct AnthropicAPIResponse {
     id: String,
     model: String,
     role: String,
-    stop_reason: String,
-    stop_sequence: String,
+    stop_reason: Option<String>,
+    stop_sequence: Option<String>,
     #[serde(rename = "type")]
     response_type: String,
     usage: TokenUsageAPIResponse,
 }
 
-#[async_trait]
-impl Summarizer for ClaudeSummarizer {
-    async fn summarize(&self, diff: &str) -> Result<String> {
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        headers.insert("x-api-key", HeaderValue::from_str(&*self.api_key)?);
-        headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+/// One event of a streamed Messages API response; only text deltas and errors matter.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    ContentBlockDelta {
+        delta: StreamDelta,
+    },
+    Error {
+        error: StreamError,
+    },
+    #[serde(other)]
+    Other,
+}
 
-        let request_body = serde_json::json!({
-            "model": "claude-3-haiku-20240307",
-            "max_tokens": 512,
-            "messages": [{
-                "role": "user",
-                "content": format!(
-                    "Summarize this git diff in ONE SHORT LINE (max 50 chars). Focus on the semantic changes, not the mechanical ones. Here's the diff:\n\n{}",
-                    diff
-                )
-            }]
-        });
-        let response = self
-            .client
-            .post("https://api.anthropic.com/v1/messages")
-            .headers(headers)
-            .json(&request_body)
-            .send()
+#[derive(Deserialize)]
+struct StreamDelta {
+    // Tool input deltas have no text
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+/// Splits a server-sent event stream into each event's `data` payload. Chunks can end
+/// anywhere, so a partial event is held until the rest of it arrives.
+#[derive(Default)]
+struct EventStream {
+    buffer: Vec<u8>,
+}
+
+impl EventStream {
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut data = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let event = String::from_utf8_lossy(&event);
+            data.extend(
+                event
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data:"))
+                    .map(|payload| payload.trim_start().to_string()),
+            );
+        }
+        data
+    }
+}
+
+/// The staged and unstaged summaries from a `summarize_sides` reply, if it has both.
+fn parse_sides(response: &str) -> Option<(String, String)> {
+    let mut staged = None;
+    let mut unstaged = None;
+    for line in response.lines() {
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("STAGED:") {
+            staged = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("UNSTAGED:") {
+            unstaged = Some(value.trim().to_string());
+        }
+    }
+    staged
+        .zip(unstaged)
+        .filter(|(staged, unstaged)| !staged.is_empty() && !unstaged.is_empty())
+}
+
+fn parse_amend_advice(response: &str) -> Result<AmendAdvice> {
+    let mut verdict = None;
+    let mut reason = String::new();
+    let mut message_lines = Vec::new();
+    let mut in_message = false;
+
+    for line in response.lines() {
+        if in_message {
+            message_lines.push(line);
+        } else if let Some(value) = line.strip_prefix("VERDICT:") {
+            verdict = Some(value.trim().eq_ignore_ascii_case("amend"));
+        } else if let Some(value) = line.strip_prefix("REASON:") {
+            reason = value.trim().to_string();
+        } else if let Some(value) = line.strip_prefix("MESSAGE:") {
+            in_message = true;
+            if !value.trim().is_empty() {
+                message_lines.push(value.trim());
+            }
+        }
+    }
+
+    let should_amend =
+        verdict.ok_or_else(|| anyhow::anyhow!("Unexpected amend-check response: {}", response))?;
+    let message = message_lines.join("\n").trim().to_string();
+
+    Ok(AmendAdvice {
+        should_amend,
+        reason,
+        message: (should_amend && !message.is_empty()).then_some(message),
+    })
+}
+
+fn truncate_chars(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((idx, _)) => &s[..idx],
+        None => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cassette::Cassette;
+
+    #[tokio::test]
+    async fn test_command_summarizer() -> Result<()> {
+        let summarizer = CommandSummarizer::new("tr a-z A-Z");
+        let summary = summarizer
+            .summarize(&SummaryRequest::new("+added a line\n"))
             .await?;
+        assert_eq!(summary.text, "+ADDED A LINE");
+        assert_eq!(summary.model, "tr a-z A-Z");
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("Claude API error: {}", error_text));
+        let summarizer = CommandSummarizer::new("echo nope >&2; exit 3");
+        let error = summarizer
+            .summarize(&SummaryRequest::new("diff"))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("nope"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_command() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let marker = temp_dir.path().join("finished");
+        let summarizer = CommandSummarizer::new(&format!("sleep 1; touch '{}'", marker.display()));
+        let request = SummaryRequest::new("+line");
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let (summary, _) = tokio::join!(
+            cancel.run_until_cancelled(summarizer.summarize(&request)),
+            async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                cancel.cancel();
+            }
+        );
+        assert!(summary.is_none());
+        // The command was stopped rather than left to finish
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_claude_request() -> Result<()> {
+        let cassette = Cassette::script("claude_complete")?;
+        let summarizer =
+            ClaudeSummarizer::new(None).with_base_url(&cassette.url(), &cassette.api_key());
+        let summary = summarizer
+            .complete("Summarize: +fn main() {}".to_string(), Reply::Line(50))
+            .await?;
+        cassette.finish()?;
+        assert_eq!(summary, "Add an empty main function");
+        Ok(())
+    }
+
+    /// Checks the request and response formats against the real API. Nothing has been
+    /// recorded yet; see the README for how.
+    #[tokio::test]
+    #[ignore = "needs a recorded cassette"]
+    async fn test_claude_recorded() -> Result<()> {
+        let cassette = Cassette::play("claude_complete")?;
+        let summarizer =
+            ClaudeSummarizer::new(None).with_base_url(&cassette.url(), &cassette.api_key());
+        let summary = summarizer
+            .complete("Summarize: +fn main() {}".to_string(), Reply::Line(50))
+            .await?;
+        cassette.finish()?;
+        assert!(!summary.is_empty() && !summary.contains('\n'));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_claude_streaming() -> Result<()> {
+        let cassette = Cassette::script("claude_streaming")?;
+        let summarizer =
+            ClaudeSummarizer::new(None).with_base_url(&cassette.url(), &cassette.api_key());
+        let mut shown = Vec::new();
+        let explanation = summarizer
+            .complete_streaming(
+                "Explain the change".to_string(),
+                Reply::Text(64),
+                &mut |text| shown.push(text.to_string()),
+            )
+            .await?;
+        cassette.finish()?;
+        assert_eq!(shown, ["The parser", " now skips blank lines.\n"]);
+        assert_eq!(explanation, "The parser now skips blank lines.");
+        Ok(())
+    }
+
+    /// Error statuses become `ApiStatus` errors, which are retried when the API is
+    /// rate limited or overloaded and returned at once otherwise.
+    #[tokio::test]
+    async fn test_claude_errors() -> Result<()> {
+        let cassette = Cassette::script("claude_errors")?;
+        let summarizer = LimitedSummarizer::new(
+            Box::new(
+                ClaudeSummarizer::new(None).with_base_url(&cassette.url(), &cassette.api_key()),
+            ),
+            Limits {
+                requests_per_minute: None,
+                tokens_per_minute: None,
+                max_concurrent: 1,
+                max_retries: 2,
+                tokens: Estimator::default(),
+            },
+        );
+        let status = |error: anyhow::Error| match error.downcast::<HudError>() {
+            Ok(HudError::ApiStatus {
+                status, message, ..
+            }) => (status, message),
+            other => panic!("expected an API status error, got {:?}", other),
+        };
+
+        let error = summarizer
+            .complete("Rejected".to_string(), Reply::Line(50))
+            .await
+            .unwrap_err();
+        let (code, message) = status(error);
+        assert_eq!(code, 400);
+        assert!(message.contains("Scripted rejection"));
+
+        assert_eq!(
+            summarizer
+                .complete("Rate limited".to_string(), Reply::Line(50))
+                .await?,
+            "Retried after the rate limit"
+        );
+
+        let error = summarizer
+            .complete("Overloaded".to_string(), Reply::Line(50))
+            .await
+            .unwrap_err();
+        assert_eq!(status(error).0, 529);
+
+        cassette.finish()
+    }
+
+    #[test]
+    fn test_new_file_prompt() {
+        struct Echo;
+        #[async_trait]
+        impl Summarizer for Echo {
+            async fn complete(&self, prompt: String, _reply: Reply) -> Result<String> {
+                Ok(prompt)
+            }
+            fn provider(&self) -> &str {
+                "echo"
+            }
+            fn model(&self) -> &str {
+                "echo"
+            }
         }
 
-        let response = response.json::<serde_json::Value>().await?;
+        let file = NewFile::new("src/cli.rs", Some("Rust"), "fn main() {}\n");
+        assert!(!file.truncated);
+        let prompt = Echo.summarize_new_file_prompt(&file);
+        assert!(prompt.starts_with("src/cli.rs is a new Rust file."));
+        assert!(prompt.ends_with("fn main() {}\n"));
 
-        // Extract the content from the response
-        let content = response["content"][0]["text"]
-            .as_str()
-            .ok_or_else(|| anyhow::anyhow!("Unexpected API response format"))?
-            .trim();
+        let long = "é".repeat(NEW_FILE_MAX_CHARS + 1);
+        let file = NewFile::new("notes", None, &long);
+        assert!(file.truncated);
+        assert_eq!(file.content.chars().count(), NEW_FILE_MAX_CHARS);
+        let prompt = Echo.summarize_new_file_prompt(&file);
+        assert!(prompt.starts_with("notes is a new file."));
+        assert!(prompt.contains("Only the start of the file is shown."));
+    }
 
-        Ok(content.to_string())
+    #[test]
+    fn test_summarize_prompt() {
+        let command = CommandSummarizer::new("cat");
+        let plain = SummaryRequest::new("+fn main() {}\n");
+        // What's known about the file is only mentioned when it's known
+        let claude = ClaudeSummarizer::new(None);
+        let prompt = claude.summarize_prompt(&plain);
+        assert!(prompt.starts_with("Summarize this git diff in ONE SHORT LINE (max 50 chars)."));
+        assert!(prompt.contains("original. Here's the diff:\n\n+fn main() {}\n"));
+
+        let request = SummaryRequest {
+            path: Some("src/main.rs".to_string()),
+            language: Some("Rust"),
+            status: Some(StatusCode::Added),
+            context: Some("It's the first commit.".to_string()),
+            constraints: Constraints { max_chars: 30 },
+            ..plain
+        };
+        let prompt = claude.summarize_prompt(&request);
+        assert!(prompt.contains("(max 30 chars)"));
+        assert!(prompt.contains(
+            "The diff is for src/main.rs, a Rust file (new file). It's the first commit. Here's"
+        ));
+        // Commands get the bare diff either way
+        assert_eq!(command.summarize_prompt(&request), "+fn main() {}\n");
+    }
+
+    #[test]
+    fn test_new_directory() -> Result<()> {
+        let files: Vec<_> = (0..NEW_DIRECTORY_MAX_FILES + 2)
+            .map(|i| format!("{:03}.sql", i))
+            .collect();
+        let samples = files.iter().map(|path| {
+            Ok((
+                path.clone(),
+                Some("SQL"),
+                "x".repeat(NEW_DIRECTORY_SAMPLE_CHARS + 1),
+            ))
+        });
+        let directory = NewDirectory::new("migrations/", files.clone(), samples)?;
+        assert_eq!(directory.file_count, NEW_DIRECTORY_MAX_FILES + 2);
+        assert_eq!(directory.files.len(), NEW_DIRECTORY_MAX_FILES);
+        assert_eq!(directory.samples.len(), NEW_DIRECTORY_SAMPLES);
+        assert!(directory.samples.iter().all(|sample| sample.truncated));
+
+        let text = directory.describe();
+        assert!(text.starts_with("000.sql\n001.sql\n"));
+        assert!(text.contains("\n... and 2 more\n"));
+        assert!(text.contains("\n--- 000.sql (SQL)\nxxx"));
+        assert!(!text.contains("--- 005.sql"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reply_limits() {
+        assert_eq!(Reply::Line(SUMMARY_MAX_CHARS).max_tokens(), 34);
+        assert_eq!(Reply::Text(2048).max_tokens(), 2048);
+
+        let claude = ClaudeSummarizer::new(None);
+        assert_eq!(
+            claude.reply_limits(Reply::Line(SUMMARY_MAX_CHARS)),
+            (34, &[][..])
+        );
+        assert_eq!(claude.reply_limits(Reply::Text(2048)), (2048, &[][..]));
+
+        let config = ProviderConfig {
+            summary_max_tokens: Some(64),
+            stop_sequences: Some(vec![".".to_string()]),
+            ..Default::default()
+        };
+        let claude = ClaudeSummarizer::new(Some(&config));
+        assert_eq!(
+            claude.reply_limits(Reply::Line(SUMMARY_MAX_CHARS)),
+            (64, &[".".to_string()][..])
+        );
+
+        assert_eq!(
+            Reply::Line(50).keep("Add a parser\n\nIt reads"),
+            "Add a parser"
+        );
+        assert_eq!(Reply::Line(50).keep("Add a parser"), "Add a parser");
+        assert_eq!(
+            Reply::Text(50).keep("Add a parser\nIt reads"),
+            "Add a parser\nIt reads"
+        );
+    }
+
+    #[test]
+    fn test_event_stream() -> Result<()> {
+        let mut events = EventStream::default();
+        assert!(events
+            .push(b"event: message_start\ndata: {\"type\": \"message_start\"}\n\nevent: content_block_delta\ndata: {\"type\": \"content_block_delta\", \"index\": 0, \"delta\": {\"type\": \"text_delta\", \"text\": \"Hel")
+            .len()
+            == 1);
+        let data = events.push(b"lo\"}}\n\nevent: ping\ndata: {\"type\": \"ping\"}\n\n");
+        assert_eq!(data.len(), 2);
+        let StreamEvent::ContentBlockDelta { delta } = serde_json::from_str(&data[0])? else {
+            panic!("expected a text delta");
+        };
+        assert_eq!(delta.text, "Hello");
+        assert!(matches!(
+            serde_json::from_str(&data[1])?,
+            StreamEvent::Other
+        ));
+
+        let data = events.push(
+            b"event: error\ndata: {\"type\": \"error\", \"error\": {\"type\": \"overloaded_error\", \"message\": \"Overloaded\"}}\n\n",
+        );
+        let StreamEvent::Error { error } = serde_json::from_str(&data[0])? else {
+            panic!("expected an error");
+        };
+        assert_eq!(error.message, "Overloaded");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_diff() {
+        let diff = "diff --git a.rs a.rs\n--- a.rs\n+++ a.rs\n@@ -1 +1 @@\n-a\n+b\ndiff --git gone.rs gone.rs\n--- gone.rs\n+++ /dev/null\n@@ -1 +0,0 @@\n-x\ndiff --git img.png img.png\nBinary files differ\n";
+        let files = split_diff(diff);
+        let paths: Vec<&str> = files.iter().map(|(path, _)| *path).collect();
+        assert_eq!(paths, vec!["a.rs", "gone.rs", "img.png"]);
+        assert_eq!(
+            files[1].1,
+            "diff --git gone.rs gone.rs\n--- gone.rs\n+++ /dev/null\n@@ -1 +0,0 @@\n-x\n"
+        );
+        assert_eq!(
+            files.iter().map(|(_, part)| part.len()).sum::<usize>(),
+            diff.len()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_condense() -> Result<()> {
+        let summarizer = CommandSummarizer::new("cat >/dev/null; echo changed");
+        let small = "diff --git a.rs a.rs\n+x\n".to_string();
+        assert_eq!(
+            condense(&summarizer, small.clone()).await?,
+            Changes::Diff(small)
+        );
+
+        let line = format!("+{}\n", "x".repeat(99));
+        let large = ["a.rs", "b.rs"]
+            .iter()
+            .map(|path| {
+                format!(
+                    "diff --git {0} {0}\n--- {0}\n+++ {0}\n{1}",
+                    path,
+                    line.repeat(CHANGESET_DIFF_MAX_CHARS / 100)
+                )
+            })
+            .collect::<String>();
+        assert_eq!(
+            condense(&summarizer, large).await?,
+            Changes::Notes(vec![
+                "a.rs: changed".to_string(),
+                "b.rs: changed".to_string()
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_amend_advice() -> Result<()> {
+        let advice = parse_amend_advice(
+            "VERDICT: AMEND\nREASON: Fixes a typo from the last commit.\nMESSAGE:\nAdd retry loop\n\nAlso fix typo in backoff.",
+        )?;
+        assert_eq!(
+            advice,
+            AmendAdvice {
+                should_amend: true,
+                reason: "Fixes a typo from the last commit.".to_string(),
+                message: Some("Add retry loop\n\nAlso fix typo in backoff.".to_string()),
+            }
+        );
+
+        let advice = parse_amend_advice(
+            "VERDICT: SEPARATE\nREASON: Unrelated documentation change.\nMESSAGE:\n",
+        )?;
+        assert!(!advice.should_amend);
+        assert_eq!(advice.message, None);
+
+        assert!(parse_amend_advice("I think you should amend.").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_sides() {
+        assert_eq!(
+            parse_sides("STAGED: adds retry loop\nUNSTAGED: tunes the backoff\n"),
+            Some((
+                "adds retry loop".to_string(),
+                "tunes the backoff".to_string()
+            ))
+        );
+        // In either order, with whatever the model adds around them
+        assert_eq!(
+            parse_sides("Sure!\n  UNSTAGED: b\n  STAGED: a"),
+            Some(("a".to_string(), "b".to_string()))
+        );
+        assert_eq!(parse_sides("STAGED: adds retry loop"), None);
+        assert_eq!(parse_sides("STAGED:\nUNSTAGED: b"), None);
+    }
+
+    #[tokio::test]
+    async fn test_summarize_sides() -> Result<()> {
+        /// Answers both sides at once only if `paired`, counting the requests it gets.
+        struct Sides {
+            paired: bool,
+            requests: std::sync::Mutex<Vec<String>>,
+        }
+        #[async_trait]
+        impl Summarizer for Sides {
+            async fn complete(&self, prompt: String, _reply: Reply) -> Result<String> {
+                self.requests.lock().unwrap().push(prompt.clone());
+                Ok(match (self.paired, prompt.contains("Unstaged diff")) {
+                    (true, true) => "STAGED: adds retry loop\nUNSTAGED: tunes the backoff",
+                    (false, true) => "Both look fine to me.",
+                    (_, false) => "one side",
+                }
+                .to_string())
+            }
+            fn provider(&self) -> &str {
+                "sides"
+            }
+            fn model(&self) -> &str {
+                "sides"
+            }
+        }
+
+        let request = |diff: &str, status| SummaryRequest {
+            path: Some("src/net.rs".to_string()),
+            language: Some("Rust"),
+            status: Some(status),
+            constraints: Constraints { max_chars: 40 },
+            ..SummaryRequest::new(diff)
+        };
+        let (staged, unstaged) = (
+            request("+retry", StatusCode::Added),
+            request("+backoff", StatusCode::Modified),
+        );
+        let texts =
+            |(staged, unstaged): (SummaryResponse, SummaryResponse)| (staged.text, unstaged.text);
+        let paired = Sides {
+            paired: true,
+            requests: Default::default(),
+        };
+        let (staged_response, unstaged_response) =
+            paired.summarize_sides(&staged, &unstaged).await?;
+        assert_eq!(staged_response.model, "sides");
+        // The one request's tokens are split between the sides
+        assert!(staged_response.tokens > 0);
+        assert!(staged_response.tokens.abs_diff(unstaged_response.tokens) <= 1);
+        assert_eq!(
+            texts((staged_response, unstaged_response)),
+            (
+                "adds retry loop".to_string(),
+                "tunes the backoff".to_string()
+            )
+        );
+        let prompt = paired.requests.lock().unwrap()[0].clone();
+        // The file is described as it is for one side alone
+        assert!(prompt.contains("The diffs are for src/net.rs, a Rust file. "));
+        assert!(prompt.contains("(max 40 chars)"));
+        assert!(prompt.contains("Staged diff (new file):\n\n+retry"));
+        assert!(prompt.contains("Unstaged diff (modified):\n\n+backoff"));
+        assert_eq!(paired.requests.lock().unwrap().len(), 1);
+
+        // A reply without both summaries is asked for again one side at a time
+        let unpaired = Sides {
+            paired: false,
+            requests: Default::default(),
+        };
+        assert_eq!(
+            texts(unpaired.summarize_sides(&staged, &unstaged).await?),
+            ("one side".to_string(), "one side".to_string())
+        );
+        let requests = unpaired.requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1], unpaired.summarize_prompt(&staged));
+        assert_eq!(requests[2], unpaired.summarize_prompt(&unstaged));
 
-        // We'll implement this next
+        // Commands get bare diffs, so they're asked once per side
+        let command = CommandSummarizer::new("head -c 6");
+        assert_eq!(
+            texts(command.summarize_sides(&staged, &unstaged).await?),
+            ("+retry".to_string(), "+backo".to_string())
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,140 @@
+//! Highlights code in diffs with syntect's grammars, going by the file's name, in the
+//! theme `status.syntax-theme` picks.
+//!
+//! Red and green are left to the diff, so only unchanged lines are colored. Added lines
+//! are still read, so a string or comment one of them opens is highlighted where an
+//! unchanged line continues it.
+
+use anyhow::Result;
+use std::path::Path;
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::as_24_bit_terminal_escaped;
+
+/// The theme used unless `status.syntax-theme` names another.
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+/// The `status.syntax-theme` that turns highlighting off.
+const NO_THEME: &str = "none";
+
+static THEME: OnceLock<Option<Theme>> = OnceLock::new();
+/// Loaded the first time a diff is highlighted, since most runs don't show one.
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+
+/// Highlights with the theme called `name` from here on: one of syntect's bundled themes,
+/// or `none`.
+pub fn use_theme(name: &str) -> Result<()> {
+    let theme = match name {
+        NO_THEME => None,
+        name => {
+            let mut themes = ThemeSet::load_defaults().themes;
+            let names: Vec<_> = themes.keys().map(|name| format!("`{}`", name)).collect();
+            let theme = themes.remove(name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown syntax theme `{}`; use one of {}, or `{}`",
+                    name,
+                    names.join(", "),
+                    NO_THEME
+                )
+            })?;
+            Some(theme)
+        }
+    };
+    let _ = THEME.set(theme);
+    Ok(())
+}
+
+fn theme() -> Option<&'static Theme> {
+    THEME
+        .get_or_init(|| ThemeSet::load_defaults().themes.remove(DEFAULT_THEME))
+        .as_ref()
+}
+
+/// A highlighter for the code in the diff of the file at `path`, or `None` if its
+/// language isn't known or highlighting is off.
+pub fn for_path(path: &Path) -> Option<Highlighter> {
+    let theme = theme()?;
+    let syntaxes = SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines);
+    // Some files, like `Makefile`, are known by their whole name
+    let syntax = path
+        .file_name()
+        .and_then(|name| syntaxes.find_syntax_by_extension(name.to_str()?))
+        .or_else(|| syntaxes.find_syntax_by_extension(path.extension()?.to_str()?))?;
+    Some(Highlighter {
+        lines: HighlightLines::new(syntax, theme),
+        syntaxes,
+        syntax,
+        theme,
+    })
+}
+
+/// Highlights one file's lines of code, which are read in order.
+pub struct Highlighter {
+    lines: HighlightLines<'static>,
+    syntaxes: &'static SyntaxSet,
+    syntax: &'static SyntaxReference,
+    theme: &'static Theme,
+}
+
+impl Highlighter {
+    /// A line of code, colored.
+    pub fn highlight(&mut self, line: &str) -> String {
+        // The grammars expect each line to end in a newline
+        let line = format!("{}\n", line);
+        match self.lines.highlight_line(&line, self.syntaxes) {
+            Ok(ranges) => format!(
+                "{}\x1b[0m",
+                as_24_bit_terminal_escaped(&ranges, false).trim_end_matches('\n')
+            ),
+            Err(_) => line.trim_end_matches('\n').to_string(),
+        }
+    }
+
+    /// Reads a line that isn't shown highlighted, like an added one, for what it opens or
+    /// closes.
+    pub fn read(&mut self, line: &str) {
+        let _ = self
+            .lines
+            .highlight_line(&format!("{}\n", line), self.syntaxes);
+    }
+
+    /// Starts over, for a hunk, which could begin anywhere in the file.
+    pub fn reset(&mut self) {
+        self.lines = HighlightLines::new(self.syntax, self.theme);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let mut highlighter = for_path(Path::new("src/main.rs")).unwrap();
+        let line = highlighter.highlight("let s = \"text\"; // note");
+        assert!(line.contains("\x1b[38;2;"));
+        assert!(line.ends_with("\x1b[0m"));
+        // Only colors are added
+        let plain = line.split('\x1b').fold(String::new(), |mut plain, part| {
+            plain.push_str(part.split_once('m').map_or(part, |(_, text)| text));
+            plain
+        });
+        assert_eq!(plain, "let s = \"text\"; // note");
+
+        // A comment opened on an earlier line carries on
+        highlighter.read("/* start");
+        let inside = highlighter.highlight("fn not_code");
+        highlighter.reset();
+        assert_ne!(inside, highlighter.highlight("fn not_code"));
+
+        assert!(for_path(Path::new("Makefile")).is_some());
+        assert!(for_path(Path::new("notes.unknown")).is_none());
+    }
+
+    #[test]
+    fn test_use_theme() {
+        let error = use_theme("no-such-theme").unwrap_err().to_string();
+        assert!(error.contains("`InspiredGitHub`"));
+    }
+}
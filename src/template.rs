@@ -0,0 +1,166 @@
+//! Renders user-defined templates for generated text, like commit messages and release
+//! notes, so it can follow a team's required format. Templates name variables as
+//! `{{name}}`; lists are rendered as `- ` bullet lines.
+
+use anyhow::Result;
+
+/// The variables a template can use, in the order `git hud` lists them in errors.
+#[derive(Debug, Default)]
+pub struct Vars {
+    vars: Vec<(&'static str, String)>,
+}
+
+impl Vars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.vars.push((name, value.into()));
+        self
+    }
+
+    /// Sets a list, as one `- item` line per item.
+    pub fn list(self, name: &'static str, items: &[String]) -> Self {
+        let lines: Vec<String> = items.iter().map(|item| format!("- {}", item)).collect();
+        self.set(name, lines.join("\n"))
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.vars
+            .iter()
+            .find(|(var, _)| *var == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Fills in `template`'s `{{name}}` placeholders. Naming a variable that isn't set is an
+/// error, so a typo doesn't quietly leave a gap in the output.
+pub fn render(template: &str, vars: &Vars) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow::anyhow!("Unclosed `{{{{` in template"))?;
+        let name = after[..end].trim();
+        let value = vars.get(name).ok_or_else(|| {
+            let known: Vec<&str> = vars.vars.iter().map(|(var, _)| *var).collect();
+            anyhow::anyhow!(
+                "The template uses `{{{{{}}}}}`, but the variables here are: {}",
+                name,
+                known.join(", ")
+            )
+        })?;
+        output.push_str(value);
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Ticket references like `ABC-123` or `#123`, in the order they first appear.
+pub fn tickets<'a>(texts: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let is_number = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    let is_project = |part: &str| {
+        part.len() >= 2
+            && part.starts_with(|c: char| c.is_ascii_uppercase())
+            && part
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    };
+
+    let mut tickets: Vec<String> = Vec::new();
+    let mut add = |ticket: String| {
+        if !tickets.contains(&ticket) {
+            tickets.push(ticket);
+        }
+    };
+    for text in texts {
+        // Branch names join words with `-`, as in `PROJ-42-login`
+        for word in text.split(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '#') {
+            let parts: Vec<&str> = word.split('-').collect();
+            for (index, part) in parts.iter().enumerate() {
+                match part.strip_prefix('#') {
+                    Some(number) if is_number(number) => add(part.to_string()),
+                    _ => match parts.get(index + 1) {
+                        Some(number) if is_project(part) && is_number(number) => {
+                            add(format!("{}-{}", part, number))
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+    }
+    tickets
+}
+
+/// The files a unified diff touches, and how many lines it adds and removes.
+pub fn diff_stats(diff: &str) -> (Vec<String>, usize, usize) {
+    let (mut files, mut added, mut removed) = (Vec::new(), 0, 0);
+    let mut old_path = None;
+    for line in diff.lines() {
+        if let Some(path) = line.strip_prefix("--- ") {
+            old_path = Some(path);
+        } else if let Some(path) = line.strip_prefix("+++ ") {
+            // A deleted file is only named on the `---` line
+            let path = match path {
+                "/dev/null" => old_path.unwrap_or(path),
+                path => path,
+            };
+            files.push(path.to_string());
+        } else if line.starts_with('+') {
+            added += 1;
+        } else if line.starts_with('-') {
+            removed += 1;
+        }
+    }
+    (files, added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let vars = Vars::new()
+            .set("subject", "Fix the parser")
+            .list("files", &["a.rs".to_string(), "b.rs".to_string()]);
+        assert_eq!(
+            render("{{subject}}\n\nFiles:\n{{ files }}\n", &vars)?,
+            "Fix the parser\n\nFiles:\n- a.rs\n- b.rs\n"
+        );
+        assert_eq!(render("no placeholders", &vars)?, "no placeholders");
+
+        let error = render("{{ticket}}", &vars).unwrap_err().to_string();
+        assert!(error.contains("`{{ticket}}`"), "{}", error);
+        assert!(error.contains("subject, files"), "{}", error);
+        assert!(render("{{subject", &vars).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tickets() {
+        assert_eq!(
+            tickets([
+                "feature/PROJ-42-login",
+                "Fix #7 and PROJ-42 again",
+                "utf-8 x-1"
+            ]),
+            vec!["PROJ-42", "#7"]
+        );
+    }
+
+    #[test]
+    fn test_diff_stats() {
+        let diff = "diff --git a.rs a.rs\n--- a.rs\n+++ a.rs\n@@ -1 +1,2 @@\n-old\n+new\n+more\ndiff --git gone.rs gone.rs\n--- gone.rs\n+++ /dev/null\n@@ -1 +0,0 @@\n-bye\n";
+        assert_eq!(
+            diff_stats(diff),
+            (vec!["a.rs".to_string(), "gone.rs".to_string()], 2, 2)
+        );
+    }
+}
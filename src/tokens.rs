@@ -0,0 +1,100 @@
+//! Token counts estimated locally, without asking the provider, for rate limits, the
+//! audit log, `--dry-run`, and `status.confirm-cost`.
+
+use crate::config::ProviderConfig;
+
+/// Estimates tokens for one provider: `count`'s approximation, scaled by the provider's
+/// `token-factor` to match what its tokenizer actually gives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimator {
+    factor: f64,
+}
+
+impl Default for Estimator {
+    fn default() -> Self {
+        Self { factor: 1.0 }
+    }
+}
+
+impl Estimator {
+    /// The estimator calibrated by a provider's config, if it sets a factor.
+    pub fn new(config: Option<&ProviderConfig>) -> Self {
+        match config.and_then(|config| config.token_factor) {
+            Some(factor) if factor > 0.0 => Self { factor },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn estimate(&self, text: &str) -> u32 {
+        (f64::from(count(text)) * self.factor).ceil() as u32
+    }
+}
+
+/// Roughly how many tokens a BPE tokenizer splits `text` into. Words are a token for
+/// every six letters, numbers one for every three digits, and runs of punctuation like
+/// `()` or `::` one for every two marks. A character from another script is a token of
+/// its own. A single space is part of the word after it, while indentation and blank
+/// lines are a token for every four characters.
+pub fn count(text: &str) -> u32 {
+    let mut tokens = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let mut run: u32 = 1;
+        let mut take_run = |same: fn(&char) -> bool| {
+            while chars.next_if(same).is_some() {
+                run += 1;
+            }
+            run
+        };
+        tokens += match c {
+            c if c.is_ascii_alphabetic() => take_run(char::is_ascii_alphabetic).div_ceil(6),
+            c if c.is_ascii_digit() => take_run(char::is_ascii_digit).div_ceil(3),
+            ' ' | '\t' | '\n' | '\r' => {
+                let run = take_run(|c| matches!(c, ' ' | '\t' | '\n' | '\r'));
+                match (run, c) {
+                    (1, ' ') => 0,
+                    _ => run.div_ceil(4),
+                }
+            }
+            c if c.is_ascii_punctuation() => take_run(char::is_ascii_punctuation).div_ceil(2),
+            _ => 1,
+        };
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        assert_eq!(count(""), 0);
+        assert_eq!(count("hello world"), 2);
+        // Long words take more than one token, and so do numbers
+        assert_eq!(count("internationalization 2024"), 6);
+        assert_eq!(count("fn main() {}"), 4);
+        assert_eq!(count("+    let x = 1;\n"), 8);
+        assert_eq!(count("日本語"), 3);
+        // Code takes more tokens than four characters per token gives, but not by half
+        let diff = include_str!("hunks.rs");
+        let ratio = f64::from(count(diff)) / (diff.len() as f64 / 4.0);
+        assert!((1.0..1.5).contains(&ratio), "{}", ratio);
+    }
+
+    #[test]
+    fn test_estimator() {
+        let text = "hello world";
+        assert_eq!(Estimator::new(None).estimate(text), 2);
+        let config = ProviderConfig {
+            token_factor: Some(1.6),
+            ..ProviderConfig::default()
+        };
+        assert_eq!(Estimator::new(Some(&config)).estimate(text), 4);
+        let config = ProviderConfig {
+            token_factor: Some(0.0),
+            ..ProviderConfig::default()
+        };
+        assert_eq!(Estimator::new(Some(&config)).estimate(text), 2);
+    }
+}
@@ -0,0 +1,241 @@
+//! A safety net for discarding changes. Before any command throws a file's changes away,
+//! the file is copied into `.git/git-hud/trash`, and `git hud undo` puts the latest
+//! batch back.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// How many batches are kept; older ones are deleted.
+const KEPT_BATCHES: usize = 20;
+const MANIFEST: &str = "manifest.json";
+
+/// The trash in a repository's git dir.
+pub struct Trash {
+    dir: PathBuf,
+}
+
+/// The files one discard threw away, and where their copies are.
+pub struct Batch {
+    dir: PathBuf,
+    pub manifest: Manifest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub timestamp: DateTime<Utc>,
+    pub files: Vec<TrashedFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashedFile {
+    /// Relative to the repo root
+    pub path: PathBuf,
+    /// Whether the file was there; discarding a deletion brings it back
+    pub existed: bool,
+    /// A hash of what discarding left in its place, so undoing doesn't overwrite edits
+    /// made since. `None` until the discard is done, or when it left no file.
+    pub left: Option<String>,
+}
+
+impl Trash {
+    pub fn new(git_dir: &Path) -> Self {
+        Self {
+            dir: git_dir.join("git-hud").join("trash"),
+        }
+    }
+
+    /// Copies `paths`, relative to `root`, into a new batch. Call it before discarding
+    /// them, then `Batch::seal` after.
+    pub fn save(&self, root: &Path, paths: &[PathBuf]) -> Result<Batch> {
+        let timestamp = Utc::now();
+        let dir = self
+            .dir
+            .join(timestamp.format("%Y%m%dT%H%M%S%.3fZ").to_string());
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let source = root.join(path);
+            let existed = source.is_file();
+            if existed {
+                let copy = dir.join("files").join(path);
+                if let Some(parent) = copy.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::copy(&source, &copy)
+                    .with_context(|| format!("Failed to copy {} to the trash", path.display()))?;
+            }
+            files.push(TrashedFile {
+                path: path.clone(),
+                existed,
+                left: None,
+            });
+        }
+        let batch = Batch {
+            dir,
+            manifest: Manifest { timestamp, files },
+        };
+        batch.write_manifest()?;
+        self.prune()?;
+        Ok(batch)
+    }
+
+    /// The batch most recently thrown away, if any is left to undo.
+    pub fn latest(&self) -> Result<Option<Batch>> {
+        let Some(dir) = self.batches().pop() else {
+            return Ok(None);
+        };
+        let contents = std::fs::read_to_string(dir.join(MANIFEST))
+            .with_context(|| format!("Failed to read {}", dir.join(MANIFEST).display()))?;
+        let manifest = serde_json::from_str(&contents)
+            .with_context(|| format!("{} isn't a trash manifest", dir.display()))?;
+        Ok(Some(Batch { dir, manifest }))
+    }
+
+    /// Every batch, oldest first; names sort by time.
+    fn batches(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = std::fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.join(MANIFEST).is_file())
+            .collect();
+        dirs.sort();
+        dirs
+    }
+
+    fn prune(&self) -> Result<()> {
+        let batches = self.batches();
+        for dir in &batches[..batches.len().saturating_sub(KEPT_BATCHES)] {
+            std::fs::remove_dir_all(dir)
+                .with_context(|| format!("Failed to delete {}", dir.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Batch {
+    /// Records what discarding left in each file's place.
+    pub fn seal(&mut self, root: &Path) -> Result<()> {
+        for file in &mut self.manifest.files {
+            file.left = content_hash(&root.join(&file.path));
+        }
+        self.write_manifest()
+    }
+
+    /// Puts each file back the way it was before it was discarded, then empties the
+    /// batch. A file edited since is left alone, and nothing is put back, unless `force`.
+    pub fn restore(self, root: &Path, force: bool) -> Result<Vec<PathBuf>> {
+        if !force {
+            let edited: Vec<_> = self
+                .manifest
+                .files
+                .iter()
+                .filter(|file| content_hash(&root.join(&file.path)) != file.left)
+                .map(|file| file.path.display().to_string())
+                .collect();
+            if !edited.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Changed since the discard, so undoing would lose those changes: {}. Pass --force to undo anyway",
+                    edited.join(", ")
+                ));
+            }
+        }
+        for file in &self.manifest.files {
+            let target = root.join(&file.path);
+            match file.existed {
+                true => {
+                    if let Some(parent) = target.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::copy(self.dir.join("files").join(&file.path), &target)
+                        .with_context(|| format!("Failed to put back {}", file.path.display()))?;
+                }
+                false if target.is_file() => std::fs::remove_file(&target)?,
+                false => {}
+            }
+        }
+        std::fs::remove_dir_all(&self.dir)
+            .with_context(|| format!("Failed to delete {}", self.dir.display()))?;
+        Ok(self
+            .manifest
+            .files
+            .into_iter()
+            .map(|file| file.path)
+            .collect())
+    }
+
+    fn write_manifest(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        crate::cache::write_atomic(
+            &self.dir.join(MANIFEST),
+            &serde_json::to_string_pretty(&self.manifest)?,
+        )
+    }
+}
+
+/// Hex SHA-256 of a file's content, or `None` if there's no file.
+fn content_hash(path: &Path) -> Option<String> {
+    let content = std::fs::read(path).ok()?;
+    Some(
+        Sha256::digest(&content)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_and_restore() -> Result<()> {
+        let root = TempDir::new()?;
+        let trash = Trash::new(&root.path().join(".git"));
+        fs::create_dir_all(root.path().join("src"))?;
+        fs::write(root.path().join("src/lib.rs"), "edited\n")?;
+        let paths = [PathBuf::from("src/lib.rs"), PathBuf::from("gone.txt")];
+
+        let mut batch = trash.save(root.path(), &paths)?;
+        // The discard: an edit undone, and a deleted file brought back
+        fs::write(root.path().join("src/lib.rs"), "committed\n")?;
+        fs::write(root.path().join("gone.txt"), "committed\n")?;
+        batch.seal(root.path())?;
+
+        let restored = trash.latest()?.unwrap().restore(root.path(), false)?;
+        assert_eq!(restored, paths);
+        assert_eq!(
+            fs::read_to_string(root.path().join("src/lib.rs"))?,
+            "edited\n"
+        );
+        assert!(!root.path().join("gone.txt").exists());
+        assert!(trash.latest()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_keeps_later_edits() -> Result<()> {
+        let root = TempDir::new()?;
+        let trash = Trash::new(&root.path().join(".git"));
+        fs::write(root.path().join("a.txt"), "edited\n")?;
+        let mut batch = trash.save(root.path(), &[PathBuf::from("a.txt")])?;
+        fs::write(root.path().join("a.txt"), "committed\n")?;
+        batch.seal(root.path())?;
+
+        fs::write(root.path().join("a.txt"), "edited again\n")?;
+        let error = trash.latest()?.unwrap().restore(root.path(), false);
+        assert!(error.unwrap_err().to_string().contains("a.txt"));
+        assert_eq!(
+            fs::read_to_string(root.path().join("a.txt"))?,
+            "edited again\n"
+        );
+
+        trash.latest()?.unwrap().restore(root.path(), true)?;
+        assert_eq!(fs::read_to_string(root.path().join("a.txt"))?, "edited\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+//! Measures, pads, and shortens text by the columns it takes up in a terminal, so CJK
+//! and emoji in summaries and paths don't throw off alignment or get cut in half.
+//!
+//! Text is cut between extended grapheme clusters, so accents, skin tones, ZWJ sequences,
+//! and flags stay whole.
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const ELLIPSIS: char = '…';
+
+/// How many terminal columns `text` takes up.
+pub fn width(text: &str) -> usize {
+    text.width()
+}
+
+/// `text` followed by enough spaces to fill `columns`, or as is if it's already wider.
+pub fn pad(text: &str, columns: usize) -> String {
+    let padding = columns.saturating_sub(width(text));
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+/// `text` cut down to at most `columns`, ending in an ellipsis if anything was dropped.
+pub fn ellipsize(text: &str, columns: usize) -> String {
+    if width(text) <= columns || columns == 0 {
+        return match columns {
+            0 => String::new(),
+            _ => text.to_string(),
+        };
+    }
+    let room = columns - ELLIPSIS.width().unwrap_or(1);
+    let mut kept = String::new();
+    let mut used = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = width(grapheme);
+        if used + grapheme_width > room {
+            break;
+        }
+        kept.push_str(grapheme);
+        used += grapheme_width;
+    }
+    // An ellipsis after a space reads as a separate word
+    let mut kept = kept.trim_end().to_string();
+    kept.push(ELLIPSIS);
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_width() {
+        assert_eq!(width("abc"), 3);
+        assert_eq!(width("日本語"), 6);
+        assert_eq!(width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!(pad("日本", 6), "日本  ");
+        assert_eq!(pad("abc", 2), "abc");
+    }
+
+    #[test]
+    fn test_ellipsize() {
+        assert_eq!(ellipsize("short", 10), "short");
+        assert_eq!(ellipsize("adds a parser", 8), "adds a…");
+        // Wide characters count double, and one can't be split to fill the last column
+        assert_eq!(ellipsize("日本語のテキスト", 6), "日本…");
+        assert_eq!(ellipsize("日本語のテキスト", 7), "日本語…");
+        // Accents, skin tones, ZWJ sequences, and flags stay whole
+        assert_eq!(ellipsize("cafe\u{301} au lait", 5), "cafe\u{301}…");
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        assert!(ellipsize(&format!("{} family", family), 5).starts_with(family));
+        assert_eq!(
+            ellipsize("\u{1f44b}\u{1f3fd} hello", 4),
+            "\u{1f44b}\u{1f3fd}…"
+        );
+        assert_eq!(
+            ellipsize("\u{1f1ef}\u{1f1f5}\u{1f1fa}\u{1f1f8}", 3),
+            "\u{1f1ef}\u{1f1f5}…"
+        );
+        assert_eq!(ellipsize("anything", 0), "");
+    }
+}
@@ -0,0 +1,275 @@
+//! Works out which words changed between the lines a diff removes and the lines that
+//! replace them, for showing a diff word by word like `git diff --word-diff`.
+//!
+//! Words are runs of letters, digits, and underscores; whitespace runs and every other
+//! character are tokens of their own, so `foo(bar)` to `foo(baz)` only changes `bar`.
+
+/// A stretch of text and whether it was kept, removed, or added.
+#[derive(Debug, PartialEq)]
+pub enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Lines in a unified diff, with each run of removed lines grouped with the added lines
+/// right after it, which are usually what replaced them.
+#[derive(Debug, PartialEq)]
+pub enum Block<'a> {
+    Line(&'a str),
+    Replaced {
+        removed: Vec<&'a str>,
+        added: Vec<&'a str>,
+    },
+}
+
+/// Past this many token pairs, a change is shown as a whole rather than compared word by
+/// word, which would be slow and too scattered to read anyway.
+const MAX_COMPARISONS: usize = 250_000;
+
+/// Compares `old` and `new` word by word. Between any two stretches that match, what
+/// was removed comes before what was added.
+pub fn diff_words<'a>(old: &'a str, new: &'a str) -> Vec<Change<'a>> {
+    let (old_tokens, new_tokens) = (tokens(old), tokens(new));
+    if old_tokens.len() * new_tokens.len() > MAX_COMPARISONS {
+        return [Change::Removed(old), Change::Added(new)]
+            .into_iter()
+            .filter(|change| !text(change).is_empty())
+            .collect();
+    }
+
+    // The longest common subsequence of tokens from each position on
+    let mut lengths = vec![vec![0u32; new_tokens.len() + 1]; old_tokens.len() + 1];
+    for i in (0..old_tokens.len()).rev() {
+        for j in (0..new_tokens.len()).rev() {
+            lengths[i][j] = match old_tokens[i].1 == new_tokens[j].1 {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+
+    // Byte ranges, removals in `old` and everything else in `new`
+    let mut ranges: Vec<(Kind, usize, usize)> = Vec::new();
+    let mut push = |kind: Kind, start: usize, end: usize| match ranges.last_mut() {
+        _ if start == end => {}
+        Some(last) if last.0 == kind && last.2 == start => last.2 = end,
+        _ => ranges.push((kind, start, end)),
+    };
+    let (mut i, mut j) = (0, 0);
+    let (mut old_gap, mut new_gap) = (0, 0);
+    loop {
+        let matched =
+            i < old_tokens.len() && j < new_tokens.len() && old_tokens[i].1 == new_tokens[j].1;
+        let done = i == old_tokens.len() && j == new_tokens.len();
+        if matched || done {
+            push(
+                Kind::Removed,
+                offset(&old_tokens, old_gap, old),
+                offset(&old_tokens, i, old),
+            );
+            push(
+                Kind::Added,
+                offset(&new_tokens, new_gap, new),
+                offset(&new_tokens, j, new),
+            );
+        }
+        if done {
+            break;
+        }
+        if matched {
+            push(
+                Kind::Same,
+                offset(&new_tokens, j, new),
+                offset(&new_tokens, j + 1, new),
+            );
+            i += 1;
+            j += 1;
+            (old_gap, new_gap) = (i, j);
+        } else if j == new_tokens.len()
+            || (i < old_tokens.len() && lengths[i + 1][j] >= lengths[i][j + 1])
+        {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(kind, start, end)| match kind {
+            Kind::Same => Change::Same(&new[start..end]),
+            Kind::Removed => Change::Removed(&old[start..end]),
+            Kind::Added => Change::Added(&new[start..end]),
+        })
+        .collect()
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    Same,
+    Removed,
+    Added,
+}
+
+/// Groups a unified diff's lines into blocks.
+pub fn blocks(diff: &str) -> Vec<Block<'_>> {
+    let mut blocks = Vec::new();
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let mut in_hunk = false;
+    for line in diff.lines() {
+        if line.starts_with("diff ") {
+            in_hunk = false;
+        } else if line.starts_with("@@") {
+            in_hunk = true;
+        }
+        let is_header = !in_hunk && (line.starts_with("--- ") || line.starts_with("+++ "));
+        match line.as_bytes().first() {
+            Some(b'-') if !is_header && added.is_empty() => {
+                removed.push(&line[1..]);
+                continue;
+            }
+            Some(b'+') if !is_header => {
+                added.push(&line[1..]);
+                continue;
+            }
+            _ => {}
+        }
+        flush(&mut blocks, &mut removed, &mut added);
+        match line.starts_with('-') && !is_header {
+            // A removal after additions starts a new replacement
+            true => removed.push(&line[1..]),
+            false => blocks.push(Block::Line(line)),
+        }
+    }
+    flush(&mut blocks, &mut removed, &mut added);
+    blocks
+}
+
+fn flush<'a>(blocks: &mut Vec<Block<'a>>, removed: &mut Vec<&'a str>, added: &mut Vec<&'a str>) {
+    if removed.is_empty() && added.is_empty() {
+        return;
+    }
+    blocks.push(Block::Replaced {
+        removed: std::mem::take(removed),
+        added: std::mem::take(added),
+    });
+}
+
+fn text<'a>(change: &Change<'a>) -> &'a str {
+    match change {
+        Change::Same(text) | Change::Removed(text) | Change::Added(text) => text,
+    }
+}
+
+/// Where the `index`th token starts, or the end of `text` past the last one.
+fn offset(tokens: &[(usize, &str)], index: usize, text: &str) -> usize {
+    tokens.get(index).map_or(text.len(), |(start, _)| *start)
+}
+
+/// Splits text into words, whitespace runs, and single other characters, with where
+/// each one starts.
+fn tokens(text: &str) -> Vec<(usize, &str)> {
+    #[derive(PartialEq)]
+    enum Class {
+        Word,
+        Space,
+        Other,
+    }
+    let class = |c: char| match c {
+        c if c.is_alphanumeric() || c == '_' => Class::Word,
+        // Line breaks stay separate so lines line up with lines
+        c if c.is_whitespace() && c != '\n' => Class::Space,
+        _ => Class::Other,
+    };
+
+    let mut tokens: Vec<(usize, &str)> = Vec::new();
+    let mut start = 0;
+    let mut current = None;
+    for (index, c) in text.char_indices() {
+        let next = class(c);
+        let continues = current.as_ref() == Some(&next) && next != Class::Other;
+        if !continues && index > start {
+            tokens.push((start, &text[start..index]));
+            start = index;
+        }
+        current = Some(next);
+    }
+    if start < text.len() {
+        tokens.push((start, &text[start..]));
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        let texts: Vec<_> = tokens("let x  = foo(bar);\n")
+            .into_iter()
+            .map(|(_, t)| t)
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["let", " ", "x", "  ", "=", " ", "foo", "(", "bar", ")", ";", "\n"]
+        );
+    }
+
+    #[test]
+    fn test_diff_words() {
+        assert_eq!(
+            diff_words("call(foo, 1)", "call(bar, 1)"),
+            vec![
+                Change::Same("call("),
+                Change::Removed("foo"),
+                Change::Added("bar"),
+                Change::Same(", 1)"),
+            ]
+        );
+        assert_eq!(
+            diff_words("a b", "a c d"),
+            vec![
+                Change::Same("a "),
+                Change::Removed("b"),
+                Change::Added("c d"),
+            ]
+        );
+        assert_eq!(diff_words("", "new"), vec![Change::Added("new")]);
+        assert_eq!(diff_words("same", "same"), vec![Change::Same("same")]);
+    }
+
+    #[test]
+    fn test_blocks() {
+        let diff = "--- a.rs\n+++ a.rs\n@@ -1,3 +1,3 @@\n keep\n-old\n+new\n+more\n-gone\n";
+        assert_eq!(
+            blocks(diff),
+            vec![
+                Block::Line("--- a.rs"),
+                Block::Line("+++ a.rs"),
+                Block::Line("@@ -1,3 +1,3 @@"),
+                Block::Line(" keep"),
+                Block::Replaced {
+                    removed: vec!["old"],
+                    added: vec!["new", "more"],
+                },
+                Block::Replaced {
+                    removed: vec!["gone"],
+                    added: vec![],
+                },
+            ]
+        );
+        // Inside a hunk, a removed line that starts with "-- " is still a removal
+        assert_eq!(
+            blocks("@@ -1 +1 @@\n--- note\n"),
+            vec![
+                Block::Line("@@ -1 +1 @@"),
+                Block::Replaced {
+                    removed: vec!["-- note"],
+                    added: vec![],
+                },
+            ]
+        );
+    }
+}